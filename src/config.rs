@@ -1,11 +1,18 @@
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroU32;
+use std::path::Path;
+use std::time::Duration;
 
 use serde_derive::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
+use crate::http;
+use crate::ip::SourceSettings;
 use crate::services::*;
-use crate::util::{one_or_more_string, parse_number_into_optional_nonzero};
+use crate::util::{
+    self, one_or_more_string, parse_number_into_optional_nonzero, parse_optional_dns_server,
+};
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct General {
@@ -17,6 +24,93 @@ pub struct General {
     pub user_agent: Box<str>,
     #[serde(default = "default_persistent_state")]
     pub persistent_state: Box<str>,
+    #[serde(default)]
+    pub diagnostics_dir: Box<str>,
+    #[serde(default)]
+    pub debounce: u32,
+    #[serde(default = "default_control_socket")]
+    pub control_socket: Box<str>,
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(default)]
+    pub events: Box<str>,
+    #[serde(default, deserialize_with = "parse_optional_dns_server")]
+    pub resolver: Option<SocketAddr>,
+    #[serde(default)]
+    pub pins: HashMap<Box<str>, IpAddr>,
+    #[serde(default)]
+    pub reconcile: bool,
+    #[serde(default = "default_max_connections_per_host")]
+    pub max_connections_per_host: u32,
+    #[serde(default = "default_plugins_dir")]
+    pub plugins_dir: Box<str>,
+}
+
+impl General {
+    /// Gives the instance its own persistent state, control socket and
+    /// diagnostics directory, so that several instances (see `--instance`)
+    /// can run side by side.
+    pub fn namespace(&mut self, instance: &str) {
+        self.persistent_state = namespaced_path(&self.persistent_state, instance);
+        self.control_socket = namespaced_path(&self.control_socket, instance);
+
+        if !self.diagnostics_dir.is_empty() {
+            let dir = Path::new(&*self.diagnostics_dir).join(instance);
+            self.diagnostics_dir = dir.to_string_lossy().into();
+        }
+    }
+
+    /// How long a changed address has to stay the same before it is published.
+    /// A fire-once program has no later cycle to confirm the change in, so it
+    /// publishes the change right away.
+    pub fn debounce(&self) -> Duration {
+        match self.update_rate {
+            Some(_) => Duration::from_secs(self.debounce as u64),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// The options the HTTP clients of the IP sources and services follow.
+    pub fn http_settings(&self) -> http::Settings {
+        http::Settings {
+            user_agent: self.user_agent.clone(),
+            pins: self.pins.clone(),
+            resolver: self.resolver,
+            max_connections_per_host: self.max_connections_per_host,
+            capture_exchanges: !self.diagnostics_dir.is_empty(),
+        }
+    }
+
+    pub fn source_settings(&self) -> SourceSettings {
+        SourceSettings {
+            http: self.http_settings(),
+            shell: self.shell.clone(),
+        }
+    }
+
+    pub fn service_settings(&self) -> ServiceSettings {
+        ServiceSettings {
+            http: self.http_settings(),
+            shell: self.shell.clone(),
+            plugins_dir: self.plugins_dir.clone(),
+        }
+    }
+}
+
+/// Puts the file into a subdirectory named after the instance, e.g.
+/// /var/lib/dynners/persistence becomes /var/lib/dynners/<instance>/persistence.
+/// An empty path (which disables the file) is left alone.
+pub fn namespaced_path(path: &str, instance: &str) -> Box<str> {
+    let path = Path::new(path);
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => parent
+            .join(instance)
+            .join(file_name)
+            .to_string_lossy()
+            .into(),
+        _ => path.to_string_lossy().into(),
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
@@ -25,13 +119,26 @@ pub struct General {
 pub enum IpConfigMethod {
     Exec {
         command: Box<str>,
+
+        /// The output stream the address is read from.
+        #[serde(default)]
+        stream: ExecStream,
+
+        /// If given, the command fails unless it exits with this code.
+        #[serde(default)]
+        exit_code: Option<i32>,
+
+        /// If given, the first capture group is taken as the address instead
+        /// of the whole (trimmed) output.
+        #[serde(default)]
+        regex: Option<Box<str>>,
     },
 
     Interface {
         iface: Box<str>,
 
-        #[serde(default)]
-        matches: Box<str>,
+        #[serde(default, deserialize_with = "one_or_more_string")]
+        matches: Vec<Box<str>>,
     },
 
     Http {
@@ -40,6 +147,23 @@ pub enum IpConfigMethod {
         #[serde(default = "default_regex")]
         regex: Box<str>,
     },
+
+    Stun {
+        /// Asked in turn until one of them answers, as "host" or "host:port".
+        #[serde(
+            default = "default_stun_servers",
+            deserialize_with = "one_or_more_string"
+        )]
+        servers: Vec<Box<str>>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecStream {
+    #[default]
+    Stdout,
+    Stderr,
 }
 
 #[derive(Deserialize_repr, Serialize_repr, Clone, Debug, PartialEq, Eq)]
@@ -52,48 +176,824 @@ pub enum IpVersion {
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct IpConfig {
     pub version: IpVersion,
+    /// In seconds, 0 waits forever. See `timeout()` for the default.
+    #[serde(default)]
+    pub timeout: Option<u32>,
+    #[serde(default)]
+    pub cache_ttl: u32,
+    #[serde(default)]
+    pub compare_with: Option<Box<str>>,
+    #[serde(default)]
+    pub skip_behind_nat: bool,
+    #[serde(default)]
+    pub failover: Option<Box<str>>,
+    #[serde(default = "default_failover_after")]
+    pub failover_after: u32,
     #[serde(flatten)]
     pub method: IpConfigMethod,
 }
 
+impl IpConfig {
+    /// The timeout of the source in seconds. A command is waited for as long
+    /// as it takes unless a timeout is given, the network sources give up
+    /// after 10 seconds.
+    pub fn timeout(&self) -> u32 {
+        match (self.timeout, &self.method) {
+            (Some(timeout), _) => timeout,
+            (None, IpConfigMethod::Exec { .. }) => 0,
+            (None, _) => 10,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 #[serde(tag = "service")]
 #[serde(rename_all = "kebab-case")]
 pub enum DdnsConfigService {
+    #[serde(rename = "1984-hosting")]
+    Hosting1984(hosting1984::Config),
+    Alidns(alidns::Config),
+    AllInkl(allinkl::Config),
+    Azure(azure::Config),
+    Bunny(bunny::Config),
     CloudflareV4(cloudflare::Config),
+    Cloudns(cloudns::Config),
+    Constellix(constellix::Config),
+    CoreNetworks(corenetworks::Config),
+    CustomHttp(custom_http::Config),
+    Ddnss(ddnss::Config),
+    Desec(desec::Config),
+    Digitalocean(digitalocean::Config),
+    Dnshome(dnshome::Config),
+    Dnsimple(dnsimple::Config),
     DnsOMatic(dnsomatic::Config),
+    Dnspod(dnspod::Config),
+    DoDe(dode::Config),
+    Domeneshop(domeneshop::Config),
+    Dreamhost(dreamhost::Config),
     Duckdns(duckdns::Config),
+    Dyfi(dyfi::Config),
+    Dyndns2(dyndns2::Config),
     Dynu(dynu::Config),
-    Ipv64(dynu::Config),
+    Dynv6(dynv6::Config),
+    Easydns(easydns::Config),
+    Exec(exec::Config),
+    Exoscale(exoscale::Config),
+    Freedns(freedns::Config),
+    Gandi(gandi::Config),
+    Gcore(gcore::Config),
+    Glesys(glesys::Config),
+    Godaddy(godaddy::Config),
+    Goip(goip::Config),
+    GoogleCloudDns(google::Config),
+    HeNet(henet::Config),
+    Hetzner(hetzner::Config),
+    Hover(hover::Config),
+    Infomaniak(infomaniak::Config),
+    Inwx(inwx::Config),
+    Ipv64(ipv64::Config),
     Linode(linode::Config),
+    Loopia(loopia::Config),
+    MythicBeasts(mythicbeasts::Config),
+    Namecheap(namecheap::Config),
+    NameCom(namecom::Config),
+    Netcup(netcup::Config),
+    Nfsn(nfsn::Config),
+    Njalla(njalla::Config),
+    Ns1(ns1::Config),
+    NsupdateInfo(nsupdateinfo::Config),
+    Oci(oci::Config),
+    Plugin(plugin::Config),
     PorkbunV3(porkbun::Config),
-    Selfhost(dynu::Config),
+    Powerdns(powerdns::Config),
+    Rfc2136(rfc2136::Config),
+    Route53(route53::Config),
+    Scaleway(scaleway::Config),
+    Variomedia(variomedia::Config),
+    Selfhost(selfhost::Config),
+    Spdyn(spdyn::Config),
+    Strato(strato::Config),
+    Transip(transip::Config),
+    Vultr(vultr::Config),
+    Webhook(webhook::Config),
+    Zoneedit(zoneedit::Config),
     NoIp(noip::Config),
+    Ovh(ovh::Config),
     Dummy(dummy::Config),
 }
 
 impl DdnsConfigService {
-    pub fn into_boxed(self) -> Box<dyn DdnsService> {
+    /// Every kind of service compiled into the program, as listed by
+    /// `dynners providers`.
+    pub const PROVIDERS: &'static [ProviderInfo] = &[
+        ProviderInfo {
+            service: "1984-hosting",
+            name: "1984 Hosting",
+            config_keys: util::struct_fields::<hosting1984::Config>,
+            records: hosting1984::RECORDS,
+        },
+        ProviderInfo {
+            service: "alidns",
+            name: "Alibaba Cloud DNS",
+            config_keys: util::struct_fields::<alidns::Config>,
+            records: alidns::RECORDS,
+        },
+        ProviderInfo {
+            service: "all-inkl",
+            name: "all-inkl",
+            config_keys: util::struct_fields::<allinkl::Config>,
+            records: allinkl::RECORDS,
+        },
+        ProviderInfo {
+            service: "azure",
+            name: "Azure DNS",
+            config_keys: util::struct_fields::<azure::Config>,
+            records: azure::RECORDS,
+        },
+        ProviderInfo {
+            service: "bunny",
+            name: "Bunny DNS",
+            config_keys: util::struct_fields::<bunny::Config>,
+            records: bunny::RECORDS,
+        },
+        ProviderInfo {
+            service: "cloudflare-v4",
+            name: "Cloudflare",
+            config_keys: util::struct_fields::<cloudflare::Config>,
+            records: cloudflare::RECORDS,
+        },
+        ProviderInfo {
+            service: "cloudns",
+            name: "ClouDNS",
+            config_keys: util::struct_fields::<cloudns::Config>,
+            records: cloudns::RECORDS,
+        },
+        ProviderInfo {
+            service: "constellix",
+            name: "Constellix",
+            config_keys: util::struct_fields::<constellix::Config>,
+            records: constellix::RECORDS,
+        },
+        ProviderInfo {
+            service: "core-networks",
+            name: "Core-Networks",
+            config_keys: util::struct_fields::<corenetworks::Config>,
+            records: corenetworks::RECORDS,
+        },
+        ProviderInfo {
+            service: "custom-http",
+            name: "custom HTTP",
+            config_keys: util::struct_fields::<custom_http::Config>,
+            records: custom_http::RECORDS,
+        },
+        ProviderInfo {
+            service: "ddnss",
+            name: "ddnss.de",
+            config_keys: util::struct_fields::<ddnss::Config>,
+            records: ddnss::RECORDS,
+        },
+        ProviderInfo {
+            service: "desec",
+            name: "deSEC",
+            config_keys: util::struct_fields::<desec::Config>,
+            records: desec::RECORDS,
+        },
+        ProviderInfo {
+            service: "digitalocean",
+            name: "DigitalOcean",
+            config_keys: util::struct_fields::<digitalocean::Config>,
+            records: digitalocean::RECORDS,
+        },
+        ProviderInfo {
+            service: "dnshome",
+            name: "dnshome.de",
+            config_keys: util::struct_fields::<dnshome::Config>,
+            records: dnshome::RECORDS,
+        },
+        ProviderInfo {
+            service: "dnsimple",
+            name: "DNSimple",
+            config_keys: util::struct_fields::<dnsimple::Config>,
+            records: dnsimple::RECORDS,
+        },
+        ProviderInfo {
+            service: "dns-o-matic",
+            name: "DNS-O-Matic",
+            config_keys: util::struct_fields::<dnsomatic::Config>,
+            records: dnsomatic::RECORDS,
+        },
+        ProviderInfo {
+            service: "dnspod",
+            name: "DNSPod",
+            config_keys: util::struct_fields::<dnspod::Config>,
+            records: dnspod::RECORDS,
+        },
+        ProviderInfo {
+            service: "do-de",
+            name: "do.de",
+            config_keys: util::struct_fields::<dode::Config>,
+            records: dode::RECORDS,
+        },
+        ProviderInfo {
+            service: "domeneshop",
+            name: "Domeneshop",
+            config_keys: util::struct_fields::<domeneshop::Config>,
+            records: domeneshop::RECORDS,
+        },
+        ProviderInfo {
+            service: "dreamhost",
+            name: "DreamHost",
+            config_keys: util::struct_fields::<dreamhost::Config>,
+            records: dreamhost::RECORDS,
+        },
+        ProviderInfo {
+            service: "duckdns",
+            name: "DuckDNS",
+            config_keys: util::struct_fields::<duckdns::Config>,
+            records: duckdns::RECORDS,
+        },
+        ProviderInfo {
+            service: "dyfi",
+            name: "dy.fi",
+            config_keys: util::struct_fields::<dyfi::Config>,
+            records: dyfi::RECORDS,
+        },
+        ProviderInfo {
+            service: "dyndns2",
+            name: "DynDNS v2",
+            config_keys: util::struct_fields::<dyndns2::Config>,
+            records: dyndns2::RECORDS,
+        },
+        ProviderInfo {
+            service: "dynu",
+            name: "Dynu",
+            config_keys: util::struct_fields::<dynu::Config>,
+            records: dynu::RECORDS,
+        },
+        ProviderInfo {
+            service: "dynv6",
+            name: "dynv6",
+            config_keys: util::struct_fields::<dynv6::Config>,
+            records: dynv6::RECORDS,
+        },
+        ProviderInfo {
+            service: "easydns",
+            name: "EasyDNS",
+            config_keys: util::struct_fields::<easydns::Config>,
+            records: easydns::RECORDS,
+        },
+        ProviderInfo {
+            service: "exec",
+            name: "custom command",
+            config_keys: util::struct_fields::<exec::Config>,
+            records: exec::RECORDS,
+        },
+        ProviderInfo {
+            service: "exoscale",
+            name: "Exoscale",
+            config_keys: util::struct_fields::<exoscale::Config>,
+            records: exoscale::RECORDS,
+        },
+        ProviderInfo {
+            service: "freedns",
+            name: "FreeDNS",
+            config_keys: util::struct_fields::<freedns::Config>,
+            records: freedns::RECORDS,
+        },
+        ProviderInfo {
+            service: "gandi",
+            name: "Gandi",
+            config_keys: util::struct_fields::<gandi::Config>,
+            records: gandi::RECORDS,
+        },
+        ProviderInfo {
+            service: "gcore",
+            name: "Gcore",
+            config_keys: util::struct_fields::<gcore::Config>,
+            records: gcore::RECORDS,
+        },
+        ProviderInfo {
+            service: "glesys",
+            name: "GleSYS",
+            config_keys: util::struct_fields::<glesys::Config>,
+            records: glesys::RECORDS,
+        },
+        ProviderInfo {
+            service: "godaddy",
+            name: "GoDaddy",
+            config_keys: util::struct_fields::<godaddy::Config>,
+            records: godaddy::RECORDS,
+        },
+        ProviderInfo {
+            service: "goip",
+            name: "goip.de",
+            config_keys: util::struct_fields::<goip::Config>,
+            records: goip::RECORDS,
+        },
+        ProviderInfo {
+            service: "google-cloud-dns",
+            name: "Google Cloud DNS",
+            config_keys: util::struct_fields::<google::Config>,
+            records: google::RECORDS,
+        },
+        ProviderInfo {
+            service: "he-net",
+            name: "Hurricane Electric",
+            config_keys: util::struct_fields::<henet::Config>,
+            records: henet::RECORDS,
+        },
+        ProviderInfo {
+            service: "hetzner",
+            name: "Hetzner",
+            config_keys: util::struct_fields::<hetzner::Config>,
+            records: hetzner::RECORDS,
+        },
+        ProviderInfo {
+            service: "hover",
+            name: "Hover",
+            config_keys: util::struct_fields::<hover::Config>,
+            records: hover::RECORDS,
+        },
+        ProviderInfo {
+            service: "infomaniak",
+            name: "Infomaniak",
+            config_keys: util::struct_fields::<infomaniak::Config>,
+            records: infomaniak::RECORDS,
+        },
+        ProviderInfo {
+            service: "inwx",
+            name: "INWX",
+            config_keys: util::struct_fields::<inwx::Config>,
+            records: inwx::RECORDS,
+        },
+        ProviderInfo {
+            service: "ipv64",
+            name: "IPv64",
+            config_keys: util::struct_fields::<ipv64::Config>,
+            records: ipv64::RECORDS,
+        },
+        ProviderInfo {
+            service: "linode",
+            name: "Linode",
+            config_keys: util::struct_fields::<linode::Config>,
+            records: linode::RECORDS,
+        },
+        ProviderInfo {
+            service: "loopia",
+            name: "Loopia",
+            config_keys: util::struct_fields::<loopia::Config>,
+            records: loopia::RECORDS,
+        },
+        ProviderInfo {
+            service: "mythic-beasts",
+            name: "Mythic Beasts",
+            config_keys: util::struct_fields::<mythicbeasts::Config>,
+            records: mythicbeasts::RECORDS,
+        },
+        ProviderInfo {
+            service: "namecheap",
+            name: "Namecheap",
+            config_keys: util::struct_fields::<namecheap::Config>,
+            records: namecheap::RECORDS,
+        },
+        ProviderInfo {
+            service: "name-com",
+            name: "name.com",
+            config_keys: util::struct_fields::<namecom::Config>,
+            records: namecom::RECORDS,
+        },
+        ProviderInfo {
+            service: "netcup",
+            name: "Netcup",
+            config_keys: util::struct_fields::<netcup::Config>,
+            records: netcup::RECORDS,
+        },
+        ProviderInfo {
+            service: "nfsn",
+            name: "NearlyFreeSpeech.NET",
+            config_keys: util::struct_fields::<nfsn::Config>,
+            records: nfsn::RECORDS,
+        },
+        ProviderInfo {
+            service: "njalla",
+            name: "Njalla",
+            config_keys: util::struct_fields::<njalla::Config>,
+            records: njalla::RECORDS,
+        },
+        ProviderInfo {
+            service: "ns1",
+            name: "NS1",
+            config_keys: util::struct_fields::<ns1::Config>,
+            records: ns1::RECORDS,
+        },
+        ProviderInfo {
+            service: "nsupdate-info",
+            name: "nsupdate.info",
+            config_keys: util::struct_fields::<nsupdateinfo::Config>,
+            records: nsupdateinfo::RECORDS,
+        },
+        ProviderInfo {
+            service: "oci",
+            name: "Oracle Cloud DNS",
+            config_keys: util::struct_fields::<oci::Config>,
+            records: oci::RECORDS,
+        },
+        ProviderInfo {
+            service: "plugin",
+            name: "plugin",
+            config_keys: util::struct_fields::<plugin::Config>,
+            records: plugin::RECORDS,
+        },
+        ProviderInfo {
+            service: "porkbun-v3",
+            name: "Porkbun",
+            config_keys: util::struct_fields::<porkbun::Config>,
+            records: porkbun::RECORDS,
+        },
+        ProviderInfo {
+            service: "powerdns",
+            name: "PowerDNS",
+            config_keys: util::struct_fields::<powerdns::Config>,
+            records: powerdns::RECORDS,
+        },
+        ProviderInfo {
+            service: "rfc2136",
+            name: "RFC 2136 (nsupdate)",
+            config_keys: util::struct_fields::<rfc2136::Config>,
+            records: rfc2136::RECORDS,
+        },
+        ProviderInfo {
+            service: "route53",
+            name: "Route 53",
+            config_keys: util::struct_fields::<route53::Config>,
+            records: route53::RECORDS,
+        },
+        ProviderInfo {
+            service: "scaleway",
+            name: "Scaleway",
+            config_keys: util::struct_fields::<scaleway::Config>,
+            records: scaleway::RECORDS,
+        },
+        ProviderInfo {
+            service: "variomedia",
+            name: "Variomedia",
+            config_keys: util::struct_fields::<variomedia::Config>,
+            records: variomedia::RECORDS,
+        },
+        ProviderInfo {
+            service: "selfhost",
+            name: "selfHOST.de",
+            config_keys: util::struct_fields::<selfhost::Config>,
+            records: selfhost::RECORDS,
+        },
+        ProviderInfo {
+            service: "spdyn",
+            name: "SPDYN",
+            config_keys: util::struct_fields::<spdyn::Config>,
+            records: spdyn::RECORDS,
+        },
+        ProviderInfo {
+            service: "strato",
+            name: "Strato",
+            config_keys: util::struct_fields::<strato::Config>,
+            records: strato::RECORDS,
+        },
+        ProviderInfo {
+            service: "transip",
+            name: "TransIP",
+            config_keys: util::struct_fields::<transip::Config>,
+            records: transip::RECORDS,
+        },
+        ProviderInfo {
+            service: "vultr",
+            name: "Vultr",
+            config_keys: util::struct_fields::<vultr::Config>,
+            records: vultr::RECORDS,
+        },
+        ProviderInfo {
+            service: "webhook",
+            name: "webhook",
+            config_keys: util::struct_fields::<webhook::Config>,
+            records: webhook::RECORDS,
+        },
+        ProviderInfo {
+            service: "zoneedit",
+            name: "ZoneEdit",
+            config_keys: util::struct_fields::<zoneedit::Config>,
+            records: zoneedit::RECORDS,
+        },
+        ProviderInfo {
+            service: "no-ip",
+            name: "NoIP",
+            config_keys: util::struct_fields::<noip::Config>,
+            records: noip::RECORDS,
+        },
+        ProviderInfo {
+            service: "ovh",
+            name: "OVH",
+            config_keys: util::struct_fields::<ovh::Config>,
+            records: ovh::RECORDS,
+        },
+        ProviderInfo {
+            service: "dummy",
+            name: "Dummy",
+            config_keys: util::struct_fields::<dummy::Config>,
+            records: dummy::RECORDS,
+        },
+    ];
+
+    /// The kinds of records the service is able to update.
+    pub fn records(&self) -> &'static [RecordType] {
+        match self {
+            DdnsConfigService::Hosting1984(_) => hosting1984::RECORDS,
+            DdnsConfigService::Alidns(_) => alidns::RECORDS,
+            DdnsConfigService::AllInkl(_) => allinkl::RECORDS,
+            DdnsConfigService::Azure(_) => azure::RECORDS,
+            DdnsConfigService::Bunny(_) => bunny::RECORDS,
+            DdnsConfigService::CloudflareV4(_) => cloudflare::RECORDS,
+            DdnsConfigService::Cloudns(_) => cloudns::RECORDS,
+            DdnsConfigService::Constellix(_) => constellix::RECORDS,
+            DdnsConfigService::CoreNetworks(_) => corenetworks::RECORDS,
+            DdnsConfigService::CustomHttp(_) => custom_http::RECORDS,
+            DdnsConfigService::Ddnss(_) => ddnss::RECORDS,
+            DdnsConfigService::Desec(_) => desec::RECORDS,
+            DdnsConfigService::Digitalocean(_) => digitalocean::RECORDS,
+            DdnsConfigService::Dnshome(_) => dnshome::RECORDS,
+            DdnsConfigService::Dnsimple(_) => dnsimple::RECORDS,
+            DdnsConfigService::DnsOMatic(_) => dnsomatic::RECORDS,
+            DdnsConfigService::Dnspod(_) => dnspod::RECORDS,
+            DdnsConfigService::DoDe(_) => dode::RECORDS,
+            DdnsConfigService::Domeneshop(_) => domeneshop::RECORDS,
+            DdnsConfigService::Dreamhost(_) => dreamhost::RECORDS,
+            DdnsConfigService::Duckdns(_) => duckdns::RECORDS,
+            DdnsConfigService::Dyfi(_) => dyfi::RECORDS,
+            DdnsConfigService::Dyndns2(_) => dyndns2::RECORDS,
+            DdnsConfigService::Dynu(_) => dynu::RECORDS,
+            DdnsConfigService::Dynv6(_) => dynv6::RECORDS,
+            DdnsConfigService::Easydns(_) => easydns::RECORDS,
+            DdnsConfigService::Exec(_) => exec::RECORDS,
+            DdnsConfigService::Exoscale(_) => exoscale::RECORDS,
+            DdnsConfigService::Freedns(_) => freedns::RECORDS,
+            DdnsConfigService::Gandi(_) => gandi::RECORDS,
+            DdnsConfigService::Gcore(_) => gcore::RECORDS,
+            DdnsConfigService::Glesys(_) => glesys::RECORDS,
+            DdnsConfigService::Godaddy(_) => godaddy::RECORDS,
+            DdnsConfigService::Goip(_) => goip::RECORDS,
+            DdnsConfigService::GoogleCloudDns(_) => google::RECORDS,
+            DdnsConfigService::HeNet(_) => henet::RECORDS,
+            DdnsConfigService::Hetzner(_) => hetzner::RECORDS,
+            DdnsConfigService::Hover(_) => hover::RECORDS,
+            DdnsConfigService::Infomaniak(_) => infomaniak::RECORDS,
+            DdnsConfigService::Inwx(_) => inwx::RECORDS,
+            DdnsConfigService::Ipv64(_) => ipv64::RECORDS,
+            DdnsConfigService::Linode(_) => linode::RECORDS,
+            DdnsConfigService::Loopia(_) => loopia::RECORDS,
+            DdnsConfigService::MythicBeasts(_) => mythicbeasts::RECORDS,
+            DdnsConfigService::Namecheap(_) => namecheap::RECORDS,
+            DdnsConfigService::NameCom(_) => namecom::RECORDS,
+            DdnsConfigService::Netcup(_) => netcup::RECORDS,
+            DdnsConfigService::Nfsn(_) => nfsn::RECORDS,
+            DdnsConfigService::Njalla(_) => njalla::RECORDS,
+            DdnsConfigService::Ns1(_) => ns1::RECORDS,
+            DdnsConfigService::NsupdateInfo(_) => nsupdateinfo::RECORDS,
+            DdnsConfigService::Oci(_) => oci::RECORDS,
+            DdnsConfigService::Plugin(_) => plugin::RECORDS,
+            DdnsConfigService::PorkbunV3(_) => porkbun::RECORDS,
+            DdnsConfigService::Powerdns(_) => powerdns::RECORDS,
+            DdnsConfigService::Rfc2136(_) => rfc2136::RECORDS,
+            DdnsConfigService::Route53(_) => route53::RECORDS,
+            DdnsConfigService::Scaleway(_) => scaleway::RECORDS,
+            DdnsConfigService::Variomedia(_) => variomedia::RECORDS,
+            DdnsConfigService::Selfhost(_) => selfhost::RECORDS,
+            DdnsConfigService::Spdyn(_) => spdyn::RECORDS,
+            DdnsConfigService::Strato(_) => strato::RECORDS,
+            DdnsConfigService::Transip(_) => transip::RECORDS,
+            DdnsConfigService::Vultr(_) => vultr::RECORDS,
+            DdnsConfigService::Webhook(_) => webhook::RECORDS,
+            DdnsConfigService::Zoneedit(_) => zoneedit::RECORDS,
+            DdnsConfigService::NoIp(_) => noip::RECORDS,
+            DdnsConfigService::Ovh(_) => ovh::RECORDS,
+            DdnsConfigService::Dummy(_) => dummy::RECORDS,
+        }
+    }
+
+    /// The human-readable name of the provider behind this service. Services
+    /// sharing a provider also share its API quota.
+    pub fn provider(&self) -> &'static str {
+        match self {
+            DdnsConfigService::Hosting1984(_) => "1984 Hosting",
+            DdnsConfigService::Alidns(_) => "Alibaba Cloud DNS",
+            DdnsConfigService::AllInkl(_) => "all-inkl",
+            DdnsConfigService::Azure(_) => "Azure DNS",
+            DdnsConfigService::Bunny(_) => "Bunny DNS",
+            DdnsConfigService::CloudflareV4(_) => "Cloudflare",
+            DdnsConfigService::Cloudns(_) => "ClouDNS",
+            DdnsConfigService::Constellix(_) => "Constellix",
+            DdnsConfigService::CoreNetworks(_) => "Core-Networks",
+            DdnsConfigService::CustomHttp(_) => "custom HTTP",
+            DdnsConfigService::Ddnss(_) => "ddnss.de",
+            DdnsConfigService::Desec(_) => "deSEC",
+            DdnsConfigService::Digitalocean(_) => "DigitalOcean",
+            DdnsConfigService::Dnshome(_) => "dnshome.de",
+            DdnsConfigService::Dnsimple(_) => "DNSimple",
+            DdnsConfigService::DnsOMatic(_) => "DNS-O-Matic",
+            DdnsConfigService::Dnspod(_) => "DNSPod",
+            DdnsConfigService::DoDe(_) => "do.de",
+            DdnsConfigService::Domeneshop(_) => "Domeneshop",
+            DdnsConfigService::Dreamhost(_) => "DreamHost",
+            DdnsConfigService::Duckdns(_) => "DuckDNS",
+            DdnsConfigService::Dyfi(_) => "dy.fi",
+            DdnsConfigService::Dyndns2(_) => "DynDNS v2",
+            DdnsConfigService::Dynu(_) => "Dynu",
+            DdnsConfigService::Dynv6(_) => "dynv6",
+            DdnsConfigService::Easydns(_) => "EasyDNS",
+            DdnsConfigService::Exec(_) => "exec",
+            DdnsConfigService::Exoscale(_) => "Exoscale",
+            DdnsConfigService::Freedns(_) => "FreeDNS",
+            DdnsConfigService::Gandi(_) => "Gandi",
+            DdnsConfigService::Gcore(_) => "Gcore",
+            DdnsConfigService::Glesys(_) => "GleSYS",
+            DdnsConfigService::Godaddy(_) => "GoDaddy",
+            DdnsConfigService::Goip(_) => "goip.de",
+            DdnsConfigService::GoogleCloudDns(_) => "Google Cloud DNS",
+            DdnsConfigService::HeNet(_) => "Hurricane Electric",
+            DdnsConfigService::Hetzner(_) => "Hetzner",
+            DdnsConfigService::Hover(_) => "Hover",
+            DdnsConfigService::Infomaniak(_) => "Infomaniak",
+            DdnsConfigService::Inwx(_) => "INWX",
+            DdnsConfigService::Ipv64(_) => "IPv64",
+            DdnsConfigService::Linode(_) => "Linode",
+            DdnsConfigService::Loopia(_) => "Loopia",
+            DdnsConfigService::MythicBeasts(_) => "Mythic Beasts",
+            DdnsConfigService::Namecheap(_) => "Namecheap",
+            DdnsConfigService::NameCom(_) => "name.com",
+            DdnsConfigService::Netcup(_) => "Netcup",
+            DdnsConfigService::Nfsn(_) => "NearlyFreeSpeech.NET",
+            DdnsConfigService::Njalla(_) => "Njalla",
+            DdnsConfigService::Ns1(_) => "NS1",
+            DdnsConfigService::NsupdateInfo(_) => "nsupdate.info",
+            DdnsConfigService::Oci(_) => "Oracle Cloud DNS",
+            DdnsConfigService::Plugin(_) => "plugin",
+            DdnsConfigService::PorkbunV3(_) => "Porkbun",
+            DdnsConfigService::Powerdns(_) => "PowerDNS",
+            DdnsConfigService::Rfc2136(_) => "RFC 2136 (nsupdate)",
+            DdnsConfigService::Route53(_) => "Route 53",
+            DdnsConfigService::Scaleway(_) => "Scaleway",
+            DdnsConfigService::Variomedia(_) => "Variomedia",
+            DdnsConfigService::Selfhost(_) => "selfHOST.de",
+            DdnsConfigService::Spdyn(_) => "SPDYN",
+            DdnsConfigService::Strato(_) => "Strato",
+            DdnsConfigService::Transip(_) => "TransIP",
+            DdnsConfigService::Vultr(_) => "Vultr",
+            DdnsConfigService::Webhook(_) => "webhook",
+            DdnsConfigService::Zoneedit(_) => "ZoneEdit",
+            DdnsConfigService::NoIp(_) => "NoIP",
+            DdnsConfigService::Ovh(_) => "OVH",
+            DdnsConfigService::Dummy(_) => "Dummy",
+        }
+    }
+
+    pub fn into_boxed(self, settings: &ServiceSettings) -> Box<dyn DdnsService> {
+        let http = &settings.http;
+
         match self {
-            DdnsConfigService::CloudflareV4(cf) => Box::new(cloudflare::Service::from(cf)),
+            DdnsConfigService::Hosting1984(h) => Box::new(hosting1984::Service::new(h, http)),
+
+            DdnsConfigService::Alidns(ad) => Box::new(alidns::Service::new(ad, http)),
+
+            DdnsConfigService::AllInkl(ai) => Box::new(allinkl::Service::new(ai, http)),
+
+            DdnsConfigService::Azure(az) => Box::new(azure::Service::new(az, http)),
+
+            DdnsConfigService::Bunny(bn) => Box::new(bunny::Service::new(bn, http)),
+
+            DdnsConfigService::CloudflareV4(cf) => Box::new(cloudflare::Service::new(cf, http)),
+
+            DdnsConfigService::Cloudns(cd) => Box::new(cloudns::Service::new(cd, http)),
+
+            DdnsConfigService::Constellix(cx) => Box::new(constellix::Service::new(cx, http)),
+
+            DdnsConfigService::CoreNetworks(cn) => Box::new(corenetworks::Service::new(cn, http)),
+
+            DdnsConfigService::CustomHttp(ch) => Box::new(custom_http::Service::new(ch, http)),
+
+            DdnsConfigService::Ddnss(dn) => Box::new(ddnss::Service::new(dn, http)),
+
+            DdnsConfigService::Desec(ds) => Box::new(desec::Service::new(ds, http)),
+
+            DdnsConfigService::Digitalocean(dgo) => Box::new(digitalocean::Service::new(dgo, http)),
+
+            DdnsConfigService::Dnshome(dh) => Box::new(dnshome::Service::new(dh, http)),
+
+            DdnsConfigService::Dnsimple(dns) => Box::new(dnsimple::Service::new(dns, http)),
+
+            DdnsConfigService::NoIp(np) => Box::new(noip::Service::new(np, http)),
+
+            DdnsConfigService::Ovh(ovh) => Box::new(ovh::Service::new(ovh, http)),
+
+            DdnsConfigService::DnsOMatic(dom) => Box::new(dnsomatic::Service::new(dom, http)),
+
+            DdnsConfigService::Dnspod(dp) => Box::new(dnspod::Service::new(dp, http)),
+
+            DdnsConfigService::DoDe(dd) => Box::new(dode::Service::new(dd, http)),
+
+            DdnsConfigService::Domeneshop(ds) => Box::new(domeneshop::Service::new(ds, http)),
+
+            DdnsConfigService::Dreamhost(dh) => Box::new(dreamhost::Service::new(dh, http)),
+
+            DdnsConfigService::Duckdns(dk) => Box::new(duckdns::Service::new(dk, http)),
+
+            DdnsConfigService::Dyfi(df) => Box::new(dyfi::Service::new(df, http)),
+
+            DdnsConfigService::Dyndns2(d2) => Box::new(dyndns2::Service::new(d2, http)),
+
+            DdnsConfigService::Dynu(du) => Box::new(dynu::Service::new(du, http)),
+
+            DdnsConfigService::Dynv6(d6) => Box::new(dynv6::Service::new(d6, http)),
+
+            DdnsConfigService::Easydns(ed) => Box::new(easydns::Service::new(ed, http)),
+
+            DdnsConfigService::Exec(ex) => Box::new(exec::Service::new(ex, &settings.shell)),
+
+            DdnsConfigService::Exoscale(ex) => Box::new(exoscale::Service::new(ex, http)),
+
+            DdnsConfigService::Freedns(fd) => Box::new(freedns::Service::new(fd, http)),
+
+            DdnsConfigService::Gandi(gd) => Box::new(gandi::Service::new(gd, http)),
+
+            DdnsConfigService::Gcore(gc) => Box::new(gcore::Service::new(gc, http)),
+
+            DdnsConfigService::Glesys(gs) => Box::new(glesys::Service::new(gs, http)),
+
+            DdnsConfigService::Godaddy(gd) => Box::new(godaddy::Service::new(gd, http)),
+
+            DdnsConfigService::Goip(gp) => Box::new(goip::Service::new(gp, http)),
+
+            DdnsConfigService::GoogleCloudDns(gc) => Box::new(google::Service::new(gc, http)),
+
+            DdnsConfigService::HeNet(he) => Box::new(henet::Service::new(he, http)),
+
+            DdnsConfigService::Hetzner(hz) => Box::new(hetzner::Service::new(hz, http)),
+
+            DdnsConfigService::Hover(hv) => Box::new(hover::Service::new(hv, http)),
+
+            DdnsConfigService::Infomaniak(im) => Box::new(infomaniak::Service::new(im, http)),
+
+            DdnsConfigService::Inwx(iw) => Box::new(inwx::Service::new(iw, http)),
+
+            DdnsConfigService::Ipv64(ip) => Box::new(ipv64::Service::new(ip, http)),
+
+            DdnsConfigService::Linode(li) => Box::new(linode::Service::new(li, http)),
+
+            DdnsConfigService::Loopia(lp) => Box::new(loopia::Service::new(lp, http)),
+
+            DdnsConfigService::MythicBeasts(mb) => Box::new(mythicbeasts::Service::new(mb, http)),
+
+            DdnsConfigService::Namecheap(nc) => Box::new(namecheap::Service::new(nc, http)),
+
+            DdnsConfigService::NameCom(nc) => Box::new(namecom::Service::new(nc, http)),
+
+            DdnsConfigService::Netcup(nc) => Box::new(netcup::Service::new(nc, http)),
+
+            DdnsConfigService::Nfsn(nf) => Box::new(nfsn::Service::new(nf, http)),
+
+            DdnsConfigService::Njalla(nj) => Box::new(njalla::Service::new(nj, http)),
+
+            DdnsConfigService::Ns1(ns) => Box::new(ns1::Service::new(ns, http)),
 
-            DdnsConfigService::NoIp(np) => Box::new(noip::Service::from(np)),
+            DdnsConfigService::NsupdateInfo(ni) => Box::new(nsupdateinfo::Service::new(ni, http)),
 
-            DdnsConfigService::DnsOMatic(dom) => Box::new(dnsomatic::Service::from(dom)),
+            DdnsConfigService::Oci(oc) => Box::new(oci::Service::new(oc, http)),
 
-            DdnsConfigService::Duckdns(dk) => Box::new(duckdns::Service::from(dk)),
+            DdnsConfigService::Plugin(pl) => Box::new(plugin::Service::new(pl, settings)),
 
-            DdnsConfigService::Dynu(du) => Box::new(dynu::Service::from(du)),
+            DdnsConfigService::PorkbunV3(pb) => Box::new(porkbun::Service::new(pb, http)),
 
-            DdnsConfigService::Ipv64(ip) => Box::new(ipv64::Service::from(ip)),
+            DdnsConfigService::Powerdns(pdns) => Box::new(powerdns::Service::new(pdns, http)),
 
-            DdnsConfigService::Linode(li) => Box::new(linode::Service::from(li)),
+            DdnsConfigService::Rfc2136(rfc) => Box::new(rfc2136::Service::new(rfc, http)),
 
-            DdnsConfigService::PorkbunV3(pb) => Box::new(porkbun::Service::from(pb)),
+            DdnsConfigService::Route53(r53) => Box::new(route53::Service::new(r53, http)),
 
-            DdnsConfigService::Selfhost(sh) => Box::new(selfhost::Service::from(sh)),
+            DdnsConfigService::Scaleway(sw) => Box::new(scaleway::Service::new(sw, http)),
 
-            DdnsConfigService::Dummy(dm) => Box::new(dummy::Service::from(dm)),
+            DdnsConfigService::Variomedia(vm) => Box::new(variomedia::Service::new(vm, http)),
+
+            DdnsConfigService::Selfhost(sh) => Box::new(selfhost::Service::new(sh, http)),
+
+            DdnsConfigService::Spdyn(sp) => Box::new(spdyn::Service::new(sp, http)),
+
+            DdnsConfigService::Strato(st) => Box::new(strato::Service::new(st, http)),
+
+            DdnsConfigService::Transip(tp) => Box::new(transip::Service::new(tp, http)),
+
+            DdnsConfigService::Vultr(vu) => Box::new(vultr::Service::new(vu, http)),
+
+            DdnsConfigService::Webhook(wh) => Box::new(webhook::Service::new(wh, http)),
+
+            DdnsConfigService::Zoneedit(ze) => Box::new(zoneedit::Service::new(ze, http)),
+
+            DdnsConfigService::Dummy(dm) => Box::new(dummy::Service::new(dm, http)),
         }
     }
 }
@@ -103,8 +1003,28 @@ pub struct DdnsConfig {
     #[serde(deserialize_with = "one_or_more_string")]
     pub ip: Vec<Box<str>>,
 
+    #[serde(default)]
+    pub suspended: bool,
+
+    /// The SRV and CAA records maintained alongside the A and AAAA records,
+    /// for the services with a full zone API.
+    #[serde(default)]
+    pub srv: Vec<SrvRecord>,
+    #[serde(default)]
+    pub caa: Vec<CaaRecord>,
+
     #[serde(flatten)]
     pub service: DdnsConfigService,
+
+    /// The `domains` of the service (after expanding the placeholders), which
+    /// are looked up by `reconcile`. Empty if the service has no such key.
+    #[serde(skip)]
+    pub domains: Vec<Box<str>>,
+}
+
+impl DdnsConfig {
+    /// The keys accepted by every [ddns.*] entry, regardless of the service.
+    pub const KEYS: &'static [&'static str] = &["service", "ip", "suspended", "srv", "caa"];
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
@@ -112,10 +1032,177 @@ pub struct Config {
     pub general: General,
     pub ip: HashMap<Box<str>, IpConfig>,
     pub ddns: HashMap<Box<str>, DdnsConfig>,
+
+    /// The [ddns.*] entries which could not be parsed, along with the reason.
+    /// Unless `strict` is set in [general], these are left out instead of
+    /// rejecting the whole config file.
+    #[serde(skip)]
+    pub invalid_ddns: Vec<(Box<str>, String)>,
+}
+
+impl Config {
+    /// Parses the config file, expanding the placeholders in the `domains` of
+    /// every [ddns.*] entry along the way.
+    pub fn parse(config: &str) -> Result<Self, toml::de::Error> {
+        let mut table = toml::from_str::<toml::Table>(config)?;
+
+        let strict = table
+            .get("general")
+            .and_then(|general| general.get("strict"))
+            .and_then(|strict| strict.as_bool())
+            .unwrap_or(false);
+
+        // Parse the config file as-is first, so that any errors will point to
+        // the exact location within the file.
+        let invalid_ddns = match toml::from_str::<Config>(config) {
+            Ok(_) => Vec::new(),
+            Err(e) if strict => return Err(e),
+            Err(e) => {
+                let invalid_ddns = remove_invalid_ddns(&mut table);
+
+                // The error lies somewhere else.
+                if invalid_ddns.is_empty() {
+                    return Err(e);
+                }
+
+                invalid_ddns
+            }
+        };
+
+        expand_domain_placeholders(&mut table)?;
+        let domains = collect_domains(&table);
+
+        let mut config = toml::Value::Table(table).try_into::<Config>()?;
+        config.invalid_ddns = invalid_ddns;
+
+        for (name, ddns) in config.ddns.iter_mut() {
+            if let Some(domains) = domains.get(name) {
+                ddns.domains = domains.clone();
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Removes the [ddns.*] entries which cannot be parsed on their own, and
+/// returns their names along with the reason.
+fn remove_invalid_ddns(table: &mut toml::Table) -> Vec<(Box<str>, String)> {
+    let Some(toml::Value::Table(ddns)) = table.get_mut("ddns") else {
+        return Vec::new();
+    };
+
+    let mut invalid = Vec::new();
+
+    ddns.retain(|name, entry| match entry.clone().try_into::<DdnsConfig>() {
+        Ok(_) => true,
+        Err(e) => {
+            invalid.push((name[..].into(), e.message().to_owned()));
+            false
+        }
+    });
+
+    invalid.sort_unstable();
+    invalid
+}
+
+/// Returns the `domains` of every [ddns.*] entry which has them.
+fn collect_domains(table: &toml::Table) -> HashMap<Box<str>, Vec<Box<str>>> {
+    let Some(toml::Value::Table(ddns)) = table.get("ddns") else {
+        return HashMap::new();
+    };
+
+    ddns.iter()
+        .filter_map(|(name, entry)| {
+            let domains = match entry.get("domains")? {
+                toml::Value::String(domain) => vec![domain[..].into()],
+                toml::Value::Array(domains) => domains
+                    .iter()
+                    .filter_map(|domain| domain.as_str())
+                    .map(Box::from)
+                    .collect(),
+                _ => return None,
+            };
+
+            Some((name[..].into(), domains))
+        })
+        .collect()
+}
+
+/// Replaces the following placeholders found in `domains`:
+///  - `{hostname}`: the hostname of this machine (without the domain part)
+///  - `{ip_name}`: the name of the first IP listed in `ip`
+///
+/// This allows the same config file to be deployed on multiple machines.
+fn expand_domain_placeholders(table: &mut toml::Table) -> Result<(), toml::de::Error> {
+    use serde::de::Error;
+
+    let Some(toml::Value::Table(ddns)) = table.get_mut("ddns") else {
+        return Ok(());
+    };
+
+    let mut hostname = None;
+
+    for (name, entry) in ddns.iter_mut() {
+        let Some(entry) = entry.as_table_mut() else {
+            continue;
+        };
+
+        let ip_name = match entry.get("ip") {
+            Some(toml::Value::String(ip)) => Some(ip.clone()),
+            Some(toml::Value::Array(ips)) => {
+                ips.first().and_then(|ip| ip.as_str()).map(String::from)
+            }
+            _ => None,
+        };
+
+        let mut expand = |domain: &mut String| {
+            if domain.contains("{hostname}") {
+                if hostname.is_none() {
+                    hostname = util::hostname();
+                }
+
+                let Some(hostname) = &hostname else {
+                    return Err(toml::de::Error::custom(
+                        "unable to obtain the hostname of this machine",
+                    ));
+                };
+
+                *domain = domain.replace("{hostname}", hostname);
+            }
+
+            if domain.contains("{ip_name}") {
+                let Some(ip_name) = &ip_name else {
+                    return Err(toml::de::Error::custom(format!(
+                        "ddns.{}: {{ip_name}} is used, but no IP is specified",
+                        name
+                    )));
+                };
+
+                *domain = domain.replace("{ip_name}", ip_name);
+            }
+
+            Ok(())
+        };
+
+        match entry.get_mut("domains") {
+            Some(toml::Value::String(domain)) => expand(domain)?,
+            Some(toml::Value::Array(domains)) => {
+                for domain in domains {
+                    if let toml::Value::String(domain) = domain {
+                        expand(domain)?;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
 }
 
 fn default_user_agent() -> Box<str> {
-    concat!("github.com/hch12907/dynners ", env!("CARGO_PKG_VERSION")).into()
+    http::DEFAULT_USER_AGENT.into()
 }
 
 fn default_shell() -> Box<str> {
@@ -126,6 +1213,200 @@ fn default_regex() -> Box<str> {
     "(.*)".into()
 }
 
+fn default_stun_servers() -> Vec<Box<str>> {
+    vec![
+        "stun.l.google.com:19302".into(),
+        "stun.cloudflare.com:3478".into(),
+    ]
+}
+
+fn default_plugins_dir() -> Box<str> {
+    "/usr/local/lib/dynners/plugins".into()
+}
+
+pub fn default_control_socket() -> Box<str> {
+    "/run/dynners/control.sock".into()
+}
+
+fn default_max_connections_per_host() -> u32 {
+    2
+}
+
+fn default_failover_after() -> u32 {
+    3
+}
+
 fn default_persistent_state() -> Box<str> {
     "/var/lib/dynners/persistence".into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domain_placeholders() {
+        let mut table = toml::from_str::<toml::Table>(
+            r#"
+            [ddns.one]
+            ip = ["wan6", "wan4"]
+            domains = ["{ip_name}.{hostname}.example.com", "{hostname}.example.com"]
+
+            [ddns.two]
+            ip = "wan4"
+            domains = "{ip_name}.example.com"
+
+            [ddns.three]
+            domains = "example.com"
+            "#,
+        )
+        .unwrap();
+
+        expand_domain_placeholders(&mut table).unwrap();
+
+        let hostname = util::hostname().unwrap();
+        let ddns = table["ddns"].as_table().unwrap();
+
+        assert_eq!(
+            ddns["one"]["domains"].as_array().unwrap(),
+            &[
+                toml::Value::from(format!("wan6.{}.example.com", hostname)),
+                toml::Value::from(format!("{}.example.com", hostname)),
+            ]
+        );
+        assert_eq!(ddns["two"]["domains"].as_str(), Some("wan4.example.com"));
+        assert_eq!(ddns["three"]["domains"].as_str(), Some("example.com"));
+
+        let mut table = toml::from_str::<toml::Table>(
+            r#"
+            [ddns.four]
+            domains = "{ip_name}.example.com"
+            "#,
+        )
+        .unwrap();
+
+        assert!(expand_domain_placeholders(&mut table).is_err());
+    }
+
+    #[test]
+    fn namespaced_paths() {
+        let mut general = toml::from_str::<General>(
+            r#"
+            update_rate = 300
+            diagnostics_dir = "/var/log/dynners"
+            control_socket = ""
+            "#,
+        )
+        .unwrap();
+
+        general.namespace("home");
+
+        assert_eq!(
+            &*general.persistent_state,
+            "/var/lib/dynners/home/persistence"
+        );
+        assert_eq!(&*general.diagnostics_dir, "/var/log/dynners/home");
+        assert_eq!(&*general.control_socket, "");
+        assert_eq!(&*namespaced_path("persistence", "home"), "home/persistence");
+    }
+
+    #[test]
+    fn debounce() {
+        let general = |update_rate| {
+            let general = format!("update_rate = {}\ndebounce = 60", update_rate);
+            toml::from_str::<General>(&general).unwrap()
+        };
+
+        assert_eq!(general(300).debounce(), Duration::from_secs(60));
+        assert_eq!(general(0).debounce(), Duration::ZERO);
+    }
+
+    #[test]
+    fn providers() {
+        for provider in DdnsConfigService::PROVIDERS {
+            let table = format!("service = \"{}\"", provider.service);
+            let error = toml::from_str::<DdnsConfigService>(&table).unwrap_err();
+
+            // The service is known, only the other keys are missing.
+            assert!(
+                !error.to_string().contains("unknown variant"),
+                "{}: {}",
+                provider.service,
+                error
+            );
+        }
+
+        // Every kind of service is listed, which is checked against the ones
+        // serde expects when it is given an unknown one.
+        let error = toml::from_str::<DdnsConfigService>(r#"service = "?""#).unwrap_err();
+        let error = error.to_string();
+        let (_, expected) = error.split_once("expected one of ").unwrap();
+        let expected = expected.lines().next().unwrap();
+
+        let mut services = expected
+            .split(", ")
+            .map(|service| service.trim_matches('`'))
+            .collect::<Vec<_>>();
+        let mut listed = DdnsConfigService::PROVIDERS
+            .iter()
+            .map(|provider| provider.service)
+            .collect::<Vec<_>>();
+        services.sort_unstable();
+        listed.sort_unstable();
+        assert_eq!(services, listed);
+
+        assert_eq!(
+            util::struct_fields::<cloudflare::Config>(),
+            ["ttl", "proxied", "token", "domains"]
+        );
+    }
+
+    #[test]
+    fn invalid_ddns() {
+        let config = r#"
+            [general]
+            update_rate = 300
+
+            [ip.wan4]
+            version = 4
+            method = "exec"
+            command = "echo 192.0.2.1"
+
+            [ddns.good]
+            service = "duckdns"
+            ip = "wan4"
+            token = "token"
+            domains = "example.duckdns.org"
+
+            [ddns.typo]
+            service = "duckdsn"
+            ip = "wan4"
+
+            [ddns.missing]
+            service = "duckdns"
+            ip = "wan4"
+        "#;
+
+        let parsed = Config::parse(config).unwrap();
+        assert_eq!(parsed.ddns.len(), 1);
+        assert!(parsed.ddns.contains_key("good"));
+        assert_eq!(
+            parsed.ddns["good"].domains,
+            [Box::from("example.duckdns.org")]
+        );
+
+        let invalid = parsed
+            .invalid_ddns
+            .iter()
+            .map(|(name, _)| &**name)
+            .collect::<Vec<_>>();
+        assert_eq!(invalid, ["missing", "typo"]);
+
+        // Errors outside of [ddns.*] are never tolerated.
+        let broken = config.replace("version = 4", "version = 5");
+        assert!(Config::parse(&broken).is_err());
+
+        let config = config.replace("update_rate = 300", "update_rate = 300\nstrict = true");
+        assert!(Config::parse(&config).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// How long we wait for the other end of the control socket before giving up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The commands accepted by the control socket. On the wire, every command is
+/// a single line of text, e.g. "suspend my service".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Show the state of every IP and DDNS service.
+    Status,
+
+    /// List the DDNS services, one per line.
+    List,
+
+    /// Re-read the config file.
+    Reload,
+
+    /// Start an update cycle right away. If a service is named, it is updated
+    /// even if none of its IPs have changed.
+    Update(Option<Box<str>>),
+
+    /// Stop updating a service until it is resumed.
+    Suspend(Box<str>),
+
+    Resume(Box<str>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandParseErr(Box<str>);
+
+impl Display for CommandParseErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Command {
+    type Err = CommandParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (command, argument) = match s.trim().split_once(' ') {
+            Some((command, argument)) => (command, Some(argument.trim())),
+            None => (s.trim(), None),
+        };
+
+        let service = |argument: Option<&str>| match argument {
+            Some(name) if !name.is_empty() => Ok(Box::from(name)),
+            _ => Err(CommandParseErr(
+                (String::from("expected a service name after ") + command).into(),
+            )),
+        };
+
+        match command {
+            "status" => Ok(Self::Status),
+            "list" => Ok(Self::List),
+            "reload" => Ok(Self::Reload),
+            "update" => Ok(Self::Update(argument.map(Box::from))),
+            "suspend" => service(argument).map(Self::Suspend),
+            "resume" => service(argument).map(Self::Resume),
+            _ => Err(CommandParseErr(
+                (String::from("unknown command: ") + command).into(),
+            )),
+        }
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Status => write!(f, "status"),
+            Command::List => write!(f, "list"),
+            Command::Reload => write!(f, "reload"),
+            Command::Update(None) => write!(f, "update"),
+            Command::Update(Some(service)) => write!(f, "update {}", service),
+            Command::Suspend(service) => write!(f, "suspend {}", service),
+            Command::Resume(service) => write!(f, "resume {}", service),
+        }
+    }
+}
+
+/// A command received from a client, which is waiting for the reply.
+pub struct Request {
+    pub command: Command,
+    stream: UnixStream,
+}
+
+impl Request {
+    /// Sends the outcome of the command back to the client. The first line
+    /// of the reply is either "ok" or "error", the rest is for humans.
+    pub fn reply(mut self, result: Result<String, String>) {
+        let mut reply = match result {
+            Ok(message) => String::from("ok\n") + &message,
+            Err(message) => String::from("error\n") + &message,
+        };
+
+        if !reply.ends_with('\n') {
+            reply.push('\n');
+        }
+
+        // The client might have given up already, there is nothing we can do
+        // about that.
+        let _ = self.stream.write_all(reply.as_bytes());
+    }
+}
+
+/// Starts listening on the control socket. The commands received are sent
+/// through the returned channel.
+pub fn listen(path: &str) -> io::Result<Receiver<Request>> {
+    let path = Path::new(path);
+
+    if path.exists() {
+        // If the socket is still alive, another instance is running. If not,
+        // it was left behind by a previous instance and can be removed.
+        if UnixStream::connect(path).is_ok() {
+            let message = "another instance is already listening on the control socket";
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, message));
+        }
+
+        fs::remove_file(path)?;
+    }
+
+    let parent = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+
+    // Only the owner (and its group) may control the daemon. The socket is
+    // bound inside a private directory and only moved into place once its
+    // permissions are narrowed down, so that nobody else can connect to it
+    // in between.
+    let private = parent.join(format!(".dynners-control-{}", std::process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&private)?;
+
+    let bound = private.join("socket");
+    let listener = UnixListener::bind(&bound).and_then(|listener| {
+        fs::set_permissions(&bound, fs::Permissions::from_mode(0o660))?;
+        fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+
+    let _ = fs::remove_file(&bound);
+    let _ = fs::remove_dir(&private);
+    let listener = listener?;
+
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+
+            // Each client is read from on its own thread, so that a client
+            // which never sends its command does not hold up the others.
+            let sender = sender.clone();
+            thread::spawn(move || accept(stream, sender));
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Reads the command of a client and passes it on to the daemon.
+fn accept(mut stream: UnixStream, sender: Sender<Request>) {
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line).is_err() {
+        return;
+    }
+
+    match line.parse::<Command>() {
+        // The daemon has quit if this fails, and the client is left without
+        // a reply.
+        Ok(command) => {
+            let _ = sender.send(Request { command, stream });
+        }
+
+        Err(e) => {
+            let _ = stream.write_all(format!("error\n{}\n", e).as_bytes());
+        }
+    }
+}
+
+/// Sends a command to the daemon listening on `path` and waits for its reply.
+/// The outer error means that the daemon could not be reached, the inner one
+/// means that the daemon was unable to carry out the command.
+pub fn send(path: &str, command: &Command) -> io::Result<Result<String, String>> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    stream.write_all((command.to_string() + "\n").as_bytes())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+
+    match reply.split_once('\n') {
+        Some(("ok", message)) => Ok(Ok(message.into())),
+        Some(("error", message)) => Ok(Err(message.into())),
+        _ => {
+            let message = "unexpected reply from the daemon";
+            Err(io::Error::new(io::ErrorKind::InvalidData, message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let commands = [
+            Command::Status,
+            Command::List,
+            Command::Reload,
+            Command::Update(None),
+            Command::Update(Some("my service".into())),
+            Command::Suspend("cloudflare".into()),
+            Command::Resume("cloudflare".into()),
+        ];
+
+        for command in commands {
+            assert_eq!(command.to_string().parse::<Command>(), Ok(command));
+        }
+
+        assert_eq!(
+            "  suspend   my service \n".parse::<Command>(),
+            Ok(Command::Suspend("my service".into()))
+        );
+        assert!("suspend".parse::<Command>().is_err());
+        assert!("resume ".parse::<Command>().is_err());
+        assert!("restart".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn listen() {
+        let path = std::env::temp_dir().join(format!("dynners-control-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let receiver = super::listen(path).unwrap();
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        // A client which never sends anything does not hold up the others.
+        let _idle = UnixStream::connect(path).unwrap();
+
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(b"status\n").unwrap();
+
+        let request = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.command, Command::Status);
+        request.reply(Ok("running".into()));
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "ok\nrunning\n");
+
+        fs::remove_file(path).unwrap();
+    }
+}
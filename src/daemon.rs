@@ -0,0 +1,1539 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Write as _};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, DdnsConfig, IpVersion};
+use crate::dns;
+use crate::events::{Event, EventStream, ResponseSummary};
+use crate::http;
+use crate::ip::DynamicIp;
+use crate::log::{error, info, warn};
+use crate::persistence::{self, CachedIp, PersistentState};
+use crate::services::{DdnsService, ErrorKind, RecordType, StaticRecords};
+use crate::GENERAL_CONFIG;
+
+/// Everything the daemon keeps track of between update cycles. This is
+/// rebuilt from scratch whenever the config file is reloaded.
+pub struct Daemon {
+    config_hash: u64,
+    update_rate: Option<NonZeroU32>,
+    ips: HashMap<Box<str>, DynamicIp>,
+    services: Vec<ServiceEntry>,
+
+    /// The [ddns.*] entries left out because of errors in the config file,
+    /// along with the reason. See `strict` in the config.
+    invalid_services: Vec<(Box<str>, String)>,
+
+    /// The hash of the configuration of each IP source, which tells whether
+    /// an address cached by a previous session can be trusted.
+    ip_source_hashes: HashMap<Box<str>, u64>,
+
+    /// The API calls made to each provider, shown by `dynners ctl status`.
+    api_usage: HashMap<&'static str, ApiUsage>,
+
+    /// The IPv4 sources compared with the address of the WAN interface, see
+    /// `compare_with` in the config.
+    nat_checks: HashMap<Box<str>, NatCheck>,
+
+    /// The IP sources replaced by a backup while they keep failing, see
+    /// `failover` in the config.
+    failovers: HashMap<Box<str>, Failover>,
+
+    events: EventStream,
+}
+
+/// A DDNS service along with its [ddns.*] entry name and the names of the IPs
+/// it uses.
+struct ServiceEntry {
+    name: Box<str>,
+    provider: &'static str,
+    ips: Vec<Box<str>>,
+    service: Box<dyn DdnsService>,
+
+    /// Set by `dynners ctl update <service>`, so that the service is updated
+    /// in the next cycle even if none of its IPs have changed. Also set when
+    /// an update is held back by a suspension of the service itself, so that
+    /// it is sent once the suspension is over.
+    forced: bool,
+
+    /// Set by `dynners ctl suspend <service>`, and remembered across restarts
+    /// through the persistent state.
+    suspended: bool,
+
+    /// Set by `suspended = true` in the config file. This cannot be undone
+    /// with `dynners ctl resume <service>`.
+    suspended_by_config: bool,
+
+    /// The outcome of the last update, shown by `dynners ctl status`.
+    status: Box<str>,
+
+    /// The domains looked up by `reconcile`, and reported in the events.
+    domains: Vec<Box<str>>,
+
+    /// The addresses the records are known to point to: those of the last
+    /// successful update, or those of its IPs in the persistent state until
+    /// then. Reported in the events, so that scripts can undo what they did
+    /// for the old addresses.
+    addresses: Vec<IpAddr>,
+
+    /// Set by `reconcile` in the config file, until the service is first due
+    /// for an update. The update is skipped if its records are already
+    /// correct, which saves a fresh install from pushing every record anew.
+    reconcile: bool,
+
+    /// The SRV and CAA records set by `srv` and `caa` in the config, until
+    /// they have been set. This is done along with the first update of the
+    /// service, once per startup or reload of the config.
+    static_records: Option<StaticRecords>,
+
+    /// When the suspension the service has imposed on itself was last logged,
+    /// while it is in effect.
+    suspension_logged: Option<Instant>,
+
+    /// Set when an update was rate limited and the provider told how long to
+    /// wait. The update is held back until then.
+    retry_at: Option<Instant>,
+
+    /// When the service was last updated successfully, for the services whose
+    /// records expire unless they are refreshed (see `refresh_interval`).
+    /// Remembered across restarts through the persistent state.
+    refreshed_at: Option<SystemTime>,
+}
+
+/// How often a service which has suspended itself is reminded of in the logs.
+const SUSPENSION_LOG_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+impl ServiceEntry {
+    fn is_suspended(&self) -> bool {
+        self.suspended || self.suspended_by_config
+    }
+
+    /// Whether the records have gone without an update for as long as the
+    /// provider allows, or were never known to be updated at all.
+    fn is_refresh_due(&self) -> bool {
+        let Some(interval) = self.service.refresh_interval() else {
+            return false;
+        };
+
+        self.refreshed_at.is_none_or(|at| {
+            SystemTime::now()
+                .duration_since(at)
+                .is_ok_and(|age| age >= interval)
+        })
+    }
+
+    /// Logs the suspension the service has imposed on itself when it starts,
+    /// every `SUSPENSION_LOG_INTERVAL` while it lasts, and when it is over.
+    fn log_suspension(&mut self, now: Instant) {
+        match (self.service.suspension(), self.suspension_logged) {
+            (Some(_), Some(logged))
+                if now.saturating_duration_since(logged) < SUSPENSION_LOG_INTERVAL => {}
+
+            (Some(state), _) => {
+                let age = SystemTime::now()
+                    .duration_since(state.since)
+                    .unwrap_or_default();
+
+                warn!(
+                    "DDNS service {} suspended itself {} ago ({}), its updates are held back until then. Reason: {}",
+                    self.name,
+                    format_age(age),
+                    state.suspension,
+                    state.reason
+                );
+                self.suspension_logged = Some(now);
+            }
+
+            (None, Some(_)) => {
+                info!("DDNS service {} is no longer suspended", self.name);
+                self.suspension_logged = None;
+            }
+
+            (None, None) => (),
+        }
+    }
+
+    /// A short description of whether the service is being updated, as shown
+    /// by `dynners ctl list`.
+    fn state(&self) -> String {
+        if self.suspended_by_config {
+            return String::from("suspended in the config file");
+        }
+
+        if self.suspended {
+            return String::from("suspended");
+        }
+
+        if let Some(state) = self.service.suspension() {
+            return format!("suspended itself ({})", state.suspension);
+        }
+
+        let left = self
+            .retry_at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+            .filter(|left| !left.is_zero());
+
+        if let Some(left) = left {
+            return format!("rate limited (tried again in {})", format_age(left));
+        }
+
+        match self.status.starts_with("failed") {
+            true => String::from("failing"),
+            false => String::from("active"),
+        }
+    }
+
+    /// Sets the SRV and CAA records, unless that has been done already. They
+    /// are tried again with the next update if this fails.
+    fn set_static_records(&mut self, api_usage: &mut HashMap<&'static str, ApiUsage>) {
+        let Some(records) = &self.static_records else {
+            return;
+        };
+
+        http::take_request_count();
+        let result = self.service.set_static_records(records);
+
+        if let Some(usage) = api_usage.get_mut(self.provider) {
+            usage.record(Instant::now(), http::take_request_count());
+        }
+
+        match result {
+            Some(Ok(0)) => info!(
+                "The SRV and CAA records of DDNS service {} are up to date",
+                self.name
+            ),
+
+            Some(Ok(changed)) => info!(
+                "Set {} SRV or CAA record(s) of DDNS service {}",
+                changed, self.name
+            ),
+
+            Some(Err(e)) => {
+                error!(
+                    "Unable to set the SRV and CAA records of DDNS service {}, reason: {}",
+                    self.name, e
+                );
+                return;
+            }
+
+            // Ruled out when the service was set up.
+            None => (),
+        }
+
+        self.static_records = None;
+    }
+}
+
+/// Tells whether the ISP puts the network behind a NAT of its own (as with
+/// DS-Lite), by comparing the public address of an IPv4 source with the
+/// address of the WAN interface. Inbound IPv4 connections cannot work then,
+/// whatever the A records say.
+struct NatCheck {
+    /// The [ip.*] source reading the address of the WAN interface.
+    local: Box<str>,
+
+    /// Set by `skip_behind_nat` in the config, which keeps the address from
+    /// being sent to any DDNS service while the network is behind a NAT.
+    skip: bool,
+
+    /// The kind of NAT found in the last cycle, if any.
+    detected: Option<&'static str>,
+}
+
+/// Guesses the kind of NAT from the address of the WAN interface, as in
+/// "behind <kind>": DS-Lite gives it an address from 192.0.0.0/29, a
+/// carrier-grade NAT one from 100.64.0.0/10.
+fn nat_kind(local: IpAddr) -> &'static str {
+    let IpAddr::V4(local) = local else {
+        return "a NAT of the ISP";
+    };
+
+    match local.octets() {
+        [192, 0, 0, d] if d < 8 => "DS-Lite",
+        [100, b, _, _] if b & 0xc0 == 64 => "a carrier-grade NAT",
+        _ => "a NAT of the ISP",
+    }
+}
+
+/// Publishes the address of a backup source in place of one which keeps on
+/// failing, e.g. that of the second connection of a dual-WAN network.
+struct Failover {
+    /// The [ip.*] source taking over.
+    backup: Box<str>,
+
+    /// The number of failed cycles in a row after which the backup takes over.
+    after: u32,
+
+    /// The number of cycles the source has failed in a row.
+    failures: u32,
+
+    /// Whether the address of the backup is published at the moment.
+    active: bool,
+}
+
+impl Failover {
+    /// Counts a cycle in which the source failed or not, and tells whether the
+    /// backup took over or was given up on because of it.
+    fn record(&mut self, failed: bool) -> bool {
+        self.failures = match failed {
+            true => self.failures.saturating_add(1),
+            false => 0,
+        };
+
+        let active = self.failures >= self.after;
+        let switched = active != self.active;
+        self.active = active;
+
+        switched
+    }
+}
+
+/// The IP source actually used in place of the given one, which is its backup
+/// while that has taken over.
+fn source_of<'a>(failovers: &'a HashMap<Box<str>, Failover>, name: &'a str) -> &'a str {
+    match failovers.get(name) {
+        Some(failover) if failover.active => &failover.backup,
+        _ => name,
+    }
+}
+
+/// How many API calls were made to a provider over the last day, so that
+/// users on strict free tiers can tell how close they are to the limits.
+#[derive(Default)]
+struct ApiUsage {
+    /// When the calls were made, oldest first.
+    calls: VecDeque<(Instant, u32)>,
+}
+
+impl ApiUsage {
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn record(&mut self, now: Instant, calls: u32) {
+        while let Some((time, _)) = self.calls.front() {
+            if now.saturating_duration_since(*time) < Self::DAY {
+                break;
+            }
+            self.calls.pop_front();
+        }
+
+        if calls > 0 {
+            self.calls.push_back((now, calls));
+        }
+    }
+
+    fn calls_within(&self, now: Instant, period: Duration) -> u32 {
+        self.calls
+            .iter()
+            .rev()
+            .take_while(|(time, _)| now.saturating_duration_since(*time) < period)
+            .map(|(_, calls)| calls)
+            .sum()
+    }
+}
+
+/// What happened during a single update cycle. This is logged at the end of
+/// every cycle, so that the logs can be skimmed without reading every line.
+pub struct CycleSummary {
+    started: Instant,
+    ips_checked: usize,
+    ips_changed: usize,
+    services_updated: usize,
+    failures: usize,
+}
+
+impl CycleSummary {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            ips_checked: 0,
+            ips_changed: 0,
+            services_updated: 0,
+            failures: 0,
+        }
+    }
+}
+
+impl Display for CycleSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cycle finished in {:.2}s: {} IP(s) checked, {} changed, {} service(s) updated, {} failure(s)",
+            self.started.elapsed().as_secs_f64(),
+            self.ips_checked,
+            self.ips_changed,
+            self.services_updated,
+            self.failures
+        )
+    }
+}
+
+impl Daemon {
+    pub fn new(mut config: Config, persistent_state: &PersistentState) -> Result<Self, String> {
+        let debounce = config.general.debounce();
+        let settings = config.general.source_settings();
+        let service_settings = config.general.service_settings();
+
+        // Collect IP addresses specified in [ip.*] entries into (ip name, ip)
+        let mut ips = HashMap::with_capacity(config.ip.len());
+        let mut ip_source_hashes = HashMap::with_capacity(config.ip.len());
+        let mut nat_checks = HashMap::new();
+        let mut failovers = HashMap::new();
+
+        for (name, ip) in config.ip.iter() {
+            let Some(backup) = &ip.failover else {
+                continue;
+            };
+
+            match config.ip.get(backup) {
+                _ if backup == name => {
+                    return Err(format!("IP {}: it cannot be its own failover", name))
+                }
+                Some(backup) if backup.version == ip.version => (),
+                Some(_) => {
+                    return Err(format!(
+                        "IP {}: {} is not of the same IP version",
+                        name, backup
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "IP {}: {} is not specified anywhere in config",
+                        name, backup
+                    ))
+                }
+            }
+
+            if ip.failover_after == 0 {
+                return Err(format!("IP {}: failover_after must be at least 1", name));
+            }
+
+            let failover = Failover {
+                backup: backup.clone(),
+                after: ip.failover_after,
+                failures: 0,
+                active: false,
+            };
+            failovers.insert(name.clone(), failover);
+        }
+
+        for (name, ip) in config.ip.iter() {
+            let Some(local) = &ip.compare_with else {
+                if ip.skip_behind_nat {
+                    warn!(
+                        "IP {}: skip_behind_nat only applies along with compare_with",
+                        name
+                    );
+                }
+                continue;
+            };
+
+            if ip.version != IpVersion::V4 {
+                return Err(format!("IP {}: compare_with only applies to IPv4", name));
+            }
+
+            match config.ip.get(local) {
+                Some(local) if local.version == IpVersion::V4 => (),
+                Some(_) => return Err(format!("IP {}: {} is not an IPv4 source", name, local)),
+                None => {
+                    return Err(format!(
+                        "IP {}: {} is not specified anywhere in config",
+                        name, local
+                    ))
+                }
+            }
+
+            let check = NatCheck {
+                local: local.clone(),
+                skip: ip.skip_behind_nat,
+                detected: None,
+            };
+            nat_checks.insert(name.clone(), check);
+        }
+
+        for (name, ip) in config.ip.into_iter() {
+            let mut dyn_ip = DynamicIp::from_config(&ip, debounce, &settings)
+                .map_err(|e| format!("Unable to parse IP configuration: {}", e))?;
+
+            if let Some(ip) = persistent_state.ip_addresses.get(&name) {
+                info!(
+                    "Initialized IP {} using the persistent state with {}",
+                    &name, &ip
+                );
+                dyn_ip.update_from_cache(*ip);
+            }
+
+            if ip.cache_ttl > 0 && !dyn_ip.is_cached() {
+                warn!("IP {}: cache_ttl only applies to the http method", &name);
+            }
+
+            let source_hash =
+                persistence::hash_bytes(format!("{:?} {:?}", ip.version, ip.method).as_bytes());
+
+            let cached = persistent_state
+                .cached_ips
+                .get(&name)
+                .filter(|cached| cached.source_hash == source_hash);
+
+            if let Some(cached) = cached {
+                let polled_at = UNIX_EPOCH + Duration::from_secs(cached.timestamp);
+
+                if dyn_ip.restore_from_cache(cached.address, polled_at) {
+                    info!(
+                        "IP {} will use the cached address {} until its cache_ttl has passed",
+                        &name, &cached.address
+                    );
+                }
+            }
+
+            ip_source_hashes.insert(name.clone(), source_hash);
+            ips.insert(name, dyn_ip);
+        }
+
+        if ips.is_empty() {
+            return Err(String::from("No IPs were configured."));
+        }
+
+        let mut invalid_services = config.invalid_ddns;
+
+        // Verify whether the IPs in [ddns.*] are actually specified by [ip.*]
+        let mut errors = Vec::new();
+        for (service_name, ddns) in config.ddns.iter_mut() {
+            for ip in ddns.ip.iter() {
+                if !ips.contains_key(ip) {
+                    let reason = format!("the IP {} is not specified anywhere in config", ip);
+                    errors.push(format!("[FATAL] service {}: {}", service_name, reason));
+                    invalid_services.push((service_name.clone(), reason));
+                }
+            }
+
+            if let Err(reason) = check_static_records(ddns) {
+                errors.push(format!("[FATAL] service {}: {}", service_name, reason));
+                invalid_services.push((service_name.clone(), reason));
+            }
+        }
+
+        // A service using several unknown IPs only needs to be listed once.
+        invalid_services.dedup_by(|a, b| a.0 == b.0);
+
+        if !errors.is_empty() && config.general.strict {
+            return Err(errors.join("\n"));
+        }
+
+        for (name, reason) in &invalid_services {
+            error!(
+                "DDNS service {} is disabled because of an error in the config file: {}",
+                name, reason
+            );
+        }
+
+        let reconcile = config.general.reconcile;
+
+        // Initialize each DDNS service entry into a `services` array
+        let services: Vec<ServiceEntry> = config
+            .ddns
+            .into_iter()
+            .filter(|(name, _)| !invalid_services.iter().any(|(invalid, _)| invalid == name))
+            .map(|(name, ddns)| ServiceEntry {
+                suspended: persistent_state.suspended_services.contains(&name),
+                refreshed_at: persistent_state
+                    .refreshed_services
+                    .get(&name)
+                    .map(|timestamp| UNIX_EPOCH + Duration::from_secs(*timestamp)),
+                name,
+                provider: ddns.service.provider(),
+                addresses: ddns
+                    .ip
+                    .iter()
+                    .filter_map(|ip| ips.get(ip)?.address())
+                    .copied()
+                    .collect(),
+                ips: ddns.ip,
+                service: ddns.service.into_boxed(&service_settings),
+                forced: false,
+                suspended_by_config: ddns.suspended,
+                status: "not updated yet".into(),
+                domains: ddns.domains,
+                reconcile,
+                static_records: Some(StaticRecords {
+                    srv: ddns.srv,
+                    caa: ddns.caa,
+                })
+                .filter(|records| !records.is_empty()),
+                suspension_logged: None,
+                retry_at: None,
+            })
+            .collect();
+
+        for entry in services.iter().filter(|entry| entry.is_suspended()) {
+            info!("DDNS service {} is suspended", entry.name);
+        }
+
+        let api_usage = services
+            .iter()
+            .map(|entry| (entry.provider, ApiUsage::default()))
+            .collect();
+
+        Ok(Self {
+            config_hash: persistent_state.config_hash,
+            update_rate: config.general.update_rate,
+            ips,
+            services,
+            invalid_services,
+            ip_source_hashes,
+            api_usage,
+            nat_checks,
+            failovers,
+            events: EventStream::new(&config.general.events),
+        })
+    }
+
+    /// Keeps the API usage recorded by the daemon being replaced, the quotas
+    /// of the providers do not reset just because the config was reloaded.
+    /// The same goes for the statistics of the IP sources left unchanged.
+    pub fn take_stats_from(&mut self, old: &mut Daemon) {
+        for (provider, usage) in self.api_usage.iter_mut() {
+            if let Some(old_usage) = old.api_usage.remove(provider) {
+                *usage = old_usage;
+            }
+        }
+
+        for (name, ip) in self.ips.iter_mut() {
+            let is_unchanged = self.ip_source_hashes.get(name) == old.ip_source_hashes.get(name);
+
+            if let Some(old_ip) = old.ips.get(name).filter(|_| is_unchanged) {
+                ip.set_stats(old_ip.stats().clone());
+            }
+        }
+    }
+
+    pub fn update_rate(&self) -> Option<NonZeroU32> {
+        self.update_rate
+    }
+
+    /// How long to wait until the next update cycle.
+    pub fn sleep_duration(&self) -> Option<Duration> {
+        let mut sleep_for = Duration::from_secs(self.update_rate?.get() as u64);
+
+        // Check again as soon as a pending IP can be confirmed, instead of
+        // letting it wait for an entire cycle.
+        let now = Instant::now();
+        for ip in self.ips.values() {
+            match ip.pending_deadline() {
+                Some(deadline) if deadline > now => {
+                    sleep_for = sleep_for.min(deadline - now);
+                }
+                _ => (),
+            }
+        }
+
+        Some(sleep_for)
+    }
+
+    pub fn run_cycle(&mut self) -> CycleSummary {
+        let mut is_ip_updated = false;
+        let mut is_service_refreshed = false;
+        let mut summary = CycleSummary::new();
+
+        // The IP sources are independent of each other, so query all of them
+        // at once instead of waiting for each one in turn.
+        let results = thread::scope(|scope| {
+            let handles = self
+                .ips
+                .iter_mut()
+                .map(|(name, ip)| {
+                    let was_backing_off = ip.is_backing_off();
+                    let is_skipped = ip.skipped_cycles() > 0;
+                    (
+                        name,
+                        was_backing_off,
+                        is_skipped,
+                        scope.spawn(|| ip.update()),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|(name, was_backing_off, is_skipped, handle)| {
+                    (name.clone(), was_backing_off, is_skipped, handle.join())
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut is_cache_refreshed = false;
+
+        // The IPs whose backup took over or was given up on in this cycle.
+        let mut switched = Vec::new();
+
+        for (name, was_backing_off, is_skipped, result) in results {
+            let ip = &self.ips[&name];
+
+            is_cache_refreshed |= ip.is_cache_refreshed();
+
+            // A source skipped while backing off was not checked at all.
+            summary.ips_checked += !is_skipped as usize;
+            summary.ips_changed += ip.is_dirty() as usize;
+            summary.failures += matches!(result, Ok(Err(_)) | Err(_)) as usize;
+
+            if let Some(failover) = self.failovers.get_mut(&name) {
+                // The cycles skipped while backing off count as failures too.
+                let failed = matches!(result, Ok(Err(_)) | Err(_)) || ip.is_backing_off();
+
+                if failover.record(failed) {
+                    switched.push(name.clone());
+                }
+            }
+
+            match result {
+                Ok(Ok(())) if was_backing_off && !ip.is_backing_off() => {
+                    info!("IP {} is working again", name);
+                }
+
+                Ok(Ok(())) => (),
+
+                // Keep quiet about a source which keeps on failing, we have
+                // already warned about it once.
+                Ok(Err(_)) if was_backing_off => (),
+
+                Ok(Err(e)) if ip.is_backing_off() => {
+                    warn!(
+                        "IP {} failed several times in a row, it will be polled less often until it recovers. Last reason: {}",
+                        name,
+                        e
+                    );
+                }
+
+                Ok(Err(e)) => {
+                    error!("Unable to update IP {}, reason: {}", name, e);
+                }
+
+                Err(_) => {
+                    error!("Unable to update IP {}, reason: panicked", name);
+                }
+            }
+        }
+
+        self.check_nat();
+
+        for name in &switched {
+            let failover = &self.failovers[name];
+
+            match failover.active {
+                true => warn!(
+                    "IP {} failed {} cycle(s) in a row, the address of {} is sent to DDNS services in its place until it works again",
+                    name, failover.failures, failover.backup
+                ),
+                false => info!(
+                    "IP {} is working again, its address is sent to DDNS services instead of that of {}",
+                    name, failover.backup
+                ),
+            }
+        }
+
+        // The addresses which must not be sent to any DDNS service.
+        let behind_nat = self
+            .nat_checks
+            .iter()
+            .filter(|(_, check)| check.skip && check.detected.is_some())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        for (name, ip) in &self.ips {
+            if let Some(pending) = ip.pending_address() {
+                info!(
+                    "IP {} changed to {}, waiting for it to settle down",
+                    name, pending
+                );
+            }
+
+            if let Some(address) = ip.address().filter(|_| ip.is_dirty()) {
+                self.events.emit(Event::IpChanged {
+                    ip: name,
+                    previous: ip.previous_address().copied(),
+                    address: *address,
+                });
+            }
+        }
+
+        for entry in self.services.iter_mut() {
+            let is_dirty = entry
+                .ips
+                .iter()
+                .map(|name| &self.ips[source_of(&self.failovers, name)])
+                .any(|ip| ip.is_dirty())
+                || entry.ips.iter().any(|name| switched.contains(name));
+
+            is_ip_updated |= is_dirty;
+
+            entry.log_suspension(Instant::now());
+
+            let is_refresh_due = entry.is_refresh_due();
+
+            if entry.is_suspended() || !(is_dirty || entry.forced || is_refresh_due) {
+                continue;
+            }
+
+            if entry.service.suspension().is_some() {
+                entry.forced = true;
+                continue;
+            }
+
+            if entry.retry_at.is_some_and(|at| at > Instant::now()) {
+                entry.forced = true;
+                continue;
+            }
+
+            entry.retry_at = None;
+
+            let is_forced = entry.forced;
+            entry.forced = false;
+
+            let ips = entry
+                .ips
+                .iter()
+                .filter(|name| !behind_nat.contains(name))
+                .map(|name| &self.ips[source_of(&self.failovers, name)])
+                .filter_map(|ip| ip.address())
+                .cloned()
+                .collect::<Vec<_>>(); // TODO: use collect_into in the future
+
+            let name = &entry.name;
+
+            if ips.is_empty() && entry.ips.iter().any(|ip| behind_nat.contains(ip)) {
+                info!(
+                    "DDNS service {} is not updated, its IPv4 address is behind a NAT",
+                    name
+                );
+                entry.status = "skipped (behind NAT)".into();
+                continue;
+            }
+
+            if !(is_dirty || is_forced) {
+                if let Some(interval) = entry.service.refresh_interval() {
+                    info!(
+                        "Refreshing DDNS service {}, its records expire unless they are updated every {}",
+                        name,
+                        format_age(interval)
+                    );
+                }
+            }
+
+            let reconcile = entry.reconcile && !is_forced && !is_refresh_due;
+            entry.reconcile = false;
+
+            if reconcile && records_match(&entry.domains, &ips, dns::lookup) {
+                let addresses = ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+                info!(
+                    "The records of DDNS service {} already point to {}, skipping the update",
+                    name,
+                    addresses.join(", ")
+                );
+
+                self.events.emit(Event::ServiceUpdated {
+                    service: name,
+                    provider: entry.provider,
+                    domains: &entry.domains,
+                    previous: &entry.addresses,
+                    addresses: &[],
+                    response: None,
+                });
+
+                entry.status = "up to date (checked DNS)".into();
+                entry.addresses = ips;
+                entry.set_static_records(&mut self.api_usage);
+                continue;
+            }
+
+            http::clear_last_exchange();
+            http::take_request_count();
+            http::take_last_status();
+
+            let result = entry.service.update_record(ips.as_slice());
+
+            let response = ResponseSummary {
+                requests: http::take_request_count(),
+                last_status: http::take_last_status(),
+            };
+
+            if let Some(usage) = self.api_usage.get_mut(entry.provider) {
+                usage.record(Instant::now(), response.requests);
+            }
+
+            match result {
+                Ok(updated) => {
+                    self.events.emit(Event::ServiceUpdated {
+                        service: name,
+                        provider: entry.provider,
+                        domains: &entry.domains,
+                        previous: &entry.addresses,
+                        addresses: updated.as_slice(),
+                        response: Some(response),
+                    });
+
+                    for ip in &updated {
+                        info!("Updated DDNS service {} with IP {}", name, ip);
+                    }
+
+                    if updated.get(0).is_none() {
+                        info!(
+                            "Tried to update DDNS service {}, but no changes were made",
+                            name
+                        );
+                        entry.status = "up to date".into();
+                    } else {
+                        summary.services_updated += 1;
+
+                        let updated = updated.iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+                        entry.status = (String::from("updated with ") + &updated.join(", ")).into();
+                    }
+
+                    // Either way, the records now point to the current addresses.
+                    entry.addresses = ips;
+
+                    if entry.service.refresh_interval().is_some() {
+                        entry.refreshed_at = Some(SystemTime::now());
+                        is_service_refreshed = true;
+                    }
+                    entry.set_static_records(&mut self.api_usage);
+                }
+
+                Err(e) => {
+                    let kind = e.kind();
+
+                    // A bad token or a missing record stays that way until
+                    // the config is fixed, but anything transient is tried
+                    // again with the next cycle, or once the provider allows.
+                    match kind {
+                        ErrorKind::RateLimited(Some(after)) => {
+                            error!(
+                                "DDNS service {} was rate limited, it is tried again in {}. Reason: {}",
+                                name,
+                                format_age(after),
+                                e
+                            );
+                            entry.retry_at = Some(Instant::now() + after);
+                        }
+
+                        kind if kind.is_transient() => error!(
+                            "DDNS service {} failed, it is tried again in the next cycle. Reason: {}",
+                            name, e
+                        ),
+
+                        ErrorKind::Auth => error!(
+                            "DDNS service {} failed, check its credentials. Reason: {}",
+                            name, e
+                        ),
+
+                        _ => error!("DDNS service {} failed, reason: {}", name, e),
+                    }
+
+                    entry.forced = kind.is_transient();
+
+                    self.events.emit(Event::ServiceFailed {
+                        service: name,
+                        provider: entry.provider,
+                        domains: &entry.domains,
+                        previous: &entry.addresses,
+                        error: e.to_string(),
+                        kind: kind.name(),
+                        retry_after: match kind {
+                            ErrorKind::RateLimited(after) => after.map(|after| after.as_secs()),
+                            _ => None,
+                        },
+                        response,
+                    });
+
+                    summary.failures += 1;
+                    entry.status = format!("failed: {}", e).into();
+                    save_diagnostics(name);
+                }
+            };
+        }
+
+        info!("{}", summary);
+
+        // We only update the persistent state if any of the IPs have changed,
+        // if the addresses to be cached have been obtained again, or if a
+        // service which has to be refreshed was updated.
+        if is_ip_updated || is_cache_refreshed || is_service_refreshed {
+            self.save_persistent_state();
+        }
+
+        summary
+    }
+
+    /// Compares the public addresses with those of the WAN interfaces, and
+    /// tells whenever the network goes behind a NAT of the ISP or comes out
+    /// of it.
+    fn check_nat(&mut self) {
+        for (name, check) in self.nat_checks.iter_mut() {
+            let public = self.ips[name].address();
+            let local = self.ips.get(&check.local).and_then(|ip| ip.address());
+
+            let (Some(public), Some(local)) = (public, local) else {
+                continue;
+            };
+
+            let detected = (public != local).then(|| nat_kind(*local));
+
+            match (check.detected, detected) {
+                (None, Some(kind)) => warn!(
+                    "IP {} ({}) differs from the address of {} ({}), the network is most likely behind {} and cannot be reached through IPv4{}",
+                    name,
+                    public,
+                    check.local,
+                    local,
+                    kind,
+                    if check.skip { ", the address will not be sent to DDNS services" } else { "" }
+                ),
+
+                (Some(_), None) => info!(
+                    "IP {} matches the address of {} again, the network is no longer behind a NAT",
+                    name, check.local
+                ),
+
+                _ => (),
+            }
+
+            check.detected = detected;
+        }
+    }
+
+    /// Called when the system clock has jumped ahead, most likely because the
+    /// system was suspended. Every IP source is polled again in the next
+    /// cycle, even those which were failing before.
+    pub fn after_clock_jump(&mut self) {
+        self.ips.values_mut().for_each(DynamicIp::reset_backoff);
+    }
+
+    fn save_persistent_state(&self) {
+        let mut persistent_state = PersistentState::new_with_config_hash(self.config_hash);
+        persistent_state.ip_addresses = self
+            .ips
+            .iter()
+            .flat_map(|(name, dyn_ip)| dyn_ip.address().map(|ip| (name.clone(), *ip)))
+            .collect();
+        persistent_state.suspended_services = self
+            .services
+            .iter()
+            .filter(|entry| entry.suspended)
+            .map(|entry| entry.name.clone())
+            .collect();
+        persistent_state.refreshed_services = self
+            .services
+            .iter()
+            .flat_map(|entry| {
+                let refreshed_at = entry.refreshed_at?;
+                let timestamp = refreshed_at.duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+                Some((entry.name.clone(), timestamp))
+            })
+            .collect();
+        persistent_state.cached_ips = self
+            .ips
+            .iter()
+            .flat_map(|(name, dyn_ip)| {
+                let (address, polled_at) = dyn_ip.cache_entry()?;
+                let timestamp = polled_at.duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+                let cached = CachedIp {
+                    source_hash: *self.ip_source_hashes.get(name)?,
+                    timestamp,
+                    address,
+                };
+
+                Some((name.clone(), cached))
+            })
+            .collect();
+
+        let path = GENERAL_CONFIG.get().unwrap().persistent_state.as_ref();
+
+        let file = match File::create(path) {
+            Ok(f) => Some(f),
+            Err(_) if path.is_empty() => None,
+            Err(e) => {
+                warn!("Couldn't open persistent state file for writing: {}", e);
+                None
+            }
+        };
+
+        if let Some(file) = file {
+            match persistent_state.write_to(BufWriter::new(file)) {
+                Ok(_) => (),
+                Err(e) => {
+                    warn!("Couldn't write to persistent state file: {}", e);
+                }
+            }
+        }
+    }
+
+    fn service_mut(&mut self, name: &str) -> Result<&mut ServiceEntry, String> {
+        self.services
+            .iter_mut()
+            .find(|entry| &*entry.name == name)
+            .ok_or_else(|| format!("no such DDNS service: {}", name))
+    }
+
+    /// Makes the next cycle update the given service (or every service if
+    /// none is given), regardless of whether its IPs have changed.
+    pub fn force_update(&mut self, name: Option<&str>) -> Result<(), String> {
+        match name {
+            Some(name) => self.service_mut(name)?.forced = true,
+            None => self
+                .services
+                .iter_mut()
+                .for_each(|entry| entry.forced = true),
+        }
+
+        Ok(())
+    }
+
+    pub fn suspend(&mut self, name: &str) -> Result<String, String> {
+        let entry = self.service_mut(name)?;
+
+        if entry.is_suspended() {
+            return Ok(format!("DDNS service {} is already suspended", name));
+        }
+
+        entry.suspended = true;
+        info!("Suspended DDNS service {}", name);
+        self.save_persistent_state();
+
+        Ok(format!("Suspended DDNS service {}", name))
+    }
+
+    pub fn resume(&mut self, name: &str) -> Result<String, String> {
+        let entry = self.service_mut(name)?;
+
+        if entry.suspended_by_config {
+            return Err(format!(
+                "DDNS service {} is suspended in the config file",
+                name
+            ));
+        }
+
+        if !entry.suspended {
+            return Ok(format!("DDNS service {} is not suspended", name));
+        }
+
+        // The IPs might have changed in the meantime, so update it in the
+        // next cycle regardless.
+        entry.suspended = false;
+        entry.forced = true;
+        info!("Resumed DDNS service {}", name);
+        self.save_persistent_state();
+
+        Ok(format!("Resumed DDNS service {}", name))
+    }
+
+    /// Every DDNS service along with its provider and whether it is being
+    /// updated, one per line and separated by tabs, for scripts.
+    pub fn list(&self) -> Result<String, String> {
+        let mut services = self.services.iter().collect::<Vec<_>>();
+        services.sort_unstable_by_key(|entry| &entry.name);
+
+        let mut list = String::new();
+
+        for entry in services {
+            let _ = writeln!(
+                list,
+                "{}\t{}\t{}",
+                entry.name,
+                entry.provider,
+                entry.state()
+            );
+        }
+
+        for (name, _) in &self.invalid_services {
+            let _ = writeln!(list, "{}\t-\tdisabled", name);
+        }
+
+        Ok(list)
+    }
+
+    /// A human-readable description of every IP and DDNS service. It is an
+    /// error if any of the services could not be set up, so that health checks
+    /// relying on `dynners ctl status` notice.
+    pub fn status(&self) -> Result<String, String> {
+        let now = Instant::now();
+        let mut status = String::from("IPs:\n");
+
+        let mut ips = self.ips.iter().collect::<Vec<_>>();
+        ips.sort_unstable_by_key(|(name, _)| *name);
+
+        for (name, ip) in ips {
+            let _ = match ip.address() {
+                Some(address) => write!(status, "  {}: {}", name, address),
+                None => write!(status, "  {}: (unknown)", name),
+            };
+
+            if let Some(pending) = ip.pending_address() {
+                let _ = write!(status, ", changing to {}", pending);
+            }
+
+            if ip.is_backing_off() {
+                status += ", failing";
+
+                if ip.skipped_cycles() > 0 {
+                    let _ = write!(
+                        status,
+                        " (polled again in {} cycle(s))",
+                        ip.skipped_cycles() + 1
+                    );
+                }
+            }
+
+            if let Some(check) = self.nat_checks.get(name) {
+                if let Some(kind) = check.detected {
+                    let _ = write!(status, ", behind {}", kind);
+
+                    if check.skip {
+                        status += " (not sent to DDNS services)";
+                    }
+                }
+            }
+
+            if let Some(failover) = self.failovers.get(name).filter(|f| f.active) {
+                let _ = write!(status, ", replaced by {}", failover.backup);
+            }
+
+            status += "\n";
+
+            let stats = ip.stats();
+            if stats.polls > 0 {
+                let _ = write!(
+                    status,
+                    "    {}/{} polls succeeded ({}%)",
+                    stats.successes,
+                    stats.polls,
+                    stats.successes as u64 * 100 / stats.polls as u64
+                );
+
+                if let Some(latency) = stats.last_latency {
+                    let _ = write!(status, ", last took {:.2}s", latency.as_secs_f64());
+                }
+
+                if let Some((error, at)) = &stats.last_error {
+                    let age = format_age(now.saturating_duration_since(*at));
+                    let _ = write!(status, ", last error {} ago: {}", age, error);
+                }
+
+                status += "\n";
+            }
+        }
+
+        status += "DDNS services:\n";
+
+        let mut services = self.services.iter().collect::<Vec<_>>();
+        services.sort_unstable_by_key(|entry| &entry.name);
+
+        for entry in services {
+            let _ = write!(status, "  {}: {}", entry.name, entry.status);
+
+            if entry.suspended_by_config {
+                status += ", suspended in the config file";
+            } else if entry.suspended {
+                status += ", suspended";
+            }
+
+            status += "\n";
+
+            if let Some(state) = entry.service.suspension() {
+                let age = SystemTime::now()
+                    .duration_since(state.since)
+                    .unwrap_or_default();
+
+                let _ = writeln!(
+                    status,
+                    "    suspended itself {} ago ({}), updates are held back: {}",
+                    format_age(age),
+                    state.suspension,
+                    state.reason
+                );
+            }
+        }
+
+        for (name, reason) in &self.invalid_services {
+            let _ = writeln!(status, "  {}: disabled, invalid config: {}", name, reason);
+        }
+
+        status += "API calls (last hour / last day):\n";
+
+        let mut api_usage = self.api_usage.iter().collect::<Vec<_>>();
+        api_usage.sort_unstable_by_key(|(provider, _)| *provider);
+
+        for (provider, usage) in api_usage {
+            let _ = writeln!(
+                status,
+                "  {}: {} / {}",
+                provider,
+                usage.calls_within(now, ApiUsage::HOUR),
+                usage.calls_within(now, ApiUsage::DAY)
+            );
+        }
+
+        match self.invalid_services.is_empty() {
+            true => Ok(status),
+            false => Err(status),
+        }
+    }
+}
+
+/// Makes sure the service is able to maintain the SRV and CAA records of its
+/// entry, and points the SRV records without a target to its first domain.
+fn check_static_records(ddns: &mut DdnsConfig) -> Result<(), String> {
+    let records = ddns.service.records();
+
+    for (kind, is_used) in [
+        (RecordType::Srv, !ddns.srv.is_empty()),
+        (RecordType::Caa, !ddns.caa.is_empty()),
+    ] {
+        if is_used && !records.contains(&kind) {
+            return Err(format!(
+                "{} is unable to maintain {} records",
+                ddns.service.provider(),
+                kind
+            ));
+        }
+    }
+
+    for srv in ddns.srv.iter_mut().filter(|srv| srv.target.is_empty()) {
+        let Some(domain) = ddns.domains.first() else {
+            return Err(format!(
+                "the SRV record {} needs a target, the service has no domains",
+                srv.name
+            ));
+        };
+
+        srv.target = domain.clone();
+    }
+
+    Ok(())
+}
+
+/// Formats the duration in its largest whole unit, e.g. "42s" or "3h".
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        seconds @ 0..60 => format!("{}s", seconds),
+        seconds @ 60..3600 => format!("{}m", seconds / 60),
+        seconds @ 3600..86400 => format!("{}h", seconds / 3600),
+        seconds => format!("{}d", seconds / 86400),
+    }
+}
+
+/// Whether every domain resolves to exactly the given addresses, as far as the
+/// address families of `ips` are concerned. Anything going wrong while looking
+/// them up counts as a mismatch.
+fn records_match(
+    domains: &[Box<str>],
+    ips: &[IpAddr],
+    lookup: impl Fn(&str) -> io::Result<Vec<IpAddr>>,
+) -> bool {
+    if domains.is_empty() || ips.is_empty() {
+        return false;
+    }
+
+    domains.iter().all(|domain| {
+        let Ok(found) = lookup(domain) else {
+            return false;
+        };
+
+        ips.iter().all(|ip| {
+            found
+                .iter()
+                .filter(|address| address.is_ipv4() == ip.is_ipv4())
+                .eq([ip])
+        })
+    })
+}
+
+/// Dumps the last HTTP exchange made by a failed DDNS service into the
+/// diagnostics directory, if the user has configured one.
+fn save_diagnostics(service_name: &str) {
+    let dir = GENERAL_CONFIG.get().unwrap().diagnostics_dir.as_ref();
+
+    if dir.is_empty() {
+        return;
+    }
+
+    let Some(exchange) = http::take_last_exchange() else {
+        return;
+    };
+
+    // Service names are arbitrary strings, make sure they are usable as
+    // file names.
+    let file_name = service_name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        + ".txt";
+
+    let path = Path::new(dir).join(file_name);
+
+    let result = fs::create_dir_all(dir).and_then(|_| fs::write(&path, exchange.to_string()));
+
+    match result {
+        Ok(_) => info!(
+            "Saved diagnostics of DDNS service {} to {}",
+            service_name,
+            path.display()
+        ),
+        Err(e) => warn!(
+            "Couldn't save diagnostics of DDNS service {}: {}",
+            service_name, e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_usage() {
+        let start = Instant::now();
+        let minutes = |m: u64| start + Duration::from_secs(m * 60);
+
+        let mut usage = ApiUsage::default();
+        usage.record(minutes(0), 3);
+        usage.record(minutes(30), 0);
+        usage.record(minutes(90), 2);
+        usage.record(minutes(100), 1);
+
+        assert_eq!(usage.calls_within(minutes(100), ApiUsage::HOUR), 3);
+        assert_eq!(usage.calls_within(minutes(100), ApiUsage::DAY), 6);
+        assert_eq!(usage.calls.len(), 3);
+
+        // Calls older than a day are forgotten.
+        usage.record(minutes(24 * 60 + 95), 4);
+        assert_eq!(usage.calls_within(minutes(24 * 60 + 95), ApiUsage::DAY), 5);
+        assert_eq!(usage.calls.len(), 2);
+    }
+
+    #[test]
+    fn nat() {
+        let kind = |ip: &str| nat_kind(ip.parse().unwrap());
+
+        assert_eq!(kind("192.0.0.2"), "DS-Lite");
+        assert_eq!(kind("192.0.0.8"), "a NAT of the ISP");
+        assert_eq!(kind("100.64.0.1"), "a carrier-grade NAT");
+        assert_eq!(kind("100.127.255.254"), "a carrier-grade NAT");
+        assert_eq!(kind("100.128.0.1"), "a NAT of the ISP");
+        assert_eq!(kind("192.168.1.1"), "a NAT of the ISP");
+    }
+
+    #[test]
+    fn failover() {
+        let mut failover = Failover {
+            backup: "wan2".into(),
+            after: 2,
+            failures: 0,
+            active: false,
+        };
+
+        assert!(!failover.record(true));
+        assert!(!failover.record(false));
+        assert!(!failover.record(true));
+        assert!(failover.record(true));
+        assert!(failover.active);
+        assert!(!failover.record(true));
+        assert!(failover.record(false));
+        assert!(!failover.active);
+    }
+
+    #[test]
+    fn static_records() {
+        let mut ddns = toml::from_str::<DdnsConfig>(
+            r#"
+            service = "hetzner"
+            ip = "wan4"
+            token = "token"
+            domains = ["example.com", "www.example.com"]
+            ttl = 300
+            srv = [{ name = "_minecraft._tcp.example.com", port = 25565 }]
+            "#,
+        )
+        .unwrap();
+
+        // The domains are only filled in by `Config::parse`.
+        assert!(check_static_records(&mut ddns).is_err());
+
+        ddns.domains = vec!["example.com".into(), "www.example.com".into()];
+        assert_eq!(check_static_records(&mut ddns), Ok(()));
+        assert_eq!(&*ddns.srv[0].target, "example.com");
+
+        let mut ddns = toml::from_str::<DdnsConfig>(
+            r#"
+            service = "duckdns"
+            ip = "wan4"
+            token = "token"
+            domains = "example.duckdns.org"
+            caa = [{ name = "example.duckdns.org", tag = "issue", value = "letsencrypt.org" }]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            check_static_records(&mut ddns),
+            Err(String::from("DuckDNS is unable to maintain CAA records"))
+        );
+    }
+
+    #[test]
+    fn ages() {
+        assert_eq!(format_age(Duration::from_secs(59)), "59s");
+        assert_eq!(format_age(Duration::from_secs(60)), "1m");
+        assert_eq!(format_age(Duration::from_secs(7199)), "1h");
+        assert_eq!(format_age(Duration::from_secs(3 * 86400)), "3d");
+    }
+
+    #[test]
+    fn reconcile() {
+        let lookup = |domain: &str| match domain {
+            "a.example.com" => Ok(vec![
+                "192.0.2.1".parse().unwrap(),
+                "2001:db8::1".parse().unwrap(),
+            ]),
+            "b.example.com" => Ok(vec![
+                "192.0.2.1".parse().unwrap(),
+                "192.0.2.2".parse().unwrap(),
+            ]),
+            _ => Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+        let a = [Box::from("a.example.com")];
+
+        assert!(records_match(&a, &[ipv4], lookup));
+        assert!(records_match(&a, &[ipv4, ipv6], lookup));
+        assert!(!records_match(
+            &a,
+            &["2001:db8::2".parse().unwrap()],
+            lookup
+        ));
+        assert!(!records_match(&a, &[], lookup));
+
+        // An extra address has to go as well.
+        assert!(!records_match(
+            &[Box::from("b.example.com")],
+            &[ipv4],
+            lookup
+        ));
+        assert!(!records_match(
+            &[a[0].clone(), "c.example.com".into()],
+            &[ipv4],
+            lookup
+        ));
+    }
+}
@@ -0,0 +1,244 @@
+//! A minimal DNS client, only able to look up the A and AAAA records of a host
+//! through a single recursive resolver. It is used instead of the resolver of
+//! the system when `resolver` is set in the [general] section, see the config.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+const ATTEMPTS: usize = 3;
+
+/// Looks up the addresses of the host through `resolver` if it is set in
+/// [general], or through the resolver of the system otherwise.
+pub fn lookup(host: &str) -> io::Result<Vec<IpAddr>> {
+    // UNWRAP-SAFETY: The general config is set at startup.
+    match crate::GENERAL_CONFIG.get().unwrap().resolver {
+        Some(server) => resolve(server, host),
+        None => Ok((host, 0)
+            .to_socket_addrs()?
+            .map(|address| address.ip())
+            .collect()),
+    }
+}
+
+/// Looks up the addresses of the host, IPv4 ones first.
+pub fn resolve(server: SocketAddr, host: &str) -> io::Result<Vec<IpAddr>> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+
+    let mut addresses = query(&socket, host, TYPE_A)?;
+    addresses.extend(query(&socket, host, TYPE_AAAA)?);
+
+    if addresses.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no addresses", host),
+        ));
+    }
+
+    Ok(addresses)
+}
+
+fn query(socket: &UdpSocket, host: &str, qtype: u16) -> io::Result<Vec<IpAddr>> {
+    let mut id = [0u8; 2];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| io::Error::other("no random numbers available"))?;
+    let id = u16::from_be_bytes(id);
+
+    let request = build_query(id, host, qtype)?;
+    let mut buffer = [0u8; 1232];
+    let mut last_error = io::Error::new(io::ErrorKind::TimedOut, "DNS query timed out");
+
+    for _ in 0..ATTEMPTS {
+        socket.send(&request)?;
+
+        // Anything not answering our query (e.g. a late answer to a previous
+        // attempt) is ignored until the timeout.
+        loop {
+            let length = match socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(e) => {
+                    last_error = e;
+                    break;
+                }
+            };
+
+            if let Some(result) = parse_response(id, &buffer[..length]) {
+                return result;
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+fn build_query(id: u16, host: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut request = Vec::with_capacity(512);
+    request.extend(id.to_be_bytes());
+    request.extend([0x01, 0x00]); // A standard query, recursion desired.
+    request.extend([0, 1, 0, 0, 0, 0, 0, 0]); // One question, nothing else.
+
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid host name {}", host),
+            ));
+        }
+
+        request.push(label.len() as u8);
+        request.extend(label.as_bytes());
+    }
+
+    request.push(0);
+    request.extend(qtype.to_be_bytes());
+    request.extend(CLASS_IN.to_be_bytes());
+
+    Ok(request)
+}
+
+/// Returns None if the response does not belong to the query.
+fn parse_response(id: u16, response: &[u8]) -> Option<io::Result<Vec<IpAddr>>> {
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed DNS response");
+
+    if response.len() < 12 || response[0..2] != id.to_be_bytes() || response[2] & 0x80 == 0 {
+        return None;
+    }
+
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = response.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    match response[3] & 0x0F {
+        0 => (),
+        // NXDOMAIN simply means there are no addresses.
+        3 => return Some(Ok(Vec::new())),
+        rcode => {
+            let error = format!("DNS server returned error code {}", rcode);
+            return Some(Err(io::Error::other(error)));
+        }
+    }
+
+    if response[2] & 0x02 != 0 {
+        let error = "DNS response was truncated";
+        return Some(Err(io::Error::new(io::ErrorKind::InvalidData, error)));
+    }
+
+    // UNWRAP-SAFETY: The header is at least 12 bytes long.
+    let questions = u16_at(4).unwrap();
+    let answers = u16_at(6).unwrap();
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        let Some(end) = skip_name(response, offset) else {
+            return Some(Err(malformed()));
+        };
+        offset = end + 4;
+    }
+
+    let mut addresses = Vec::new();
+    for _ in 0..answers {
+        let Some(end) = skip_name(response, offset) else {
+            return Some(Err(malformed()));
+        };
+
+        let (Some(rtype), Some(length)) = (u16_at(end), u16_at(end + 8)) else {
+            return Some(Err(malformed()));
+        };
+
+        let start = end + 10;
+        let Some(data) = response.get(start..start + length as usize) else {
+            return Some(Err(malformed()));
+        };
+
+        // Other records, such as the CNAMEs leading to the addresses, are of
+        // no interest.
+        if let Ok(v4) = <[u8; 4]>::try_from(data) {
+            if rtype == TYPE_A {
+                addresses.push(IpAddr::from(v4));
+            }
+        } else if let Ok(v6) = <[u8; 16]>::try_from(data) {
+            if rtype == TYPE_AAAA {
+                addresses.push(IpAddr::from(v6));
+            }
+        }
+
+        offset = start + length as usize;
+    }
+
+    Some(Ok(addresses))
+}
+
+/// Returns the offset right after the (possibly compressed) name.
+fn skip_name(message: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(offset)?;
+
+        match length {
+            0 => return Some(offset + 1),
+            // A pointer to a name elsewhere, which always ends the name.
+            _ if length & 0xC0 == 0xC0 => return Some(offset + 2),
+            _ => offset += 1 + length as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query() {
+        let request = build_query(0x1234, "api.example.com", TYPE_A).unwrap();
+        assert_eq!(
+            request,
+            b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+              \x03api\x07example\x03com\x00\x00\x01\x00\x01"
+        );
+
+        assert!(build_query(0, "api..example.com", TYPE_A).is_err());
+    }
+
+    #[test]
+    fn response() {
+        let mut response = build_query(0x1234, "api.example.com", TYPE_A).unwrap();
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[7] = 2; // Two answers.
+
+        // api.example.com CNAME www.example.com
+        response.extend(b"\xC0\x0C\x00\x05\x00\x01\x00\x00\x0E\x10\x00\x06\x03www\xC0\x10");
+        // www.example.com A 192.0.2.1
+        response.extend(b"\xC0\x2D\x00\x01\x00\x01\x00\x00\x0E\x10\x00\x04\xC0\x00\x02\x01");
+
+        let addresses = parse_response(0x1234, &response).unwrap().unwrap();
+        assert_eq!(addresses, [IpAddr::from([192, 0, 2, 1])]);
+
+        // The answer to another query.
+        assert!(parse_response(0x4321, &response).is_none());
+
+        // The answer section is cut short.
+        let truncated = &response[..response.len() - 2];
+        assert!(parse_response(0x1234, truncated).unwrap().is_err());
+
+        // NXDOMAIN
+        response[3] = 0x83;
+        let addresses = parse_response(0x1234, &response).unwrap().unwrap();
+        assert!(addresses.is_empty());
+    }
+}
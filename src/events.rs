@@ -0,0 +1,224 @@
+//! A stream of events, one JSON object per line, for scripts to consume. See
+//! `events` in the [general] section of the config.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::SystemTime;
+
+use serde_derive::Serialize;
+
+use crate::log::warn;
+use crate::INSTANCE;
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// An IP has changed (and settled down, if `debounce` is set).
+    IpChanged {
+        ip: &'a str,
+        previous: Option<IpAddr>,
+        address: IpAddr,
+    },
+
+    /// A DDNS service was updated with the given addresses, which may be none
+    /// if the service reported that nothing had to be changed. `previous` are
+    /// the addresses the service was known to point to before.
+    ServiceUpdated {
+        service: &'a str,
+        provider: &'a str,
+        domains: &'a [Box<str>],
+        previous: &'a [IpAddr],
+        addresses: &'a [IpAddr],
+
+        /// None if the provider was not contacted at all.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        response: Option<ResponseSummary>,
+    },
+
+    ServiceFailed {
+        service: &'a str,
+        provider: &'a str,
+        domains: &'a [Box<str>],
+        previous: &'a [IpAddr],
+        error: String,
+
+        /// What went wrong in broad strokes, e.g. "auth" or "outage". See
+        /// `ErrorKind::name`.
+        kind: &'static str,
+
+        /// How long the provider asked to wait before the next request, in
+        /// seconds, if it was rate limited.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+
+        response: ResponseSummary,
+    },
+}
+
+/// What the provider answered during an update, in short.
+#[derive(Serialize, Debug)]
+pub struct ResponseSummary {
+    /// The number of HTTP requests made.
+    pub requests: u32,
+
+    /// The status of the last response, absent if no response was received.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_status: Option<u16>,
+}
+
+/// Every event is wrapped with the time it happened and the instance it
+/// comes from, in case several instances write to the same pipe.
+#[derive(Serialize)]
+struct Line<'a> {
+    timestamp: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<&'a str>,
+
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+pub enum EventStream {
+    Disabled,
+    Stdout,
+
+    /// A named pipe or a regular file, which the events are appended to.
+    File(Box<str>),
+}
+
+impl EventStream {
+    /// "" disables the stream, "-" writes to stdout, anything else is the path
+    /// of the file to write to.
+    pub fn new(target: &str) -> Self {
+        match target {
+            "" => EventStream::Disabled,
+            "-" => EventStream::Stdout,
+            path => EventStream::File(path.into()),
+        }
+    }
+
+    pub fn emit(&self, event: Event) {
+        if let EventStream::Disabled = self {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let line = Line {
+            timestamp,
+            instance: INSTANCE.get().map(|instance| &**instance),
+            event,
+        };
+
+        // UNWRAP-SAFETY: The events only contain strings and numbers.
+        let mut line = serde_json::to_string(&line).unwrap();
+        line.push('\n');
+
+        let result = match self {
+            EventStream::Disabled => Ok(()),
+            EventStream::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+            EventStream::File(path) => write_to_file(path, &line),
+        };
+
+        if let Err(e) = result {
+            warn!("Unable to write event, reason: {}", e);
+        }
+    }
+}
+
+/// The file is opened anew for every event, so that a reader of a named pipe
+/// can come and go as it likes. The pipe is opened without blocking: if
+/// nobody is reading from it, the event is dropped instead of stalling the
+/// daemon.
+fn write_to_file(path: &str, line: &str) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path);
+
+    match file {
+        Ok(mut file) => file.write_all(line.as_bytes()),
+
+        // A named pipe without any reader.
+        Err(e) if e.raw_os_error() == Some(libc::ENXIO) => Ok(()),
+
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events() {
+        let path = std::env::temp_dir().join(format!("dynners-events-{}", std::process::id()));
+        let stream = EventStream::new(&path.to_string_lossy());
+
+        let address = "192.0.2.1".parse().unwrap();
+        let previous = "192.0.2.2".parse().unwrap();
+        stream.emit(Event::IpChanged {
+            ip: "v4",
+            previous: Some(previous),
+            address,
+        });
+        stream.emit(Event::ServiceUpdated {
+            service: "ddns",
+            provider: "DuckDNS",
+            domains: &["example.duckdns.org".into()],
+            previous: &[previous],
+            addresses: &[address],
+            response: Some(ResponseSummary {
+                requests: 1,
+                last_status: Some(200),
+            }),
+        });
+        stream.emit(Event::ServiceFailed {
+            service: "ddns",
+            provider: "DuckDNS",
+            domains: &["example.duckdns.org".into()],
+            previous: &[address],
+            error: String::from("DuckDNS returned error: ..."),
+            kind: "rate_limited",
+            retry_after: Some(30),
+            response: ResponseSummary {
+                requests: 1,
+                last_status: Some(429),
+            },
+        });
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines = written
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["event"], "ip_changed");
+        assert_eq!(lines[0]["ip"], "v4");
+        assert_eq!(lines[0]["address"], "192.0.2.1");
+        assert!(lines[0]["timestamp"].is_u64());
+        assert!(lines[0].get("instance").is_none());
+        assert_eq!(lines[1]["event"], "service_updated");
+        assert_eq!(lines[0]["previous"], "192.0.2.2");
+        assert_eq!(lines[1]["addresses"][0], "192.0.2.1");
+        assert_eq!(lines[1]["previous"][0], "192.0.2.2");
+        assert_eq!(lines[1]["domains"][0], "example.duckdns.org");
+        assert_eq!(
+            lines[1]["response"],
+            serde_json::json!({ "requests": 1, "last_status": 200 })
+        );
+        assert_eq!(lines[2]["event"], "service_failed");
+        assert_eq!(lines[2]["kind"], "rate_limited");
+        assert_eq!(lines[2]["retry_after"], 30);
+    }
+}
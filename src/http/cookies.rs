@@ -0,0 +1,100 @@
+//! Keeps the cookies of a session, for the few providers which have no real
+//! API and are driven through their website instead. Only the name and value
+//! of each cookie are kept: the session is short-lived and only ever sent to
+//! the site which set it, so expiry, paths and domains are not looked at.
+
+use super::Response;
+use crate::util::Secret;
+
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Vec<(Box<str>, Secret<Box<str>>)>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the cookies set by the response, replacing the ones of the same
+    /// name. A cookie set to an empty value is removed.
+    pub fn store(&mut self, response: &Response) {
+        let set_cookies = response
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("Set-Cookie"));
+
+        for (_, set_cookie) in set_cookies {
+            // e.g. "session=abc; path=/; HttpOnly", only the first part of
+            // which is sent back.
+            let pair = set_cookie.split(';').next().unwrap_or("");
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+
+            let (name, value) = (name.trim(), value.trim());
+            self.cookies.retain(|(existing, _)| &**existing != name);
+
+            if !value.is_empty() {
+                self.cookies.push((name.into(), Secret::new(value.into())));
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies
+            .iter()
+            .find(|(existing, _)| &**existing == name)
+            .map(|(_, value)| &**value.expose())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+    }
+
+    /// Returns the value of the Cookie header carrying all of the cookies,
+    /// e.g. "a=1; b=2".
+    pub fn header(&self) -> String {
+        self.cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value.expose()))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn response(set_cookies: &[&str]) -> Response {
+        Response {
+            status: 200,
+            headers: set_cookies
+                .iter()
+                .map(|cookie| ("set-cookie".into(), (*cookie).into()))
+                .collect(),
+            reader: Box::new(Cursor::new(Vec::new())),
+        }
+    }
+
+    #[test]
+    fn cookie_jar() {
+        let mut jar = CookieJar::new();
+        assert!(jar.is_empty());
+
+        jar.store(&response(&["a=1; path=/; HttpOnly", "b=2"]));
+        assert_eq!(jar.header(), "a=1; b=2");
+
+        jar.store(&response(&["a=3; Secure", "b=; Max-Age=0", "malformed"]));
+        assert_eq!(jar.header(), "a=3");
+        assert_eq!(jar.get("a"), Some("3"));
+        assert_eq!(jar.get("b"), None);
+    }
+}
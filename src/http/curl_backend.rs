@@ -1,113 +1,221 @@
+use std::fmt::Debug;
 use std::io::Cursor;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use curl::easy::{Easy, List};
 use serde::Serialize;
 
-use crate::GENERAL_CONFIG;
+use super::diagnostics::{capture, Exchange};
+#[cfg(any(test, feature = "mock"))]
+use super::mock_backend;
+use super::{host_limit, resolve_override, Error, Response, Settings};
 
-use super::{Error, Response};
-
-pub struct Request {
-    curl: Easy,
-    header_list: List,
-    url: Box<str>,
-    queries: String,
+/// A HTTP client backed by a single curl handle. The handle is reset (but not
+/// destroyed) between requests, so that its connection cache survives and the
+/// connections to the same host are reused.
+#[derive(Clone)]
+pub struct Client {
+    curl: Arc<Mutex<Easy>>,
+    settings: Arc<Settings>,
+    timeout: Option<Duration>,
 }
 
-impl Request {
-    pub fn get(url: &str) -> Self {
-        let mut curl = Easy::new();
-        // UNWRAP-SAFETY: HTTP is supported. And we are already screwed if it isn't...
-        curl.get(true).unwrap();
-        curl.useragent(&GENERAL_CONFIG.get().unwrap().user_agent)
-            .expect("out of memory");
-
+impl Client {
+    pub fn new(settings: &Settings) -> Self {
         Self {
-            curl,
-            header_list: List::new(),
-            url: url.into(),
-            queries: String::new(),
+            curl: Arc::new(Mutex::new(Easy::new())),
+            settings: Arc::new(settings.clone()),
+            timeout: None,
         }
     }
 
-    pub fn post(url: &str) -> Self {
-        let mut curl = Easy::new();
-        // UNWRAP-SAFETY: HTTP is supported.
-        curl.post(true).unwrap();
-        curl.useragent(&GENERAL_CONFIG.get().unwrap().user_agent)
-            .expect("out of memory");
-
+    /// Creates a client whose requests fail once they take longer than
+    /// `timeout` in total.
+    pub fn with_timeout(settings: &Settings, timeout: Duration) -> Self {
         Self {
-            curl,
-            header_list: List::new(),
-            url: url.into(),
-            queries: String::new(),
+            timeout: Some(timeout),
+            ..Self::new(settings)
         }
     }
 
-    pub fn put(url: &str) -> Self {
-        let mut curl = Easy::new();
-        // UNWRAP-SAFETY: HTTP is supported. And we are already screwed if it isn't...
-        curl.put(true).unwrap();
-        curl.useragent(&GENERAL_CONFIG.get().unwrap().user_agent)
-            .expect("out of memory");
+    pub fn get(&self, url: &str) -> Request {
+        Request::new(self.clone(), "GET", url)
+    }
+
+    pub fn post(&self, url: &str) -> Request {
+        Request::new(self.clone(), "POST", url)
+    }
+
+    pub fn put(&self, url: &str) -> Request {
+        Request::new(self.clone(), "PUT", url)
+    }
+
+    pub fn patch(&self, url: &str) -> Request {
+        Request::new(self.clone(), "PATCH", url)
+    }
+}
 
+impl Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client").finish_non_exhaustive()
+    }
+}
+
+pub struct Request {
+    client: Client,
+    method: &'static str,
+    headers: Vec<(Box<str>, Box<str>)>,
+    url: Box<str>,
+    queries: Vec<(Box<str>, Box<str>)>,
+    body: Option<Vec<u8>>,
+}
+
+impl Request {
+    fn new(client: Client, method: &'static str, url: &str) -> Self {
         Self {
-            curl,
-            header_list: List::new(),
+            client,
+            method,
+            headers: Vec::new(),
             url: url.into(),
-            queries: String::new(),
+            queries: Vec::new(),
+            body: None,
         }
     }
 
     pub fn query(mut self, param: &str, value: &str) -> Self {
-        if self.queries.is_empty() {
-            self.queries = self.queries + "?" + param + "=" + value;
-        } else {
-            self.queries = self.queries + "&" + param + "=" + value;
-        }
-
+        self.queries.push((param.into(), value.into()));
         self
     }
 
     pub fn set(mut self, header: &str, value: &str) -> Self {
-        let header = String::from(header) + ": " + value;
-        self.header_list.append(&header).expect("out of memory");
+        self.headers.push((header.into(), value.into()));
         self
     }
 
     pub fn send_json(mut self, data: impl Serialize) -> Result<Response, Error> {
-        let mut request = serde_json::to_vec(&data)
-            .expect("unable to serialize data into JSON string")
-            .into_iter();
-
-        self.curl
-            .read_function(move |dest| {
-                let to_write = dest.len();
-                let actual_written = request.len().min(to_write);
-
-                request
-                    .by_ref()
-                    .take(actual_written)
-                    .enumerate()
-                    .for_each(|(i, byte)| dest[i] = byte);
-
-                Ok(actual_written)
-            })
-            .unwrap(); // UNWRAP-SAFETY: This is always CURLE_OK.
+        let request = serde_json::to_vec(&data).expect("unable to serialize data into JSON string");
+
+        self.headers
+            .push(("Content-Type".into(), "application/json".into()));
+        self.body = Some(request);
 
         self.call()
     }
 
-    pub fn call(mut self) -> Result<Response, Error> {
-        let url = String::from(self.url) + &self.queries;
-        self.curl.url(&url).expect("out of memory");
+    /// Sends the data as the body as-is. The Content-Type header is up to the
+    /// caller.
+    pub fn send_string(mut self, data: &str) -> Result<Response, Error> {
+        self.body = Some(data.as_bytes().to_vec());
+        self.call()
+    }
+
+    pub fn call(self) -> Result<Response, Error> {
+        let settings = self.client.settings.clone();
+        let _permit = host_limit::acquire(&self.url, settings.max_connections_per_host);
+
+        // A poisoned handle is still perfectly usable, it is reset anyway.
+        let mut curl = self
+            .client
+            .curl
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        curl.reset();
+
+        let mut url = String::from(self.url);
+        for (i, (param, value)) in self.queries.iter().enumerate() {
+            url += if i == 0 && !url.contains('?') {
+                "?"
+            } else {
+                "&"
+            };
+            url += &curl.url_encode(param.as_bytes());
+            url += "=";
+            url += &curl.url_encode(value.as_bytes());
+        }
+
+        let mut exchange = Exchange::new(&settings, self.method, &url);
+        if let Some(exchange) = exchange.as_mut() {
+            for (name, value) in &self.headers {
+                exchange.header(name, value);
+            }
+
+            if let Some(body) = &self.body {
+                exchange.body(body);
+            }
+        }
+
+        #[cfg(any(test, feature = "mock"))]
+        if let Some(result) = mock_backend::intercept(self.method, &url, self.body.as_deref()) {
+            return capture(exchange, result);
+        }
+
+        // libcurl races the IPv6 and IPv4 addresses of the host by itself
+        // (Happy Eyeballs), including the ones given below.
+        if let Some((host, port)) = host_and_port(&url) {
+            match resolve_override(&settings, host, port) {
+                Some(Ok(addresses)) => {
+                    let addresses = addresses
+                        .iter()
+                        .map(|address| match address {
+                            SocketAddr::V4(v4) => v4.ip().to_string(),
+                            SocketAddr::V6(v6) => format!("[{}]", v6.ip()),
+                        })
+                        .collect::<Vec<_>>();
+
+                    let mut resolve = List::new();
+                    resolve
+                        .append(&format!("{}:{}:{}", host, port, addresses.join(",")))
+                        .expect("out of memory");
+
+                    // UNWRAP-SAFETY: This is always CURLE_OK.
+                    curl.resolve(resolve).unwrap();
+                }
+
+                Some(Err(e)) => {
+                    let error = format!("Could not resolve host: {} ({})", host, e);
+                    return capture(exchange, Err(Error::Transport(error.into())));
+                }
+
+                None => (),
+            }
+        }
+
+        curl.url(&url).expect("out of memory");
+        curl.useragent(&settings.user_agent).expect("out of memory");
+
+        if let Some(timeout) = self.client.timeout {
+            // UNWRAP-SAFETY: This is always CURLE_OK.
+            curl.timeout(timeout).unwrap();
+        }
+
+        // UNWRAP-SAFETY: HTTP is supported. And we are already screwed if it isn't...
+        if self.method == "GET" {
+            curl.get(true).unwrap();
+        } else {
+            curl.post(true).unwrap();
+            curl.post_fields_copy(self.body.as_deref().unwrap_or_default())
+                .expect("out of memory");
+
+            if self.method != "POST" {
+                curl.custom_request(self.method).expect("out of memory");
+            }
+        }
+
+        let mut header_list = List::new();
+        for (name, value) in &self.headers {
+            let header = String::from(&**name) + ": " + value;
+            header_list.append(&header).expect("out of memory");
+        }
 
         // UNWRAP-SAFETY: HTTP is supported.
-        self.curl.http_headers(self.header_list).unwrap();
+        curl.http_headers(header_list).unwrap();
 
         let mut response = Vec::with_capacity(1024);
-        let mut transfer = self.curl.transfer();
+        let mut headers = Vec::new();
+        let mut transfer = curl.transfer();
 
         transfer
             .write_function(|src| {
@@ -116,23 +224,62 @@ impl Request {
             })
             .unwrap(); // UNWRAP-SAFETY: This is always CURLE_OK.
 
+        // The status line and the empty line ending the headers are passed in
+        // as well, neither of which contains a colon.
+        transfer
+            .header_function(|line| {
+                let line = String::from_utf8_lossy(line);
+                if let Some((name, value)) = line.split_once(':') {
+                    headers.push((name.trim().into(), value.trim().into()));
+                }
+                true
+            })
+            .unwrap(); // UNWRAP-SAFETY: This is always CURLE_OK.
+
         if let Err(err) = transfer.perform() {
-            return Err(Error::Transport(err.description().into()));
+            return capture(exchange, Err(Error::Transport(err.description().into())));
         };
 
         drop(transfer);
 
+        // UNWRAP-SAFETY: The only error condition is when the curl version
+        //                is too old. Let's just not support that.
+        let response_code = curl.response_code().unwrap();
+
         let response = Response {
+            status: response_code as u16,
+            headers,
             reader: Box::new(Cursor::new(response)),
         };
 
-        // UNWRAP-SAFETY: The only error condition is when the curl version
-        //                is too old. Let's just not support that.
-        let response_code = self.curl.response_code().unwrap();
         if response_code >= 400 {
-            return Err(Error::Status(response_code as u16, response));
+            return capture(exchange, Err(Error::Status(response_code as u16, response)));
         };
 
-        Ok(response)
+        capture(exchange, Ok(response))
+    }
+}
+
+/// Extracts the host and the port from the URL, so that they can be resolved
+/// by `resolve_override`.
+fn host_and_port(url: &str) -> Option<(&str, u16)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, a)| a);
+
+    let default_port = if scheme == "https" { 443 } else { 80 };
+
+    // An IPv6 address is enclosed in brackets, colons and all.
+    let (host, port) = match authority.rfind(']') {
+        Some(end) => (&authority[..=end], &authority[end + 1..]),
+        None => match authority.rsplit_once(':') {
+            Some((host, _)) => (host, &authority[host.len()..]),
+            None => (authority, ""),
+        },
+    };
+
+    match port.strip_prefix(':') {
+        Some(port) => Some((host, port.parse().ok()?)),
+        None => Some((host, default_port)),
     }
 }
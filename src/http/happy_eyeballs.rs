@@ -0,0 +1,160 @@
+//! Dual-stack connection racing ("Happy Eyeballs", RFC 8305), so that a host
+//! with broken IPv6 (or an API with a broken IPv6 endpoint) does not stall
+//! every request until the connection attempt times out.
+//!
+//! libcurl already does this by itself. ureq, on the other hand, tries the
+//! addresses one after another and does not let us make the connection for
+//! it, so its resolver races them instead and puts the address which answered
+//! first at the front. The family which won is remembered for a while, so
+//! that the race is not run again on every request to the same host.
+
+use std::collections::BTreeMap;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an attempt is given before the next address is tried alongside.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// How long the race may take in total, after which the addresses are left to
+/// the HTTP client in their interleaved order.
+const RACE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the family which won a race is preferred for, before the host is
+/// raced again.
+const WINNER_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+/// Whether IPv6 won the last race to each host, and when.
+static WINNERS: Mutex<BTreeMap<Box<str>, (bool, Instant)>> = Mutex::new(BTreeMap::new());
+
+/// Returns the addresses of the host with IPv4 and IPv6 interleaved, starting
+/// with the family which connected first. Addresses of a single family are
+/// returned as-is.
+pub fn order(host: &str, addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first_ipv6) = addresses.first().map(SocketAddr::is_ipv6) else {
+        return addresses;
+    };
+
+    if addresses.iter().all(|a| a.is_ipv6() == first_ipv6) {
+        return addresses;
+    }
+
+    // The entries are always left consistent, so a poisoned lock is fine.
+    let mut winners = WINNERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if let Some((ipv6, since)) = winners.get(host) {
+        if since.elapsed() < WINNER_LIFETIME {
+            return interleave(addresses, *ipv6);
+        }
+    }
+
+    drop(winners);
+
+    let mut ordered = interleave(addresses, first_ipv6);
+
+    if let Some(winner) = race(&ordered) {
+        ordered.retain(|address| *address != winner);
+        ordered.insert(0, winner);
+
+        winners = WINNERS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        winners.insert(host.into(), (winner.is_ipv6(), Instant::now()));
+    }
+
+    ordered
+}
+
+/// Alternates between the two families, starting with IPv6 if asked to.
+fn interleave(addresses: Vec<SocketAddr>, ipv6_first: bool) -> Vec<SocketAddr> {
+    let (preferred, other): (Vec<SocketAddr>, Vec<SocketAddr>) = addresses
+        .iter()
+        .copied()
+        .partition(|address| address.is_ipv6() == ipv6_first);
+
+    let mut ordered = Vec::with_capacity(addresses.len());
+    let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+
+    ordered
+}
+
+/// Connects to the addresses in order, each one `ATTEMPT_DELAY` after the
+/// previous one, and returns the first which answers.
+fn race(ordered: &[SocketAddr]) -> Option<SocketAddr> {
+    let (sender, receiver) = mpsc::channel();
+    let deadline = Instant::now() + RACE_TIMEOUT;
+    let mut started = 0;
+    let mut pending = 0;
+
+    loop {
+        if let Some(&address) = ordered.get(started) {
+            let sender = sender.clone();
+
+            // The connection only tells which address works, it is closed
+            // right away. An attempt which hangs ends on its own at the
+            // timeout, nobody waits for it.
+            thread::spawn(move || {
+                let connected = TcpStream::connect_timeout(&address, RACE_TIMEOUT).is_ok();
+                let _ = sender.send((address, connected));
+            });
+
+            started += 1;
+            pending += 1;
+        } else if pending == 0 {
+            return None;
+        }
+
+        let wait = if started < ordered.len() {
+            ATTEMPT_DELAY
+        } else {
+            deadline.saturating_duration_since(Instant::now())
+        };
+
+        match receiver.recv_timeout(wait) {
+            Ok((address, true)) => return Some(address),
+
+            // A failed attempt makes way for the next one immediately.
+            Ok((_, false)) => pending -= 1,
+
+            Err(_) if started < ordered.len() => (),
+            Err(_) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn racing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+
+        // Nothing listens on this port (or IPv6 is not available at all),
+        // either way the connection fails.
+        let closed = TcpListener::bind("[::1]:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap_or_else(|_| "[::1]:9".parse().unwrap());
+
+        let other = "127.0.0.2:80".parse().unwrap();
+
+        assert_eq!(order("racing.invalid", vec![closed, open]), [open, closed]);
+        assert_eq!(order("single.invalid", vec![open, other]), [open, other]);
+
+        // The winner is remembered, the addresses are not raced again.
+        drop(listener);
+        assert_eq!(order("racing.invalid", vec![closed, open]), [open, closed]);
+        assert_eq!(order("lost.invalid", vec![closed, open]), [closed, open]);
+    }
+}
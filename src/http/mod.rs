@@ -3,23 +3,108 @@
 #[error("The features `curl` and `ureq` must not be enabled together!")]
 const FORCED_ERROR: u8 = 1 / 0;
 
+// The mock backend answers the requests of the real ones from fixtures, for
+// the threads of the tests which added any, or for every request with the
+// `mock` feature.
+#[cfg(any(test, feature = "mock"))]
+mod mock_backend;
+
 #[cfg(feature = "curl")]
 mod curl_backend;
 
 #[cfg(feature = "ureq")]
 mod ureq_backend;
 
+mod cookies;
+mod diagnostics;
+#[cfg(feature = "ureq")]
+mod happy_eyeballs;
+mod host_limit;
+
+use std::collections::HashMap;
 use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
 
 use serde::de::DeserializeOwned;
 
+#[cfg(test)]
+pub use mock_backend::{add_fixtures, take_sent};
+
 #[cfg(feature = "curl")]
-pub use curl_backend::Request;
+pub use curl_backend::Client;
 
 #[cfg(feature = "ureq")]
-pub use ureq_backend::Request;
+pub use ureq_backend::Client;
+
+pub use cookies::CookieJar;
+pub use diagnostics::{
+    clear_last_exchange, register_secret, restore_secrets, take_last_exchange, take_last_status,
+    take_request_count, take_secrets,
+};
+
+pub const DEFAULT_USER_AGENT: &str =
+    concat!("github.com/hch12907/dynners ", env!("CARGO_PKG_VERSION"));
+
+/// The options of [general] which the HTTP clients follow. Every client is
+/// given its own copy when it is created, so that the clients work the same
+/// whether a config file was read or not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub user_agent: Box<str>,
+    pub pins: HashMap<Box<str>, IpAddr>,
+    pub resolver: Option<SocketAddr>,
+
+    /// 0 means no limit, see `host_limit`.
+    pub max_connections_per_host: u32,
+
+    /// Whether the last exchange is kept for the diagnostics directory.
+    pub capture_exchanges: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            user_agent: DEFAULT_USER_AGENT.into(),
+            pins: HashMap::new(),
+            resolver: None,
+            max_connections_per_host: host_limit::DEFAULT_LIMIT,
+            capture_exchanges: false,
+        }
+    }
+}
+
+/// Looks up the host as configured by `pins` and `resolver` in the settings.
+/// Returns None if the resolver of the system is to be used instead.
+pub fn resolve_override(
+    settings: &Settings,
+    host: &str,
+    port: u16,
+) -> Option<io::Result<Vec<SocketAddr>>> {
+    // An IPv6 address within a URL is enclosed in brackets.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    if host.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+
+    if let Some(ip) = settings.pins.get(host) {
+        return Some(Ok(vec![SocketAddr::new(*ip, port)]));
+    }
+
+    let server = settings.resolver?;
+    let result = crate::dns::resolve(server, host).map(|addresses| {
+        addresses
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect()
+    });
+
+    Some(result)
+}
 
 pub struct Response {
+    pub(self) status: u16,
+    pub(self) headers: Vec<(Box<str>, Box<str>)>,
     pub(self) reader: Box<dyn Read>,
 }
 
@@ -29,6 +114,19 @@ pub enum Error {
 }
 
 impl Response {
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the value of the first header with the given name, which is
+    /// matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &**value)
+    }
+
     pub fn into_json<T: DeserializeOwned>(self) -> Result<T, io::Error> {
         serde_json::from_reader(self.reader)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
@@ -41,3 +139,99 @@ impl Response {
         String::from_utf8(vec).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 }
+
+/// Answers a single request on a local port with the response as-is, and
+/// returns the port along with the request received, for testing the real
+/// backends without the mock.
+#[cfg(test)]
+fn serve_once(response: &'static str) -> (u16, std::thread::JoinHandle<String>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        let mut length = 0;
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+
+            request += &line;
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+        }
+
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        request += &String::from_utf8(body).unwrap();
+
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        request
+    });
+
+    (port, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend() {
+        let (port, server) = serve_once(
+            "HTTP/1.1 201 Created\r\nX-Test: yes\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        );
+
+        // The host does not exist, it is only reachable through the pin.
+        let settings = Settings {
+            pins: [("dynners.invalid".into(), "127.0.0.1".parse().unwrap())].into(),
+            ..Settings::default()
+        };
+
+        let url = format!("http://dynners.invalid:{}/update", port);
+        let response = Client::new(&settings)
+            .post(&url)
+            .query("host", "a.example.com")
+            .set("X-Token", "secret")
+            .send_string("ip=192.0.2.1");
+
+        let Ok(response) = response else {
+            panic!("expected a response");
+        };
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.header("x-test"), Some("yes"));
+        assert_eq!(response.into_string().unwrap(), "ok");
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /update?host=a.example.com HTTP/1.1\r\n"));
+        assert!(request.contains("X-Token: secret\r\n"));
+        assert!(request.ends_with("\r\n\r\nip=192.0.2.1"));
+    }
+
+    #[test]
+    fn backend_status() {
+        let (port, server) = serve_once(
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope",
+        );
+
+        let url = format!("http://127.0.0.1:{}/", port);
+        let response = Client::new(&Settings::default()).get(&url).call();
+
+        let Err(Error::Status(404, response)) = response else {
+            panic!("expected a 404 response");
+        };
+        assert_eq!(response.into_string().unwrap(), "nope");
+        server.join().unwrap();
+    }
+}
@@ -1,29 +1,83 @@
-use serde::Serialize;
+use std::fmt::Debug;
+use std::io::{self, Cursor, Read};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::GENERAL_CONFIG;
+use serde::Serialize;
 
-use super::{Error, Response};
+use super::diagnostics::{capture, Exchange};
+#[cfg(any(test, feature = "mock"))]
+use super::mock_backend;
+use super::{happy_eyeballs, host_limit, resolve_override, Error, Response, Settings};
 
-pub struct Request {
-    inner: ureq::Request,
+/// A HTTP client backed by a ureq agent. The agent keeps a pool of
+/// connections, so that requests to the same host can reuse them.
+#[derive(Clone)]
+pub struct Client {
+    agent: ureq::Agent,
+    settings: Arc<Settings>,
 }
 
-impl Request {
-    pub fn get(url: &str) -> Self {
-        let inner = ureq::get(url).set("User-Agent", &GENERAL_CONFIG.get().unwrap().user_agent);
-        Self { inner }
+impl Client {
+    pub fn new(settings: &Settings) -> Self {
+        Self::with_builder(settings, ureq::AgentBuilder::new())
+    }
+
+    /// Creates a client whose requests fail once they take longer than
+    /// `timeout` in total.
+    pub fn with_timeout(settings: &Settings, timeout: Duration) -> Self {
+        Self::with_builder(settings, ureq::AgentBuilder::new().timeout(timeout))
+    }
+
+    fn with_builder(settings: &Settings, builder: ureq::AgentBuilder) -> Self {
+        let settings = Arc::new(settings.clone());
+        let resolver_settings = settings.clone();
+
+        let agent = builder
+            .user_agent(&settings.user_agent)
+            .resolver(move |address: &str| resolve(&resolver_settings, address))
+            .build();
+
+        Self { agent, settings }
+    }
+
+    fn request(&self, inner: ureq::Request) -> Request {
+        Request {
+            inner,
+            settings: self.settings.clone(),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Request {
+        self.request(self.agent.get(url))
     }
 
-    pub fn post(url: &str) -> Self {
-        let inner = ureq::post(url).set("User-Agent", &GENERAL_CONFIG.get().unwrap().user_agent);
-        Self { inner }
+    pub fn post(&self, url: &str) -> Request {
+        self.request(self.agent.post(url))
     }
 
-    pub fn put(url: &str) -> Self {
-        let inner = ureq::put(url).set("User-Agent", &GENERAL_CONFIG.get().unwrap().user_agent);
-        Self { inner }
+    pub fn put(&self, url: &str) -> Request {
+        self.request(self.agent.put(url))
     }
 
+    pub fn patch(&self, url: &str) -> Request {
+        self.request(self.agent.request("PATCH", url))
+    }
+}
+
+impl Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client").finish_non_exhaustive()
+    }
+}
+
+pub struct Request {
+    inner: ureq::Request,
+    settings: Arc<Settings>,
+}
+
+impl Request {
     pub fn query(mut self, param: &str, value: &str) -> Self {
         self.inner = self.inner.query(param, value);
         self
@@ -34,37 +88,139 @@ impl Request {
         self
     }
 
+    #[cfg(any(test, feature = "mock"))]
+    fn intercept(&self, body: Option<&[u8]>) -> Option<Result<Response, Error>> {
+        mock_backend::intercept(self.inner.method(), self.inner.url(), body)
+    }
+
+    fn exchange(&self) -> Option<Exchange> {
+        let mut exchange = Exchange::new(&self.settings, self.inner.method(), self.inner.url())?;
+
+        for name in self.inner.header_names() {
+            for value in self.inner.all(&name) {
+                exchange.header(&name, value);
+            }
+        }
+
+        Some(exchange)
+    }
+
     pub fn send_json(self, data: impl Serialize) -> Result<Response, Error> {
-        self.inner
+        let mut exchange = self.exchange();
+
+        if let Some(exchange) = exchange.as_mut() {
+            exchange.body(&serde_json::to_vec(&data).unwrap_or_default());
+        }
+
+        let _permit = host_limit::acquire(self.inner.url(), self.settings.max_connections_per_host);
+
+        #[cfg(any(test, feature = "mock"))]
+        {
+            let body = serde_json::to_vec(&data).unwrap_or_default();
+            if let Some(result) = self.intercept(Some(&body)) {
+                return capture(exchange, result);
+            }
+        }
+
+        let result = self
+            .inner
             .send_json(data)
-            .map_err(|e| match e {
-                ureq::Error::Status(code, resp) => Error::Status(
-                    code,
-                    Response {
-                        reader: resp.into_reader(),
-                    },
-                ),
-                ureq::Error::Transport(tp) => Error::Transport(tp.to_string().into()),
-            })
-            .map(|resp| Response {
-                reader: resp.into_reader(),
-            })
+            .map_err(map_error)
+            .and_then(map_response);
+
+        capture(exchange, result)
+    }
+
+    /// Sends the data as the body as-is. The Content-Type header is up to the
+    /// caller.
+    pub fn send_string(self, data: &str) -> Result<Response, Error> {
+        let mut exchange = self.exchange();
+
+        if let Some(exchange) = exchange.as_mut() {
+            exchange.body(data.as_bytes());
+        }
+
+        let _permit = host_limit::acquire(self.inner.url(), self.settings.max_connections_per_host);
+
+        #[cfg(any(test, feature = "mock"))]
+        if let Some(result) = self.intercept(Some(data.as_bytes())) {
+            return capture(exchange, result);
+        }
+
+        let result = self
+            .inner
+            .send_string(data)
+            .map_err(map_error)
+            .and_then(map_response);
+
+        capture(exchange, result)
     }
 
     pub fn call(self) -> Result<Response, Error> {
-        self.inner
-            .call()
-            .map_err(|e| match e {
-                ureq::Error::Status(code, resp) => Error::Status(
-                    code,
-                    Response {
-                        reader: resp.into_reader(),
-                    },
-                ),
-                ureq::Error::Transport(tp) => Error::Transport(tp.to_string().into()),
-            })
-            .map(|resp| Response {
-                reader: resp.into_reader(),
-            })
+        let exchange = self.exchange();
+        let _permit = host_limit::acquire(self.inner.url(), self.settings.max_connections_per_host);
+
+        #[cfg(any(test, feature = "mock"))]
+        if let Some(result) = self.intercept(None) {
+            return capture(exchange, result);
+        }
+
+        let result = self.inner.call().map_err(map_error).and_then(map_response);
+
+        capture(exchange, result)
+    }
+}
+
+/// Resolves "host:port" as ureq wants it to, honouring `pins` and `resolver`.
+/// The addresses are ordered by `happy_eyeballs`.
+fn resolve(settings: &Settings, address: &str) -> io::Result<Vec<SocketAddr>> {
+    let Some((host, port)) = address.rsplit_once(':') else {
+        return address.to_socket_addrs().map(Iterator::collect);
+    };
+
+    let port = port
+        .parse::<u16>()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid port"))?;
+
+    let addresses = match resolve_override(settings, host, port) {
+        Some(result) => result?,
+        None => address.to_socket_addrs()?.collect(),
+    };
+
+    Ok(happy_eyeballs::order(host, addresses))
+}
+
+/// The body is read into memory right away, as the connection stays open until
+/// it is, and the request must not give back its `host_limit` permit before.
+fn map_response(response: ureq::Response) -> Result<Response, Error> {
+    let mut headers = Vec::new();
+
+    for name in response.headers_names() {
+        for value in response.all(&name) {
+            headers.push((name.as_str().into(), value.into()));
+        }
+    }
+
+    let status = response.status();
+    let mut body = Vec::with_capacity(1024);
+    response
+        .into_reader()
+        .read_to_end(&mut body)
+        .map_err(|e| Error::Transport(e.to_string().into()))?;
+
+    Ok(Response {
+        status,
+        headers,
+        reader: Box::new(Cursor::new(body)),
+    })
+}
+
+fn map_error(error: ureq::Error) -> Error {
+    match error {
+        ureq::Error::Status(code, resp) => match map_response(resp) {
+            Ok(resp) => Error::Status(code, resp),
+            Err(e) => e,
+        },
+        ureq::Error::Transport(tp) => Error::Transport(tp.to_string().into()),
     }
 }
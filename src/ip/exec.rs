@@ -1,24 +1,249 @@
 use std::ffi::OsString;
+use std::io::Read;
 use std::net::AddrParseError;
-use std::os::unix::prelude::OsStringExt;
-use std::process::Command;
+use std::os::unix::prelude::{CommandExt, OsStringExt};
+use std::process::{Child, Command, Stdio};
 use std::str::FromStr;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::GENERAL_CONFIG;
+#[cfg(feature = "regex")]
+use regex::Regex;
 
-pub(super) fn execute_command_for_ip<T>(command: &str) -> Result<T, String>
+use crate::config::ExecStream;
+
+/// How often we check whether the child process has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Decides what counts as a successful run of the command, and where the
+/// address is found in its output.
+#[derive(Debug, Clone)]
+pub struct ExecOptions {
+    /// The shell the command line is given to with `-c`.
+    pub(super) shell: Box<str>,
+    pub(super) stream: ExecStream,
+    pub(super) exit_code: Option<i32>,
+
+    #[cfg(feature = "regex")]
+    pub(super) regex: Option<Regex>,
+}
+
+impl Default for ExecOptions {
+    fn default() -> Self {
+        Self {
+            shell: "/bin/sh".into(),
+            stream: ExecStream::default(),
+            exit_code: None,
+
+            #[cfg(feature = "regex")]
+            regex: None,
+        }
+    }
+}
+
+pub(super) fn execute_command_for_ip<T>(
+    command_line: &str,
+    timeout: Option<Duration>,
+    options: &ExecOptions,
+) -> Result<T, String>
 where
     T: FromStr<Err = AddrParseError>,
 {
-    let process = Command::new(GENERAL_CONFIG.get().unwrap().shell.as_ref())
-        .arg("-c")
-        .arg(command)
-        .output()
-        .map_err(|e| e.to_string())?;
+    let mut command = Command::new(&*options.shell);
+    command.arg("-c").arg(command_line).stdin(Stdio::null());
+
+    // The command gets a process group of its own, so that whatever it starts
+    // is killed along with it once it times out.
+    command.process_group(0);
+
+    // The stream which is not parsed is left as it is, except that a command
+    // printing its address to stderr has its stdout thrown away.
+    match options.stream {
+        ExecStream::Stdout => command.stdout(Stdio::piped()),
+        ExecStream::Stderr => command.stdout(Stdio::null()).stderr(Stdio::piped()),
+    };
+
+    let mut child = command.spawn().map_err(|e| e.to_string())?;
+
+    let reader = match options.stream {
+        ExecStream::Stdout => read_in_background(child.stdout.take()),
+        ExecStream::Stderr => read_in_background(child.stderr.take()),
+    };
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
 
-    let output = OsString::from_vec(process.stdout)
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            kill_group(&mut child);
+            return Err(String::from("the child process timed out"));
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    if !wait_for_reader(&reader, &mut child, deadline) {
+        return Err(String::from("the child process timed out"));
+    }
+
+    let output = reader
+        .join()
+        .map_err(|_| String::from("unable to read from child process"))?;
+
+    let output = OsString::from_vec(output)
         .into_string()
         .map_err(|_| String::from("got gibberish from child process"))?;
 
+    if let Some(expected) = options.exit_code {
+        if status.code() != Some(expected) {
+            return Err(format!(
+                "the child process ended with {} (expected exit code {})",
+                status, expected
+            ));
+        }
+    }
+
+    extract_address(&output, options)
+}
+
+/// Waits for the reader of a child process which has already exited. Whatever
+/// it left running in the background may still hold the pipe open, which must
+/// not keep us waiting past the deadline either, so its process group is
+/// killed then. Returns false if that happened.
+pub(crate) fn wait_for_reader<T>(
+    reader: &JoinHandle<T>,
+    child: &mut Child,
+    deadline: Option<Instant>,
+) -> bool {
+    let Some(deadline) = deadline else {
+        return true;
+    };
+
+    while Instant::now() < deadline && !reader.is_finished() {
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    if !reader.is_finished() {
+        kill_group(child);
+        return false;
+    }
+
+    true
+}
+
+/// Kills the child process along with the rest of its process group. The
+/// process must have been spawned with a process group of its own.
+pub(crate) fn kill_group(child: &mut Child) {
+    // SAFETY: kill() has no memory safety requirements. The group is the one
+    //         the child leads, so its ID is the PID of the child.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+
+    let _ = child.wait();
+}
+
+/// The output is read on another thread, so that a chatty child process does
+/// not block on a full pipe while we are waiting for it to exit.
+pub(crate) fn read_in_background<R>(output: Option<R>) -> JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut output) = output {
+            let _ = output.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Finds the address in the output of the command: the first capture group of
+/// the regex if there is one, or else the whole output.
+fn extract_address<T>(output: &str, options: &ExecOptions) -> Result<T, String>
+where
+    T: FromStr<Err = AddrParseError>,
+{
+    #[cfg(feature = "regex")]
+    if let Some(regex) = &options.regex {
+        let addr = regex
+            .captures(output)
+            .and_then(|captured| captured.get(1))
+            .ok_or_else(|| {
+                String::from("the following output does not match regex: ") + output.trim()
+            })?;
+
+        return addr.as_str().trim().parse::<T>().map_err(|e| e.to_string());
+    }
+
+    #[cfg(not(feature = "regex"))]
+    let _ = options;
+
     output.trim().parse::<T>().map_err(|e| e.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn options() {
+        let run = |command: &str, options: &ExecOptions| {
+            execute_command_for_ip::<Ipv4Addr>(command, Some(Duration::from_secs(5)), options)
+        };
+
+        let mut options = ExecOptions::default();
+        assert_eq!(run("echo 192.0.2.1", &options), Ok([192, 0, 2, 1].into()));
+        assert!(run("echo 192.0.2.1 >&2", &options).is_err());
+
+        options.stream = ExecStream::Stderr;
+        assert_eq!(
+            run("echo 192.0.2.9; echo 192.0.2.1 >&2", &options),
+            Ok([192, 0, 2, 1].into())
+        );
+
+        // The exit code is only checked if asked to.
+        options.stream = ExecStream::Stdout;
+        assert!(run("echo 192.0.2.1; exit 3", &options).is_ok());
+        options.exit_code = Some(0);
+        assert_eq!(
+            run("echo 192.0.2.1; exit 3", &options),
+            Err(String::from(
+                "the child process ended with exit status: 3 (expected exit code 0)"
+            ))
+        );
+
+        #[cfg(feature = "regex")]
+        {
+            options.regex = Some(Regex::new(r"Public IP: (\S+)").unwrap());
+            assert_eq!(
+                run(
+                    "echo 'Gateway: 192.0.2.254'; echo 'Public IP: 192.0.2.1'",
+                    &options
+                ),
+                Ok([192, 0, 2, 1].into())
+            );
+            assert!(run("echo 192.0.2.1", &options).is_err());
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        let options = ExecOptions::default();
+        let run = |command: &str| {
+            execute_command_for_ip::<Ipv4Addr>(command, Some(Duration::from_millis(300)), &options)
+        };
+
+        // The background sleep keeps stdout open after the shell has exited,
+        // and is killed along with it.
+        let started = Instant::now();
+        assert!(run("sleep 30").is_err());
+        assert!(run("echo 192.0.2.1; sleep 30 &").is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
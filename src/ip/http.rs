@@ -4,16 +4,17 @@ use std::str::FromStr;
 #[cfg(feature = "regex")]
 use regex::Regex;
 
-use crate::http::{Error, Request};
+use crate::http::{Client, Error};
 
 pub(super) fn get_address<T>(
+    client: &Client,
     url: &str,
     #[cfg(feature = "regex")] regex: &Regex,
 ) -> Result<T, String>
 where
     T: FromStr<Err = AddrParseError>,
 {
-    let response = match Request::get(url).call() {
+    let response = match client.get(url).call() {
         Ok(r) => r,
         Err(Error::Status(code, response)) => {
             Err(code.to_string() + &response.into_string().unwrap_or_default())?
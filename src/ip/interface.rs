@@ -1,12 +1,18 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
 
-use super::netmask::{NetworkV4, NetworkV6};
+use crate::portable::netmask::{NetworkRules, NetworkV4, NetworkV6};
 
-pub(super) fn get_interface_v4_addresses(iface: &str, mask: &NetworkV4) -> Option<Ipv4Addr> {
+pub(super) fn get_interface_v4_addresses(
+    iface: &str,
+    mask: &NetworkRules<NetworkV4>,
+) -> Option<Ipv4Addr> {
     os::get_interface_v4_addresses(iface, mask)
 }
 
-pub(super) fn get_interface_v6_addresses(iface: &str, mask: &NetworkV6) -> Option<Ipv6Addr> {
+pub(super) fn get_interface_v6_addresses(
+    iface: &str,
+    mask: &NetworkRules<NetworkV6>,
+) -> Option<Ipv6Addr> {
     os::get_interface_v6_addresses(iface, mask)
 }
 
@@ -16,7 +22,7 @@ mod os {
     use std::mem::MaybeUninit;
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-    use crate::ip::netmask::{NetworkV4, NetworkV6};
+    use crate::portable::netmask::{NetworkRules, NetworkV4, NetworkV6};
 
     pub(super) fn transverse_ifaddr(iface: &str) -> Vec<IpAddr> {
         let mut ip_addrs = Vec::new();
@@ -141,12 +147,15 @@ mod os {
         addresses
     }
 
-    pub fn get_interface_v4_addresses(iface: &str, mask: &NetworkV4) -> Option<Ipv4Addr> {
+    pub fn get_interface_v4_addresses(
+        iface: &str,
+        mask: &NetworkRules<NetworkV4>,
+    ) -> Option<Ipv4Addr> {
         let mut result = None;
 
         for addr in transverse_ifaddr(iface) {
             match addr {
-                IpAddr::V4(v4) if mask.in_range(v4) => result = Some(v4),
+                IpAddr::V4(v4) if mask.contains(addr) => result = Some(v4),
                 _ => (),
             }
         }
@@ -154,7 +163,10 @@ mod os {
         result
     }
 
-    pub fn get_interface_v6_addresses(iface: &str, mask: &NetworkV6) -> Option<Ipv6Addr> {
+    pub fn get_interface_v6_addresses(
+        iface: &str,
+        mask: &NetworkRules<NetworkV6>,
+    ) -> Option<Ipv6Addr> {
         let deprecated = get_deprecated_v6_addresses(iface);
 
         transverse_ifaddr(iface)
@@ -163,7 +175,7 @@ mod os {
                 IpAddr::V6(v6) => Some(*v6),
                 _ => None,
             })
-            .filter(|v6| mask.in_range(*v6) && !deprecated.iter().any(|ip| *v6 == *ip))
+            .filter(|v6| mask.contains(IpAddr::V6(*v6)) && !deprecated.iter().any(|ip| *v6 == *ip))
             .last()
     }
 }
@@ -1,27 +1,51 @@
 mod exec;
 mod http;
 mod interface;
-mod netmask;
+mod stun;
 
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant, SystemTime};
 
 #[cfg(feature = "regex")]
 use regex::Regex;
 
 use thiserror::Error;
 
+use self::exec::ExecOptions;
+pub(crate) use self::exec::{kill_group, read_in_background, wait_for_reader};
 use crate::config::{IpConfig, IpConfigMethod, IpVersion};
+use crate::http::{Client, Settings};
+use crate::portable::netmask::{Network, NetworkParseErr, NetworkRules, NetworkV4, NetworkV6};
 
-use netmask::{NetworkParseErr, NetworkV4, NetworkV6};
+/// The options of [general] which the IP sources follow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceSettings {
+    pub http: Settings,
+
+    /// The shell running the commands of the exec method.
+    pub shell: Box<str>,
+}
+
+impl Default for SourceSettings {
+    fn default() -> Self {
+        Self {
+            http: Settings::default(),
+            shell: "/bin/sh".into(),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum IpService {
     ExecV4 {
         command: Box<str>,
+        timeout: Option<Duration>,
+        options: ExecOptions,
     },
 
     HttpV4 {
         url: Box<str>,
+        client: Client,
 
         #[cfg(feature = "regex")]
         regex: Regex,
@@ -29,15 +53,24 @@ pub enum IpService {
 
     InterfaceV4 {
         iface: Box<str>,
-        matches: NetworkV4,
+        matches: NetworkRules<NetworkV4>,
+    },
+
+    StunV4 {
+        servers: Vec<Box<str>>,
+        timeout: Option<Duration>,
+        settings: Settings,
     },
 
     ExecV6 {
         command: Box<str>,
+        timeout: Option<Duration>,
+        options: ExecOptions,
     },
 
     HttpV6 {
         url: Box<str>,
+        client: Client,
 
         #[cfg(feature = "regex")]
         regex: Regex,
@@ -45,15 +78,78 @@ pub enum IpService {
 
     InterfaceV6 {
         iface: Box<str>,
-        matches: NetworkV6,
+        matches: NetworkRules<NetworkV6>,
+    },
+
+    StunV6 {
+        servers: Vec<Box<str>>,
+        timeout: Option<Duration>,
+        settings: Settings,
     },
 }
 
 #[derive(Debug)]
 pub struct DynamicIp {
     address: Option<IpAddr>,
+
+    /// The address replaced by the last change, if there was one before.
+    previous: Option<IpAddr>,
+
     dirty: bool,
     service: IpService,
+
+    /// A changed address is only published after it has stayed the same for
+    /// this long. Until then, it is kept in `pending` along with the time it
+    /// was first seen.
+    debounce: Duration,
+    pending: Option<(IpAddr, Instant)>,
+
+    /// The number of consecutive failures, and the number of cycles to be
+    /// skipped before the source is polled again. Only used by the sources
+    /// which are expensive to poll (see `IpService::backs_off`).
+    failures: u32,
+    skipped_cycles: u32,
+
+    /// The last address obtained from the source and when it was obtained.
+    /// If `cache_ttl` is non-zero, this is saved in the persistent state and
+    /// used in place of polling after a restart, until the TTL has passed.
+    /// Only the sources which query external services are cached.
+    cache_ttl: Duration,
+    last_polled: Option<(IpAddr, SystemTime)>,
+    use_cache: bool,
+    refreshed: bool,
+
+    stats: SourceStats,
+}
+
+/// How the source has fared so far, as shown by `dynners ctl status`. Neither
+/// cached addresses nor skipped cycles count as polls.
+#[derive(Debug, Default, Clone)]
+pub struct SourceStats {
+    pub polls: u32,
+    pub successes: u32,
+
+    /// How long the last poll took, successful or not.
+    pub last_latency: Option<Duration>,
+
+    /// The last error and when it happened, kept after the source recovers.
+    pub last_error: Option<(Box<str>, Instant)>,
+}
+
+/// The number of consecutive failures after which an IP source is polled less
+/// often. From then on, the number of skipped cycles doubles with every
+/// failure, up to `MAX_SKIPPED_CYCLES`.
+const FAILURES_BEFORE_BACKOFF: u32 = 3;
+const MAX_SKIPPED_CYCLES: u32 = 32;
+
+fn skipped_cycles_after(failures: u32) -> u32 {
+    match failures.checked_sub(FAILURES_BEFORE_BACKOFF) {
+        Some(exponent) => 1u32
+            .checked_shl(exponent)
+            .unwrap_or(MAX_SKIPPED_CYCLES)
+            .min(MAX_SKIPPED_CYCLES),
+        None => 0,
+    }
 }
 
 #[derive(Debug, Error, Clone)]
@@ -67,32 +163,90 @@ pub enum DynamicIpError {
     #[error("unable to obtain matching IP using HTTP: {0}")]
     HttpFailure(Box<str>),
 
+    #[error("unable to obtain IP using STUN: {0}")]
+    StunFailure(Box<str>),
+
     #[cfg(feature = "regex")]
     #[error("unable to parse the regex: {0}")]
     InvalidRegex(regex::Error),
 
     #[error("unable to parse the netmask: {0}")]
     InvalidNetwork(NetworkParseErr),
+
+    #[error("the netmask {0} does not match the configured IP version")]
+    MismatchedNetwork(Network),
 }
 
 impl IpService {
-    fn from_config(config: &IpConfig) -> Result<Self, DynamicIpError> {
+    /// Whether repeatedly failing sources of this kind should be polled less
+    /// often. Reading the address of an interface is cheap, so it is not.
+    fn backs_off(&self) -> bool {
+        !matches!(self, Self::InterfaceV4 { .. } | Self::InterfaceV6 { .. })
+    }
+
+    /// Whether the source queries an external service, which a quick restart
+    /// should not query all over again.
+    fn is_cacheable(&self) -> bool {
+        matches!(
+            self,
+            Self::HttpV4 { .. } | Self::HttpV6 { .. } | Self::StunV4 { .. } | Self::StunV6 { .. }
+        )
+    }
+
+    fn http_client(settings: &SourceSettings, timeout: Option<Duration>) -> Client {
+        match timeout {
+            Some(timeout) => Client::with_timeout(&settings.http, timeout),
+            None => Client::new(&settings.http),
+        }
+    }
+
+    fn exec_options(
+        settings: &SourceSettings,
+        method: &IpConfigMethod,
+    ) -> Result<ExecOptions, DynamicIpError> {
+        let IpConfigMethod::Exec {
+            stream,
+            exit_code,
+            regex,
+            ..
+        } = method
+        else {
+            return Ok(ExecOptions::default());
+        };
+
+        #[cfg(not(feature = "regex"))]
+        let _ = regex;
+
+        Ok(ExecOptions {
+            shell: settings.shell.clone(),
+            stream: *stream,
+            exit_code: *exit_code,
+
+            #[cfg(feature = "regex")]
+            regex: regex
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(DynamicIpError::InvalidRegex)?,
+        })
+    }
+
+    fn from_config(config: &IpConfig, settings: &SourceSettings) -> Result<Self, DynamicIpError> {
+        let timeout = config.timeout();
+        let timeout = (timeout > 0).then(|| Duration::from_secs(timeout as u64));
+
         match (&config.version, &config.method) {
-            (IpVersion::V4, IpConfigMethod::Exec { command }) => Ok(Self::ExecV4 {
+            (IpVersion::V4, IpConfigMethod::Exec { command, .. }) => Ok(Self::ExecV4 {
                 command: command.clone(),
+                timeout,
+                options: Self::exec_options(settings, &config.method)?,
             }),
 
             (IpVersion::V4, IpConfigMethod::Interface { iface, matches }) => {
-                let matches = if matches.is_empty() {
-                    "0.0.0.0/0"
-                } else {
-                    matches.as_ref()
-                };
-
-                let matches = matches
-                    .trim()
-                    .parse::<NetworkV4>()
-                    .map_err(DynamicIpError::InvalidNetwork)?;
+                let matches = NetworkRules::<Network>::parse(matches)
+                    .map_err(DynamicIpError::InvalidNetwork)?
+                    .into_v4()
+                    .map_err(DynamicIpError::MismatchedNetwork)?;
                 Ok(Self::InterfaceV4 {
                     iface: iface.clone(),
                     matches,
@@ -100,9 +254,10 @@ impl IpService {
             }
 
             #[cfg(not(feature = "regex"))]
-            (IpVersion::V4, IpConfigMethod::Http { url, .. }) => {
-                Ok(Self::HttpV4 { url: url.clone() })
-            }
+            (IpVersion::V4, IpConfigMethod::Http { url, .. }) => Ok(Self::HttpV4 {
+                url: url.clone(),
+                client: Self::http_client(settings, timeout),
+            }),
 
             #[cfg(feature = "regex")]
             (IpVersion::V4, IpConfigMethod::Http { url, regex }) => {
@@ -111,25 +266,28 @@ impl IpService {
 
                 Ok(Self::HttpV4 {
                     url: url.clone(),
+                    client: Self::http_client(settings, timeout),
                     regex,
                 })
             }
 
-            (IpVersion::V6, IpConfigMethod::Exec { command }) => Ok(Self::ExecV6 {
+            (IpVersion::V4, IpConfigMethod::Stun { servers }) => Ok(Self::StunV4 {
+                servers: servers.clone(),
+                timeout,
+                settings: settings.http.clone(),
+            }),
+
+            (IpVersion::V6, IpConfigMethod::Exec { command, .. }) => Ok(Self::ExecV6 {
                 command: command.clone(),
+                timeout,
+                options: Self::exec_options(settings, &config.method)?,
             }),
 
             (IpVersion::V6, IpConfigMethod::Interface { iface, matches }) => {
-                let matches = if matches.is_empty() {
-                    "::/0"
-                } else {
-                    matches.as_ref()
-                };
-
-                let matches = matches
-                    .trim()
-                    .parse::<NetworkV6>()
-                    .map_err(DynamicIpError::InvalidNetwork)?;
+                let matches = NetworkRules::<Network>::parse(matches)
+                    .map_err(DynamicIpError::InvalidNetwork)?
+                    .into_v6()
+                    .map_err(DynamicIpError::MismatchedNetwork)?;
                 Ok(Self::InterfaceV6 {
                     iface: iface.clone(),
                     matches,
@@ -137,30 +295,58 @@ impl IpService {
             }
 
             #[cfg(not(feature = "regex"))]
-            (IpVersion::V6, IpConfigMethod::Http { url, .. }) => {
-                Ok(Self::HttpV6 { url: url.clone() })
-            }
+            (IpVersion::V6, IpConfigMethod::Http { url, .. }) => Ok(Self::HttpV6 {
+                url: url.clone(),
+                client: Self::http_client(settings, timeout),
+            }),
 
             #[cfg(feature = "regex")]
             (IpVersion::V6, IpConfigMethod::Http { url, regex }) => {
-                let regex =
-                    Regex::new(regex.as_ref()).map_err(DynamicIpError::InvalidRegex)?;
+                let regex = Regex::new(regex.as_ref()).map_err(DynamicIpError::InvalidRegex)?;
 
                 Ok(Self::HttpV6 {
                     url: url.clone(),
+                    client: Self::http_client(settings, timeout),
                     regex,
                 })
             }
+
+            (IpVersion::V6, IpConfigMethod::Stun { servers }) => Ok(Self::StunV6 {
+                servers: servers.clone(),
+                timeout,
+                settings: settings.http.clone(),
+            }),
         }
     }
 }
 
 impl DynamicIp {
-    pub fn from_config(config: &IpConfig) -> Result<Self, DynamicIpError> {
+    pub fn from_config(
+        config: &IpConfig,
+        debounce: Duration,
+        settings: &SourceSettings,
+    ) -> Result<Self, DynamicIpError> {
+        let service = IpService::from_config(config, settings)?;
+
+        let cache_ttl = match service.is_cacheable() {
+            true => Duration::from_secs(config.cache_ttl as u64),
+            false => Duration::ZERO,
+        };
+
         Ok(Self {
             address: None,
+            previous: None,
             dirty: false,
-            service: IpService::from_config(config)?,
+            service,
+            debounce,
+            pending: None,
+            failures: 0,
+            skipped_cycles: 0,
+            cache_ttl,
+            last_polled: None,
+            use_cache: false,
+            refreshed: false,
+            stats: SourceStats::default(),
         })
     }
 
@@ -168,13 +354,190 @@ impl DynamicIp {
         self.address.as_ref()
     }
 
+    pub fn previous_address(&self) -> Option<&IpAddr> {
+        self.previous.as_ref()
+    }
+
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
 
+    /// The newly obtained address which is still waiting to be confirmed.
+    pub fn pending_address(&self) -> Option<&IpAddr> {
+        self.pending.as_ref().map(|(ip, _)| ip)
+    }
+
+    /// The point in time after which the pending address (if any) will be
+    /// published, provided that it stays the same.
+    pub fn pending_deadline(&self) -> Option<Instant> {
+        self.pending.map(|(_, since)| since + self.debounce)
+    }
+
+    /// Whether the source has failed often enough to be polled less often.
+    pub fn is_backing_off(&self) -> bool {
+        self.failures >= FAILURES_BEFORE_BACKOFF
+    }
+
+    /// The number of cycles left before a failing source is polled again.
+    pub fn skipped_cycles(&self) -> u32 {
+        self.skipped_cycles
+    }
+
+    /// Forgets the failures so far. After the system has been suspended they
+    /// most likely do not say anything about the network we woke up on.
+    pub fn reset_backoff(&mut self) {
+        self.failures = 0;
+        self.skipped_cycles = 0;
+    }
+
+    pub fn stats(&self) -> &SourceStats {
+        &self.stats
+    }
+
+    /// Carries the statistics over from the same source in a previous config.
+    pub fn set_stats(&mut self, stats: SourceStats) {
+        self.stats = stats;
+    }
+
+    /// Whether the source is cached at all, see `cache_ttl` in the config.
+    pub fn is_cached(&self) -> bool {
+        !self.cache_ttl.is_zero()
+    }
+
+    /// The address to be saved in the persistent state, if the source is
+    /// cached.
+    pub fn cache_entry(&self) -> Option<(IpAddr, SystemTime)> {
+        self.last_polled.filter(|_| self.is_cached())
+    }
+
+    /// Whether the last update has polled the source of a cached IP, i.e.
+    /// whether the cache entry has to be saved again.
+    pub fn is_cache_refreshed(&self) -> bool {
+        self.refreshed
+    }
+
+    /// Uses an address obtained by a previous session instead of polling the
+    /// source, until the TTL has passed. Returns false if it is too old.
+    pub fn restore_from_cache(&mut self, address: IpAddr, polled_at: SystemTime) -> bool {
+        let expired = match SystemTime::now().duration_since(polled_at) {
+            Ok(age) => age >= self.cache_ttl,
+            // It comes from the future, better not trust it.
+            Err(_) => true,
+        };
+
+        if self.is_cached() && !expired {
+            self.last_polled = Some((address, polled_at));
+            self.use_cache = true;
+        }
+
+        self.use_cache
+    }
+
     pub fn update(&mut self) -> Result<(), DynamicIpError> {
-        let new_ip = match self.service {
-            IpService::ExecV4 { ref command } => exec::execute_command_for_ip::<Ipv4Addr>(command)
+        self.refreshed = false;
+
+        if self.skipped_cycles > 0 {
+            self.skipped_cycles -= 1;
+            self.dirty = false;
+            return Ok(());
+        }
+
+        let cached = match self.last_polled {
+            Some((ip, polled_at)) if self.use_cache => {
+                let age = SystemTime::now().duration_since(polled_at);
+                age.is_ok_and(|age| age < self.cache_ttl).then_some(ip)
+            }
+            _ => None,
+        };
+
+        self.use_cache = cached.is_some();
+
+        let result = match cached {
+            Some(ip) => Ok(ip),
+            None => self.poll_with_stats(),
+        };
+
+        let new_ip = match result {
+            Ok(ip) => {
+                if cached.is_none() {
+                    self.last_polled = Some((ip, SystemTime::now()));
+                    self.refreshed = self.is_cached();
+                }
+
+                self.failures = 0;
+                ip
+            }
+
+            Err(e) => {
+                if self.service.backs_off() {
+                    self.failures = self.failures.saturating_add(1);
+
+                    self.skipped_cycles = skipped_cycles_after(self.failures);
+                }
+
+                return Err(e);
+            }
+        };
+
+        match self.address {
+            Some(old_ip) if old_ip == new_ip => {
+                // If the address flapped back to what we had, there is
+                // nothing to confirm anymore.
+                self.pending = None;
+                self.dirty = false;
+            }
+
+            Some(_) if !self.debounce.is_zero() => {
+                let now = Instant::now();
+
+                match self.pending {
+                    Some((pending, since)) if pending == new_ip => {
+                        self.dirty = now.duration_since(since) >= self.debounce;
+                    }
+                    _ => {
+                        self.pending = Some((new_ip, now));
+                        self.dirty = false;
+                    }
+                }
+
+                if self.dirty {
+                    self.pending = None;
+                    self.previous = self.address.replace(new_ip);
+                }
+            }
+
+            // There is nothing to flap from if we never had an address.
+            _ => {
+                self.dirty = true;
+                self.previous = self.address.replace(new_ip);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn poll_with_stats(&mut self) -> Result<IpAddr, DynamicIpError> {
+        let started = Instant::now();
+        let result = self.poll();
+
+        self.stats.polls = self.stats.polls.saturating_add(1);
+        self.stats.last_latency = Some(started.elapsed());
+
+        match &result {
+            Ok(_) => self.stats.successes = self.stats.successes.saturating_add(1),
+            Err(e) => self.stats.last_error = Some((e.to_string().into(), Instant::now())),
+        }
+
+        result
+    }
+
+    fn poll(&self) -> Result<IpAddr, DynamicIpError> {
+        match self.service {
+            IpService::ExecV4 {
+                ref command,
+                timeout,
+                ref options,
+            } => exec::execute_command_for_ip::<Ipv4Addr>(command, timeout, options)
                 .map(IpAddr::from)
                 .map_err(|e| DynamicIpError::ExecutionFailure(e.into())),
 
@@ -186,16 +549,34 @@ impl DynamicIp {
                 .ok_or(DynamicIpError::InterfaceFailure),
 
             #[cfg(not(feature = "regex"))]
-            IpService::HttpV4 { ref url } => http::get_address::<Ipv4Addr>(url)
+            IpService::HttpV4 {
+                ref url,
+                ref client,
+            } => http::get_address::<Ipv4Addr>(client, url)
                 .map(IpAddr::from)
                 .map_err(|e| DynamicIpError::HttpFailure(e.into())),
 
             #[cfg(feature = "regex")]
-            IpService::HttpV4 { ref url, ref regex } => http::get_address::<Ipv4Addr>(url, regex)
+            IpService::HttpV4 {
+                ref url,
+                ref client,
+                ref regex,
+            } => http::get_address::<Ipv4Addr>(client, url, regex)
                 .map(IpAddr::from)
                 .map_err(|e| DynamicIpError::HttpFailure(e.into())),
 
-            IpService::ExecV6 { ref command } => exec::execute_command_for_ip::<Ipv6Addr>(command)
+            IpService::StunV4 {
+                ref servers,
+                timeout,
+                ref settings,
+            } => stun::get_address(servers, false, timeout, settings)
+                .map_err(|e| DynamicIpError::StunFailure(e.into())),
+
+            IpService::ExecV6 {
+                ref command,
+                timeout,
+                ref options,
+            } => exec::execute_command_for_ip::<Ipv6Addr>(command, timeout, options)
                 .map(IpAddr::from)
                 .map_err(|e| DynamicIpError::ExecutionFailure(e.into())),
 
@@ -207,28 +588,151 @@ impl DynamicIp {
                 .ok_or(DynamicIpError::InterfaceFailure),
 
             #[cfg(not(feature = "regex"))]
-            IpService::HttpV6 { ref url } => http::get_address::<Ipv6Addr>(url)
+            IpService::HttpV6 {
+                ref url,
+                ref client,
+            } => http::get_address::<Ipv6Addr>(client, url)
                 .map(IpAddr::from)
                 .map_err(|e| DynamicIpError::HttpFailure(e.into())),
 
             #[cfg(feature = "regex")]
-            IpService::HttpV6 { ref url, ref regex } => http::get_address::<Ipv6Addr>(url, regex)
+            IpService::HttpV6 {
+                ref url,
+                ref client,
+                ref regex,
+            } => http::get_address::<Ipv6Addr>(client, url, regex)
                 .map(IpAddr::from)
                 .map_err(|e| DynamicIpError::HttpFailure(e.into())),
-        }?;
 
-        if let Some(old_ip) = &self.address {
-            self.dirty = *old_ip != new_ip;
-        } else {
-            self.dirty = true;
+            IpService::StunV6 {
+                ref servers,
+                timeout,
+                ref settings,
+            } => stun::get_address(servers, true, timeout, settings)
+                .map_err(|e| DynamicIpError::StunFailure(e.into())),
         }
-
-        self.address = Some(new_ip);
-
-        Ok(())
     }
 
     pub fn update_from_cache(&mut self, address: IpAddr) {
         self.address = Some(address);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache() {
+        let config = toml::from_str::<IpConfig>(
+            r#"
+            version = 4
+            method = "http"
+            url = "https://example.com/"
+            cache_ttl = 60
+            "#,
+        )
+        .unwrap();
+
+        let mut ip =
+            DynamicIp::from_config(&config, Duration::ZERO, &SourceSettings::default()).unwrap();
+        assert!(ip.is_cached());
+
+        let address = "192.0.2.1".parse().unwrap();
+        let too_old = SystemTime::now() - Duration::from_secs(61);
+        assert!(!ip.restore_from_cache(address, too_old));
+
+        let fresh = SystemTime::now() - Duration::from_secs(30);
+        assert!(ip.restore_from_cache(address, fresh));
+
+        // The source is not polled while the cached address is fresh.
+        ip.update().unwrap();
+        assert_eq!(ip.address(), Some(&address));
+        assert!(ip.is_dirty());
+        assert!(!ip.is_cache_refreshed());
+        assert_eq!(ip.cache_entry(), Some((address, fresh)));
+
+        // Only the sources querying external services are cached.
+        let config = toml::from_str::<IpConfig>(
+            r#"
+            version = 4
+            method = "exec"
+            command = "echo 192.0.2.1"
+            cache_ttl = 60
+            "#,
+        )
+        .unwrap();
+
+        let mut ip =
+            DynamicIp::from_config(&config, Duration::ZERO, &SourceSettings::default()).unwrap();
+        assert!(!ip.is_cached());
+        assert!(!ip.restore_from_cache(address, fresh));
+    }
+
+    #[test]
+    fn stats() {
+        let config = |iface: &str| {
+            let config = format!("version = 4\nmethod = \"interface\"\niface = \"{}\"", iface);
+            toml::from_str::<IpConfig>(&config).unwrap()
+        };
+
+        let mut ip =
+            DynamicIp::from_config(&config("lo"), Duration::ZERO, &SourceSettings::default())
+                .unwrap();
+        ip.update().unwrap();
+
+        ip.service =
+            IpService::from_config(&config("nonexistent0"), &SourceSettings::default()).unwrap();
+        assert!(ip.update().is_err());
+
+        let stats = ip.stats();
+        assert_eq!((stats.polls, stats.successes), (2, 1));
+        assert!(stats.last_latency.is_some());
+        assert!(stats.last_error.is_some());
+    }
+
+    #[test]
+    fn previous() {
+        let config = |address: &str| {
+            let config = format!(
+                "version = 4\nmethod = \"exec\"\ncommand = \"echo {}\"",
+                address
+            );
+            toml::from_str::<IpConfig>(&config).unwrap()
+        };
+
+        let mut ip = DynamicIp::from_config(
+            &config("192.0.2.1"),
+            Duration::ZERO,
+            &SourceSettings::default(),
+        )
+        .unwrap();
+        ip.update().unwrap();
+        assert_eq!(ip.previous_address(), None);
+
+        ip.service =
+            IpService::from_config(&config("192.0.2.2"), &SourceSettings::default()).unwrap();
+        ip.update().unwrap();
+        assert_eq!(ip.address(), Some(&"192.0.2.2".parse().unwrap()));
+        assert_eq!(ip.previous_address(), Some(&"192.0.2.1".parse().unwrap()));
+
+        // It is kept until the address changes again.
+        ip.update().unwrap();
+        assert!(!ip.is_dirty());
+        assert_eq!(ip.previous_address(), Some(&"192.0.2.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn backoff() {
+        assert_eq!(skipped_cycles_after(0), 0);
+        assert_eq!(skipped_cycles_after(FAILURES_BEFORE_BACKOFF - 1), 0);
+        assert_eq!(skipped_cycles_after(FAILURES_BEFORE_BACKOFF), 1);
+        assert_eq!(skipped_cycles_after(FAILURES_BEFORE_BACKOFF + 1), 2);
+        assert_eq!(skipped_cycles_after(FAILURES_BEFORE_BACKOFF + 2), 4);
+        assert_eq!(
+            skipped_cycles_after(FAILURES_BEFORE_BACKOFF + 10),
+            MAX_SKIPPED_CYCLES
+        );
+        assert_eq!(skipped_cycles_after(u32::MAX), MAX_SKIPPED_CYCLES);
+    }
+}
@@ -0,0 +1,341 @@
+//! A minimal STUN client (RFC 8489), only able to send a binding request and
+//! read the address the server saw it coming from. No authentication or other
+//! attributes are supported, as the public STUN servers need none.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::http::{self, Settings};
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const BINDING_ERROR: u16 = 0x0111;
+
+const MAGIC_COOKIE: u32 = 0x2112_a442;
+
+const MAPPED_ADDRESS: u16 = 0x0001;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+const DEFAULT_PORT: u16 = 3478;
+
+/// How long to wait for each answer before sending the request again, as it
+/// travels over UDP.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(1);
+const ATTEMPTS: usize = 3;
+
+/// Asks the servers in turn for the address of this host, until one of them
+/// answers. Only the servers reachable over the requested IP version are
+/// asked, so that the address is of that version.
+pub(super) fn get_address(
+    servers: &[Box<str>],
+    ipv6: bool,
+    timeout: Option<Duration>,
+    settings: &Settings,
+) -> Result<IpAddr, String> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut errors = Vec::new();
+
+    for server in servers {
+        let result = resolve(server, settings).and_then(|addresses| {
+            let address = addresses
+                .into_iter()
+                .find(|address| address.is_ipv6() == ipv6)
+                .ok_or_else(|| {
+                    let version = if ipv6 { "IPv6" } else { "IPv4" };
+                    io::Error::new(io::ErrorKind::NotFound, format!("no {} address", version))
+                })?;
+
+            query(address, deadline)
+        });
+
+        match result {
+            Ok(ip) => return Ok(ip),
+            Err(e) => errors.push(format!("{}: {}", server, e)),
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+    }
+
+    Err(errors.join(", "))
+}
+
+/// Splits "host", "host:port" or "[v6]:port" and looks the host up.
+fn resolve(server: &str, settings: &Settings) -> io::Result<Vec<SocketAddr>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid server");
+
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) if host.starts_with('[') || !host.contains(':') => {
+            (host, port.parse().map_err(|_| invalid())?)
+        }
+        _ => (server, DEFAULT_PORT),
+    };
+
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(invalid());
+    }
+
+    match http::resolve_override(settings, host, port) {
+        Some(result) => result,
+        None => Ok((host, port).to_socket_addrs()?.collect()),
+    }
+}
+
+fn query(server: SocketAddr, deadline: Option<Instant>) -> io::Result<IpAddr> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(server)?;
+
+    let mut transaction_id = [0u8; 12];
+    SystemRandom::new()
+        .fill(&mut transaction_id)
+        .map_err(|_| io::Error::other("no random numbers available"))?;
+
+    let request = build_request(&transaction_id);
+    let mut buffer = [0u8; 1280];
+    let mut last_error = io::Error::new(io::ErrorKind::TimedOut, "STUN request timed out");
+
+    for _ in 0..ATTEMPTS {
+        let attempt_timeout = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    break;
+                }
+                left.min(ATTEMPT_TIMEOUT)
+            }
+            None => ATTEMPT_TIMEOUT,
+        };
+
+        socket.set_read_timeout(Some(attempt_timeout))?;
+        socket.send(&request)?;
+
+        // Anything not answering our request (e.g. a late answer to a previous
+        // attempt) is ignored until the timeout.
+        loop {
+            let length = match socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(e) => {
+                    last_error = e;
+                    break;
+                }
+            };
+
+            if let Some(result) = parse_response(&transaction_id, &buffer[..length]) {
+                return result;
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+fn build_request(transaction_id: &[u8; 12]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(20);
+    request.extend(BINDING_REQUEST.to_be_bytes());
+    request.extend(0u16.to_be_bytes()); // No attributes.
+    request.extend(MAGIC_COOKIE.to_be_bytes());
+    request.extend(transaction_id);
+    request
+}
+
+/// Returns None if the response does not belong to the request.
+fn parse_response(transaction_id: &[u8; 12], response: &[u8]) -> Option<io::Result<IpAddr>> {
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed STUN response");
+
+    if response.len() < 20
+        || response[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || response[8..20] != transaction_id[..]
+    {
+        return None;
+    }
+
+    let kind = u16::from_be_bytes([response[0], response[1]]);
+    let length = u16::from_be_bytes([response[2], response[3]]) as usize;
+
+    match kind {
+        BINDING_SUCCESS => (),
+        BINDING_ERROR => return Some(Err(io::Error::other("the server returned an error"))),
+        _ => return None,
+    }
+
+    let Some(mut attributes) = response.get(20..20 + length) else {
+        return Some(Err(malformed()));
+    };
+
+    let mut mapped = None;
+
+    while attributes.len() >= 4 {
+        let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let length = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let padded = (length + 3) & !3;
+
+        let Some(value) = attributes.get(4..4 + length) else {
+            return Some(Err(malformed()));
+        };
+
+        match kind {
+            XOR_MAPPED_ADDRESS => {
+                let ip = parse_address(value, Some(transaction_id)).ok_or_else(malformed);
+                return Some(ip);
+            }
+            // Only sent by servers predating RFC 5389, which know nothing of
+            // XOR-MAPPED-ADDRESS.
+            MAPPED_ADDRESS => mapped = parse_address(value, None),
+            _ => (),
+        }
+
+        attributes = attributes.get(4 + padded..).unwrap_or_default();
+    }
+
+    Some(mapped.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no mapped address")))
+}
+
+/// Reads a (XOR-)MAPPED-ADDRESS, which is XOR-ed with the magic cookie and the
+/// transaction ID if the latter is given.
+fn parse_address(value: &[u8], transaction_id: Option<&[u8; 12]>) -> Option<IpAddr> {
+    let mut key = [0u8; 16];
+    if let Some(transaction_id) = transaction_id {
+        key[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        key[4..].copy_from_slice(transaction_id);
+    }
+
+    let mut address = [0u8; 16];
+    let family = *value.get(1)?;
+    let length = match family {
+        0x01 => 4,
+        0x02 => 16,
+        _ => return None,
+    };
+
+    let bytes = value.get(4..4 + length)?;
+    for (i, byte) in bytes.iter().enumerate() {
+        address[i] = byte ^ key[i];
+    }
+
+    match family {
+        0x01 => Some(IpAddr::from([
+            address[0], address[1], address[2], address[3],
+        ])),
+        _ => Some(IpAddr::from(address)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use data_encoding::HEXLOWER;
+
+    const TRANSACTION_ID: [u8; 12] = [
+        0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    ];
+
+    #[test]
+    fn response() {
+        // The sample responses of RFC 5769, along with their SOFTWARE,
+        // MESSAGE-INTEGRITY and FINGERPRINT attributes.
+        let ipv4 = HEXLOWER
+            .decode(
+                concat!(
+                    "0101003c2112a442b7e7a701bc34d686fa87dfae",
+                    "8022000b7465737420766563746f7220",
+                    "002000080001a147e112a643",
+                    "000800142b91f599fd9e90c38c7489f92af9ba53f06be7d7",
+                    "80280004c07d4c96",
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+
+        let address = parse_response(&TRANSACTION_ID, &ipv4).unwrap().unwrap();
+        assert_eq!(address, "192.0.2.1".parse::<IpAddr>().unwrap());
+
+        let ipv6 = HEXLOWER
+            .decode(
+                concat!(
+                    "010100482112a442b7e7a701bc34d686fa87dfae",
+                    "8022000b7465737420766563746f7220",
+                    "002000140002a1470113a9faa5d3f179bc25f4b5bed2b9d9",
+                    "00080014a382954e4be67bf11784c97c8292c275bfe3ed41",
+                    "80280004c8fb0b4c",
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+
+        let address = parse_response(&TRANSACTION_ID, &ipv6).unwrap().unwrap();
+        assert_eq!(
+            address,
+            "2001:db8:1234:5678:11:2233:4455:6677"
+                .parse::<IpAddr>()
+                .unwrap()
+        );
+
+        // Responses to other requests are ignored.
+        let mut other = TRANSACTION_ID;
+        other[0] = 0;
+        assert!(parse_response(&other, &ipv4).is_none());
+    }
+
+    #[test]
+    fn server() {
+        assert_eq!(
+            resolve("127.0.0.1", &Settings::default()).unwrap(),
+            ["127.0.0.1:3478".parse().unwrap()]
+        );
+        assert_eq!(
+            resolve("[::1]:19302", &Settings::default()).unwrap(),
+            ["[::1]:19302".parse().unwrap()]
+        );
+        assert!(resolve(":3478", &Settings::default()).is_err());
+    }
+
+    #[test]
+    fn query() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+
+        // Answers with a MAPPED-ADDRESS of 192.0.2.1, then the XOR-MAPPED-ADDRESS
+        // of the client, which takes precedence.
+        let responder = std::thread::spawn(move || {
+            let mut request = [0u8; 64];
+            let (length, client) = server.recv_from(&mut request).unwrap();
+            assert_eq!(length, 20);
+            assert_eq!(request[..8], [0, 1, 0, 0, 0x21, 0x12, 0xa4, 0x42]);
+
+            let SocketAddr::V4(client_v4) = client else {
+                unreachable!()
+            };
+
+            let mut response = Vec::new();
+            response.extend(BINDING_SUCCESS.to_be_bytes());
+            response.extend(24u16.to_be_bytes());
+            response.extend(&request[4..20]);
+            response.extend([0, 1, 0, 8, 0, 1, 0, 0, 192, 0, 2, 1]);
+            response.extend([0, 0x20, 0, 8, 0, 1]);
+            response.extend((client.port() ^ 0x2112).to_be_bytes());
+            response.extend((u32::from(*client_v4.ip()) ^ MAGIC_COOKIE).to_be_bytes());
+
+            server.send_to(&response, client).unwrap();
+        });
+
+        let servers = [format!("127.0.0.1:{}", port).into()];
+        let address = get_address(&servers, false, None, &Settings::default()).unwrap();
+        assert_eq!(address, "127.0.0.1".parse::<IpAddr>().unwrap());
+
+        responder.join().unwrap();
+
+        // There is no IPv6 address to ask for one.
+        assert!(get_address(&servers, true, None, &Settings::default()).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Every log line goes through here, so that all of them share the same
+//! format: the level, then the instance name (if `--instance` was given),
+//! then the message itself.
+//!
+//! Warnings and errors which repeat themselves, such as a missing interface
+//! being reported every cycle, are only printed once per `REPEAT_INTERVAL`,
+//! along with how many times they were suppressed in the meantime.
+
+use std::collections::BTreeMap;
+use std::fmt::Arguments;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::INSTANCE;
+
+/// How long a repeated warning or error is kept quiet for.
+const REPEAT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long a message is remembered for after it was last printed.
+const FORGET_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The warnings and errors printed recently.
+static REPEATS: Mutex<BTreeMap<String, Repeat>> = Mutex::new(BTreeMap::new());
+
+struct Repeat {
+    printed_at: Instant,
+    suppressed: u32,
+}
+
+/// Decides whether the message should be printed. If so, the number of times
+/// it was suppressed since it was last printed is returned.
+fn check_repeat(
+    repeats: &mut BTreeMap<String, Repeat>,
+    message: String,
+    now: Instant,
+) -> Option<u32> {
+    // Forget about the messages which have not been seen for a long while.
+    repeats.retain(|_, repeat| now.saturating_duration_since(repeat.printed_at) < FORGET_AFTER);
+
+    let Some(repeat) = repeats.get_mut(&message) else {
+        repeats.insert(
+            message,
+            Repeat {
+                printed_at: now,
+                suppressed: 0,
+            },
+        );
+        return Some(0);
+    };
+
+    if now.saturating_duration_since(repeat.printed_at) < REPEAT_INTERVAL {
+        repeat.suppressed += 1;
+        return None;
+    }
+
+    let suppressed = repeat.suppressed;
+    repeat.printed_at = now;
+    repeat.suppressed = 0;
+
+    Some(suppressed)
+}
+
+pub fn log(level: &str, args: Arguments) {
+    let mut suffix = String::new();
+
+    if level != "INFO" {
+        let message = format!("[{}] {}", level, args);
+        let mut repeats = REPEATS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        match check_repeat(&mut repeats, message, Instant::now()) {
+            None => return,
+            Some(0) => (),
+            Some(suppressed) => {
+                suffix = format!(" (repeated {} time(s) since last shown)", suppressed)
+            }
+        }
+    }
+
+    match INSTANCE.get() {
+        Some(instance) => println!("[{}] [{}] {}{}", level, instance, args, suffix),
+        None => println!("[{}] {}{}", level, args, suffix),
+    }
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::log("INFO", format_args!($($arg)*)) };
+}
+
+// Named differently from the macro itself, as `warn` clashes with the
+// built-in attribute of the same name.
+macro_rules! warning {
+    ($($arg:tt)*) => { $crate::log::log("WARN", format_args!($($arg)*)) };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log::log("ERROR", format_args!($($arg)*)) };
+}
+
+pub(crate) use {error, info, warning as warn};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats() {
+        let mut repeats = BTreeMap::new();
+        let start = Instant::now();
+        let message = || String::from("[ERROR] interface eth0 not found");
+
+        assert_eq!(check_repeat(&mut repeats, message(), start), Some(0));
+
+        for minutes in 1..60 {
+            let now = start + Duration::from_secs(minutes * 60);
+            assert_eq!(check_repeat(&mut repeats, message(), now), None);
+        }
+
+        // Other messages are not affected.
+        let other = String::from("[ERROR] something else");
+        assert_eq!(check_repeat(&mut repeats, other, start), Some(0));
+
+        let now = start + REPEAT_INTERVAL;
+        assert_eq!(check_repeat(&mut repeats, message(), now), Some(59));
+
+        let now = start + REPEAT_INTERVAL + FORGET_AFTER;
+        assert_eq!(check_repeat(&mut repeats, message(), now), Some(0));
+        assert_eq!(repeats.len(), 1);
+    }
+}
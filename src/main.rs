@@ -1,19 +1,31 @@
+extern crate alloc;
+
 mod config;
+mod control;
+mod daemon;
+mod dns;
+mod events;
 mod http;
 mod ip;
+mod log;
 mod persistence;
+mod portable;
 mod services;
 mod util;
 
-use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{self, BufReader, BufWriter, Read};
+use std::io::{self, BufReader, Read};
 use std::path::Path;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::sync::OnceLock;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
-use config::{Config, General};
+use config::{Config, DdnsConfig, DdnsConfigService, General};
+use control::Command;
+use daemon::Daemon;
+use log::{error, info, warn};
 use persistence::PersistentState;
+use services::RecordType;
 
 const CONFIG_PATHS: [&str; 2] = [
     "./config.toml",
@@ -25,6 +37,10 @@ const CONFIG_PATHS: [&str; 2] = [
 /// config.toml.
 static GENERAL_CONFIG: OnceLock<General> = OnceLock::new();
 
+/// Set by `--instance <name>`, which lets several instances with their own
+/// config files coexist on one host.
+static INSTANCE: OnceLock<Box<str>> = OnceLock::new();
+
 fn check_curl_version() {
     #[cfg(feature = "curl")]
     {
@@ -46,12 +62,25 @@ fn check_curl_version() {
     }
 }
 
-fn main() {
-    check_curl_version();
+/// The config files to look for. An instance named foo reads foo.toml instead
+/// of config.toml.
+fn config_paths() -> Vec<String> {
+    match INSTANCE.get() {
+        Some(instance) => CONFIG_PATHS
+            .iter()
+            .map(|path| path.replace("config.toml", &format!("{}.toml", instance)))
+            .collect(),
+        None => CONFIG_PATHS.iter().map(|path| path.to_string()).collect(),
+    }
+}
 
+/// Reads the first config file found in `config_paths()`. Both the parsed
+/// config and the file content (which the persistent state is validated
+/// against) are returned.
+fn read_config() -> Result<(String, Config), String> {
     let mut config_str = String::new();
 
-    for path in CONFIG_PATHS {
+    for path in config_paths() {
         let mut file = match File::open(path) {
             Ok(f) => f,
             Err(_) => continue,
@@ -64,224 +93,574 @@ fn main() {
     }
 
     if config_str.is_empty() {
-        println!("No configuration found. Quitting.");
-        return;
+        return Err(String::from("No configuration found."));
     }
 
-    // Calculating the hash of current config file
-    let config_hash = PersistentState::new(&config_str).config_hash;
+    let mut config = Config::parse(config_str.as_str()).map_err(|e| e.to_string())?;
 
-    // Parsing the config file
-    let config = match toml::from_str::<Config>(config_str.as_str()) {
-        Ok(conf) => conf,
-        Err(e) => return println!("{}", e),
-    };
+    if let Some(instance) = INSTANCE.get() {
+        config.general.namespace(instance);
+    }
+
+    Ok((config_str, config))
+}
 
-    // Reading and parsing the persistent state
+/// Reads and parses the persistent state, which is discarded if it does not
+/// belong to the given config file.
+fn read_persistent_state(path: &str, config_str: &str) -> PersistentState {
     let mut persistent_state = 'block: {
-        let file = match File::open(config.general.persistent_state.as_ref()) {
+        let file = match File::open(path) {
             Ok(f) => f,
             Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                if let Some(parent) = Path::new(config.general.persistent_state.as_ref()).parent() {
+                if let Some(parent) = Path::new(path).parent() {
                     if let Err(e) = fs::create_dir_all(parent) {
                         if e.kind() != io::ErrorKind::AlreadyExists {
-                            println!(
-                                "[WARN] Unable to create parent directory for persistent state, reason: {}",
+                            warn!(
+                                "Unable to create parent directory for persistent state, reason: {}",
                                 e
                             );
                         }
                     }
                 }
 
-                break 'block PersistentState::new(&config_str);
+                break 'block PersistentState::new(config_str);
             }
-            Err(_) => break 'block PersistentState::new(&config_str),
+            Err(_) => break 'block PersistentState::new(config_str),
         };
 
         match PersistentState::from_reader(BufReader::new(file)) {
             Ok(state) => {
-                println!("[INFO] Loaded persistent state.");
+                info!("Loaded persistent state.");
                 state
             }
 
             Err(e) => {
-                println!(
-                    "[WARN] Couldn't read persistent state file, reason: {}",
-                    e
-                );
-                PersistentState::new(&config_str)
+                warn!("Couldn't read persistent state file, reason: {}", e);
+                PersistentState::new(config_str)
             }
         }
     };
 
-    if !persistent_state.validate_against(&config_str) {
-        println!("[INFO] Discarded the persistent state because config file has changed.")
+    if !persistent_state.validate_against(config_str) {
+        info!("Discarded the persistent state because config file has changed.")
     }
 
-    let update_rate = config.general.update_rate;
+    persistent_state
+}
 
-    println!(
-        "dynners v{} started, updating every {} second(s)",
-        env!("CARGO_PKG_VERSION"),
-        update_rate.map(u32::from).unwrap_or(0)
-    );
+/// Re-reads the config file and builds a new daemon out of it. Once it is in
+/// use, the secrets of the old config file are forgotten.
+fn reload() -> Result<Daemon, String> {
+    let secrets = http::take_secrets();
 
-    // It's safe to unwrap here - the program is single-threaded and USER_AGENT
-    // is never initialized before reaching this point of program.
-    GENERAL_CONFIG.set(config.general).unwrap();
-
-    // Collect IP addresses specified in [ip.*] entries into (ip name, ip)
-    let mut ips = HashMap::with_capacity(config.ip.len());
-    for (name, ip) in config.ip.into_iter() {
-        let mut dyn_ip = match ip::DynamicIp::from_config(&ip) {
-            Ok(d) => d,
-            Err(e) => return println!("Unable to parse IP configuration: {}", e),
-        };
+    let reloaded = reload_config();
+    if reloaded.is_err() {
+        http::restore_secrets(secrets);
+    }
 
-        if let Some(ip) = persistent_state.ip_addresses.get(&name) {
-            println!(
-                "[INFO] Initialized IP {} using the persistent state with {}",
-                &name, &ip
-            );
-            dyn_ip.update_from_cache(*ip);
-        }
+    reloaded
+}
 
-        ips.insert(name, dyn_ip);
-    }
+/// Builds the daemon for `reload`. Most of the [general] section can only be
+/// applied by restarting the program though.
+fn reload_config() -> Result<Daemon, String> {
+    let (config_str, config) = read_config()?;
+
+    // UNWRAP-SAFETY: The general config is always set before reloading.
+    let general = GENERAL_CONFIG.get().unwrap();
+
+    let reloadable = General {
+        update_rate: general.update_rate,
+        debounce: general.debounce,
+        strict: general.strict,
+        events: general.events.clone(),
+        reconcile: general.reconcile,
+        ..config.general.clone()
+    };
 
-    if ips.is_empty() {
-        println!("No IPs were configured. Quitting.");
-        return;
+    if reloadable != *general {
+        warn!("Changes to [general] other than update_rate, debounce, strict, events and reconcile require a restart");
     }
 
-    // Collect IP addresses specified in [ddns.*] entries into (ddns name, ip name)
-    let service_ips = config
-        .ddns
-        .iter()
-        .map(|(name, ddns)| (name, &ddns.ip))
-        .collect::<HashMap<_, _>>();
-
-    // Verify whether the IPs in [ddns.*] are actually specified by [ip.*]
-    let mut errored = false;
-    for (service_name, service_ips) in service_ips.iter() {
-        for ip in service_ips.iter() {
-            if !ips.contains_key(ip) {
-                println!(
-                    "[FATAL] service {}: the IP {} is not specified anywhere in config",
-                    service_name, ip
-                );
-                errored = true
-            }
+    let persistent_state = read_persistent_state(&general.persistent_state, &config_str);
+
+    // The rest stays as it was started with, including the settings handed
+    // to the HTTP clients and IP sources.
+    let mut config = config;
+    config.general = General {
+        update_rate: config.general.update_rate,
+        debounce: config.general.debounce,
+        strict: config.general.strict,
+        events: config.general.events.clone(),
+        reconcile: config.general.reconcile,
+        ..general.clone()
+    };
+
+    Daemon::new(config, &persistent_state)
+}
+
+/// Implements `dynners ctl <command>`, which sends the command to the running
+/// daemon through its control socket.
+fn ctl(args: &[String]) {
+    let command = match args.join(" ").parse::<Command>() {
+        Ok(command) => command,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: dynners ctl status | list | reload | update [service] | suspend <service> | resume <service>");
+            std::process::exit(2);
         }
-    }
+    };
 
-    if errored {
-        return;
-    }
+    // The socket path comes from the config file, if there is one.
+    let path = match read_config() {
+        Ok((_, config)) => config.general.control_socket,
+        Err(_) => match INSTANCE.get() {
+            Some(instance) => config::namespaced_path(&config::default_control_socket(), instance),
+            None => config::default_control_socket(),
+        },
+    };
+
+    match control::send(&path, &command) {
+        Ok(Ok(reply)) => print!("{}", reply),
 
-    // Initialize each DDNS service entry into a `services` array
-    let mut services = Vec::new();
-    for (name, service_conf) in &config.ddns {
-        let service = service_conf.service.clone().into_boxed();
-        services.push((name, service))
+        Ok(Err(reply)) => {
+            print!("{}", reply);
+            std::process::exit(1);
+        }
+
+        Err(e) => {
+            println!("Unable to reach the daemon at {}, reason: {}", path, e);
+            std::process::exit(1);
+        }
     }
+}
+
+/// How often the wall clock is checked while waiting for the next cycle.
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How far ahead the wall clock has to be of the monotonic clock before it
+/// is considered to have jumped.
+const CLOCK_JUMP_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Sleeps until the next update cycle is due, handling the commands received
+/// on the control socket in the meantime. Returns the command which cut the
+/// sleep short, it must be replied to once the next cycle is over.
+///
+/// The monotonic clock stands still while the system is suspended, so the
+/// wall clock is watched as well. If it shows that the cycle is overdue, the
+/// next cycle starts right away instead of leaving the DNS records stale.
+fn wait_for_next_cycle(
+    daemon: &mut Daemon,
+    control: Option<&Receiver<control::Request>>,
+) -> Option<control::Request> {
+    let sleep_for = daemon.sleep_duration()?;
+
+    let started = Instant::now();
+    let wall_started = SystemTime::now();
 
-    // Main loop here
     loop {
-        let mut is_ip_updated = false;
-
-        for (name, ip) in &mut ips {
-            if let Err(e) = ip.update() {
-                println!(
-                    "[ERROR] Unable to update IP {}, reason: {}",
-                    name,
-                    e
+        let elapsed = started.elapsed();
+
+        if elapsed >= sleep_for {
+            return None;
+        }
+
+        // A clock going backwards is of no concern, the monotonic clock
+        // still tells when the cycle is due.
+        if let Ok(wall_elapsed) = wall_started.elapsed() {
+            let jump = wall_elapsed.saturating_sub(elapsed);
+
+            if wall_elapsed >= sleep_for && jump >= CLOCK_JUMP_THRESHOLD {
+                info!(
+                    "The system clock jumped ahead by {} second(s), was the system suspended? Updating now",
+                    jump.as_secs()
                 );
+                daemon.after_clock_jump();
+                return None;
             }
         }
 
-        for (name, service) in services.iter_mut() {
-            let is_dirty = service_ips[name]
-                .iter()
-                .map(|name| &ips[name])
-                .any(|ip| ip.is_dirty());
+        let timeout = (sleep_for - elapsed).min(CLOCK_CHECK_INTERVAL);
 
-            is_ip_updated |= is_dirty;
+        let Some(control) = control else {
+            std::thread::sleep(timeout);
+            continue;
+        };
 
-            if !is_dirty {
+        let request = match control.recv_timeout(timeout) {
+            Ok(request) => request,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                std::thread::sleep(timeout);
                 continue;
             }
+        };
 
-            let ips = service_ips[name]
-                .iter()
-                .map(|name| &ips[name])
-                .filter_map(|ip| ip.address())
-                .cloned()
-                .collect::<Vec<_>>(); // TODO: use collect_into in the future
-
-            match service.update_record(ips.as_slice()) {
-                Ok(updated) => {
-                    for ip in updated.as_slice() {
-                        println!("[INFO] Updated DDNS service {} with IP {}", name, ip);
-                    }
+        let command = request.command.clone();
 
-                    if updated.get(0).is_none() {
-                        println!(
-                            "[INFO] Tried to update DDNS service {}, but no changes were made",
-                            name
-                        );
-                    }
+        match command {
+            Command::Status => request.reply(daemon.status()),
+
+            Command::List => request.reply(daemon.list()),
+
+            Command::Reload => match reload() {
+                Ok(mut reloaded) => {
+                    info!("Reloaded the config file");
+                    reloaded.take_stats_from(daemon);
+                    *daemon = reloaded;
+                    return Some(request);
                 }
 
                 Err(e) => {
-                    println!(
-                        "[ERROR] DDNS service {} failed, reason: {}",
-                        name,
-                        e
-                    )
+                    error!("Unable to reload the config file: {}", e);
+                    request.reply(Err(e));
                 }
-            };
+            },
+
+            Command::Update(service) => match daemon.force_update(service.as_deref()) {
+                Ok(()) => return Some(request),
+                Err(e) => request.reply(Err(e)),
+            },
+
+            Command::Suspend(service) => request.reply(daemon.suspend(&service)),
+
+            Command::Resume(service) => request.reply(daemon.resume(&service)),
         }
+    }
+}
 
-        // We only update the persistent state if any of the IPs have changed.
-        if is_ip_updated {
-            persistent_state = PersistentState::new_with_config_hash(config_hash);
-            persistent_state.ip_addresses = ips
-                .iter()
-                .flat_map(|(name, dyn_ip)| dyn_ip.address().map(|ip| (name.clone(), *ip)))
-                .collect();
+/// Implements `dynners providers`, which lists every kind of DDNS service along
+/// with the keys it accepts in the config file.
+fn providers() {
+    println!(
+        "Every [ddns.*] entry accepts: {}",
+        DdnsConfig::KEYS.join(", ")
+    );
 
-            let path = GENERAL_CONFIG.get().unwrap().persistent_state.as_ref();
+    for provider in DdnsConfigService::PROVIDERS {
+        let records = provider
+            .records
+            .iter()
+            .map(|record| record.to_string())
+            .collect::<Vec<_>>();
+
+        println!();
+        println!("{} ({})", provider.service, provider.name);
+        println!("  records: {}", records.join(", "));
+        println!("  keys: {}", (provider.config_keys)().join(", "));
+    }
+}
 
-            let file = match File::create(path) {
-                Ok(f) => Some(f),
-                Err(_) if path.is_empty() => None,
-                Err(e) => {
-                    println!(
-                        "[WARN] Couldn't open persistent state file for writing: {}",
-                        e
-                    );
-                    None
-                }
-            };
-
-            if let Some(file) = file {
-                match persistent_state.write_to(BufWriter::new(file)) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        println!(
-                            "[WARN] Couldn't write to persistent state file: {}",
-                            e
-                        );
-                    }
+/// Implements `dynners test [service]`, which checks the credentials of every
+/// [ddns.*] entry (or only the given one) through read-only requests, without
+/// modifying any records.
+fn test(args: &[String]) {
+    let (_, config) = match read_config() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let only = args.first().map(String::as_str);
+    let mut failed = false;
+
+    let mut entries = config.ddns.into_iter().collect::<Vec<_>>();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    entries.retain(|(name, _)| only.is_none_or(|only| &**name == only));
+
+    let mut invalid = config.invalid_ddns;
+    invalid.retain(|(name, _)| only.is_none_or(|only| &**name == only));
+
+    if entries.is_empty() && invalid.is_empty() {
+        if let Some(only) = only {
+            println!("No [ddns.{}] entry found", only);
+            std::process::exit(2);
+        }
+    }
+
+    for (name, reason) in invalid {
+        println!("{}: invalid entry, {}", name, reason);
+        failed = true;
+    }
+
+    let settings = config.general.service_settings();
+
+    for (name, ddns) in entries {
+        let provider = ddns.service.provider();
+        let mut service = ddns.service.into_boxed(&settings);
+
+        match service.test_credentials() {
+            Some(Ok(description)) => println!("{}: ok, {}", name, description),
+            Some(Err(e)) => {
+                println!("{}: failed, {}", name, e);
+                failed = true;
+            }
+            None => println!("{}: skipped, {} has no read-only API", name, provider),
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Implements `dynners discover <service>`, which lists the A and AAAA records
+/// the credentials of the [ddns.*] entry can see and prints them as `domains`
+/// lines to be pasted into the config file.
+fn discover(name: &str) {
+    let (_, config) = match read_config() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some((_, reason)) = config.invalid_ddns.iter().find(|(n, _)| &**n == name) {
+        println!("{}: invalid entry, {}", name, reason);
+        std::process::exit(1);
+    }
+
+    let Some(ddns) = config.ddns.get(name) else {
+        println!("No [ddns.{}] entry found", name);
+        std::process::exit(2);
+    };
+
+    let provider = ddns.service.provider();
+    let mut service = ddns
+        .service
+        .clone()
+        .into_boxed(&config.general.service_settings());
+
+    let mut records = match service.discover_records() {
+        Some(Ok(records)) => records,
+        Some(Err(e)) => {
+            println!("{}: failed, {}", name, e);
+            std::process::exit(1);
+        }
+        None => {
+            println!(
+                "{}: {} cannot list the records of an account",
+                name, provider
+            );
+            std::process::exit(1);
+        }
+    };
+
+    records.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let names_of = |kind: Option<RecordType>| {
+        let mut names = records
+            .iter()
+            .filter(|record| kind.is_none_or(|kind| record.kind == kind))
+            .map(|record| &*record.name)
+            .collect::<Vec<_>>();
+        names.dedup();
+        names
+    };
+
+    let all = names_of(None);
+    println!(
+        "# {} A/AAAA record(s) under {} name(s) found for [ddns.{}]",
+        records.len(),
+        all.len(),
+        name
+    );
+
+    if all.is_empty() {
+        return;
+    }
+
+    println!("{}", domains_snippet(&all));
+
+    // Entries updating only one kind of address are better off with only the
+    // names having such a record.
+    for kind in [RecordType::A, RecordType::Aaaa] {
+        let names = names_of(Some(kind));
+        if !names.is_empty() && names.len() != all.len() {
+            println!();
+            println!("# Only the names with an {} record", kind);
+            println!("{}", domains_snippet(&names));
+        }
+    }
+}
+
+/// Formats the names as a `domains = [...]` line of the config file, spread
+/// over several lines once there are more than a few of them.
+fn domains_snippet(names: &[&str]) -> String {
+    let quoted = names
+        .iter()
+        .map(|name| format!("\"{}\"", name))
+        .collect::<Vec<_>>();
+
+    if quoted.len() <= 3 {
+        return format!("domains = [{}]", quoted.join(", "));
+    }
+
+    let mut snippet = String::from("domains = [\n");
+    for name in quoted {
+        snippet += &format!("    {},\n", name);
+    }
+    snippet + "]"
+}
+
+/// Implements `dynners config-hash [file]`, which prints the hash of the given
+/// config file (or the one in use) and whether the persistent state would be
+/// kept with it, so that deployments can tell beforehand.
+fn config_hash(args: &[String]) {
+    let parsed = match args.first() {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}, reason: {}", path, e))
+            .and_then(|config_str| {
+                let mut config = Config::parse(&config_str).map_err(|e| e.to_string())?;
+
+                if let Some(instance) = INSTANCE.get() {
+                    config.general.namespace(instance);
                 }
+
+                Ok((config_str, config))
+            }),
+        None => read_config(),
+    };
+
+    let (config_str, config) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let hash = persistence::config_hash(&config_str);
+    println!("{:016x}", hash);
+
+    let path = &config.general.persistent_state;
+    let state =
+        File::open(&**path).and_then(|file| PersistentState::from_reader(BufReader::new(file)));
+
+    match state {
+        Ok(state) if state.config_hash == hash => {
+            println!("The persistent state in {} would be kept", path)
+        }
+        Ok(_) => println!("The persistent state in {} would be discarded", path),
+        Err(e) => println!("No usable persistent state in {}, reason: {}", path, e),
+    }
+}
+
+/// Instance names end up in file names, so keep them simple.
+fn is_valid_instance(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn main() {
+    const USAGE: &str =
+        "Usage: dynners [--instance <name>] [ctl <command> | providers | test [service] | discover <service> | config-hash [file]]";
+
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if args.first().map(String::as_str) == Some("--instance") {
+        match args.get(1) {
+            Some(name) if is_valid_instance(name) => {
+                // UNWRAP-SAFETY: Nothing has set the instance before this.
+                INSTANCE.set(name.as_str().into()).unwrap();
+            }
+            Some(name) => {
+                println!("Invalid instance name: {}", name);
+                println!("Instance names may only contain letters, digits, '-', '_' and '.'");
+                std::process::exit(2);
+            }
+            None => {
+                println!("{}", USAGE);
+                std::process::exit(2);
+            }
+        }
+
+        args.drain(..2);
+    }
+
+    match args.first().map(String::as_str) {
+        None => (),
+        Some("ctl") => return ctl(&args[1..]),
+        Some("providers") => return providers(),
+        Some("discover") if args.len() == 2 => {
+            check_curl_version();
+            return discover(&args[1]);
+        }
+        Some("config-hash") if args.len() <= 2 => return config_hash(&args[1..]),
+        Some("test") if args.len() <= 2 => {
+            check_curl_version();
+            return test(&args[1..]);
+        }
+        Some(arg) => {
+            println!("Unknown argument: {}", arg);
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+
+    check_curl_version();
+
+    let (config_str, config) = match read_config() {
+        Ok(config) => config,
+        Err(e) => return println!("{}", e),
+    };
+
+    let instance = match INSTANCE.get() {
+        Some(instance) => format!(" (instance {})", instance),
+        None => String::new(),
+    };
+
+    println!(
+        "dynners v{}{} started, updating every {} second(s)",
+        env!("CARGO_PKG_VERSION"),
+        instance,
+        config.general.update_rate.map(u32::from).unwrap_or(0)
+    );
+
+    // It's safe to unwrap here - the program is single-threaded and USER_AGENT
+    // is never initialized before reaching this point of program.
+    GENERAL_CONFIG.set(config.general.clone()).unwrap();
+
+    let persistent_state = read_persistent_state(&config.general.persistent_state, &config_str);
+
+    let mut daemon = match Daemon::new(config, &persistent_state) {
+        Ok(daemon) => daemon,
+        Err(e) => return println!("{}", e),
+    };
+
+    // A fire-once program has nothing to be controlled.
+    let control = match GENERAL_CONFIG.get().unwrap().control_socket.as_ref() {
+        _ if daemon.update_rate().is_none() => None,
+        "" => None,
+        path => match control::listen(path) {
+            Ok(control) => Some(control),
+            Err(e) => {
+                warn!("Unable to listen on control socket {}, reason: {}", path, e);
+                None
             }
+        },
+    };
+
+    let mut waiting: Option<control::Request> = None;
+
+    // Main loop here
+    loop {
+        let summary = daemon.run_cycle();
+
+        if let Some(request) = waiting.take() {
+            request.reply(Ok(summary.to_string()));
         }
 
-        if let Some(sleep_for) = &update_rate {
-            std::thread::sleep(Duration::from_secs(sleep_for.get() as u64));
-        } else {
+        if daemon.update_rate().is_none() {
             break; // 0 timeout makes this a fire-once program.
         }
+
+        waiting = wait_for_next_cycle(&mut daemon, control.as_ref());
     }
 }
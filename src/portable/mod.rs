@@ -0,0 +1,9 @@
+//! Logic which agents other than dynners may want to share with it, e.g. one
+//! built into the firmware of a router. Nothing in here does any IO or needs
+//! std, only `core` and `alloc`: the persistent state is decoded from and
+//! encoded into byte buffers, reading and writing the file is left to the
+//! caller. The only dependency is crc32fast, which builds without std when
+//! its default features are disabled.
+
+pub mod netmask;
+pub mod state;
@@ -0,0 +1,594 @@
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use core::str::FromStr;
+
+/// A network of either IP version. This is mostly useful when the version is
+/// not known in advance, e.g. when validating user-provided netmasks.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Network {
+    V4(NetworkV4),
+    V6(NetworkV6),
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NetworkV4 {
+    address: Ipv4Addr,
+    mask: Ipv4Addr,
+}
+
+impl Default for NetworkV4 {
+    fn default() -> Self {
+        Self {
+            address: Ipv4Addr::from(0),
+            mask: Ipv4Addr::from(0),
+        }
+    }
+}
+
+impl Display for NetworkV4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prefix_len() {
+            Some(prefix) => write!(f, "{}/{}", self.address, prefix),
+            None => write!(f, "{}/{}", self.address, self.mask),
+        }
+    }
+}
+
+impl Debug for NetworkV4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NetworkV6 {
+    address: Ipv6Addr,
+    mask: Ipv6Addr,
+}
+
+impl Default for NetworkV6 {
+    fn default() -> Self {
+        Self {
+            address: Ipv6Addr::from(0),
+            mask: Ipv6Addr::from(0),
+        }
+    }
+}
+
+impl Display for NetworkV6 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prefix_len() {
+            Some(prefix) => write!(f, "{}/{}", self.address, prefix),
+            None => write!(f, "{}/{}", self.address, self.mask),
+        }
+    }
+}
+
+impl Debug for NetworkV6 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Network::V4(v4) => Display::fmt(v4, f),
+            Network::V6(v6) => Display::fmt(v6, f),
+        }
+    }
+}
+
+impl Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+fn v4_to_u32(ipv4: Ipv4Addr) -> u32 {
+    u32::from_ne_bytes(ipv4.octets())
+}
+
+fn v6_to_u128(ipv6: Ipv6Addr) -> u128 {
+    u128::from_ne_bytes(ipv6.octets())
+}
+
+impl FromStr for Network {
+    type Err = NetworkParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Only IPv6 addresses can contain colons.
+        if s.contains(':') {
+            s.parse::<NetworkV6>().map(Network::V6)
+        } else {
+            s.parse::<NetworkV4>().map(Network::V4)
+        }
+    }
+}
+
+impl NetworkV4 {
+    pub fn from_prefix(addr: Ipv4Addr, prefix: u8) -> Self {
+        let bits = (32 - prefix) as u32;
+        let mask = if bits < 32 { !0u32 >> bits << bits } else { 0 };
+
+        Self {
+            address: addr,
+            mask: Ipv4Addr::from(mask),
+        }
+    }
+
+    pub fn from_mask(addr: Ipv4Addr, mask: Ipv4Addr) -> Self {
+        Self {
+            address: addr,
+            mask,
+        }
+    }
+
+    /// Returns the prefix length, or None if the mask is not contiguous (and
+    /// thus cannot be written in CIDR notation).
+    pub fn prefix_len(&self) -> Option<u8> {
+        let mask = u32::from(self.mask);
+
+        if mask.leading_ones() + mask.trailing_zeros() == 32 {
+            Some(mask.leading_ones() as u8)
+        } else {
+            None
+        }
+    }
+
+    pub fn in_range(&self, addr: Ipv4Addr) -> bool {
+        (v4_to_u32(self.address) & v4_to_u32(self.mask)) == (v4_to_u32(addr) & v4_to_u32(self.mask))
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(v4) => self.in_range(v4),
+            IpAddr::V6(_) => false,
+        }
+    }
+}
+
+/// A list of networks an address must (or must not) fall into. Rules prefixed
+/// with "!" are exclusions: an address matches when it is in range of any of
+/// the included networks and none of the excluded ones. If no inclusions are
+/// given, every address is included by default, e.g. `["!fe80::/10"]` matches
+/// everything except link-local addresses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetworkRules<N> {
+    include: Vec<N>,
+    exclude: Vec<N>,
+}
+
+impl<N> NetworkRules<N>
+where
+    N: FromStr<Err = NetworkParseErr>,
+{
+    pub fn parse<S: AsRef<str>>(rules: &[S]) -> Result<Self, NetworkParseErr> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+
+        for rule in rules {
+            let rule = rule.as_ref().trim();
+
+            if let Some(excluded) = rule.strip_prefix('!') {
+                exclude.push(excluded.trim().parse::<N>()?);
+            } else {
+                include.push(rule.parse::<N>()?);
+            }
+        }
+
+        Ok(Self { include, exclude })
+    }
+}
+
+impl NetworkRules<Network> {
+    /// Narrows the rules down to IPv4 networks. If any of the rules is not an
+    /// IPv4 network, it is returned as the error.
+    pub fn into_v4(self) -> Result<NetworkRules<NetworkV4>, Network> {
+        let narrow = |networks: Vec<Network>| {
+            networks
+                .into_iter()
+                .map(|net| match net {
+                    Network::V4(v4) => Ok(v4),
+                    other => Err(other),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(NetworkRules {
+            include: narrow(self.include)?,
+            exclude: narrow(self.exclude)?,
+        })
+    }
+
+    /// Narrows the rules down to IPv6 networks. If any of the rules is not an
+    /// IPv6 network, it is returned as the error.
+    pub fn into_v6(self) -> Result<NetworkRules<NetworkV6>, Network> {
+        let narrow = |networks: Vec<Network>| {
+            networks
+                .into_iter()
+                .map(|net| match net {
+                    Network::V6(v6) => Ok(v6),
+                    other => Err(other),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(NetworkRules {
+            include: narrow(self.include)?,
+            exclude: narrow(self.exclude)?,
+        })
+    }
+}
+
+impl NetworkRules<NetworkV4> {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let included = if self.include.is_empty() {
+            addr.is_ipv4()
+        } else {
+            self.include.iter().any(|net| net.contains(addr))
+        };
+
+        included && !self.exclude.iter().any(|net| net.contains(addr))
+    }
+}
+
+impl NetworkRules<NetworkV6> {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let included = if self.include.is_empty() {
+            addr.is_ipv6()
+        } else {
+            self.include.iter().any(|net| net.contains(addr))
+        };
+
+        included && !self.exclude.iter().any(|net| net.contains(addr))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NetworkParseErr {
+    MaskUnspecified,
+    InvalidAddress,
+    InvalidMask,
+    MaskTooLarge,
+}
+
+// thiserror needs std, hence the hand-written impls.
+impl Display for NetworkParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NetworkParseErr::MaskUnspecified => "a prefix or bitmask was not specified",
+            NetworkParseErr::InvalidAddress => "an invalid address was specified",
+            NetworkParseErr::InvalidMask => "an invalid netmask was provided",
+            NetworkParseErr::MaskTooLarge => "the provided netmask was too large for the protocol",
+        })
+    }
+}
+
+impl core::error::Error for NetworkParseErr {}
+
+impl FromStr for NetworkV4 {
+    type Err = NetworkParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(slash) = s.find('/') else {
+            return Err(NetworkParseErr::MaskUnspecified);
+        };
+
+        let (addr, mask) = s.split_at(slash);
+        let mask = &mask[1..];
+
+        let Ok(addr) = addr.parse::<Ipv4Addr>() else {
+            return Err(NetworkParseErr::InvalidAddress);
+        };
+
+        if let Ok(prefix) = mask.parse::<u8>() {
+            if prefix <= 32 {
+                Ok(NetworkV4::from_prefix(addr, prefix))
+            } else {
+                Err(NetworkParseErr::MaskTooLarge)
+            }
+        } else if let Ok(mask) = mask.parse::<Ipv4Addr>() {
+            Ok(NetworkV4::from_mask(addr, mask))
+        } else {
+            Err(NetworkParseErr::InvalidMask)
+        }
+    }
+}
+
+impl NetworkV6 {
+    pub fn from_prefix(addr: Ipv6Addr, prefix: u8) -> Self {
+        let bits = (128 - prefix) as u32;
+        let mask = if bits < 128 {
+            !0u128 >> bits << bits
+        } else {
+            0
+        };
+        Self {
+            address: addr,
+            mask: Ipv6Addr::from(mask),
+        }
+    }
+
+    pub fn from_mask(addr: Ipv6Addr, mask: Ipv6Addr) -> Self {
+        Self {
+            address: addr,
+            mask,
+        }
+    }
+
+    /// Returns the prefix length, or None if the mask is not contiguous (and
+    /// thus cannot be written in CIDR notation).
+    pub fn prefix_len(&self) -> Option<u8> {
+        let mask = u128::from(self.mask);
+
+        if mask.leading_ones() + mask.trailing_zeros() == 128 {
+            Some(mask.leading_ones() as u8)
+        } else {
+            None
+        }
+    }
+
+    pub fn in_range(&self, addr: Ipv6Addr) -> bool {
+        (v6_to_u128(self.address) & v6_to_u128(self.mask))
+            == (v6_to_u128(addr) & v6_to_u128(self.mask))
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(_) => false,
+            IpAddr::V6(v6) => self.in_range(v6),
+        }
+    }
+}
+
+impl FromStr for NetworkV6 {
+    type Err = NetworkParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(slash) = s.find('/') else {
+            return Err(NetworkParseErr::MaskUnspecified);
+        };
+
+        let (addr, mask) = s.split_at(slash);
+        let mask = &mask[1..];
+
+        let Ok(addr) = addr.parse::<Ipv6Addr>() else {
+            return Err(NetworkParseErr::InvalidAddress);
+        };
+
+        if let Ok(prefix) = mask.parse::<u8>() {
+            if prefix <= 128 {
+                Ok(NetworkV6::from_prefix(addr, prefix))
+            } else {
+                Err(NetworkParseErr::MaskTooLarge)
+            }
+        } else if let Ok(mask) = mask.parse::<Ipv6Addr>() {
+            Ok(NetworkV6::from_mask(addr, mask))
+        } else {
+            Err(NetworkParseErr::InvalidMask)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::{Network, NetworkRules, NetworkV4, NetworkV6};
+
+    #[test]
+    fn network_v4() {
+        let addr = "198.51.100.2".parse::<Ipv4Addr>().unwrap();
+        let network = NetworkV4::from_prefix(addr, 24);
+
+        assert!(network.in_range("198.51.100.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.51.100.1".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.51.100.98".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.51.100.255".parse::<Ipv4Addr>().unwrap()));
+        assert!(!network.in_range("198.51.101.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(!network.in_range("198.52.101.132".parse::<Ipv4Addr>().unwrap()));
+
+        let network = NetworkV4::from_prefix(addr, 8);
+        assert!(network.in_range("198.0.0.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.10.0.1".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.51.100.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.51.100.1".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.128.100.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.255.100.1".parse::<Ipv4Addr>().unwrap()));
+        assert!(!network.in_range("199.0.100.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(!network.in_range("255.255.255.0".parse::<Ipv4Addr>().unwrap()));
+
+        let network = NetworkV4::from_prefix(addr, 0);
+        assert!(network.in_range("198.0.0.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.10.0.1".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.51.100.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.51.100.1".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.128.100.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.255.100.1".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("199.0.100.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("255.255.255.0".parse::<Ipv4Addr>().unwrap()));
+
+        let mask = "0.0.255.255".parse::<Ipv4Addr>().unwrap();
+        let network = NetworkV4::from_mask(addr, mask);
+        assert!(network.in_range("198.0.100.2".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.10.100.2".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("198.51.100.2".parse::<Ipv4Addr>().unwrap()));
+        assert!(network.in_range("255.51.100.2".parse::<Ipv4Addr>().unwrap()));
+        assert!(!network.in_range("198.0.98.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(!network.in_range("198.10.10.1".parse::<Ipv4Addr>().unwrap()));
+        assert!(!network.in_range("198.51.100.0".parse::<Ipv4Addr>().unwrap()));
+        assert!(!network.in_range("255.51.100.1".parse::<Ipv4Addr>().unwrap()));
+    }
+
+    #[test]
+    fn network_v6() {
+        let addr = "fe80::1234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap();
+        let network = NetworkV6::from_prefix(addr, 64);
+
+        assert!(network.in_range("fe80::1".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("fe80::ff:1".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("fe80::8:7:6:5".parse::<Ipv6Addr>().unwrap()));
+        assert!(!network.in_range("fe81::1".parse::<Ipv6Addr>().unwrap()));
+        assert!(!network.in_range("2001:db8:ff::1".parse::<Ipv6Addr>().unwrap()));
+        assert!(!network.in_range("8:7:6:5:4:3:2:1".parse::<Ipv6Addr>().unwrap()));
+
+        let mask = "::ffff:ffff:ffff:ffff".parse::<Ipv6Addr>().unwrap();
+        let network = NetworkV6::from_mask(addr, mask);
+
+        assert!(network.in_range("2001:db8::1234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("2001:db9::1234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("fe80::1234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(!network.in_range("fe80::2234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(!network.in_range("2001::2234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(!network.in_range("fe80:1234:cafe:babe::".parse::<Ipv6Addr>().unwrap()));
+
+        let network = NetworkV6::from_prefix(addr, 0);
+        assert!(network.in_range("2001:db8::1234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("2001:db9::1234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("fe80::1234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("8:7:6:5:4:3:2:1".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("2001::2234:cafe:babe:5678".parse::<Ipv6Addr>().unwrap()));
+        assert!(network.in_range("fe80:1234:cafe:babe::".parse::<Ipv6Addr>().unwrap()));
+    }
+
+    #[test]
+    fn network_parse() {
+        "192.168.0.1/255.255.255.255".parse::<NetworkV4>().unwrap();
+        "192.168.0.1/255.255.0.255".parse::<NetworkV4>().unwrap();
+        "192.168.0.1/0".parse::<NetworkV4>().unwrap();
+        "192.168.0.1/16".parse::<NetworkV4>().unwrap();
+        "192.168.0.1/32".parse::<NetworkV4>().unwrap();
+        "255.255.255.255/255.255.255.255"
+            .parse::<NetworkV4>()
+            .unwrap();
+        "255.255.255.255/255.255.0.255"
+            .parse::<NetworkV4>()
+            .unwrap();
+        "255.255.255.255/32".parse::<NetworkV4>().unwrap();
+        "255.255.255.255/0".parse::<NetworkV4>().unwrap();
+
+        "fe80::/0".parse::<NetworkV6>().unwrap();
+        "fe80::/10".parse::<NetworkV6>().unwrap();
+        "fe80::/64".parse::<NetworkV6>().unwrap();
+        "fe80::/128".parse::<NetworkV6>().unwrap();
+        "::dead:beef/::ffff:ffff:ffff:ffff"
+            .parse::<NetworkV6>()
+            .unwrap();
+        "::dead:beef/::f00f:ffff:f00f:ffff"
+            .parse::<NetworkV6>()
+            .unwrap();
+        "2001:db8::/ffff:ffff::".parse::<NetworkV6>().unwrap();
+        "2001:db8::/f0f0:fcfc::".parse::<NetworkV6>().unwrap();
+
+        assert!("255.255.255.255/33".parse::<NetworkV4>().is_err());
+        assert!("::/129".parse::<NetworkV6>().is_err())
+    }
+
+    #[test]
+    fn network_rules() {
+        let rules = NetworkRules::<NetworkV6>::parse(&["2000::/3", "!2001:db8:bad::/48"]).unwrap();
+        assert!(rules.contains("2001:db8::1".parse::<IpAddr>().unwrap()));
+        assert!(rules.contains("2001:db8:bae::1".parse::<IpAddr>().unwrap()));
+        assert!(rules.contains("2a00:1450::1".parse::<IpAddr>().unwrap()));
+        assert!(!rules.contains("2001:db8:bad::1".parse::<IpAddr>().unwrap()));
+        assert!(!rules.contains("2001:db8:bad:ffff::1".parse::<IpAddr>().unwrap()));
+        assert!(!rules.contains("fe80::1".parse::<IpAddr>().unwrap()));
+
+        let rules = NetworkRules::<NetworkV6>::parse(&["! fe80::/10"]).unwrap();
+        assert!(rules.contains("2001:db8::1".parse::<IpAddr>().unwrap()));
+        assert!(!rules.contains("fe80::1".parse::<IpAddr>().unwrap()));
+
+        let rules = NetworkRules::<NetworkV4>::parse(&[
+            "10.0.0.0/8",
+            "192.168.0.0/16",
+            "!10.1.0.0/16",
+            "!192.168.1.1/32",
+        ])
+        .unwrap();
+        assert!(rules.contains("10.0.0.1".parse::<IpAddr>().unwrap()));
+        assert!(rules.contains("192.168.1.2".parse::<IpAddr>().unwrap()));
+        assert!(!rules.contains("10.1.2.3".parse::<IpAddr>().unwrap()));
+        assert!(!rules.contains("192.168.1.1".parse::<IpAddr>().unwrap()));
+        assert!(!rules.contains("172.16.0.1".parse::<IpAddr>().unwrap()));
+
+        let rules = NetworkRules::<NetworkV4>::parse::<&str>(&[]).unwrap();
+        assert!(rules.contains("198.51.100.1".parse::<IpAddr>().unwrap()));
+
+        assert!(!rules.contains("::ffff:10.0.0.1".parse::<IpAddr>().unwrap()));
+
+        assert!(NetworkRules::<NetworkV4>::parse(&["!"]).is_err());
+        assert!(NetworkRules::<NetworkV4>::parse(&["10.0.0.0/8", "!::/0"]).is_err());
+
+        let rules = NetworkRules::<Network>::parse(&["10.0.0.0/8", "!10.1.0.0/16"]).unwrap();
+        assert!(rules.clone().into_v4().is_ok());
+        assert_eq!(
+            rules.into_v6().unwrap_err(),
+            "10.0.0.0/8".parse::<Network>().unwrap()
+        );
+
+        let rules = NetworkRules::<Network>::parse(&["2000::/3", "!10.0.0.0/8"]).unwrap();
+        assert!(rules.clone().into_v4().is_err());
+        assert!(rules.into_v6().is_err());
+    }
+
+    #[test]
+    fn network_display() {
+        let networks = [
+            "192.168.0.1/24",
+            "192.168.0.1/0",
+            "192.168.0.1/32",
+            "192.168.0.1/255.255.0.255",
+            "2001:db8::/32",
+            "fe80::1234:cafe:babe:5678/64",
+            "::/0",
+            "::dead:beef/::f00f:ffff:f00f:ffff",
+        ];
+
+        for network in networks {
+            assert_eq!(network.parse::<Network>().unwrap().to_string(), network);
+        }
+
+        assert_eq!(
+            "10.0.0.1/255.255.255.0"
+                .parse::<NetworkV4>()
+                .unwrap()
+                .to_string(),
+            "10.0.0.1/24"
+        );
+        assert_eq!(
+            "2001:db8::1/ffff:ffff::"
+                .parse::<NetworkV6>()
+                .unwrap()
+                .to_string(),
+            "2001:db8::1/32"
+        );
+
+        let network = "10.0.0.1/255.0.255.0".parse::<NetworkV4>().unwrap();
+        assert_eq!(network.prefix_len(), None);
+        let network = "10.0.0.1/255.255.0.0".parse::<NetworkV4>().unwrap();
+        assert_eq!(network.prefix_len(), Some(16));
+        let network = "::/ffff::ffff".parse::<NetworkV6>().unwrap();
+        assert_eq!(network.prefix_len(), None);
+        let network = "2001:db8::/48".parse::<NetworkV6>().unwrap();
+        assert_eq!(network.prefix_len(), Some(48));
+
+        let Network::V6(network) = "2001:db8::/32".parse::<Network>().unwrap() else {
+            panic!("expected an IPv6 network");
+        };
+        assert!(network.contains("2001:db8:1::1".parse::<IpAddr>().unwrap()));
+        assert!(!network.contains("2001:db9::1".parse::<IpAddr>().unwrap()));
+        assert!(!network.contains("32.1.13.184".parse::<IpAddr>().unwrap()));
+
+        let Network::V4(network) = "198.51.100.0/24".parse::<Network>().unwrap() else {
+            panic!("expected an IPv4 network");
+        };
+        assert!(network.contains("198.51.100.7".parse::<IpAddr>().unwrap()));
+        assert!(!network.contains("::ffff:198.51.100.7".parse::<IpAddr>().unwrap()));
+
+        assert!("198.51.100.0".parse::<Network>().is_err());
+        assert!("198.51.100.0/::".parse::<Network>().is_err());
+        assert!(":/0".parse::<Network>().is_err());
+    }
+}
@@ -0,0 +1,532 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use core::num::Wrapping;
+
+/// The current persistent state file version. The program must reject state
+/// files newer than this, and must upgrade or reject state files older than
+/// this.
+pub const STATE_VERSION: u32 = 2;
+
+/// The magic number present in every persistent state file.
+const MAGIC: &[u8; 8] = b"dynners\0";
+
+/// This struct stores all program states that will survive between multiple
+/// sessions. This is to prevent dynners from sending excessive update requests
+/// to the DDNS providers in scenarios like user restarting the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistentState {
+    /// The version of the persistent state. It is not required to be in sync
+    /// with the version of dynners itself, increment only when the file format
+    /// changes.
+    pub version: u32,
+
+    /// Unix timestamp in seconds, it is stored for debugging? purposes and has
+    /// no practical meaning beyond that.
+    pub update_timestamp: u64,
+
+    /// The config file hash. If the config file is modified, the persistent
+    /// state will be invalidated.
+    pub config_hash: u64,
+
+    /// The IP addresses from last session. On disk, each entry will be stored
+    /// as a tuple of:
+    ///     - name_length: u32
+    ///     - name: string,
+    ///     - ip_type: u8 (represented using the enum IpType)
+    ///     - ip: (u32 | u128) with size depending on ip_type
+    pub ip_addresses: BTreeMap<Box<str>, IpAddr>,
+
+    /// The DDNS services suspended using `dynners ctl suspend`. Unlike the IP
+    /// addresses, these are kept even if the config file is modified.
+    ///
+    /// Since version 2, the IP addresses are followed by a name_length of 0
+    /// and a list of sections, each of them stored as a tuple of:
+    ///     - tag: u8 (represented using the enum SectionTag)
+    ///     - length: u32
+    ///     - payload: [u8; length]
+    /// Sections with unknown tags are skipped. The last section is a checksum
+    /// of everything before it, files written before it was added have none.
+    pub suspended_services: BTreeSet<Box<str>>,
+
+    /// The addresses obtained from the IP sources with a `cache_ttl`. These
+    /// are kept even if the config file is modified, each of them is only
+    /// trusted if its source is configured the same way as it was before.
+    pub cached_ips: BTreeMap<Box<str>, CachedIp>,
+
+    /// When the DDNS services which expire unless they are refreshed were
+    /// last updated, as Unix timestamps in seconds. These are kept even if
+    /// the config file is modified.
+    pub refreshed_services: BTreeMap<Box<str>, u64>,
+}
+
+/// An IP address along with where and when it was obtained.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedIp {
+    /// The hash of the configuration of the IP source, see `hash_bytes`.
+    pub source_hash: u64,
+
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+
+    pub address: IpAddr,
+}
+
+enum IpType {
+    Ipv4 = 0,
+    Ipv6 = 1,
+}
+
+enum SectionTag {
+    /// The payload is a list of (name_length: u32, name: string).
+    SuspendedServices = 0,
+
+    /// The payload is a list of (name_length: u32, name: string,
+    /// source_hash: u64, timestamp: u64, ip_type: u8, ip: u32 | u128).
+    CachedIps = 1,
+
+    /// The payload is a list of (name_length: u32, name: string,
+    /// timestamp: u64).
+    RefreshedServices = 3,
+
+    /// The payload is (algorithm: u8, checksum: [u8]), the checksum covering
+    /// every byte of the file before the tag of this section. It must be the
+    /// last section.
+    Checksum = 2,
+}
+
+/// The algorithms the checksum section may be computed with. Checksums of an
+/// unknown algorithm are not verified, so that a newer version can switch to
+/// another one without older versions rejecting its files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// The payload is (algorithm: u8, crc32: u32).
+    Crc32 = 0,
+}
+
+impl ChecksumAlgorithm {
+    fn from_u8(algorithm: u8) -> Option<Self> {
+        match algorithm {
+            0 => Some(ChecksumAlgorithm::Crc32),
+            _ => None,
+        }
+    }
+
+    pub fn checksum(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ChecksumAlgorithm::Crc32 => crc32fast::hash(data).to_le_bytes().to_vec(),
+        }
+    }
+}
+
+/// The reasons a persistent state file may be rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// The file ended while reading the named field.
+    UnexpectedEof(&'static str),
+    InvalidMagic,
+    InvalidVersion,
+    TooNew,
+    NonUtf8Name,
+    InvalidIpType,
+
+    /// The checksum does not match the content, or something follows it.
+    ChecksumMismatch,
+
+    /// A section of a known tag could not be read, the field names the kind
+    /// of its entries.
+    MalformedSection(&'static str),
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::UnexpectedEof(field) => {
+                write!(f, "EOF while reading persistent state file for {}", field)
+            }
+            FormatError::InvalidMagic => f.write_str("unexpected file format: invalid magic"),
+            FormatError::InvalidVersion => f.write_str("unexpected file format: invalid version"),
+            FormatError::TooNew => f.write_str("the persistent state file is too new"),
+            FormatError::NonUtf8Name => f.write_str("unexpected non-UTF8 IP address name"),
+            FormatError::InvalidIpType => f.write_str("unexpected IP type"),
+            FormatError::ChecksumMismatch => {
+                f.write_str("the checksum does not match, the file is corrupted")
+            }
+            FormatError::MalformedSection(kind) => {
+                write!(f, "unexpected file format: malformed list of {}", kind)
+            }
+        }
+    }
+}
+
+impl core::error::Error for FormatError {}
+
+/// Hashes the content of a config file. The persistent state is discarded
+/// whenever this changes, so tools deploying a config file can tell in
+/// advance whether it is going to invalidate the state.
+pub fn config_hash(config: &str) -> u64 {
+    hash_bytes(config.as_bytes())
+}
+
+pub fn hash_bytes(s: &[u8]) -> u64 {
+    // Absolutely zero thinking went into the designing of this algorithm.
+    // Don't take it too seriously. This can be changed as needed.
+    let hash1 = crc32fast::hash(s);
+
+    let mut hash2 = Wrapping(hash1);
+    for byte in s {
+        hash2 *= 65539;
+        hash2 += *byte as u32;
+    }
+
+    ((hash1 as u64) << 32) | (hash2.0 as u64)
+}
+
+/// Splits the first N bytes off the buffer, or fails with the given error.
+fn take<'a, const N: usize>(
+    buffer: &mut &'a [u8],
+    error: impl FnOnce() -> FormatError,
+) -> Result<&'a [u8; N], FormatError> {
+    let (taken, rest) = buffer.split_first_chunk::<N>().ok_or_else(error)?;
+    *buffer = rest;
+    Ok(taken)
+}
+
+/// Splits a (name_length: u32, name: string) off the buffer.
+fn take_name<'a>(
+    buffer: &mut &'a [u8],
+    error: impl Fn() -> FormatError,
+) -> Result<&'a str, FormatError> {
+    let length = u32::from_le_bytes(*take::<4>(buffer, &error)?) as usize;
+
+    if buffer.len() < length {
+        return Err(error());
+    }
+
+    let (name, rest) = buffer.split_at(length);
+    *buffer = rest;
+
+    core::str::from_utf8(name).map_err(|_| error())
+}
+
+/// Splits an (ip_type: u8, ip: u32 | u128) off the buffer.
+fn take_ip(
+    buffer: &mut &[u8],
+    eof: impl Fn() -> FormatError,
+    invalid: impl Fn() -> FormatError,
+) -> Result<IpAddr, FormatError> {
+    let [ip_type] = *take::<1>(buffer, &eof)?;
+
+    if ip_type == IpType::Ipv4 as u8 {
+        let ip = take::<4>(buffer, eof)?;
+        Ok(IpAddr::V4(Ipv4Addr::from(u32::from_le_bytes(*ip))))
+    } else if ip_type == IpType::Ipv6 as u8 {
+        let ip = take::<16>(buffer, eof)?;
+        Ok(IpAddr::V6(Ipv6Addr::from(u128::from_le_bytes(*ip))))
+    } else {
+        Err(invalid())
+    }
+}
+
+fn put_name(buffer: &mut Vec<u8>, name: &str) {
+    buffer.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(name.as_bytes());
+}
+
+fn put_ip(buffer: &mut Vec<u8>, ip: IpAddr) {
+    match ip {
+        IpAddr::V4(v4) => {
+            buffer.push(IpType::Ipv4 as u8);
+            buffer.extend_from_slice(&u32::from(v4).to_le_bytes());
+        }
+
+        IpAddr::V6(v6) => {
+            buffer.push(IpType::Ipv6 as u8);
+            buffer.extend_from_slice(&u128::from(v6).to_le_bytes());
+        }
+    }
+}
+
+impl PersistentState {
+    /// Creates an empty state. The timestamp is left to the caller, as there
+    /// is no clock to read here.
+    pub fn with_config_hash(config_hash: u64, update_timestamp: u64) -> Self {
+        Self {
+            version: STATE_VERSION,
+            update_timestamp,
+            config_hash,
+            ip_addresses: BTreeMap::new(),
+            suspended_services: BTreeSet::new(),
+            cached_ips: BTreeMap::new(),
+            refreshed_services: BTreeMap::new(),
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, FormatError> {
+        let mut buffer = data;
+        let buffer = &mut buffer;
+        let eof = |field| move || FormatError::UnexpectedEof(field);
+
+        if take::<8>(buffer, eof("magic"))? != MAGIC {
+            return Err(FormatError::InvalidMagic);
+        }
+
+        let version = u32::from_le_bytes(*take::<4>(buffer, eof("version"))?);
+
+        // Reject newer persistence state files.
+        if version > STATE_VERSION {
+            return Err(FormatError::TooNew);
+        } else if version == 0 {
+            return Err(FormatError::InvalidVersion);
+        }
+
+        let update_timestamp = u64::from_le_bytes(*take::<8>(buffer, eof("update timestamp"))?);
+        let config_hash = u64::from_le_bytes(*take::<8>(buffer, eof("config hash"))?);
+
+        // Files of version 1 simply end after the last IP address.
+        let mut ip_addresses = BTreeMap::new();
+        while !buffer.is_empty() {
+            let name_len = take::<4>(buffer, eof("name length"))?;
+            let name_len = u32::from_le_bytes(*name_len) as usize;
+
+            if name_len == 0 {
+                break;
+            }
+
+            if buffer.len() < name_len {
+                return Err(FormatError::UnexpectedEof("name"));
+            }
+
+            let (name, rest) = buffer.split_at(name_len);
+            *buffer = rest;
+
+            let Ok(name) = core::str::from_utf8(name) else {
+                return Err(FormatError::NonUtf8Name);
+            };
+
+            let ip = take_ip(buffer, eof("IP address"), || FormatError::InvalidIpType)?;
+            ip_addresses.insert(name.into(), ip);
+        }
+
+        let mut suspended_services = BTreeSet::new();
+        let mut cached_ips = BTreeMap::new();
+        let mut refreshed_services = BTreeMap::new();
+        while !buffer.is_empty() {
+            let covered = &data[..data.len() - buffer.len()];
+
+            let [tag] = *take::<1>(buffer, eof("section tag"))?;
+            let length = u32::from_le_bytes(*take::<4>(buffer, eof("section length"))?) as usize;
+
+            if buffer.len() < length {
+                return Err(FormatError::UnexpectedEof("section"));
+            }
+
+            let (payload, rest) = buffer.split_at(length);
+            *buffer = rest;
+
+            if tag == SectionTag::SuspendedServices as u8 {
+                suspended_services = decode_names(payload)?;
+            } else if tag == SectionTag::CachedIps as u8 {
+                cached_ips = decode_cached_ips(payload)?;
+            } else if tag == SectionTag::RefreshedServices as u8 {
+                refreshed_services = decode_timestamps(payload)?;
+            } else if tag == SectionTag::Checksum as u8 {
+                let Some((&algorithm, checksum)) = payload.split_first() else {
+                    return Err(FormatError::MalformedSection("checksums"));
+                };
+
+                if let Some(algorithm) = ChecksumAlgorithm::from_u8(algorithm) {
+                    if *algorithm.checksum(covered) != *checksum || !buffer.is_empty() {
+                        return Err(FormatError::ChecksumMismatch);
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            version,
+            update_timestamp,
+            config_hash,
+            ip_addresses,
+            suspended_services,
+            cached_ips,
+            refreshed_services,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+
+        buffer.extend_from_slice(MAGIC);
+        buffer.extend_from_slice(&self.version.to_le_bytes());
+        buffer.extend_from_slice(&self.update_timestamp.to_le_bytes());
+        buffer.extend_from_slice(&self.config_hash.to_le_bytes());
+
+        for (name, ip) in &self.ip_addresses {
+            put_name(&mut buffer, name);
+            put_ip(&mut buffer, *ip);
+        }
+
+        // This marks the end of the IP addresses.
+        buffer.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut payload = Vec::new();
+        for name in &self.suspended_services {
+            put_name(&mut payload, name);
+        }
+
+        buffer.push(SectionTag::SuspendedServices as u8);
+        buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&payload);
+
+        let mut payload = Vec::new();
+        for (name, cached) in &self.cached_ips {
+            put_name(&mut payload, name);
+            payload.extend_from_slice(&cached.source_hash.to_le_bytes());
+            payload.extend_from_slice(&cached.timestamp.to_le_bytes());
+            put_ip(&mut payload, cached.address);
+        }
+
+        buffer.push(SectionTag::CachedIps as u8);
+        buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&payload);
+
+        let mut payload = Vec::new();
+        for (name, timestamp) in &self.refreshed_services {
+            put_name(&mut payload, name);
+            payload.extend_from_slice(&timestamp.to_le_bytes());
+        }
+
+        buffer.push(SectionTag::RefreshedServices as u8);
+        buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&payload);
+
+        let algorithm = ChecksumAlgorithm::Crc32;
+        let checksum = algorithm.checksum(&buffer);
+
+        buffer.push(SectionTag::Checksum as u8);
+        buffer.extend_from_slice(&(1 + checksum.len() as u32).to_le_bytes());
+        buffer.push(algorithm as u8);
+        buffer.extend_from_slice(&checksum);
+
+        buffer
+    }
+}
+
+/// Reads a list of (name_length: u32, name: string) found in a section.
+fn decode_names(mut payload: &[u8]) -> Result<BTreeSet<Box<str>>, FormatError> {
+    let invalid = || FormatError::MalformedSection("names");
+
+    let mut names = BTreeSet::new();
+    while !payload.is_empty() {
+        names.insert(take_name(&mut payload, invalid)?.into());
+    }
+
+    Ok(names)
+}
+
+/// Reads the list of cached IP addresses found in a section.
+fn decode_cached_ips(mut payload: &[u8]) -> Result<BTreeMap<Box<str>, CachedIp>, FormatError> {
+    let invalid = || FormatError::MalformedSection("cached IPs");
+    let payload = &mut payload;
+
+    let mut cached_ips = BTreeMap::new();
+    while !payload.is_empty() {
+        let name = take_name(payload, invalid)?;
+
+        let cached = CachedIp {
+            source_hash: u64::from_le_bytes(*take::<8>(payload, invalid)?),
+            timestamp: u64::from_le_bytes(*take::<8>(payload, invalid)?),
+            address: take_ip(payload, invalid, invalid)?,
+        };
+
+        cached_ips.insert(name.into(), cached);
+    }
+
+    Ok(cached_ips)
+}
+
+/// Reads a list of (name_length: u32, name: string, timestamp: u64) found in
+/// a section.
+fn decode_timestamps(mut payload: &[u8]) -> Result<BTreeMap<Box<str>, u64>, FormatError> {
+    let invalid = || FormatError::MalformedSection("timestamps");
+    let payload = &mut payload;
+
+    let mut timestamps = BTreeMap::new();
+    while !payload.is_empty() {
+        let name = take_name(payload, invalid)?;
+        let timestamp = u64::from_le_bytes(*take::<8>(payload, invalid)?);
+
+        timestamps.insert(name.into(), timestamp);
+    }
+
+    Ok(timestamps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections() {
+        let mut state = PersistentState::with_config_hash(42, 1_700_000_000);
+        state.suspended_services.insert("cloudflare".into());
+
+        // The checksum section takes the last 10 bytes.
+        let encoded = state.encode();
+        let unchecked = &encoded[..encoded.len() - 10];
+        assert_eq!(PersistentState::decode(&encoded), Ok(state.clone()));
+        assert_eq!(PersistentState::decode(unchecked), Ok(state.clone()));
+
+        // A section added by a newer version is skipped...
+        let mut buffer = unchecked.to_vec();
+        buffer.extend_from_slice(&[200, 3, 0, 0, 0, 1, 2, 3]);
+        assert_eq!(PersistentState::decode(&buffer), Ok(state.clone()));
+
+        // ...unless it is cut short.
+        buffer.pop();
+        assert_eq!(
+            PersistentState::decode(&buffer),
+            Err(FormatError::UnexpectedEof("section"))
+        );
+
+        // Nothing may follow the checksum, and nothing before it may change.
+        let mut buffer = encoded.clone();
+        buffer.extend_from_slice(&[200, 0, 0, 0, 0]);
+        assert_eq!(
+            PersistentState::decode(&buffer),
+            Err(FormatError::ChecksumMismatch)
+        );
+
+        let mut buffer = encoded.clone();
+        buffer[20] ^= 1;
+        assert_eq!(
+            PersistentState::decode(&buffer),
+            Err(FormatError::ChecksumMismatch)
+        );
+
+        // Without a checksum, the sections are still checked by themselves.
+        // The suspended services are followed by two empty sections.
+        let mut buffer = unchecked.to_vec();
+        let suspended = buffer.len() - 5 - 5 - 4 - "cloudflare".len();
+        buffer[suspended] = 11;
+        assert_eq!(
+            PersistentState::decode(&buffer),
+            Err(FormatError::MalformedSection("names"))
+        );
+
+        // A file cut short in the middle of an IP address is not mistaken for
+        // a file of version 1 which ends there.
+        let mut ip_state = state.clone();
+        ip_state
+            .ip_addresses
+            .insert("home".into(), "192.0.2.1".parse().unwrap());
+        let buffer = ip_state.encode();
+        assert_eq!(
+            PersistentState::decode(&buffer[..30]),
+            Err(FormatError::UnexpectedEof("name length"))
+        );
+    }
+}
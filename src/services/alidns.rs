@@ -0,0 +1,525 @@
+use std::net::IpAddr;
+
+use data_encoding::HEXLOWER;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hmac};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{url_encode, FixedVec, Secret, UtcDateTime};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const HOST: &str = "alidns.aliyuncs.com";
+
+/// The version of the API, sent along with each action.
+const VERSION: &str = "2015-01-09";
+
+/// The TTL of newly created records if none is configured, which is also the
+/// lowest the free edition accepts.
+const DEFAULT_TTL: u32 = 600;
+
+/// The largest page size the API allows.
+const PAGE_SIZE: &str = "500";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The AccessKey pair of a RAM user allowed to manage Alibaba Cloud DNS
+    /// (e.g. with the AliyunDNSFullAccess policy).
+    access_key_id: Box<str>,
+    access_key_secret: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The TTL of the records, which is otherwise left as it is.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+/// Signs a request with the V3 signature (ACS3-HMAC-SHA256) and returns the
+/// value of its Authorization header. The query must be canonical already,
+/// i.e. sorted and percent-encoded, and the headers must have lowercase names,
+/// be sorted by name, and include the host and x-acs-* headers.
+///
+/// See: https://www.alibabacloud.com/help/en/sdk/product-overview/v3-request-structure-and-signature
+fn sign_v3(
+    access_key_id: &str,
+    access_key_secret: &str,
+    method: &str,
+    query: &str,
+    headers: &[(&str, &str)],
+) -> String {
+    let mut canonical_headers = String::new();
+    for (name, value) in headers {
+        canonical_headers += &format!("{}:{}\n", name, value.trim());
+    }
+
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let payload_hash = headers
+        .iter()
+        .find(|(name, _)| *name == "x-acs-content-sha256")
+        .map_or("", |(_, value)| value);
+
+    let canonical_request = format!(
+        "{}\n/\n{}\n{}\n{}\n{}",
+        method, query, canonical_headers, signed_headers, payload_hash
+    );
+
+    let hashed_request = digest::digest(&digest::SHA256, canonical_request.as_bytes());
+    let string_to_sign = format!(
+        "ACS3-HMAC-SHA256\n{}",
+        HEXLOWER.encode(hashed_request.as_ref())
+    );
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, access_key_secret.as_bytes());
+    let signature = HEXLOWER.encode(hmac::sign(&key, string_to_sign.as_bytes()).as_ref());
+
+    format!(
+        "ACS3-HMAC-SHA256 Credential={},SignedHeaders={},Signature={}",
+        access_key_id, signed_headers, signature
+    )
+}
+
+impl Service {
+    /// Errors are reported as {"RequestId": "...", "Code": "...",
+    /// "Message": "..."}.
+    fn parse_error(&self, response: Response) -> Result<(Box<str>, Box<str>), String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let code = resp_json.get("Code").and_then(|c| c.as_str());
+        let message = resp_json.get("Message").and_then(|m| m.as_str());
+
+        match (code, message) {
+            (Some(code), Some(message)) => Ok((code.into(), message.into())),
+            _ => Err(String::from("expected Code and Message")),
+        }
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into())),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let (code, message) = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+
+                // An unknown AccessKey is reported with a 404, as if it was
+                // the record which is missing.
+                let kind = if code.starts_with("InvalidAccessKeyId")
+                    || code.starts_with("SignatureDoesNotMatch")
+                    || code.starts_with("Forbidden")
+                {
+                    ErrorKind::Auth
+                } else if code.starts_with("Throttling") {
+                    ErrorKind::RateLimited(None)
+                } else {
+                    kind
+                };
+
+                let message = format!("{} ({})", message, code).into();
+                Err(DdnsUpdateError::provider(
+                    "Alibaba Cloud DNS",
+                    kind,
+                    message,
+                ))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// Calls the action of the API with the given parameters, which are sent
+    /// in the query string of a signed POST request with an empty body.
+    fn call(
+        &self,
+        action: &str,
+        params: &[(&str, &str)],
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let mut params = params.to_vec();
+        params.sort();
+
+        let query = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", url_encode(key), url_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut nonce = [0u8; 16];
+        SystemRandom::new().fill(&mut nonce).map_err(|_| {
+            DdnsUpdateError::provider(
+                "Alibaba Cloud DNS",
+                ErrorKind::Other,
+                "no random numbers available".into(),
+            )
+        })?;
+        let nonce = HEXLOWER.encode(&nonce);
+
+        let date = UtcDateTime::now().to_iso8601();
+        let payload_hash = HEXLOWER.encode(digest::digest(&digest::SHA256, b"").as_ref());
+
+        let headers = [
+            ("host", HOST),
+            ("x-acs-action", action),
+            ("x-acs-content-sha256", &payload_hash),
+            ("x-acs-date", &date),
+            ("x-acs-signature-nonce", &nonce),
+            ("x-acs-version", VERSION),
+        ];
+
+        let authorization = sign_v3(
+            &self.config.access_key_id,
+            self.config.access_key_secret.expose(),
+            "POST",
+            &query,
+            &headers,
+        );
+
+        let mut request = self.client.post(&format!("https://{}/?{}", HOST, query));
+        for (name, value) in &headers[1..] {
+            request = request.set(name, value);
+        }
+
+        let response = request.set("Authorization", &authorization).send_string("");
+
+        self.parse_and_check_response(response)
+    }
+
+    /// Collects the items found at `pointer` in every page of the listing,
+    /// which tells how many there are in "TotalCount".
+    fn call_pages(
+        &self,
+        action: &str,
+        params: &[(&str, &str)],
+        pointer: &str,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut items = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let page_number = page.to_string();
+            let mut paged = params.to_vec();
+            paged.push(("PageNumber", &page_number));
+            paged.push(("PageSize", PAGE_SIZE));
+
+            let mut response = self.call(action, &paged)?;
+
+            let listed = match response.pointer_mut(pointer).and_then(|l| l.as_array_mut()) {
+                Some(listed) if !listed.is_empty() => listed,
+                _ => break,
+            };
+            items.append(listed);
+
+            let total = response.get("TotalCount").and_then(|t| t.as_u64());
+            if total.is_none_or(|total| items.len() as u64 >= total) {
+                break;
+            }
+
+            page += 1;
+        }
+
+        Ok(items)
+    }
+
+    /// See: https://www.alibabacloud.com/help/en/dns/api-alidns-2015-01-09-describedomains
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let listed = self.call_pages("DescribeDomains", &[], "/Domains/Domain")?;
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.get("DomainName").and_then(|n| n.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no DomainName?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// Lists the records of the zone, or only the ones of the given host
+    /// record if there is one.
+    ///
+    /// See: https://www.alibabacloud.com/help/en/dns/api-alidns-2015-01-09-describedomainrecords
+    fn get_records(
+        &self,
+        zone: &str,
+        rr: Option<&str>,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut params = vec![("DomainName", zone)];
+        if let Some(rr) = rr {
+            params.push(("RRKeyWord", rr));
+        }
+
+        self.call_pages("DescribeDomainRecords", &params, "/DomainRecords/Record")
+    }
+
+    /// Points the record of the given host record and type to the address,
+    /// creating the record if there is none. An update which changes nothing
+    /// is refused by the API (DomainRecordDuplicate), so up-to-date records
+    /// are left alone.
+    ///
+    /// See: https://www.alibabacloud.com/help/en/dns/api-alidns-2015-01-09-updatedomainrecord
+    fn set_record(
+        &self,
+        records: &[serde_json::Value],
+        zone: &str,
+        rr: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let value = ip.to_string();
+
+        // RRKeyWord matches the host records containing it, not only the one
+        // equal to it.
+        let existing = records.iter().find(|record| {
+            record.get("Type").and_then(|t| t.as_str()) == Some(kind)
+                && record.get("RR").and_then(|r| r.as_str()) == Some(rr)
+        });
+
+        match existing {
+            Some(record) => {
+                let current_ttl = record.get("TTL").and_then(|t| t.as_u64());
+                let ttl = self
+                    .config
+                    .ttl
+                    .or(current_ttl.map(|t| t as u32))
+                    .unwrap_or(DEFAULT_TTL);
+
+                if record.get("Value").and_then(|v| v.as_str()) == Some(&value)
+                    && current_ttl == Some(ttl as u64)
+                {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("RecordId").and_then(|i| i.as_str()) else {
+                    return Err(DdnsUpdateError::Json("record has no RecordId?".into()));
+                };
+
+                let ttl = ttl.to_string();
+                self.call(
+                    "UpdateDomainRecord",
+                    &[
+                        ("RecordId", id),
+                        ("RR", rr),
+                        ("Type", kind),
+                        ("Value", &value),
+                        ("TTL", &ttl),
+                    ],
+                )?;
+            }
+
+            // See: https://www.alibabacloud.com/help/en/dns/api-alidns-2015-01-09-adddomainrecord
+            None => {
+                let ttl = self.config.ttl.unwrap_or(DEFAULT_TTL).to_string();
+                self.call(
+                    "AddDomainRecord",
+                    &[
+                        ("DomainName", zone),
+                        ("RR", rr),
+                        ("Type", kind),
+                        ("Value", &value),
+                        ("TTL", &ttl),
+                    ],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone, None)? {
+                let kind = match record.get("Type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match record.get("RR").and_then(|r| r.as_str()) {
+                    None | Some("@") => zone.clone(),
+                    Some(rr) => format!("{}.{}", rr, zone).into(),
+                };
+
+                // A name has as many records of a type as it has addresses.
+                if !found
+                    .iter()
+                    .any(|r: &DiscoveredRecord| r.name == name && r.kind == kind)
+                {
+                    found.push(DiscoveredRecord { name, kind });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, rr)) = find_zone(&self.cached_zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Alibaba Cloud DNS",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            let records = self.get_records(zone, Some(rr))?;
+
+            for ip in &result {
+                self.set_record(&records, zone, rr, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn signature() {
+        let authorization = sign_v3(
+            "YourAccessKeyId",
+            "YourAccessKeySecret",
+            "POST",
+            "ImageId=win2019_1809_x64_dtc_zh-cn_40G_alibase_20230811.vhd&RegionId=cn-shanghai",
+            &[
+                ("host", "ecs.cn-shanghai.aliyuncs.com"),
+                ("x-acs-action", "RunInstances"),
+                (
+                    "x-acs-content-sha256",
+                    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                ),
+                ("x-acs-date", "2023-10-26T10:22:32Z"),
+                ("x-acs-signature-nonce", "3156853299f313e23d1673dc12e1703d"),
+                ("x-acs-version", "2014-05-26"),
+            ],
+        );
+
+        assert_eq!(
+            authorization,
+            "ACS3-HMAC-SHA256 Credential=YourAccessKeyId,\
+             SignedHeaders=host;x-acs-action;x-acs-content-sha256;x-acs-date;x-acs-signature-nonce;x-acs-version,\
+             Signature=06563a9e1b43f5dfe96b81484da74bceab24a1d853912eee15083a6f0f3283c0"
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/alidns.txt"));
+
+        let mut service = Service::new(
+            Config {
+                access_key_id: "id".into(),
+                access_key_secret: Secret::new("secret".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and www.example.com has
+        // no AAAA record yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "POST https://alidns.aliyuncs.com/?PageNumber=1&PageSize=500",
+                "POST https://alidns.aliyuncs.com/?DomainName=example.com&PageNumber=1&PageSize=500&RRKeyWord=%40",
+                "POST https://alidns.aliyuncs.com/?RR=%40&RecordId=11&TTL=600&Type=AAAA&Value=2001%3Adb8%3A%3A1",
+                "POST https://alidns.aliyuncs.com/?DomainName=example.com&PageNumber=1&PageSize=500&RRKeyWord=www",
+                "POST https://alidns.aliyuncs.com/?RR=www&RecordId=12&TTL=600&Type=A&Value=192.0.2.1",
+                "POST https://alidns.aliyuncs.com/?DomainName=example.com&RR=www&TTL=600&Type=AAAA&Value=2001%3Adb8%3A%3A1",
+            ]
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Specified access key is not found. (InvalidAccessKeyId.NotFound)"
+        ));
+    }
+}
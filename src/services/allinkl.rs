@@ -0,0 +1,116 @@
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+use crate::http::Settings;
+use crate::util::FixedVec;
+
+use super::shared_dyndns::{self, Parameters, Quirk};
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, SuspensionState};
+
+/// The username (e.g. "dyn0123456") and password are those of a DDNS login
+/// created in the KAS under Tools > DDNS settings, not those of the KAS
+/// itself. Each DDNS login is tied to the host it was created for.
+pub type Config = shared_dyndns::Config;
+
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
+/// The KAS takes the IPv6 address in `myip6`.
+const PARAMETERS: Parameters = Parameters {
+    hostname: Cow::Borrowed("hostname"),
+    ip: Cow::Borrowed("myip"),
+    ipv6: Some(Cow::Borrowed("myip6")),
+};
+
+const QUIRKS: &[Quirk] = &[
+    Quirk {
+        response: "badauth",
+        message: "Bad authentication details were provided (these are the credentials of the DDNS login, not of the KAS)",
+        kind: ErrorKind::Auth,
+        suspend_for: None,
+    },
+    Quirk {
+        response: "nohost",
+        message: "The host does not belong to the DDNS login",
+        kind: ErrorKind::NotFound,
+        suspend_for: None,
+    },
+];
+
+pub struct Service {
+    inner: shared_dyndns::Service,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let inner = shared_dyndns::Service::from_config(
+            "all-inkl",
+            "https://dyndns.kasserver.com/",
+            config,
+            http,
+        )
+        .with_parameters(PARAMETERS)
+        .with_quirks(QUIRKS);
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.suspension()
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        self.inner.update_record(ips)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent, Settings};
+    use crate::services::Suspension;
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/allinkl.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "dyn0123456"
+            password = "pass"
+            domains = "home.example.com"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { kind: ErrorKind::Auth, message: m, .. }) if m.starts_with("Bad authentication")
+        ));
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Indefinite))
+        ));
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://dyndns.kasserver.com/?hostname=home.example.com&myip=192.0.2.1&myip6=2001%3Adb8%3A%3A1",
+                "https://dyndns.kasserver.com/?hostname=home.example.com&myip=192.0.2.1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{url_encode, FixedVec, Secret};
+
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const API_VERSION: &str = "2018-05-01";
+
+/// An access token is renewed this long before it expires, so that it does
+/// not expire halfway through an update.
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The directory (tenant) the service principal belongs to.
+    tenant_id: Box<str>,
+
+    /// The application (client) ID of a service principal with the "DNS Zone
+    /// Contributor" role on the zone.
+    client_id: Box<str>,
+
+    client_secret: Secret<Box<str>>,
+
+    subscription_id: Box<str>,
+
+    resource_group: Box<str>,
+
+    /// The DNS zone, e.g. "example.com".
+    zone: Box<str>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    ttl: u32,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    access_token: Option<AccessToken>,
+}
+
+struct AccessToken {
+    /// The value of the Authorization header, i.e. "Bearer <token>".
+    authorization: Secret<Box<str>>,
+    expires_at: Instant,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            access_token: None,
+        }
+    }
+}
+
+impl Service {
+    /// Azure Resource Manager reports errors as {"error": {"message": "..."}},
+    /// while Microsoft Entra ID uses {"error": "...", "error_description": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .pointer("/error/message")
+            .or_else(|| resp_json.get("error_description"))
+            .or_else(|| resp_json.get("error"))
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Azure DNS", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// Returns the Authorization header, requesting an access token with the
+    /// client secret if there is no valid one at hand.
+    ///
+    /// See: https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-client-creds-grant-flow
+    fn authorization(&mut self) -> Result<Box<str>, DdnsUpdateError> {
+        if let Some(token) = &self.access_token {
+            if Instant::now() + TOKEN_MARGIN < token.expires_at {
+                return Ok(token.authorization.expose().clone());
+            }
+        }
+
+        let url = format!(
+            "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+            self.config.tenant_id
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .set("Content-Type", "application/x-www-form-urlencoded")
+            .send_string(&format!(
+                "grant_type=client_credentials&client_id={}&client_secret={}&scope={}",
+                url_encode(&self.config.client_id),
+                url_encode(self.config.client_secret.expose()),
+                url_encode("https://management.azure.com/.default"),
+            ));
+
+        let response = self.parse_and_check_response(response)?;
+
+        let Some(access_token) = response.get("access_token").and_then(|v| v.as_str()) else {
+            return Err(DdnsUpdateError::Json("no access_token returned".into()));
+        };
+
+        let expires_in = response
+            .get("expires_in")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(3600);
+
+        let authorization: Box<str> = format!("Bearer {}", access_token).into();
+        self.access_token = Some(AccessToken {
+            authorization: Secret::new(authorization.clone()),
+            expires_at: Instant::now() + Duration::from_secs(expires_in),
+        });
+
+        Ok(authorization)
+    }
+
+    fn zone_url(&self) -> String {
+        format!(
+            "https://management.azure.com/subscriptions/{}/resourceGroups/{}\
+             /providers/Microsoft.Network/dnsZones/{}",
+            self.config.subscription_id, self.config.resource_group, self.config.zone
+        )
+    }
+
+    /// Returns the name of the domain relative to the zone, "@" being the zone
+    /// itself.
+    fn relative_name<'a>(&self, domain: &'a str) -> Option<&'a str> {
+        if domain == &*self.config.zone {
+            return Some("@");
+        }
+
+        domain
+            .strip_suffix(&*self.config.zone)?
+            .strip_suffix('.')
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Updates the record set of the domain, or creates it if there is none.
+    ///
+    /// See: https://learn.microsoft.com/en-us/rest/api/dns/record-sets/update
+    fn patch_record_set(&mut self, domain: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let Some(name) = self.relative_name(domain) else {
+            return Err(DdnsUpdateError::provider(
+                "Azure DNS",
+                ErrorKind::NotFound,
+                format!("{} is not within the zone {}", domain, self.config.zone).into(),
+            ));
+        };
+
+        let authorization = self.authorization()?;
+
+        let (kind, properties) = match ip {
+            IpAddr::V4(ip) => (
+                "A",
+                serde_json::json!({
+                    "TTL": self.config.ttl,
+                    "ARecords": [{ "ipv4Address": ip.to_string() }],
+                }),
+            ),
+            IpAddr::V6(ip) => (
+                "AAAA",
+                serde_json::json!({
+                    "TTL": self.config.ttl,
+                    "AAAARecords": [{ "ipv6Address": ip.to_string() }],
+                }),
+            ),
+        };
+
+        let url = format!("{}/{}/{}", self.zone_url(), kind, name);
+        let body = serde_json::json!({ "properties": properties });
+
+        let response = self
+            .client
+            .patch(&url)
+            .query("api-version", API_VERSION)
+            .set("Authorization", &authorization)
+            .send_json(&body);
+
+        // Only an existing record set can be patched.
+        let response = match response {
+            Err(Error::Status(404, _)) => self
+                .client
+                .put(&url)
+                .query("api-version", API_VERSION)
+                .set("Authorization", &authorization)
+                .send_json(&body),
+            response => response,
+        };
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        for domain in self.config.domains.clone() {
+            for ip in [ipv4, ipv6].into_iter().flatten() {
+                self.patch_record_set(&domain, *ip)?;
+            }
+        }
+
+        let mut result = FixedVec::new();
+        if let Some(ipv4) = ipv4 {
+            result.push(*ipv4);
+        }
+        if let Some(ipv6) = ipv6 {
+            result.push(*ipv6);
+        }
+
+        Ok(result)
+    }
+
+    /// See: https://learn.microsoft.com/en-us/rest/api/dns/zones/get
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        // A token which is still valid says nothing about the secret.
+        self.access_token = None;
+
+        let result = self.authorization().and_then(|authorization| {
+            let response = self
+                .client
+                .get(&self.zone_url())
+                .query("api-version", API_VERSION)
+                .set("Authorization", &authorization)
+                .call();
+            self.parse_and_check_response(response)?;
+
+            let found = describe_found_domains(&self.config.domains, |domain| {
+                self.relative_name(domain).is_some()
+            });
+
+            Ok(format!("zone {}, {}", self.config.zone, found))
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/azure.txt"));
+
+        let mut service = Service::new(
+            Config {
+                tenant_id: "tenant".into(),
+                client_id: "client".into(),
+                client_secret: Secret::new("a+b/c".into()),
+                subscription_id: "subscription".into(),
+                resource_group: "dns".into(),
+                zone: "example.com".into(),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The token is only requested once, and www.example.com has no AAAA
+        // record yet.
+        let sent = take_sent();
+        let zone = "https://management.azure.com/subscriptions/subscription/resourceGroups/dns\
+                    /providers/Microsoft.Network/dnsZones/example.com";
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "POST https://login.microsoftonline.com/tenant/oauth2/v2.0/token".to_string(),
+                format!("PATCH {}/A/@?api-version=2018-05-01", zone),
+                format!("PATCH {}/AAAA/@?api-version=2018-05-01", zone),
+                format!("PATCH {}/A/www?api-version=2018-05-01", zone),
+                format!("PATCH {}/AAAA/www?api-version=2018-05-01", zone),
+                format!("PUT {}/AAAA/www?api-version=2018-05-01", zone),
+            ]
+        );
+
+        let body = sent[0].body.as_deref().unwrap();
+        assert!(body.contains("&client_secret=a%2Bb%2Fc&"));
+
+        let body = sent[5].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["properties"]["TTL"], 300);
+        assert_eq!(
+            body["properties"]["AAAARecords"][0]["ipv6Address"],
+            "2001:db8::1"
+        );
+
+        service.config.domains = vec!["example.net".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "example.net is not within the zone example.com"
+        ));
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider { message: m, .. })) if m.starts_with("AADSTS7000215: Invalid client secret provided.")
+        ));
+    }
+}
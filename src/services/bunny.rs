@@ -0,0 +1,357 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType, ZoneName,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.bunny.net/dnszone";
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 300;
+
+/// The largest page size the API allows.
+const PER_PAGE: &str = "1000";
+
+/// Bunny tells the types of records apart by number rather than by name.
+const TYPE_A: u64 = 0;
+const TYPE_AAAA: u64 = 1;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The API key of the account, found under Account Settings > API.
+    access_key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. The TTL of the records is left
+    /// as it is if not given.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+/// A DNS zone, along with the records in it, as listed by the API.
+#[derive(Debug)]
+struct Zone {
+    id: u64,
+    name: Box<str>,
+    records: Vec<serde_json::Value>,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"ErrorKey": "...", "Field": "...",
+    /// "Message": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let key = resp_json.get("ErrorKey").and_then(|k| k.as_str());
+        let message = resp_json.get("Message").and_then(|m| m.as_str());
+
+        match (key, message) {
+            (Some(key), Some(message)) => Ok(format!("{} ({})", message, key).into()),
+            (None, Some(message)) | (Some(message), None) => Ok(message.into()),
+            (None, None) => Err(String::from("expected string")),
+        }
+    }
+
+    /// Checks whether the request succeeded, returning the response if so.
+    fn check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<Response, DdnsUpdateError> {
+        match response {
+            Ok(r) => Ok(r),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Bunny", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// Lists the zones of the account, each with all of its records.
+    ///
+    /// See: https://docs.bunny.net/reference/dnszonepublic_index
+    fn get_zones(&self) -> Result<Vec<Zone>, DdnsUpdateError> {
+        let mut zones = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let response = self
+                .client
+                .get(ENDPOINT)
+                .set("AccessKey", self.config.access_key.expose())
+                .query("page", &page.to_string())
+                .query("perPage", PER_PAGE)
+                .call();
+
+            let mut response = self
+                .check_response(response)?
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+            let Some(listed) = response.get_mut("Items").and_then(|i| i.as_array_mut()) else {
+                return Err(DdnsUpdateError::Json("expected a list of zones".into()));
+            };
+
+            for zone in listed {
+                let id = zone.get("Id").and_then(|i| i.as_u64());
+                let name = zone
+                    .get("Domain")
+                    .and_then(|d| d.as_str())
+                    .map(Box::<str>::from);
+
+                let (Some(id), Some(name)) = (id, name) else {
+                    return Err(DdnsUpdateError::Json("zone has no Id or Domain?".into()));
+                };
+
+                let records = match zone.get_mut("Records").map(serde_json::Value::take) {
+                    Some(serde_json::Value::Array(records)) => records,
+                    _ => Vec::new(),
+                };
+
+                zones.push(Zone { id, name, records });
+            }
+
+            match response.get("HasMoreItems").and_then(|m| m.as_bool()) {
+                Some(true) => page += 1,
+                _ => break,
+            }
+        }
+
+        Ok(zones)
+    }
+
+    /// Points the record of the given name and type to the address, updating
+    /// it by its ID, or adding it if there is none. Updates succeed with an
+    /// empty response (204).
+    ///
+    /// See: https://docs.bunny.net/reference/dnszonepublic_updaterecord
+    fn set_record(&self, zone: &Zone, name: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { TYPE_A } else { TYPE_AAAA };
+        let url = format!("{}/{}/records", ENDPOINT, zone.id);
+
+        let existing = zone.records.iter().find(|record| {
+            record.get("Type").and_then(|t| t.as_u64()) == Some(kind)
+                && record.get("Name").and_then(|n| n.as_str()).unwrap_or("") == name
+        });
+
+        let (request, ttl) = match existing {
+            Some(record) => {
+                let current_ttl = record.get("Ttl").and_then(|t| t.as_u64());
+                let ttl = self
+                    .config
+                    .ttl
+                    .or(current_ttl.map(|t| t as u32))
+                    .unwrap_or(DEFAULT_TTL);
+
+                if record.get("Value").and_then(|v| v.as_str()) == Some(&ip.to_string())
+                    && current_ttl == Some(ttl as u64)
+                {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("Id").and_then(|i| i.as_u64()) else {
+                    return Err(DdnsUpdateError::Json("record has no Id?".into()));
+                };
+
+                (self.client.post(&format!("{}/{}", url, id)), ttl)
+            }
+
+            // See: https://docs.bunny.net/reference/dnszonepublic_addrecord
+            None => (
+                self.client.put(&url),
+                self.config.ttl.unwrap_or(DEFAULT_TTL),
+            ),
+        };
+
+        let body = serde_json::json!({
+            "Type": kind,
+            "Name": name,
+            "Value": ip.to_string(),
+            "Ttl": ttl,
+        });
+
+        let response = request
+            .set("AccessKey", self.config.access_key.expose())
+            .send_json(body);
+
+        self.check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in &zone.records {
+                let kind = match record.get("Type").and_then(|t| t.as_u64()) {
+                    Some(TYPE_A) => RecordType::A,
+                    Some(TYPE_AAAA) => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match record.get("Name").and_then(|n| n.as_str()) {
+                    None | Some("") => zone.name.clone(),
+                    Some(name) => format!("{}.{}", name, zone.name).into(),
+                };
+
+                // A name has as many records of a type as it has addresses.
+                if !found
+                    .iter()
+                    .any(|r: &DiscoveredRecord| r.name == name && r.kind == kind)
+                {
+                    found.push(DiscoveredRecord { name, kind });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        // The records come along with the zones, so they are listed anew each
+        // time rather than cached.
+        let zones = self.get_zones()?;
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&zones, domain, "") else {
+                return Err(DdnsUpdateError::provider(
+                    "Bunny",
+                    ErrorKind::NotFound,
+                    format!("no zone of the account contains {}", domain).into(),
+                ));
+            };
+
+            for ip in &result {
+                self.set_record(zone, name, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&zones, domain, "").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/bunny.txt"));
+
+        let mut service = Service::new(
+            Config {
+                access_key: Secret::new("key".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The zones are listed over two pages, the A record of example.com is
+        // up to date, and www.example.com has no AAAA record yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.bunny.net/dnszone?page=1&perPage=1000",
+                "GET https://api.bunny.net/dnszone?page=2&perPage=1000",
+                "POST https://api.bunny.net/dnszone/1/records/11",
+                "POST https://api.bunny.net/dnszone/1/records/12",
+                "PUT https://api.bunny.net/dnszone/1/records",
+            ]
+        );
+
+        let body = sent[4].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "Type": 1,
+                "Name": "www",
+                "Value": "2001:db8::1",
+                "Ttl": 300,
+            })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "The request authorization failed (unauthorized)"
+        ));
+    }
+}
@@ -2,21 +2,31 @@ use std::net::IpAddr;
 
 use serde_derive::{Deserialize, Serialize};
 
-use crate::http::{Error, Request, Response};
-use crate::util::FixedVec;
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
 
-use super::{one_or_more_string, DdnsService, DdnsUpdateError};
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType, StaticRecords, ZoneName,
+};
 
 type ZoneId = u128;
 type RecordId = u128;
 
+pub const RECORDS: &[RecordType] = &[
+    RecordType::A,
+    RecordType::Aaaa,
+    RecordType::Srv,
+    RecordType::Caa,
+];
+
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct Config {
     ttl: u32,
 
     proxied: bool,
 
-    token: Box<str>,
+    token: Secret<Box<str>>,
 
     #[serde(deserialize_with = "one_or_more_string")]
     domains: Vec<Box<str>>,
@@ -24,9 +34,21 @@ pub struct Config {
 
 pub struct Service {
     config: Config,
+    client: Client,
     cached_records: Vec<Record>,
 }
 
+struct Zone {
+    id: ZoneId,
+    name: Box<str>,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
 struct Record {
     zone_id: ZoneId,
     id: RecordId,
@@ -40,12 +62,13 @@ enum RecordKind {
     Aaaa,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
         let mut config = config;
-        config.token = (String::from("Bearer ") + &config.token).into();
+        config.token = Secret::new((String::from("Bearer ") + config.token.expose()).into());
         Self {
             config,
+            client: Client::new(http),
             cached_records: Vec::new(),
         }
     }
@@ -88,12 +111,18 @@ impl Service {
             Ok(r) => r
                 .into_json::<serde_json::Value>()
                 .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
-            Err(Error::Status(_, resp)) => {
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
                 let (code, message) = self.parse_error(resp).map_err(|ref e| {
                     let error = String::from("unexpected error message structure - ");
                     DdnsUpdateError::Json((error + e).into_boxed_str())
                 })?;
-                Err(DdnsUpdateError::Cloudflare(code, message))?
+                let message = format!("code {} \"{}\"", code, message);
+                Err(DdnsUpdateError::provider(
+                    "Cloudflare",
+                    kind,
+                    message.into(),
+                ))?
             }
             Err(Error::Transport(tp)) => {
                 Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
@@ -114,10 +143,12 @@ impl Service {
         Ok(response)
     }
 
-    fn get_zones(&self) -> Result<Vec<ZoneId>, DdnsUpdateError> {
-        let response = Request::get("https://api.cloudflare.com/client/v4/zones/")
+    fn get_zones(&self) -> Result<Vec<Zone>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://api.cloudflare.com/client/v4/zones/")
             .set("Content-Type", "application/json")
-            .set("Authorization", &self.config.token)
+            .set("Authorization", self.config.token.expose())
             .call();
 
         let response = self.parse_and_check_response(response)?;
@@ -134,6 +165,10 @@ impl Service {
                 return Err(DdnsUpdateError::Json("zone has no id?".into()));
             };
 
+            let Some(name) = zone.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("zone has no name?".into()));
+            };
+
             let Some(permissions) = zone.get("permissions").and_then(|v| v.as_array()) else {
                 continue;
             };
@@ -159,32 +194,41 @@ impl Service {
                 let Ok(id) = ZoneId::from_str_radix(id, 16) else {
                     Err(DdnsUpdateError::Json("id is not a u128 number".into()))?
                 };
-                zone_ids.push(id);
+                zone_ids.push(Zone {
+                    id,
+                    name: name.into(),
+                });
             }
         }
 
         Ok(zone_ids)
     }
 
-    fn get_records(&self, zone_id: ZoneId) -> Result<Vec<Record>, DdnsUpdateError> {
+    fn list_records(&self, zone_id: ZoneId) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
         let url = format!(
             "https://api.cloudflare.com/client/v4/zones/{:x}/dns_records",
             zone_id
         );
 
-        let response = Request::get(&url)
+        let response = self
+            .client
+            .get(&url)
             .set("Content-Type", "application/json")
-            .set("Authorization", &self.config.token)
+            .set("Authorization", self.config.token.expose())
             .call();
 
-        let response = self.parse_and_check_response(response)?;
+        let mut response = self.parse_and_check_response(response)?;
 
-        let results = response.get("result").and_then(|v| v.as_array());
-        let Some(records) = results else {
-            return Err(DdnsUpdateError::Json(
+        match response.get_mut("result").map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(records)) => Ok(records),
+            _ => Err(DdnsUpdateError::Json(
                 "cloudflare returned 0 records".into(),
-            ));
-        };
+            )),
+        }
+    }
+
+    fn get_records(&self, zone_id: ZoneId) -> Result<Vec<Record>, DdnsUpdateError> {
+        let records = self.list_records(zone_id)?;
 
         let mut returned_records = Vec::new();
         for record in records {
@@ -227,8 +271,10 @@ impl Service {
             record.zone_id, record.id
         );
 
-        let response = Request::put(&url)
-            .set("Authorization", &self.config.token)
+        let response = self
+            .client
+            .put(&url)
+            .set("Authorization", self.config.token.expose())
             .send_json(serde_json::json!({
                 "content": ip.to_string(),
                 "name": record.domain.as_ref(),
@@ -241,18 +287,159 @@ impl Service {
 
         Ok(())
     }
+
+    /// Creates a record, or replaces the one with the given ID.
+    fn send_record(
+        &self,
+        zone_id: ZoneId,
+        id: Option<&str>,
+        body: serde_json::Value,
+    ) -> Result<(), DdnsUpdateError> {
+        let url = format!(
+            "https://api.cloudflare.com/client/v4/zones/{:x}/dns_records",
+            zone_id
+        );
+
+        let request = match id {
+            Some(id) => self.client.put(&format!("{}/{}", url, id)),
+            None => self.client.post(&url),
+        };
+
+        let response = request
+            .set("Authorization", self.config.token.expose())
+            .send_json(body);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// A SRV record replaces the one of the same name, while a CAA record is
+    /// only ever added, as a name may have several of them (e.g. one for each
+    /// certificate authority).
+    fn put_static_records(&self, records: &StaticRecords) -> Result<u32, DdnsUpdateError> {
+        let zones = self.get_zones()?;
+
+        let wanted = records
+            .srv
+            .iter()
+            .map(|srv| {
+                let data = serde_json::json!({
+                    "priority": srv.priority,
+                    "weight": srv.weight,
+                    "port": srv.port,
+                    "target": srv.target.trim_end_matches('.'),
+                });
+                (&srv.name, "SRV", data)
+            })
+            .chain(records.caa.iter().map(|caa| {
+                let data = serde_json::json!({
+                    "flags": caa.flags,
+                    "tag": caa.tag,
+                    "value": caa.value,
+                });
+                (&caa.name, "CAA", data)
+            }));
+
+        // The records of each zone are only listed once.
+        let mut listed: Vec<(ZoneId, Vec<serde_json::Value>)> = Vec::new();
+        let mut changed = 0;
+
+        for (name, kind, data) in wanted {
+            let Some(zone) = find_zone(&zones, name, "").map(|(zone, _)| zone) else {
+                return Err(DdnsUpdateError::provider(
+                    "Cloudflare",
+                    ErrorKind::NotFound,
+                    format!("no zone of the account contains {}", name).into(),
+                ));
+            };
+
+            if !listed.iter().any(|(id, _)| *id == zone.id) {
+                listed.push((zone.id, self.list_records(zone.id)?));
+            }
+
+            let existing = listed
+                .iter()
+                .find(|(id, _)| *id == zone.id)
+                .map(|(_, records)| records.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .filter(|record| record["name"] == **name && record["type"] == kind);
+
+            // Cloudflare may add fields of its own to the data.
+            let is_same = |record: &serde_json::Value| {
+                data.as_object()
+                    .into_iter()
+                    .flatten()
+                    .all(|(key, value)| record["data"][key] == *value)
+            };
+
+            if existing.clone().any(is_same) {
+                continue;
+            }
+
+            let id = match kind {
+                "SRV" => existing
+                    .map(|record| record["id"].as_str())
+                    .next()
+                    .flatten(),
+                _ => None,
+            };
+
+            let body = serde_json::json!({
+                "name": name,
+                "type": kind,
+                "data": data,
+                "ttl": self.config.ttl,
+            });
+
+            self.send_record(zone.id, id, body)?;
+            changed += 1;
+        }
+
+        Ok(changed)
+    }
+
+    /// Looks up the records of the configured domains and caches them, so that
+    /// they are only looked up once.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        for zone in self.get_zones()? {
+            for record in self.get_records(zone.id)? {
+                if self.config.domains.iter().any(|d| *d == record.domain) {
+                    self.cached_records.push(record)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(zone.id)? {
+                let kind = match record.kind {
+                    RecordKind::A => RecordType::A,
+                    RecordKind::Aaaa => RecordType::Aaaa,
+                };
+
+                found.push(DiscoveredRecord {
+                    name: record.domain,
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
 }
 
 impl DdnsService for Service {
     fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
         if self.cached_records.is_empty() {
-            for zone in self.get_zones()? {
-                for record in self.get_records(zone)? {
-                    if self.config.domains.iter().any(|d| *d == record.domain) {
-                        self.cached_records.push(record)
-                    }
-                }
-            }
+            self.cache_records()?;
         }
 
         let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
@@ -276,4 +463,128 @@ impl DdnsService for Service {
 
         Ok(result)
     }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records
+                    .iter()
+                    .any(|record| &*record.domain == domain)
+            })
+        });
+
+        Some(result)
+    }
+
+    fn set_static_records(
+        &mut self,
+        records: &StaticRecords,
+    ) -> Option<Result<u32, DdnsUpdateError>> {
+        Some(self.put_static_records(records))
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+    use crate::services::{CaaRecord, SrvRecord};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/cloudflare.txt"));
+
+        let mut service = Service::new(
+            Config {
+                ttl: 1,
+                proxied: false,
+                token: Secret::new("token".into()),
+                domains: vec!["a.example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        // The records are cached, only the PUTs are sent this time.
+        let updated = service.update_record(&[ipv4, ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { kind: ErrorKind::Validation, message, .. })
+                if message.starts_with("code 9005 ")
+        ));
+
+        let sent = take_sent();
+        let methods = sent.iter().map(|s| &*s.method).collect::<Vec<_>>();
+        assert_eq!(methods, ["GET", "GET", "PUT", "PUT", "PUT"]);
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["content"], "192.0.2.1");
+        assert_eq!(body["name"], "a.example.com");
+        assert_eq!(body["type"], "A");
+    }
+
+    #[test]
+    fn static_records() {
+        add_fixtures(include_str!("fixtures/cloudflare.txt"));
+
+        let mut service = Service::new(
+            Config {
+                ttl: 1,
+                proxied: false,
+                token: Secret::new("token".into()),
+                domains: vec!["a.example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let caa = |value: &str| CaaRecord {
+            name: "example.com".into(),
+            flags: 0,
+            tag: "issue".into(),
+            value: value.into(),
+        };
+
+        let records = StaticRecords {
+            srv: vec![SrvRecord {
+                name: "_minecraft._tcp.example.com".into(),
+                priority: 0,
+                weight: 5,
+                port: 25565,
+                target: "a.example.com".into(),
+            }],
+            caa: vec![caa("letsencrypt.org"), caa("sectigo.com")],
+        };
+
+        // The CAA record of Let's Encrypt exists already.
+        let changed = service.set_static_records(&records).unwrap().unwrap();
+        assert_eq!(changed, 2);
+
+        let sent = take_sent();
+        let methods = sent.iter().map(|s| &*s.method).collect::<Vec<_>>();
+        assert_eq!(methods, ["GET", "GET", "PUT", "POST"]);
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["data"]["target"], "a.example.com");
+        assert_eq!(body["type"], "SRV");
+
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body["data"],
+            serde_json::json!({ "flags": 0, "tag": "issue", "value": "sectigo.com" })
+        );
+    }
 }
@@ -0,0 +1,535 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, DdnsService, DdnsUpdateError, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 3600;
+
+/// How many zones are listed per request, the most ClouDNS allows.
+const ZONES_PER_PAGE: usize = 100;
+
+/// ClouDNS is updated either through the dynamic URLs of the records, one per
+/// record, or through its HTTP API with the credentials of an API user.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub enum Config {
+    DynamicUrls {
+        ipv4: BTreeMap<Box<str>, Secret<Box<str>>>,
+        ipv6: BTreeMap<Box<str>, Secret<Box<str>>>,
+    },
+
+    Api(ApiConfig),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApiConfig {
+    user: ApiUser,
+    auth_password: Secret<Box<str>>,
+    domains: Vec<Box<str>>,
+    ttl: Option<u32>,
+}
+
+/// ClouDNS has API users with access to every zone, and sub-users limited to
+/// some of them, which are identified either by their ID or their name.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+enum ApiUser {
+    AuthId(Box<str>),
+    SubAuthId(Box<str>),
+    SubAuthUser(Box<str>),
+}
+
+impl ApiUser {
+    fn query_param(&self) -> (&'static str, &str) {
+        match self {
+            ApiUser::AuthId(id) => ("auth-id", id),
+            ApiUser::SubAuthId(id) => ("sub-auth-id", id),
+            ApiUser::SubAuthUser(user) => ("sub-auth-user", user),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    /// The token of the dynamic URL of each A record, i.e. the q parameter of
+    /// https://ipv4.cloudns.net/api/dynamicURL/?q=<token>.
+    #[serde(default)]
+    tokens: BTreeMap<Box<str>, Secret<Box<str>>>,
+
+    /// The same for the AAAA records.
+    #[serde(default)]
+    ipv6_tokens: BTreeMap<Box<str>, Secret<Box<str>>>,
+
+    auth_id: Option<Box<str>>,
+    sub_auth_id: Option<Box<str>>,
+    sub_auth_user: Option<Box<str>>,
+    auth_password: Option<Secret<Box<str>>>,
+
+    #[serde(default, deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let has_tokens = !raw.tokens.is_empty() || !raw.ipv6_tokens.is_empty();
+
+        let user = match (raw.auth_id, raw.sub_auth_id, raw.sub_auth_user) {
+            (None, None, None) => None,
+            (Some(id), None, None) => Some(ApiUser::AuthId(id)),
+            (None, Some(id), None) => Some(ApiUser::SubAuthId(id)),
+            (None, None, Some(user)) => Some(ApiUser::SubAuthUser(user)),
+            _ => return Err("only one of auth_id, sub_auth_id and sub_auth_user may be given"),
+        };
+
+        match (has_tokens, user, raw.auth_password) {
+            (true, None, None) if raw.domains.is_empty() => Ok(Config::DynamicUrls {
+                ipv4: raw.tokens,
+                ipv6: raw.ipv6_tokens,
+            }),
+
+            (false, Some(user), Some(auth_password)) if !raw.domains.is_empty() => {
+                Ok(Config::Api(ApiConfig {
+                    user,
+                    auth_password,
+                    domains: raw.domains,
+                    ttl: raw.ttl,
+                }))
+            }
+
+            _ => Err("either tokens (and/or ipv6_tokens), or an API user, auth_password and domains must be given"),
+        }
+    }
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+/// Updates a single record through its dynamic URL. ClouDNS takes the address
+/// the request comes from, so the IP sources should agree with it.
+fn update_with_token(
+    client: &Client,
+    domain: &str,
+    token: &str,
+    ip: IpAddr,
+) -> Result<(), DdnsUpdateError> {
+    let url = match ip {
+        IpAddr::V4(_) => "https://ipv4.cloudns.net/api/dynamicURL/",
+        IpAddr::V6(_) => "https://ipv6.cloudns.net/api/dynamicURL/",
+    };
+
+    let response = match client.get(url).query("q", token).call() {
+        Ok(resp) | Err(Error::Status(_, resp)) => resp.into_string().map_err(|e| {
+            DdnsUpdateError::provider("ClouDNS", ErrorKind::Other, e.to_string().into())
+        })?,
+
+        Err(Error::Transport(tp)) => {
+            return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+        }
+    };
+
+    match response.trim() {
+        "OK" => Ok(()),
+        // The token itself is the credential, so a bad one is merely called
+        // an "Invalid request."
+        message => Err(DdnsUpdateError::provider(
+            "ClouDNS",
+            ErrorKind::Auth,
+            format!("{} ({})", message, domain).into(),
+        )),
+    }
+}
+
+impl Service {
+    /// Calls an API method, e.g. "dns/records" for /dns/records.json. Failures
+    /// are answered with {"status": "Failed", "statusDescription": "..."}.
+    ///
+    /// See: https://www.cloudns.net/wiki/article/42/
+    fn call(
+        &self,
+        config: &ApiConfig,
+        method: &str,
+        params: &[(&str, &str)],
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let (user_param, user) = config.user.query_param();
+
+        let mut request = self
+            .client
+            .get(&format!("https://api.cloudns.net/{}.json", method))
+            .query(user_param, user)
+            .query("auth-password", config.auth_password.expose());
+
+        for (name, value) in params {
+            request = request.query(name, value);
+        }
+
+        let response = match request.call() {
+            Ok(resp) | Err(Error::Status(_, resp)) => resp
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        };
+
+        if response.get("status").and_then(|s| s.as_str()) == Some("Failed") {
+            let message = response
+                .get("statusDescription")
+                .and_then(|m| m.as_str())
+                .unwrap_or("(null)");
+
+            let kind = match message.starts_with("Invalid authentication") {
+                true => ErrorKind::Auth,
+                false => ErrorKind::Other,
+            };
+
+            return Err(DdnsUpdateError::provider("ClouDNS", kind, message.into()));
+        }
+
+        Ok(response)
+    }
+
+    fn get_zones(&self, config: &ApiConfig) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let mut zones = Vec::new();
+
+        for page in 1.. {
+            let response = self.call(
+                config,
+                "dns/list-zones",
+                &[
+                    ("page", &page.to_string()),
+                    ("rows-per-page", &ZONES_PER_PAGE.to_string()),
+                ],
+            )?;
+
+            let Some(listed) = response.as_array() else {
+                return Err(DdnsUpdateError::Json("expected a list of zones".into()));
+            };
+
+            for zone in listed {
+                let Some(name) = zone.get("name").and_then(|n| n.as_str()) else {
+                    return Err(DdnsUpdateError::Json("zone has no name".into()));
+                };
+
+                zones.push(name.into());
+            }
+
+            if listed.len() < ZONES_PER_PAGE {
+                break;
+            }
+        }
+
+        Ok(zones)
+    }
+
+    /// Points the record of the given host and type to the address, creating
+    /// the record if there is none.
+    fn set_record(
+        &self,
+        config: &ApiConfig,
+        zone: &str,
+        host: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let content = ip.to_string();
+
+        // The records are returned as {"<id>": {...}}, or as [] if there are
+        // none.
+        let records = self.call(
+            config,
+            "dns/records",
+            &[("domain-name", zone), ("host", host), ("type", kind)],
+        )?;
+
+        let existing = records
+            .as_object()
+            .and_then(|records| records.values().next());
+
+        let response = match existing {
+            Some(record) => {
+                if record.get("record").and_then(|r| r.as_str()) == Some(&content) {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("id").and_then(|i| i.as_str()) else {
+                    return Err(DdnsUpdateError::Json("record has no id".into()));
+                };
+
+                let ttl = config
+                    .ttl
+                    .map(|ttl| ttl.to_string())
+                    .or_else(|| Some(record.get("ttl")?.as_str()?.to_string()))
+                    .unwrap_or_else(|| DEFAULT_TTL.to_string());
+
+                self.call(
+                    config,
+                    "dns/mod-record",
+                    &[
+                        ("domain-name", zone),
+                        ("record-id", id),
+                        ("host", host),
+                        ("record", &content),
+                        ("ttl", &ttl),
+                    ],
+                )?
+            }
+
+            None => {
+                let ttl = config.ttl.unwrap_or(DEFAULT_TTL).to_string();
+
+                self.call(
+                    config,
+                    "dns/add-record",
+                    &[
+                        ("domain-name", zone),
+                        ("record-type", kind),
+                        ("host", host),
+                        ("record", &content),
+                        ("ttl", &ttl),
+                    ],
+                )?
+            }
+        };
+
+        match response.get("status").and_then(|s| s.as_str()) {
+            Some("Success") => Ok(()),
+            _ => Err(DdnsUpdateError::Json(
+                format!("unexpected response: {}", response).into(),
+            )),
+        }
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        match &self.config {
+            Config::DynamicUrls {
+                ipv4: ipv4_tokens,
+                ipv6: ipv6_tokens,
+            } => {
+                for (ip, tokens) in [(ipv4, ipv4_tokens), (ipv6, ipv6_tokens)] {
+                    let Some(ip) = ip else {
+                        continue;
+                    };
+
+                    for (domain, token) in tokens {
+                        update_with_token(&self.client, domain, token.expose(), *ip)?;
+                    }
+                }
+            }
+
+            Config::Api(config) => {
+                if self.cached_zones.is_empty() {
+                    self.cached_zones = self.get_zones(config)?;
+                }
+
+                for domain in &config.domains {
+                    let Some((zone, host)) = find_zone(&self.cached_zones, domain, "") else {
+                        return Err(DdnsUpdateError::provider(
+                            "ClouDNS",
+                            ErrorKind::NotFound,
+                            format!("no zone of the account contains {}", domain).into(),
+                        ));
+                    };
+
+                    for ip in [ipv4, ipv6].into_iter().flatten() {
+                        self.set_record(config, zone, host, *ip)?;
+                    }
+                }
+            }
+        }
+
+        let mut result = FixedVec::new();
+        result.extend(ipv4.into_iter().chain(ipv6));
+
+        Ok(result)
+    }
+
+    /// See: https://www.cloudns.net/wiki/article/104/
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let Config::Api(config) = &self.config else {
+            return None;
+        };
+
+        let result = self.get_zones(config).map(|zones| {
+            let found = describe_found_domains(&config.domains, |domain| {
+                find_zone(&zones, domain, "").is_some()
+            });
+
+            self.cached_zones = zones;
+            found
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            sub_auth_user = "ddns"
+            auth_password = "password"
+            domains = "example.com"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            config,
+            Config::Api(ApiConfig {
+                user: ApiUser::SubAuthUser(_),
+                ..
+            })
+        ));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            auth_id = "1234"
+            sub_auth_id = "5678"
+            auth_password = "password"
+            domains = "example.com"
+            "#,
+        );
+        assert!(config.is_err());
+
+        let config = toml::from_str::<Config>(
+            r#"
+            tokens = { "example.com" = "token" }
+            auth_password = "password"
+            "#,
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/cloudns.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            auth_id = "1234"
+            auth_password = "password"
+            domains = ["example.com", "www.example.com"]
+            "#,
+        )
+        .unwrap();
+
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is already up to date, and there is no
+        // AAAA record for www.example.com yet.
+        let sent = take_sent();
+        let methods = sent
+            .iter()
+            .map(|s| {
+                let path = s.url.split('?').next().unwrap();
+                path.trim_start_matches("https://api.cloudns.net/")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            [
+                "dns/list-zones.json",
+                "dns/records.json",
+                "dns/records.json",
+                "dns/mod-record.json",
+                "dns/records.json",
+                "dns/mod-record.json",
+                "dns/records.json",
+                "dns/add-record.json",
+            ]
+        );
+
+        // The TTL of the existing record is kept.
+        assert!(sent[3].url.ends_with(
+            "&domain-name=example.com&record-id=2&host=&record=2001%3Adb8%3A%3A1&ttl=300"
+        ));
+        assert!(sent[7].url.ends_with(
+            "&domain-name=example.com&record-type=AAAA&host=www&record=2001%3Adb8%3A%3A1&ttl=3600"
+        ));
+
+        service.cached_zones.clear();
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider { message: m, .. })) if &*m == "Invalid authentication, incorrect auth-id or auth-password."
+        ));
+    }
+
+    #[test]
+    fn update_with_tokens() {
+        add_fixtures(include_str!("fixtures/cloudns.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            tokens = { "example.com" = "token4" }
+            ipv6_tokens = { "example.com" = "token6" }
+            "#,
+        )
+        .unwrap();
+
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Invalid request. (example.com)"
+        ));
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://ipv4.cloudns.net/api/dynamicURL/?q=token4",
+                "https://ipv6.cloudns.net/api/dynamicURL/?q=token6",
+            ]
+        );
+        assert!(service.test_credentials().is_none());
+    }
+}
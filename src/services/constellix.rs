@@ -0,0 +1,444 @@
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::hmac;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, DiscoveredRecord,
+    ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.dns.constellix.com/v1";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The API key and secret key, found under Edit My Account > Security
+    /// Tokens.
+    api_key: Box<str>,
+    secret_key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. The TTL of the records is left
+    /// as it is if not given.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_records: Vec<Record>,
+}
+
+#[derive(Debug)]
+struct Record {
+    id: u64,
+
+    /// The ID of the domain (i.e. zone) the record belongs to.
+    domain_id: u64,
+
+    /// The name within the domain, which is empty for the domain itself.
+    name: Box<str>,
+
+    /// The fully-qualified name, e.g. "www.example.com".
+    fqdn: Box<str>,
+
+    kind: RecordType,
+    ttl: u32,
+
+    /// The address the record was last known to point to.
+    value: Box<str>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_records: Vec::new(),
+        }
+    }
+}
+
+/// Returns the value of the x-cns-security-token header, which is
+/// "apiKey:hmac:timestamp", the HMAC being the Base64 of the HMAC-SHA1 of the
+/// timestamp (in milliseconds) keyed with the secret key.
+///
+/// See: https://api-docs.constellix.com/#authentication
+fn security_token(api_key: &str, secret_key: &str, timestamp: u128) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret_key.as_bytes());
+    let timestamp = timestamp.to_string();
+    let signature = hmac::sign(&key, timestamp.as_bytes());
+
+    let hmac = data_encoding::BASE64.encode(signature.as_ref());
+    [api_key, &hmac, &timestamp].join(":")
+}
+
+impl Service {
+    /// Errors are reported as {"errors": ["...", ...]}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let errors = resp_json
+            .get("errors")
+            .and_then(|e| e.as_array())
+            .ok_or_else(|| String::from("expected errors"))?
+            .iter()
+            .filter_map(|e| e.as_str())
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            return Err(String::from("expected string"));
+        }
+
+        Ok(errors.join(", ").into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into())),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Constellix", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// The security token is only valid for a few minutes around its
+    /// timestamp, so it is made anew for each request.
+    fn token(&self) -> String {
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_millis(),
+            Err(_) => 0,
+        };
+
+        security_token(
+            &self.config.api_key,
+            self.config.secret_key.expose(),
+            timestamp,
+        )
+    }
+
+    fn get(&self, url: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set("x-cns-security-token", &self.token())
+            .call();
+
+        self.parse_and_check_response(response)
+    }
+
+    /// Lists the domains of the account as (ID, name).
+    ///
+    /// See: https://api-docs.constellix.com/#get-all-domains
+    fn get_zones(&self) -> Result<Vec<(u64, Box<str>)>, DdnsUpdateError> {
+        let response = self.get(&format!("{}/domains", ENDPOINT))?;
+
+        let Some(listed) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("expected a list of domains".into()));
+        };
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let id = zone.get("id").and_then(|i| i.as_u64());
+            let name = zone.get("name").and_then(|n| n.as_str());
+
+            let (Some(id), Some(name)) = (id, name) else {
+                return Err(DdnsUpdateError::Json("domain has no id or name?".into()));
+            };
+
+            zones.push((id, name.into()));
+        }
+
+        Ok(zones)
+    }
+
+    /// Lists the A and AAAA records of the domain.
+    ///
+    /// See: https://api-docs.constellix.com/#get-all-records-of-a-type
+    fn get_records(&self, domain_id: u64, zone: &str) -> Result<Vec<Record>, DdnsUpdateError> {
+        let mut records = Vec::new();
+
+        for (kind, path) in [(RecordType::A, "A"), (RecordType::Aaaa, "AAAA")] {
+            let url = format!("{}/domains/{}/records/{}", ENDPOINT, domain_id, path);
+            let response = self.get(&url)?;
+
+            let Some(listed) = response.as_array() else {
+                return Err(DdnsUpdateError::Json("expected a list of records".into()));
+            };
+
+            for record in listed {
+                let id = record.get("id").and_then(|i| i.as_u64());
+                let name = record.get("name").and_then(|n| n.as_str());
+
+                let (Some(id), Some(name)) = (id, name) else {
+                    return Err(DdnsUpdateError::Json("record has no id or name?".into()));
+                };
+
+                let ttl = record.get("ttl").and_then(|t| t.as_u64()).unwrap_or(0);
+                let value = record
+                    .pointer("/roundRobin/0/value")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+
+                // The domain itself has an empty name.
+                let fqdn: Box<str> = if name.is_empty() {
+                    zone.into()
+                } else {
+                    format!("{}.{}", name, zone).into()
+                };
+
+                records.push(Record {
+                    id,
+                    domain_id,
+                    name: name.into(),
+                    fqdn,
+                    kind,
+                    ttl: ttl as u32,
+                    value: value.into(),
+                });
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Points the record to the address alone.
+    ///
+    /// See: https://api-docs.constellix.com/#update-a-record
+    fn put_record(&self, record: &Record, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let url = format!(
+            "{}/domains/{}/records/{}/{}",
+            ENDPOINT, record.domain_id, record.kind, record.id
+        );
+
+        let body = serde_json::json!({
+            "name": record.name,
+            "ttl": self.config.ttl.unwrap_or(record.ttl),
+            "roundRobin": [{ "value": ip.to_string(), "disableFlag": false }],
+        });
+
+        let response = self
+            .client
+            .put(&url)
+            .set("x-cns-security-token", &self.token())
+            .send_json(body);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Looks up the records of the configured domains and caches them along
+    /// with the IDs of their domains, so that they are only looked up once.
+    /// Only the domains containing any of the configured ones are looked into.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        for (id, zone) in self.get_zones()? {
+            let is_relevant = self
+                .config
+                .domains
+                .iter()
+                .any(|domain| **domain == *zone || domain.ends_with(&format!(".{}", zone)));
+
+            if !is_relevant {
+                continue;
+            }
+
+            for record in self.get_records(id, &zone)? {
+                if self.config.domains.contains(&record.fqdn) {
+                    self.cached_records.push(record)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_cached_records(
+        &mut self,
+        ipv4: Option<IpAddr>,
+        ipv6: Option<IpAddr>,
+    ) -> Result<(), DdnsUpdateError> {
+        for i in 0..self.cached_records.len() {
+            let record = &self.cached_records[i];
+
+            let ip = match (record.kind, ipv4, ipv6) {
+                (RecordType::A, Some(ipv4), _) => ipv4,
+                (RecordType::Aaaa, _, Some(ipv6)) => ipv6,
+                _ => continue,
+            };
+
+            let value = ip.to_string();
+            if *record.value == value && self.config.ttl.is_none_or(|ttl| ttl == record.ttl) {
+                continue;
+            }
+
+            self.put_record(record, ip)?;
+
+            let record = &mut self.cached_records[i];
+            record.value = value.into();
+            record.ttl = self.config.ttl.unwrap_or(record.ttl);
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for (id, zone) in self.get_zones()? {
+            for record in self.get_records(id, &zone)? {
+                found.push(DiscoveredRecord {
+                    name: record.fqdn,
+                    kind: record.kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_records.is_empty() {
+            self.cache_records()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4()).copied();
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6()).copied();
+
+        // The records may have been changed or removed behind our back, so
+        // they are looked up again after a failure.
+        if let Err(e) = self.update_cached_records(ipv4, ipv6) {
+            self.cached_records.clear();
+            return Err(e);
+        }
+
+        let mut result = FixedVec::new();
+        result.extend(ipv4);
+        result.extend(ipv6);
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records
+                    .iter()
+                    .any(|record| &*record.fqdn == domain)
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn token() {
+        let token = security_token("apikey", "secret", 1_700_000_000_000);
+
+        assert_eq!(token, "apikey:GkxmUVZIPEAQC5SikuOBv4kZAhc=:1700000000000");
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/constellix.txt"));
+
+        let mut service = Service::new(
+            Config {
+                api_key: "apikey".into(),
+                secret_key: Secret::new("secret".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // example.net is not looked into, and the A record of example.com is
+        // up to date.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.dns.constellix.com/v1/domains",
+                "GET https://api.dns.constellix.com/v1/domains/1/records/A",
+                "GET https://api.dns.constellix.com/v1/domains/1/records/AAAA",
+                "PUT https://api.dns.constellix.com/v1/domains/1/records/A/11",
+                "PUT https://api.dns.constellix.com/v1/domains/1/records/AAAA/12",
+            ]
+        );
+
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "name": "www",
+                "ttl": 600,
+                "roundRobin": [{ "value": "192.0.2.1", "disableFlag": false }],
+            })
+        );
+
+        // Nothing is sent while the addresses stay the same.
+        service.update_record(&[ipv4, ipv6]).unwrap();
+        assert!(take_sent().is_empty());
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Unable to authenticate the request"
+        ));
+        assert!(service.cached_records.is_empty());
+    }
+}
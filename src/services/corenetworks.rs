@@ -0,0 +1,420 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://beta.api.core-networks.de";
+
+/// The TTL of the records if none is configured.
+const DEFAULT_TTL: u32 = 3600;
+
+/// How long before its expiry a token is replaced, so that it does not expire
+/// in the middle of an update.
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The credentials of an API user, created in the customer area under
+    /// API > Access.
+    username: Box<str>,
+    password: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default = "default_ttl")]
+    ttl: u32,
+}
+
+fn default_ttl() -> u32 {
+    DEFAULT_TTL
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+
+    /// The token of the current login along with when it expires. Logging in
+    /// is rate limited, so it is kept for as long as it is valid.
+    token: Option<(Secret<Box<str>>, Instant)>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+            token: None,
+        }
+    }
+}
+
+impl Service {
+    /// Errors are mostly reported as {"error": "..."}, and otherwise as plain
+    /// text.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let text = response
+            .into_string()
+            .map_err(|e| String::from("unable to read response:") + &e.to_string())?;
+
+        let message = serde_json::from_str::<serde_json::Value>(&text)
+            .ok()
+            .and_then(|json| Some(json.get("error")?.as_str()?.to_owned()))
+            .unwrap_or_else(|| text.trim().to_owned());
+
+        Ok(message.into())
+    }
+
+    fn check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<Response, DdnsUpdateError> {
+        match response {
+            Ok(r) => Ok(r),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Core-Networks", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// Returns the token of the current login, logging in again if there is
+    /// none or it is about to expire.
+    ///
+    /// See: https://beta.api.core-networks.de/doc/#functon_auth_token
+    fn token(&mut self) -> Result<Secret<Box<str>>, DdnsUpdateError> {
+        if let Some((token, expires_at)) = &self.token {
+            if Instant::now() + TOKEN_MARGIN < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let response = self
+            .client
+            .post(&format!("{}/auth/token", ENDPOINT))
+            .send_json(serde_json::json!({
+                "login": self.config.username,
+                "password": self.config.password.expose(),
+            }));
+
+        let response = self
+            .check_response(response)?
+            .into_json::<serde_json::Value>()
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+        let token = response.get("token").and_then(|t| t.as_str());
+        let expires = response.get("expires").and_then(|e| e.as_u64());
+
+        let (Some(token), Some(expires)) = (token, expires) else {
+            return Err(DdnsUpdateError::Json(
+                "login has no token or expires?".into(),
+            ));
+        };
+
+        let token = Secret::new(Box::from(token));
+        let expires_at = Instant::now() + Duration::from_secs(expires);
+        self.token = Some((token.clone(), expires_at));
+
+        Ok(token)
+    }
+
+    /// Sends a GET request, or a POST request if there is a body. A token
+    /// which was refused, e.g. because the login was ended elsewhere, is
+    /// replaced once.
+    fn call(
+        &mut self,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<Response, DdnsUpdateError> {
+        let was_cached = self.token.is_some();
+        let response = self.send(path, body)?;
+
+        let response = match response {
+            Err(Error::Status(401, _)) if was_cached => {
+                self.token = None;
+                self.send(path, body)?
+            }
+            response => response,
+        };
+
+        self.check_response(response)
+    }
+
+    fn send(
+        &mut self,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<Result<Response, Error>, DdnsUpdateError> {
+        let token = self.token()?;
+        let url = format!("{}{}", ENDPOINT, path);
+        let auth = format!("Bearer {}", token.expose());
+
+        Ok(match body {
+            Some(body) => self
+                .client
+                .post(&url)
+                .set("Authorization", &auth)
+                .send_json(body),
+            None => self.client.get(&url).set("Authorization", &auth).call(),
+        })
+    }
+
+    fn get_json(&mut self, path: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+        self.call(path, None)?
+            .into_json::<serde_json::Value>()
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))
+    }
+
+    /// See: https://beta.api.core-networks.de/doc/#functon_dnszones
+    fn get_zones(&mut self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let response = self.get_json("/dnszones/")?;
+
+        let Some(listed) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("expected a list of zones".into()));
+        };
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.get("name").and_then(|n| n.as_str()) else {
+                return Err(DdnsUpdateError::Json("zone has no name?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// Replaces the records of the given name and type with the address alone,
+    /// unless it is the only one already. Returns whether anything changed,
+    /// in which case the zone has to be committed.
+    ///
+    /// See: https://beta.api.core-networks.de/doc/#functon_dnszones_records_delete
+    fn set_record(&mut self, zone: &str, name: &str, ip: IpAddr) -> Result<bool, DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+
+        let existing = self.get_json(&format!(
+            "/dnszones/{}/records/?type={}&name={}",
+            zone,
+            kind,
+            crate::util::url_encode(name)
+        ))?;
+
+        let is_up_to_date = existing.as_array().is_some_and(|existing| {
+            existing.len() == 1
+                && existing[0].get("data").and_then(|d| d.as_str()) == Some(&ip.to_string())
+                && existing[0].get("ttl").and_then(|t| t.as_u64()) == Some(self.config.ttl as u64)
+        });
+
+        if is_up_to_date {
+            return Ok(false);
+        }
+
+        let delete = serde_json::json!({ "name": name, "type": kind });
+        self.call(&format!("/dnszones/{}/records/delete", zone), Some(&delete))?;
+
+        let add = serde_json::json!({
+            "name": name,
+            "ttl": self.config.ttl,
+            "type": kind,
+            "data": ip.to_string(),
+        });
+        self.call(&format!("/dnszones/{}/records/", zone), Some(&add))?;
+
+        Ok(true)
+    }
+
+    /// Changes only take effect once the zone is committed.
+    ///
+    /// See: https://beta.api.core-networks.de/doc/#functon_dnszones_commit
+    fn commit(&mut self, zone: &str) -> Result<(), DdnsUpdateError> {
+        let body = serde_json::json!({});
+        self.call(&format!("/dnszones/{}/records/commit", zone), Some(&body))?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    ///
+    /// See: https://beta.api.core-networks.de/doc/#functon_dnszones_records
+    fn discover(&mut self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            let response = self.get_json(&format!("/dnszones/{}/records/", zone))?;
+
+            let Some(records) = response.as_array() else {
+                return Err(DdnsUpdateError::Json("expected a list of records".into()));
+            };
+
+            for record in records {
+                let kind = match record.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match record.get("name").and_then(|n| n.as_str()) {
+                    Some("@") => zone.clone(),
+                    Some(name) => format!("{}.{}", name, zone).into(),
+                    None => return Err(DdnsUpdateError::Json("record has no name?".into())),
+                };
+
+                // A name has as many records of a type as it has addresses.
+                if !found
+                    .iter()
+                    .any(|r: &DiscoveredRecord| r.name == name && r.kind == kind)
+                {
+                    found.push(DiscoveredRecord { name, kind });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        // The zones which were changed, to be committed once all of their
+        // records are set.
+        let mut changed: Vec<Box<str>> = Vec::new();
+
+        for domain in self.config.domains.clone() {
+            let Some((zone, name)) = find_zone(&self.cached_zones, &domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Core-Networks",
+                    ErrorKind::NotFound,
+                    format!("no zone of the account contains {}", domain).into(),
+                ));
+            };
+
+            let (zone, name) = (zone.clone(), Box::<str>::from(name));
+
+            for ip in &result {
+                if self.set_record(&zone, &name, *ip)? && !changed.contains(&zone) {
+                    changed.push(zone.clone());
+                }
+            }
+        }
+
+        for zone in &changed {
+            self.commit(zone)?;
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/corenetworks.txt"));
+
+        let mut service = Service::new(
+            Config {
+                username: "user".into(),
+                password: Secret::new("password".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        // The A record of example.com is up to date, and the token is used
+        // for every request.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "POST https://beta.api.core-networks.de/auth/token",
+                "GET https://beta.api.core-networks.de/dnszones/",
+                "GET https://beta.api.core-networks.de/dnszones/example.com/records/?type=A&name=%40",
+                "GET https://beta.api.core-networks.de/dnszones/example.com/records/?type=A&name=www",
+                "POST https://beta.api.core-networks.de/dnszones/example.com/records/delete",
+                "POST https://beta.api.core-networks.de/dnszones/example.com/records/",
+                "POST https://beta.api.core-networks.de/dnszones/example.com/records/commit",
+            ]
+        );
+
+        let body = sent[5].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "name": "www", "ttl": 300, "type": "A", "data": "192.0.2.1" })
+        );
+
+        // The token is refused this time, so the service logs in again.
+        let tested = service.test_credentials().unwrap().unwrap();
+        assert_eq!(tested, "2 of 2 domain(s) found");
+
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://beta.api.core-networks.de/dnszones/",
+                "POST https://beta.api.core-networks.de/auth/token",
+                "GET https://beta.api.core-networks.de/dnszones/",
+            ]
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{self, Client, Error, Settings};
+use crate::util::{url_encode, FixedVec, Secret};
+
+use super::{one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The placeholders which are replaced in the URL and the body.
+const IPV4: &str = "{ipv4}";
+const IPV6: &str = "{ipv6}";
+const DOMAIN: &str = "{domain}";
+const DOMAINS: &str = "{domains}";
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Method {
+    #[default]
+    Get,
+    Post,
+    Put,
+    Patch,
+}
+
+/// An update sent to a URL given by the user, for the providers without a
+/// service of their own. The URL and the body are templates, in which
+/// `{ipv4}`, `{ipv6}`, `{domain}` and `{domains}` (comma-separated) are
+/// replaced; one request is sent for each domain if `{domain}` is used.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub struct Config {
+    url: Box<str>,
+    method: Method,
+    body: Option<Box<str>>,
+    headers: BTreeMap<Box<str>, Secret<Box<str>>>,
+    basic_auth: Option<(Box<str>, Secret<Box<str>>)>,
+    domains: Vec<Box<str>>,
+    success_status: Option<u16>,
+    success_pattern: Option<Box<str>>,
+    success_substring: Option<Box<str>>,
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    url: Box<str>,
+
+    #[serde(default)]
+    method: Method,
+
+    #[serde(default)]
+    body: Option<Box<str>>,
+
+    /// Sent as they are, e.g. { Authorization = "Bearer ..." }.
+    #[serde(default)]
+    headers: BTreeMap<Box<str>, Secret<Box<str>>>,
+
+    /// Sent as HTTP basic authentication if given.
+    #[serde(default)]
+    username: Option<Box<str>>,
+    #[serde(default)]
+    password: Option<Secret<Box<str>>>,
+
+    #[serde(default, deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The status of a successful update. Any 2xx status is, if not given.
+    #[serde(default)]
+    success_status: Option<u16>,
+
+    /// A regex the body of a successful update matches. Only available if
+    /// dynners is built with regex.
+    #[serde(default)]
+    success_pattern: Option<Box<str>>,
+
+    /// A text the body of a successful update contains.
+    #[serde(default)]
+    success_substring: Option<Box<str>>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = String;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let basic_auth = match (raw.username, raw.password) {
+            (Some(username), Some(password)) => Some((username, password)),
+            (None, None) => None,
+            _ => return Err("username and password must be given together".into()),
+        };
+
+        if raw.body.is_some() && raw.method == Method::Get {
+            return Err("a body cannot be sent with method = \"GET\"".into());
+        }
+
+        let uses_domain =
+            raw.url.contains(DOMAIN) || raw.body.as_deref().is_some_and(|b| b.contains(DOMAIN));
+        if uses_domain && raw.domains.is_empty() {
+            return Err("{domain} is used, but no domains are given".into());
+        }
+
+        #[cfg(feature = "regex")]
+        if let Some(pattern) = &raw.success_pattern {
+            Regex::new(pattern).map_err(|e| format!("invalid success_pattern: {}", e))?;
+        }
+
+        #[cfg(not(feature = "regex"))]
+        if raw.success_pattern.is_some() {
+            return Err(
+                "success_pattern needs dynners to be built with regex, use success_substring instead"
+                    .into(),
+            );
+        }
+
+        Ok(Config {
+            url: raw.url,
+            method: raw.method,
+            body: raw.body,
+            headers: raw.headers,
+            basic_auth,
+            domains: raw.domains,
+            success_status: raw.success_status,
+            success_pattern: raw.success_pattern,
+            success_substring: raw.success_substring,
+        })
+    }
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Option<Secret<Box<str>>>,
+
+    #[cfg(feature = "regex")]
+    success_regex: Option<Regex>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        // The URL is the user's own and may carry a token, so whatever comes
+        // before the first placeholder is kept out of the diagnostics.
+        let fixed = [IPV4, IPV6, DOMAIN, DOMAINS]
+            .iter()
+            .filter_map(|placeholder| config.url.find(placeholder))
+            .min()
+            .unwrap_or(config.url.len());
+        http::register_secret(&config.url[..fixed]);
+
+        let auth = config.basic_auth.as_ref().map(|(username, password)| {
+            let username_password = format!("{}:{}", username, password.expose());
+            let base64 = data_encoding::BASE64.encode(username_password.as_bytes());
+            Secret::new((String::from("Basic ") + &base64).into())
+        });
+
+        Self {
+            #[cfg(feature = "regex")]
+            success_regex: config
+                .success_pattern
+                .as_deref()
+                .map(|pattern| Regex::new(pattern).expect("checked when the config was read")),
+
+            config,
+            client: Client::new(http),
+            auth,
+        }
+    }
+}
+
+/// Replaces the placeholders of the template with the values, which are
+/// URL-encoded if `encode` is set.
+fn expand(template: &str, values: &[(&str, &str)], encode: bool) -> String {
+    let mut expanded = String::from(template);
+
+    for (placeholder, value) in values {
+        if !expanded.contains(placeholder) {
+            continue;
+        }
+
+        let value = match encode {
+            true => url_encode(value),
+            false => String::from(*value),
+        };
+
+        expanded = expanded.replace(placeholder, &value);
+    }
+
+    expanded
+}
+
+impl Service {
+    fn uses(&self, placeholder: &str) -> bool {
+        self.config.url.contains(placeholder)
+            || self
+                .config
+                .body
+                .as_deref()
+                .is_some_and(|body| body.contains(placeholder))
+    }
+
+    fn is_success(&self, status: u16, body: &str) -> bool {
+        let status_matches = match self.config.success_status {
+            Some(success) => status == success,
+            None => (200..300).contains(&status),
+        };
+
+        #[cfg(feature = "regex")]
+        let pattern_matches = self
+            .success_regex
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(body));
+
+        #[cfg(not(feature = "regex"))]
+        let pattern_matches = true;
+
+        let substring_matches = self
+            .config
+            .success_substring
+            .as_deref()
+            .is_none_or(|substring| body.contains(substring));
+
+        status_matches && pattern_matches && substring_matches
+    }
+
+    fn send(&self, values: &[(&str, &str)]) -> Result<(), DdnsUpdateError> {
+        let url = expand(&self.config.url, values, true);
+
+        let mut request = match self.config.method {
+            Method::Get => self.client.get(&url),
+            Method::Post => self.client.post(&url),
+            Method::Put => self.client.put(&url),
+            Method::Patch => self.client.patch(&url),
+        };
+
+        if let Some(auth) = &self.auth {
+            request = request.set("Authorization", auth.expose());
+        }
+
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value.expose());
+        }
+
+        let response = match &self.config.body {
+            Some(body) => request.send_string(&expand(body, values, false)),
+            None => request.call(),
+        };
+
+        let (status, response) = match response {
+            Ok(resp) => (resp.status(), resp),
+            Err(Error::Status(status, resp)) => (status, resp),
+            Err(Error::Transport(t)) => {
+                return Err(DdnsUpdateError::TransportError(t.to_string().into()))
+            }
+        };
+
+        let kind = ErrorKind::from_response(status, &response);
+        let body = response.into_string().map_err(|e| {
+            DdnsUpdateError::provider("custom HTTP", ErrorKind::Other, e.to_string().into())
+        })?;
+
+        if self.is_success(status, &body) {
+            return Ok(());
+        }
+
+        let body = body.trim();
+        let excerpt = match body.char_indices().nth(200) {
+            Some((end, _)) => &body[..end],
+            None => body,
+        };
+
+        Err(DdnsUpdateError::provider(
+            "custom HTTP",
+            kind,
+            format!("unsuccessful update (status {}): {}", status, excerpt).into(),
+        ))
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        let ipv4_text = ipv4.map(|ip| ip.to_string()).unwrap_or_default();
+        let ipv6_text = ipv6.map(|ip| ip.to_string()).unwrap_or_default();
+        let domains = self.config.domains.join(",");
+
+        if self.uses(DOMAIN) {
+            for domain in &self.config.domains {
+                self.send(&[
+                    (IPV4, &ipv4_text),
+                    (IPV6, &ipv6_text),
+                    (DOMAIN, domain),
+                    (DOMAINS, &domains),
+                ])?;
+            }
+        } else {
+            self.send(&[(IPV4, &ipv4_text), (IPV6, &ipv6_text), (DOMAINS, &domains)])?;
+        }
+
+        // Only the addresses which were sent are updated, unless neither was,
+        // in which case the server is left to find out the address itself.
+        let mut result = FixedVec::new();
+        let (uses_ipv4, uses_ipv6) = (self.uses(IPV4), self.uses(IPV6));
+
+        if uses_ipv4 || !uses_ipv6 {
+            result.extend(ipv4);
+        }
+
+        if uses_ipv6 || !uses_ipv4 {
+            result.extend(ipv6);
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            url = "https://example.com/update?host={domain}"
+            "#,
+        );
+        assert!(config.is_err());
+
+        let config = toml::from_str::<Config>(
+            r#"
+            url = "https://example.com/update"
+            body = "ip={ipv4}"
+            "#,
+        );
+        assert!(config.is_err());
+
+        let config = toml::from_str::<Config>(
+            r#"
+            url = "https://example.com/update"
+            username = "user"
+            "#,
+        );
+        assert!(config.is_err());
+
+        // The pattern is never taken as a plain text to look for instead.
+        let config = toml::from_str::<Config>(
+            r#"
+            url = "https://example.com/update"
+            success_pattern = "^good|nochg"
+            "#,
+        );
+        assert_eq!(config.is_ok(), cfg!(feature = "regex"));
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/custom_http.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            url = "https://example.com/update?host={domain}&ip={ipv6}"
+            method = "POST"
+            body = '{"hosts": "{domains}", "ipv6": "{ipv6}"}'
+            headers = { "Content-Type" = "application/json" }
+            domains = ["a.example.com", "b.example.com"]
+            success_substring = "updated"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        // Only the IPv6 address is sent.
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/update?host=a.example.com&ip=2001%3Adb8%3A%3A1",
+                "https://example.com/update?host=b.example.com&ip=2001%3Adb8%3A%3A1",
+            ]
+        );
+        assert_eq!(
+            sent[0].body.as_deref(),
+            Some(r#"{"hosts": "a.example.com,b.example.com", "ipv6": "2001:db8::1"}"#)
+        );
+
+        // A successful status is not enough if the body does not match.
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Other,
+                message: m,
+                ..
+            }) if &*m == "unsuccessful update (status 200): nothing to do"
+        ));
+    }
+}
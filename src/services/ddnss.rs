@@ -0,0 +1,228 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
+
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://www.ddnss.de/upd.php";
+
+/// ddnss.de is updated either with the update key of the account, or with its
+/// username and password.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub struct Config {
+    credentials: Credentials,
+    domains: Vec<Box<str>>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+enum Credentials {
+    Key(Secret<Box<str>>),
+    Account {
+        username: Box<str>,
+        password: Secret<Box<str>>,
+    },
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    /// The update key, found in the control panel of ddnss.de.
+    key: Option<Secret<Box<str>>>,
+
+    username: Option<Box<str>>,
+    password: Option<Secret<Box<str>>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let credentials = match (raw.key, raw.username, raw.password) {
+            (Some(key), None, None) => Credentials::Key(key),
+            (None, Some(username), Some(password)) => Credentials::Account { username, password },
+            _ => return Err("either key, or username and password must be given"),
+        };
+
+        Ok(Config {
+            credentials,
+            domains: raw.domains,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+/// Turns the HTML page ddnss.de answers with into its text, e.g. "Updated 2
+/// host(s) with 192.0.2.1".
+fn page_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        let mut request = self.client.get(ENDPOINT);
+
+        request = match &self.config.credentials {
+            Credentials::Key(key) => request.query("key", key.expose()),
+            Credentials::Account { username, password } => request
+                .query("user", username)
+                .query("pwd", password.expose()),
+        };
+
+        request = request.query("host", &self.config.domains.join(","));
+
+        let mut result = FixedVec::new();
+
+        if let Some(ipv4) = ipv4 {
+            request = request.query("ip", &ipv4.to_string());
+            result.push(*ipv4);
+        }
+
+        if let Some(ipv6) = ipv6 {
+            request = request.query("ip6", &ipv6.to_string());
+            result.push(*ipv6);
+        }
+
+        match request.call() {
+            Ok(resp) | Err(Error::Status(_, resp)) => {
+                let resp = resp.into_string().map_err(|e| {
+                    DdnsUpdateError::provider("ddnss.de", ErrorKind::Other, e.to_string().into())
+                })?;
+
+                let text = page_text(&resp);
+
+                if text.contains("Updated") || text.starts_with("good") {
+                    Ok(result)
+                } else if text.starts_with("nochg") {
+                    Ok(FixedVec::new())
+                } else if text.contains("badauth") {
+                    Err(DdnsUpdateError::provider(
+                        "ddnss.de",
+                        ErrorKind::Auth,
+                        "Bad authentication details were provided".into(),
+                    ))
+                } else if text.contains("nohost") {
+                    Err(DdnsUpdateError::provider(
+                        "ddnss.de",
+                        ErrorKind::NotFound,
+                        "Hostname does not exist in the account".into(),
+                    ))
+                } else {
+                    Err(DdnsUpdateError::provider(
+                        "ddnss.de",
+                        ErrorKind::Other,
+                        format!("unexpected response \"{}\"", text).into(),
+                    ))
+                }
+            }
+
+            Err(Error::Transport(t)) => Err(DdnsUpdateError::TransportError(t.to_string().into()))?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "home.ddnss.de"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(config.credentials, Credentials::Account { .. }));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            key = "key"
+            username = "user"
+            domains = "home.ddnss.de"
+            "#,
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/ddnss.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            key = "0123456789abcdef"
+            domains = ["home.ddnss.de", "nas.ddnss.de"]
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                ..
+            })
+        ));
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://www.ddnss.de/upd.php?key=0123456789abcdef&host=home.ddnss.de%2Cnas.ddnss.de&ip=192.0.2.1&ip6=2001%3Adb8%3A%3A1",
+                "https://www.ddnss.de/upd.php?key=0123456789abcdef&host=home.ddnss.de%2Cnas.ddnss.de&ip6=2001%3Adb8%3A%3A1",
+            ]
+        );
+    }
+}
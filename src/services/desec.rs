@@ -0,0 +1,302 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType, ZoneName,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. deSEC rejects anything below
+    /// the minimum TTL of the domain (usually 3600), so smaller values and a
+    /// missing TTL are raised to it.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    name: Box<str>,
+    minimum_ttl: u32,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+        config.token = Secret::new((String::from("Token ") + config.token.expose()).into());
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+/// deSEC reports validation errors as objects mapping the offending fields to
+/// a list of messages (one object per RRset for bulk requests), and other
+/// errors as {"detail": "..."}. Either way, the first message is picked.
+fn first_message(value: &serde_json::Value) -> Option<&str> {
+    match value {
+        serde_json::Value::String(message) => Some(message),
+        serde_json::Value::Array(values) => values.iter().find_map(first_message),
+        serde_json::Value::Object(map) => map.values().find_map(first_message),
+        _ => None,
+    }
+}
+
+impl Service {
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        first_message(&resp_json)
+            .map(Box::from)
+            .ok_or_else(|| String::from("expected string"))
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("deSEC", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// See: https://desec.readthedocs.io/en/latest/dns/domains.html#listing-domains
+    fn get_zones(&self) -> Result<Vec<Zone>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://desec.io/api/v1/domains/")
+            .set("Authorization", self.config.token.expose())
+            .call();
+
+        let response = self.parse_and_check_response(response)?;
+
+        let Some(zones) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("desec returned 0 domains".into()));
+        };
+
+        let mut returned_zones = Vec::with_capacity(zones.len());
+
+        for zone in zones {
+            let Some(name) = zone.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no name?".into()));
+            };
+
+            let Some(minimum_ttl) = zone.get("minimum_ttl").and_then(|v| v.as_u64()) else {
+                return Err(DdnsUpdateError::Json("domain has no minimum_ttl?".into()));
+            };
+
+            returned_zones.push(Zone {
+                name: name.into(),
+                minimum_ttl: minimum_ttl.try_into().unwrap_or(u32::MAX),
+            });
+        }
+
+        Ok(returned_zones)
+    }
+
+    /// See: https://desec.readthedocs.io/en/latest/dns/rrsets.html#bulk-modification-of-rrsets
+    fn patch_rrsets(
+        &self,
+        zone: &str,
+        rrsets: &[serde_json::Value],
+    ) -> Result<(), DdnsUpdateError> {
+        let url = format!("https://desec.io/api/v1/domains/{}/rrsets/", zone);
+
+        let response = self
+            .client
+            .patch(&url)
+            .set("Authorization", self.config.token.expose())
+            .send_json(rrsets);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        // The RRsets of a zone are all updated in a single request.
+        let mut updates: Vec<(&str, Vec<serde_json::Value>)> = Vec::new();
+
+        for domain in &self.config.domains {
+            let Some((zone, subname)) = find_zone(&self.cached_zones, domain, "") else {
+                return Err(DdnsUpdateError::provider(
+                    "deSEC",
+                    ErrorKind::NotFound,
+                    format!("no domain managed by the token contains {}", domain).into(),
+                ));
+            };
+
+            let ttl = self.config.ttl.unwrap_or(0).max(zone.minimum_ttl);
+
+            let rrsets = match updates.iter_mut().find(|(name, _)| *name == &*zone.name) {
+                Some((_, rrsets)) => rrsets,
+                None => {
+                    updates.push((&zone.name, Vec::new()));
+                    // UNWRAP-SAFETY: An element was just pushed.
+                    &mut updates.last_mut().unwrap().1
+                }
+            };
+
+            for (kind, ip) in [("A", ipv4), ("AAAA", ipv6)] {
+                if let Some(ip) = ip {
+                    rrsets.push(serde_json::json!({
+                        "subname": subname,
+                        "type": kind,
+                        "ttl": ttl,
+                        "records": [ip.to_string()],
+                    }));
+                }
+            }
+        }
+
+        for (zone, rrsets) in &updates {
+            self.patch_rrsets(zone, rrsets)?;
+        }
+
+        let mut result = FixedVec::new();
+        if let Some(ipv4) = ipv4 {
+            result.push(*ipv4);
+        }
+        if let Some(ipv6) = ipv6 {
+            result.push(*ipv6);
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "").is_some()
+            })
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/desec.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec![
+                    "example.dedyn.io".into(),
+                    "www.example.dedyn.io".into(),
+                    "example.com".into(),
+                ],
+                ttl: Some(60),
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://desec.io/api/v1/domains/",
+                "https://desec.io/api/v1/domains/example.dedyn.io/rrsets/",
+                "https://desec.io/api/v1/domains/example.com/rrsets/",
+            ]
+        );
+        assert_eq!(&*sent[1].method, "PATCH");
+
+        let body = sent[1].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 4);
+        assert_eq!(body[0]["subname"], "");
+        assert_eq!(body[0]["type"], "A");
+        assert_eq!(body[0]["records"][0], "192.0.2.1");
+        assert_eq!(body[3]["subname"], "www");
+        assert_eq!(body[3]["type"], "AAAA");
+
+        // The TTL is raised to the minimum TTL of each domain.
+        assert_eq!(body[0]["ttl"], 3600);
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body[0]["ttl"], 60);
+
+        service.config.domains = vec!["bad.example.com".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Ensure this value is greater than or equal to 60."
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let detail = serde_json::json!({ "detail": "Invalid token." });
+        assert_eq!(first_message(&detail), Some("Invalid token."));
+
+        let bulk = serde_json::json!([{}, { "records": ["Duplicate value."] }]);
+        assert_eq!(first_message(&bulk), Some("Duplicate value."));
+    }
+}
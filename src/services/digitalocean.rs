@@ -0,0 +1,380 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, DiscoveredRecord,
+    ErrorKind, RecordType,
+};
+
+type RecordId = u64;
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// A personal access token with read and write access to the domains.
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds, at least 30.
+    ttl: u32,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_records: Vec<Record>,
+}
+
+#[derive(Debug)]
+struct Record {
+    id: RecordId,
+
+    /// The domain (i.e. zone) the record belongs to, e.g. "example.com".
+    zone: Box<str>,
+
+    /// The fully-qualified name, e.g. "www.example.com".
+    fqdn: Box<str>,
+
+    kind: RecordKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordKind {
+    A,
+    Aaaa,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+        config.token = Secret::new((String::from("Bearer ") + config.token.expose()).into());
+        Self {
+            config,
+            client: Client::new(http),
+            cached_records: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// The largest page size the API allows, so that pagination is rarely
+    /// needed at all.
+    const PER_PAGE: &'static str = "200";
+
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("message")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("DigitalOcean", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// Fetches every page of a listing and returns the items found under
+    /// `key` in each of them. The API links to the next page, if there is one.
+    ///
+    /// See: https://docs.digitalocean.com/reference/api/api-reference/#section/Introduction/Links-and-Pagination
+    fn get_all_pages(
+        &self,
+        url: &str,
+        key: &str,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut items = Vec::new();
+
+        let response = self
+            .client
+            .get(url)
+            .query("per_page", Self::PER_PAGE)
+            .set("Authorization", self.config.token.expose())
+            .call();
+        let mut page = self.parse_and_check_response(response)?;
+
+        loop {
+            let Some(page_items) = page.get_mut(key).and_then(|v| v.as_array_mut()) else {
+                return Err(DdnsUpdateError::Json(
+                    format!("digitalocean returned no {}", key).into(),
+                ));
+            };
+
+            items.append(page_items);
+
+            let next = page.pointer("/links/pages/next").and_then(|v| v.as_str());
+
+            let Some(next) = next else {
+                break;
+            };
+
+            let response = self
+                .client
+                .get(next)
+                .set("Authorization", self.config.token.expose())
+                .call();
+            page = self.parse_and_check_response(response)?;
+        }
+
+        Ok(items)
+    }
+
+    /// See: https://docs.digitalocean.com/reference/api/api-reference/#operation/domains_list
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let domains = self.get_all_pages("https://api.digitalocean.com/v2/domains", "domains")?;
+
+        let mut zones = Vec::with_capacity(domains.len());
+        for domain in domains {
+            let Some(name) = domain.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no name?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// See: https://docs.digitalocean.com/reference/api/api-reference/#operation/domains_list_records
+    fn get_records(&self, zone: &str) -> Result<Vec<Record>, DdnsUpdateError> {
+        let url = format!("https://api.digitalocean.com/v2/domains/{}/records", zone);
+        let records = self.get_all_pages(&url, "domain_records")?;
+
+        let mut returned_records = Vec::new();
+        for record in records {
+            let Some(id) = record.get("id").and_then(|v| v.as_u64()) else {
+                return Err(DdnsUpdateError::Json("record has no id?".into()));
+            };
+
+            let Some(name) = record.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no name?".into()));
+            };
+
+            let Some(ty) = record.get("type").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no type?".into()));
+            };
+
+            let kind = match ty {
+                "A" => RecordKind::A,
+                "AAAA" => RecordKind::Aaaa,
+                _ => continue,
+            };
+
+            // The domain itself is named "@".
+            let fqdn: Box<str> = if name == "@" {
+                zone.into()
+            } else {
+                format!("{}.{}", name, zone).into()
+            };
+
+            returned_records.push(Record {
+                id,
+                zone: zone.into(),
+                fqdn,
+                kind,
+            });
+        }
+
+        Ok(returned_records)
+    }
+
+    /// See: https://docs.digitalocean.com/reference/api/api-reference/#operation/domains_update_record
+    fn put_record(&self, record: &Record, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let url = format!(
+            "https://api.digitalocean.com/v2/domains/{}/records/{}",
+            record.zone, record.id
+        );
+
+        let response = self
+            .client
+            .put(&url)
+            .set("Authorization", self.config.token.expose())
+            .send_json(serde_json::json!({
+                "type": if ip.is_ipv4() { "A" } else { "AAAA" },
+                "data": ip.to_string(),
+                "ttl": self.config.ttl,
+            }));
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Looks up the records of the configured domains and caches them, so that
+    /// they are only looked up once. Only the zones containing any of the
+    /// domains are looked into.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        for zone in self.get_zones()? {
+            let is_relevant = self
+                .config
+                .domains
+                .iter()
+                .any(|domain| **domain == *zone || domain.ends_with(&format!(".{}", zone)));
+
+            if !is_relevant {
+                continue;
+            }
+
+            for record in self.get_records(&zone)? {
+                if self.config.domains.contains(&record.fqdn) {
+                    self.cached_records.push(record)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                let kind = match record.kind {
+                    RecordKind::A => RecordType::A,
+                    RecordKind::Aaaa => RecordType::Aaaa,
+                };
+
+                found.push(DiscoveredRecord {
+                    name: record.fqdn,
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_records.is_empty() {
+            self.cache_records()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        for record in &self.cached_records {
+            match (&record.kind, ipv4, ipv6) {
+                (RecordKind::A, Some(ipv4), _) => self.put_record(record, *ipv4)?,
+                (RecordKind::Aaaa, _, Some(ipv6)) => self.put_record(record, *ipv6)?,
+                _ => (),
+            }
+        }
+
+        let mut result = FixedVec::new();
+        if let Some(ipv4) = ipv4 {
+            result.push(*ipv4);
+        }
+        if let Some(ipv6) = ipv6 {
+            result.push(*ipv6);
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records
+                    .iter()
+                    .any(|record| &*record.fqdn == domain)
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/digitalocean.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // example.net is not looked into, the records of example.com span
+        // two pages.
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.digitalocean.com/v2/domains?per_page=200",
+                "https://api.digitalocean.com/v2/domains/example.com/records?per_page=200",
+                "https://api.digitalocean.com/v2/domains/example.com/records?page=2&per_page=200",
+                "https://api.digitalocean.com/v2/domains/example.com/records/1",
+                "https://api.digitalocean.com/v2/domains/example.com/records/2",
+                "https://api.digitalocean.com/v2/domains/example.com/records/3",
+            ]
+        );
+
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["type"], "A");
+        assert_eq!(body["data"], "192.0.2.1");
+        assert_eq!(body["ttl"], 300);
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "The resource you were accessing could not be found."
+        ));
+    }
+}
@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::Settings;
+use crate::util::{one_or_more_string, FixedVec, Secret};
+
+use super::shared_dyndns::{self, Parameters, Quirk};
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, SuspensionState};
+
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// dnshome.de takes the subdomain itself as the username, with the
+    /// password set for the subdomain. Subdomains with different passwords
+    /// need an entry each.
+    password: Secret<Box<str>>,
+
+    /// The subdomains, e.g. "home.dnshome.de".
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+/// dnshome.de takes the IPv4 address in `ip`, and the IPv6 one in `ip6`.
+const PARAMETERS: Parameters = Parameters {
+    hostname: Cow::Borrowed("hostname"),
+    ip: Cow::Borrowed("ip"),
+    ipv6: Some(Cow::Borrowed("ip6")),
+};
+
+const QUIRKS: &[Quirk] = &[Quirk {
+    response: "badauth",
+    message: "Bad authentication details were provided (the username is the subdomain, with its own password)",
+    kind: ErrorKind::Auth,
+    suspend_for: None,
+}];
+
+pub struct Service {
+    /// One client for each subdomain, as each of them has credentials of its
+    /// own.
+    inner: Vec<shared_dyndns::Service>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let inner = config
+            .domains
+            .into_iter()
+            .map(|domain| {
+                let config = shared_dyndns::Config::new(
+                    Secret::new(domain.clone()),
+                    config.password.clone(),
+                    vec![domain],
+                );
+
+                shared_dyndns::Service::from_config(
+                    "dnshome.de",
+                    "https://www.dnshome.de/dyndns.php",
+                    config,
+                    http,
+                )
+                .with_parameters(PARAMETERS)
+                .with_quirks(QUIRKS)
+            })
+            .collect();
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    /// One suspended client holds back the others.
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.iter().find_map(|inner| inner.suspension())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::new();
+
+        for inner in &mut self.inner {
+            for ip in inner.update_record(ips)? {
+                if !result.as_slice().contains(&ip) {
+                    result.push(ip);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent, Settings};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dnshome.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            password = "pass"
+            domains = ["home.dnshome.de", "nas.dnshome.de"]
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://www.dnshome.de/dyndns.php?hostname=home.dnshome.de&ip=192.0.2.1&ip6=2001%3Adb8%3A%3A1",
+                "https://www.dnshome.de/dyndns.php?hostname=nas.dnshome.de&ip=192.0.2.1&ip6=2001%3Adb8%3A%3A1",
+            ]
+        );
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { kind: ErrorKind::Auth, message: m, .. }) if m.starts_with("Bad authentication")
+        ));
+        assert!(service.suspension().is_some());
+    }
+}
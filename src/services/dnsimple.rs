@@ -0,0 +1,498 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, DiscoveredRecord,
+    ErrorKind, RecordType,
+};
+
+type AccountId = u64;
+type RecordId = u64;
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// An account or user access token, created under Account > Access Tokens.
+    token: Secret<Box<str>>,
+
+    /// The account the domains belong to. Account tokens belong to a single
+    /// account already, and so do user tokens of users with one account.
+    #[serde(default)]
+    account_id: Option<AccountId>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. The TTL of the records is left
+    /// as it is if not given.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    account: Option<AccountId>,
+    cached_records: Vec<Record>,
+}
+
+#[derive(Debug)]
+struct Record {
+    id: RecordId,
+
+    /// The domain (i.e. zone) the record belongs to, e.g. "example.com".
+    zone: Box<str>,
+
+    /// The fully-qualified name, e.g. "www.example.com".
+    fqdn: Box<str>,
+
+    kind: RecordKind,
+
+    /// The address the record was last known to point to.
+    data: Box<str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordKind {
+    A,
+    Aaaa,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+        config.token = Secret::new((String::from("Bearer ") + config.token.expose()).into());
+        Self {
+            account: config.account_id,
+            config,
+            client: Client::new(http),
+            cached_records: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// The largest page size the API allows.
+    const PER_PAGE: &'static str = "100";
+
+    /// Errors are reported as {"message": "..."}, sometimes along with the
+    /// errors of each field.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("message")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("DNSimple", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    fn get(&self, url: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set("Accept", "application/json")
+            .set("Authorization", self.config.token.expose())
+            .call();
+
+        self.parse_and_check_response(response)
+    }
+
+    /// Fetches every page of a listing and returns the items of all of them.
+    ///
+    /// See: https://developer.dnsimple.com/v2/#pagination
+    fn get_all_pages(&self, url: &str) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut items = Vec::new();
+
+        for page in 1.. {
+            let response = self
+                .client
+                .get(url)
+                .query("per_page", Self::PER_PAGE)
+                .query("page", &page.to_string())
+                .set("Accept", "application/json")
+                .set("Authorization", self.config.token.expose())
+                .call();
+            let mut response = self.parse_and_check_response(response)?;
+
+            let Some(page_items) = response.get_mut("data").and_then(|v| v.as_array_mut()) else {
+                return Err(DdnsUpdateError::Json("dnsimple returned no data".into()));
+            };
+
+            items.append(page_items);
+
+            let total_pages = response
+                .pointer("/pagination/total_pages")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(1);
+
+            if page >= total_pages {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Resolves the account the token acts on, unless it is configured. User
+    /// tokens are not tied to an account, so the user has to have only one.
+    ///
+    /// See:
+    ///   - https://developer.dnsimple.com/v2/identity/#whoami
+    ///   - https://developer.dnsimple.com/v2/accounts/#listAccounts
+    fn get_account(&mut self) -> Result<AccountId, DdnsUpdateError> {
+        if let Some(account) = self.account {
+            return Ok(account);
+        }
+
+        let response = self.get("https://api.dnsimple.com/v2/whoami")?;
+
+        let account = match response
+            .pointer("/data/account/id")
+            .and_then(|v| v.as_u64())
+        {
+            Some(account) => account,
+            None => {
+                let accounts = self.get("https://api.dnsimple.com/v2/accounts")?;
+                let ids = accounts
+                    .get("data")
+                    .and_then(|v| v.as_array())
+                    .map(|accounts| {
+                        accounts
+                            .iter()
+                            .filter_map(|account| account.get("id").and_then(|v| v.as_u64()))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+
+                match ids[..] {
+                    [account] => account,
+                    _ => {
+                        return Err(DdnsUpdateError::provider(
+                            "DNSimple",
+                            ErrorKind::Validation,
+                            format!(
+                                "the token has access to {} accounts, choose one with account_id",
+                                ids.len()
+                            )
+                            .into(),
+                        ))
+                    }
+                }
+            }
+        };
+
+        self.account = Some(account);
+        Ok(account)
+    }
+
+    /// See: https://developer.dnsimple.com/v2/zones/#listZones
+    fn get_zones(&self, account: AccountId) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let url = format!("https://api.dnsimple.com/v2/{}/zones", account);
+        let zones = self.get_all_pages(&url)?;
+
+        let mut names = Vec::with_capacity(zones.len());
+        for zone in zones {
+            let Some(name) = zone.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("zone has no name?".into()));
+            };
+
+            names.push(name.into());
+        }
+
+        Ok(names)
+    }
+
+    /// See: https://developer.dnsimple.com/v2/zones/records/#listZoneRecords
+    fn get_records(&self, account: AccountId, zone: &str) -> Result<Vec<Record>, DdnsUpdateError> {
+        let url = format!(
+            "https://api.dnsimple.com/v2/{}/zones/{}/records",
+            account, zone
+        );
+        let records = self.get_all_pages(&url)?;
+
+        let mut returned_records = Vec::new();
+        for record in records {
+            let Some(id) = record.get("id").and_then(|v| v.as_u64()) else {
+                return Err(DdnsUpdateError::Json("record has no id?".into()));
+            };
+
+            let Some(name) = record.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no name?".into()));
+            };
+
+            let Some(ty) = record.get("type").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no type?".into()));
+            };
+
+            let kind = match ty {
+                "A" => RecordKind::A,
+                "AAAA" => RecordKind::Aaaa,
+                _ => continue,
+            };
+
+            let data = record.get("content").and_then(|v| v.as_str()).unwrap_or("");
+
+            // The domain itself has an empty name.
+            let fqdn: Box<str> = if name.is_empty() {
+                zone.into()
+            } else {
+                format!("{}.{}", name, zone).into()
+            };
+
+            returned_records.push(Record {
+                id,
+                zone: zone.into(),
+                fqdn,
+                kind,
+                data: data.into(),
+            });
+        }
+
+        Ok(returned_records)
+    }
+
+    /// See: https://developer.dnsimple.com/v2/zones/records/#updateZoneRecord
+    fn patch_record(
+        &self,
+        account: AccountId,
+        record: &Record,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let url = format!(
+            "https://api.dnsimple.com/v2/{}/zones/{}/records/{}",
+            account, record.zone, record.id
+        );
+
+        let mut body = serde_json::json!({ "content": ip.to_string() });
+        if let Some(ttl) = self.config.ttl {
+            body["ttl"] = ttl.into();
+        }
+
+        let response = self
+            .client
+            .patch(&url)
+            .set("Accept", "application/json")
+            .set("Authorization", self.config.token.expose())
+            .send_json(body);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Looks up the records of the configured domains and caches them, so that
+    /// they are only looked up once. Only the zones containing any of the
+    /// domains are looked into.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        let account = self.get_account()?;
+
+        for zone in self.get_zones(account)? {
+            let is_relevant = self
+                .config
+                .domains
+                .iter()
+                .any(|domain| **domain == *zone || domain.ends_with(&format!(".{}", zone)));
+
+            if !is_relevant {
+                continue;
+            }
+
+            for record in self.get_records(account, &zone)? {
+                if self.config.domains.contains(&record.fqdn) {
+                    self.cached_records.push(record)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_cached_records(
+        &mut self,
+        ipv4: Option<IpAddr>,
+        ipv6: Option<IpAddr>,
+    ) -> Result<(), DdnsUpdateError> {
+        let account = self.get_account()?;
+
+        for i in 0..self.cached_records.len() {
+            let record = &self.cached_records[i];
+
+            let ip = match (&record.kind, ipv4, ipv6) {
+                (RecordKind::A, Some(ipv4), _) => ipv4,
+                (RecordKind::Aaaa, _, Some(ipv6)) => ipv6,
+                _ => continue,
+            };
+
+            let data = ip.to_string();
+            if *record.data == data {
+                continue;
+            }
+
+            self.patch_record(account, record, ip)?;
+            self.cached_records[i].data = data.into();
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    fn discover(&mut self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let account = self.get_account()?;
+        let mut found = Vec::new();
+
+        for zone in self.get_zones(account)? {
+            for record in self.get_records(account, &zone)? {
+                let kind = match record.kind {
+                    RecordKind::A => RecordType::A,
+                    RecordKind::Aaaa => RecordType::Aaaa,
+                };
+
+                found.push(DiscoveredRecord {
+                    name: record.fqdn,
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_records.is_empty() {
+            self.cache_records()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4()).copied();
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6()).copied();
+
+        // The records may have been changed or removed behind our back, so
+        // they are looked up again after a failure.
+        if let Err(e) = self.update_cached_records(ipv4, ipv6) {
+            self.cached_records.clear();
+            return Err(e);
+        }
+
+        let mut result = FixedVec::new();
+        result.extend(ipv4);
+        result.extend(ipv6);
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records
+                    .iter()
+                    .any(|record| &*record.fqdn == domain)
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dnsimple.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                account_id: None,
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: Some(300),
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The user token is not tied to an account, example.net is not looked
+        // into, the records of example.com span two pages, and the AAAA
+        // record of example.com is up to date.
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.dnsimple.com/v2/whoami",
+                "https://api.dnsimple.com/v2/accounts",
+                "https://api.dnsimple.com/v2/1010/zones?per_page=100&page=1",
+                "https://api.dnsimple.com/v2/1010/zones/example.com/records?per_page=100&page=1",
+                "https://api.dnsimple.com/v2/1010/zones/example.com/records?per_page=100&page=2",
+                "https://api.dnsimple.com/v2/1010/zones/example.com/records/1",
+                "https://api.dnsimple.com/v2/1010/zones/example.com/records/3",
+            ]
+        );
+
+        let body = sent[5].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "content": "192.0.2.1", "ttl": 300 })
+        );
+
+        // Nothing is sent while the addresses stay the same.
+        service.update_record(&[ipv4, ipv6]).unwrap();
+        assert!(take_sent().is_empty());
+
+        let ipv4 = "192.0.2.2".parse().unwrap();
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Authentication failed"
+        ));
+        assert!(service.cached_records.is_empty());
+    }
+}
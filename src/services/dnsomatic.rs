@@ -1,22 +1,26 @@
 use std::net::IpAddr;
 
+use crate::http::Settings;
 use crate::util::FixedVec;
 
-use super::{shared_dyndns, DdnsService, DdnsUpdateError};
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, RecordType, SuspensionState};
 
 pub type Config = shared_dyndns::Config;
 
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
 pub struct Service {
     inner: shared_dyndns::Service,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
         Self {
             inner: shared_dyndns::Service::from_config(
                 "DNS-O-Matic",
                 "https://updates.dnsomatic.com/nic/update",
                 config,
+                http,
             ),
         }
     }
@@ -26,4 +30,41 @@ impl DdnsService for Service {
     fn update_record(&mut self, ip: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
         self.inner.update_record(ip)
     }
+
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.suspension()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dnsomatic.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "all.dnsomatic.com"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        // DNS-O-Matic answers a bare "good", which stands for both addresses.
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert!(updated.as_slice().is_empty());
+
+        assert_eq!(take_sent().len(), 2);
+    }
 }
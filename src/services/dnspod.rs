@@ -0,0 +1,531 @@
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use data_encoding::HEXLOWER;
+use ring::{digest, hmac};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret, UtcDateTime};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const HOST: &str = "dnspod.tencentcloudapi.com";
+
+/// The version of the API, sent along with each action.
+const VERSION: &str = "2021-03-23";
+
+/// The Content-Type of the requests, which is signed as it is.
+const CONTENT_TYPE: &str = "application/json; charset=utf-8";
+
+/// The TTL of newly created records if none is configured, which is also the
+/// lowest the free plan accepts.
+const DEFAULT_TTL: u32 = 600;
+
+/// The largest page size the API allows.
+const PAGE_SIZE: u64 = 3000;
+
+/// The line (i.e. the resolvers a record applies to) of new records, which is
+/// the default one covering all of them.
+const DEFAULT_LINE: &str = "默认";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The API key of a Tencent Cloud account or CAM user allowed to manage
+    /// DNSPod, created under Access Management > API Keys.
+    secret_id: Box<str>,
+    secret_key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The TTL of the records, which is otherwise left as it is.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+/// Signs a request with TC3-HMAC-SHA256 and returns the value of its
+/// Authorization header. The Content-Type, Host and X-TC-Action headers are
+/// signed.
+///
+/// See: https://www.tencentcloud.com/document/api/1157/50198
+fn sign_tc3(
+    secret_id: &str,
+    secret_key: &str,
+    action: &str,
+    timestamp: u64,
+    payload: &str,
+) -> String {
+    let sha256 = |data: &[u8]| HEXLOWER.encode(digest::digest(&digest::SHA256, data).as_ref());
+    let hmac = |key: &[u8], data: &str| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        hmac::sign(&key, data.as_bytes())
+    };
+
+    let time = UtcDateTime::from(UNIX_EPOCH + Duration::from_secs(timestamp));
+    let date = &time.to_iso8601()[..10];
+
+    let canonical_request = format!(
+        "POST\n/\n\ncontent-type:{}\nhost:{}\nx-tc-action:{}\n\ncontent-type;host;x-tc-action\n{}",
+        CONTENT_TYPE,
+        HOST,
+        action.to_lowercase(),
+        sha256(payload.as_bytes())
+    );
+
+    let scope = format!("{}/dnspod/tc3_request", date);
+    let string_to_sign = format!(
+        "TC3-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        sha256(canonical_request.as_bytes())
+    );
+
+    let key = hmac(format!("TC3{}", secret_key).as_bytes(), date);
+    let key = hmac(key.as_ref(), "dnspod");
+    let key = hmac(key.as_ref(), "tc3_request");
+    let signature = HEXLOWER.encode(hmac(key.as_ref(), &string_to_sign).as_ref());
+
+    format!(
+        "TC3-HMAC-SHA256 Credential={}/{}, SignedHeaders=content-type;host;x-tc-action, Signature={}",
+        secret_id, scope, signature
+    )
+}
+
+/// Classifies an error by its code, e.g. "AuthFailure.SignatureFailure". The
+/// errors are reported with a 200 status, which tells nothing.
+///
+/// See: https://www.tencentcloud.com/document/api/1157/49347
+fn error_kind(code: &str) -> ErrorKind {
+    let category = code.split('.').next().unwrap_or(code);
+
+    match category {
+        "AuthFailure" | "UnauthorizedOperation" => ErrorKind::Auth,
+        "RequestLimitExceeded" => ErrorKind::RateLimited(None),
+        "ResourceNotFound" => ErrorKind::NotFound,
+        "InvalidParameter" | "InvalidParameterValue" | "MissingParameter" => ErrorKind::Validation,
+        "InternalError" | "ServiceUnavailable" => ErrorKind::Outage,
+        _ => ErrorKind::Other,
+    }
+}
+
+impl Service {
+    /// Returns the "Response" of the reply, which is an error if it has an
+    /// "Error": {"Code": "...", "Message": "..."}.
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let mut response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = resp
+                    .into_string()
+                    .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+                Err(DdnsUpdateError::provider("DNSPod", kind, message.into()))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        let Some(response) = response.get_mut("Response").map(serde_json::Value::take) else {
+            return Err(DdnsUpdateError::Json("expected Response".into()));
+        };
+
+        if let Some(error) = response.get("Error") {
+            let code = error.get("Code").and_then(|c| c.as_str()).unwrap_or("");
+            let message = error.get("Message").and_then(|m| m.as_str()).unwrap_or("");
+
+            return Err(DdnsUpdateError::provider(
+                "DNSPod",
+                error_kind(code),
+                format!("{} ({})", message, code).into(),
+            ));
+        }
+
+        Ok(response)
+    }
+
+    /// Calls the action of the API with the given parameters.
+    fn call(
+        &self,
+        action: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let payload = params.to_string();
+
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs(),
+            Err(_) => 0,
+        };
+
+        let authorization = sign_tc3(
+            &self.config.secret_id,
+            self.config.secret_key.expose(),
+            action,
+            timestamp,
+            &payload,
+        );
+
+        let response = self
+            .client
+            .post(&format!("https://{}/", HOST))
+            .set("Authorization", &authorization)
+            .set("Content-Type", CONTENT_TYPE)
+            .set("X-TC-Action", action)
+            .set("X-TC-Timestamp", &timestamp.to_string())
+            .set("X-TC-Version", VERSION)
+            .send_string(&payload);
+
+        self.parse_and_check_response(response)
+    }
+
+    /// Collects the items under `key` from every page of the listing, which
+    /// tells how many there are at `total`.
+    fn call_pages(
+        &self,
+        action: &str,
+        params: serde_json::Value,
+        key: &str,
+        total: &str,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut items = Vec::new();
+
+        loop {
+            let mut params = params.clone();
+            params["Offset"] = items.len().into();
+            params["Limit"] = PAGE_SIZE.into();
+
+            let mut response = self.call(action, params)?;
+
+            let listed = match response.get_mut(key).and_then(|l| l.as_array_mut()) {
+                Some(listed) if !listed.is_empty() => listed,
+                _ => break,
+            };
+            items.append(listed);
+
+            let total = response.pointer(total).and_then(|t| t.as_u64());
+            if total.is_none_or(|total| items.len() as u64 >= total) {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// See: https://www.tencentcloud.com/document/api/1157/49354
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let listed = self.call_pages(
+            "DescribeDomainList",
+            serde_json::json!({}),
+            "DomainList",
+            "/DomainCountInfo/AllTotal",
+        )?;
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.get("Name").and_then(|n| n.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no Name?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// Lists the records of the zone, or only the ones of the given subdomain
+    /// if there is one. Having none at all is reported as an error, which is
+    /// taken as an empty list.
+    ///
+    /// See: https://www.tencentcloud.com/document/api/1157/49355
+    fn get_records(
+        &self,
+        zone: &str,
+        subdomain: Option<&str>,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut params = serde_json::json!({ "Domain": zone });
+        if let Some(subdomain) = subdomain {
+            params["Subdomain"] = subdomain.into();
+        }
+
+        let records = self.call_pages(
+            "DescribeRecordList",
+            params,
+            "RecordList",
+            "/RecordCountInfo/TotalCount",
+        );
+
+        match records {
+            Err(DdnsUpdateError::Provider { message, .. })
+                if message.ends_with("(ResourceNotFound.NoDataOfRecord)") =>
+            {
+                Ok(Vec::new())
+            }
+            records => records,
+        }
+    }
+
+    /// Points the record of the given subdomain and type to the address. An
+    /// existing record is updated with ModifyDynamicDNS, which only changes
+    /// its address (and TTL), and a missing one is created.
+    ///
+    /// See: https://www.tencentcloud.com/document/api/1157/49352
+    fn set_record(
+        &self,
+        records: &[serde_json::Value],
+        zone: &str,
+        subdomain: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let value = ip.to_string();
+
+        let existing = records.iter().find(|record| {
+            record.get("Type").and_then(|t| t.as_str()) == Some(kind)
+                && record.get("Name").and_then(|n| n.as_str()) == Some(subdomain)
+        });
+
+        match existing {
+            Some(record) => {
+                let current_ttl = record.get("TTL").and_then(|t| t.as_u64());
+
+                if record.get("Value").and_then(|v| v.as_str()) == Some(&value)
+                    && self
+                        .config
+                        .ttl
+                        .is_none_or(|ttl| current_ttl == Some(ttl as u64))
+                {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("RecordId").and_then(|i| i.as_u64()) else {
+                    return Err(DdnsUpdateError::Json("record has no RecordId?".into()));
+                };
+
+                let line = record
+                    .get("Line")
+                    .and_then(|l| l.as_str())
+                    .unwrap_or(DEFAULT_LINE);
+
+                let mut params = serde_json::json!({
+                    "Domain": zone,
+                    "SubDomain": subdomain,
+                    "RecordId": id,
+                    "RecordLine": line,
+                    "Value": value,
+                });
+                if let Some(line_id) = record.get("LineId") {
+                    params["RecordLineId"] = line_id.clone();
+                }
+                if let Some(ttl) = self.config.ttl {
+                    params["Ttl"] = ttl.into();
+                }
+
+                self.call("ModifyDynamicDNS", params)?;
+            }
+
+            // See: https://www.tencentcloud.com/document/api/1157/49360
+            None => {
+                let params = serde_json::json!({
+                    "Domain": zone,
+                    "SubDomain": subdomain,
+                    "RecordType": kind,
+                    "RecordLine": DEFAULT_LINE,
+                    "Value": value,
+                    "TTL": self.config.ttl.unwrap_or(DEFAULT_TTL),
+                });
+
+                self.call("CreateRecord", params)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone, None)? {
+                let kind = match record.get("Type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match record.get("Name").and_then(|n| n.as_str()) {
+                    None | Some("@") => zone.clone(),
+                    Some(name) => format!("{}.{}", name, zone).into(),
+                };
+
+                // A name has as many records of a type as it has addresses,
+                // and one more for each line.
+                if !found
+                    .iter()
+                    .any(|r: &DiscoveredRecord| r.name == name && r.kind == kind)
+                {
+                    found.push(DiscoveredRecord { name, kind });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, subdomain)) = find_zone(&self.cached_zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "DNSPod",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            let records = self.get_records(zone, Some(subdomain))?;
+
+            for ip in &result {
+                self.set_record(&records, zone, subdomain, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn signature() {
+        let authorization = sign_tc3(
+            "AKIDEXAMPLE",
+            "secret",
+            "DescribeDomainList",
+            1_700_000_000,
+            r#"{"Limit":3000,"Offset":0}"#,
+        );
+
+        assert_eq!(
+            authorization,
+            "TC3-HMAC-SHA256 Credential=AKIDEXAMPLE/2023-11-14/dnspod/tc3_request, \
+             SignedHeaders=content-type;host;x-tc-action, \
+             Signature=e9a191a9ddd9254cf9a1dc86957f6fe4345a1dafe7da58db8bb975e2c7578917"
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dnspod.txt"));
+
+        let mut service = Service::new(
+            Config {
+                secret_id: "id".into(),
+                secret_key: Secret::new("key".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and www.example.com has
+        // no records at all yet. Every action is sent to the same URL.
+        let bodies = take_sent()
+            .iter()
+            .map(|s| serde_json::from_str::<serde_json::Value>(s.body.as_deref().unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(bodies.len(), 6);
+        assert_eq!(
+            bodies[2],
+            serde_json::json!({
+                "Domain": "example.com",
+                "SubDomain": "@",
+                "RecordId": 11,
+                "RecordLine": "默认",
+                "RecordLineId": "0",
+                "Value": "2001:db8::1",
+            })
+        );
+        assert_eq!(
+            bodies[5],
+            serde_json::json!({
+                "Domain": "example.com",
+                "SubDomain": "www",
+                "RecordType": "AAAA",
+                "RecordLine": "默认",
+                "Value": "2001:db8::1",
+                "TTL": 600,
+            })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "The SecretId is not found. (AuthFailure.SecretIdNotFound)"
+        ));
+    }
+}
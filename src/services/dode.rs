@@ -0,0 +1,146 @@
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::http::Settings;
+use crate::util::FixedVec;
+
+use super::shared_dyndns::{self, Parameters, Quirk};
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, SuspensionState};
+
+/// The username and password are those of the FlexDNS entry, not those of the
+/// customer login.
+pub type Config = shared_dyndns::Config;
+
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
+/// FlexDNS takes the domain in `domain`, and the IPv6 address in `myip6`.
+const PARAMETERS: Parameters = Parameters {
+    hostname: Cow::Borrowed("domain"),
+    ip: Cow::Borrowed("myip"),
+    ipv6: Some(Cow::Borrowed("myip6")),
+};
+
+/// do.de answers like a DynDNS v2 server, but "abuse" only means that the
+/// entry was updated too often and is blocked for a while.
+const QUIRKS: &[Quirk] = &[
+    Quirk {
+        response: "abuse",
+        message: "Too many updates were sent, suspending for 15 minutes",
+        kind: ErrorKind::RateLimited(Some(Duration::from_secs(15 * 60))),
+        suspend_for: Some(Duration::from_secs(15 * 60)),
+    },
+    Quirk {
+        response: "badauth",
+        message: "Bad authentication details were provided (these are the credentials of the FlexDNS entry)",
+        kind: ErrorKind::Auth,
+        suspend_for: None,
+    },
+    Quirk {
+        response: "nohost",
+        message: "The domain has no FlexDNS entry, or it belongs to another one",
+        kind: ErrorKind::NotFound,
+        suspend_for: None,
+    },
+];
+
+pub struct Service {
+    /// FlexDNS takes a single domain per request, so there is one client for
+    /// each of them.
+    inner: Vec<shared_dyndns::Service>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let inner = config
+            .split_domains()
+            .into_iter()
+            .map(|config| {
+                shared_dyndns::Service::from_config("do.de", "https://ddns.do.de/", config, http)
+                    .with_parameters(PARAMETERS)
+                    .with_quirks(QUIRKS)
+            })
+            .collect();
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    /// One suspended client holds back the others.
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.iter().find_map(|inner| inner.suspension())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::new();
+
+        for inner in &mut self.inner {
+            for ip in inner.update_record(ips)? {
+                if !result.as_slice().contains(&ip) {
+                    result.push(ip);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent, Settings};
+    use crate::services::Suspension;
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dode.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "DDNS-K12345-ABCD"
+            password = "pass"
+            domains = ["home.example.com", "nas.example.com"]
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://ddns.do.de/?domain=home.example.com&myip=192.0.2.1&myip6=2001%3Adb8%3A%3A1",
+                "https://ddns.do.de/?domain=nas.example.com&myip=192.0.2.1&myip6=2001%3Adb8%3A%3A1",
+            ]
+        );
+
+        // Updating too often only holds the service back for a while.
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { kind: ErrorKind::RateLimited(Some(_)), message: m, .. }) if m.starts_with("Too many updates")
+        ));
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Until(_)))
+        ));
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            &*sent[0].url,
+            "https://ddns.do.de/?domain=home.example.com&myip6=2001%3Adb8%3A%3A1"
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType, ZoneName,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 3600;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The token and secret of an API key, created under Account > API.
+    token: Box<str>,
+    secret: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds, a multiple of 60. The TTL of
+    /// the records is left as it is if not given.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Secret<Box<str>>,
+    cached_zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    id: u64,
+    name: Box<str>,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let token_secret = String::from(config.token.clone()) + ":" + config.secret.expose();
+        let base64 = data_encoding::BASE64.encode(token_secret.as_bytes());
+        let auth = String::from("Basic ") + &base64;
+
+        Self {
+            config,
+            client: Client::new(http),
+            auth: Secret::new(auth.into()),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"code": "...", "help": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let code = resp_json.get("code").and_then(|c| c.as_str());
+        let help = resp_json.get("help").and_then(|h| h.as_str());
+
+        match (code, help) {
+            (Some(code), Some(help)) => Ok(format!("{} ({})", help, code).into()),
+            (Some(message), None) | (None, Some(message)) => Ok(message.into()),
+            (None, None) => Err(String::from("expected string")),
+        }
+    }
+
+    /// Updating a record is answered with 204 and no body, so the response is
+    /// left to the caller to parse.
+    fn check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<Response, DdnsUpdateError> {
+        match response {
+            Ok(r) => Ok(r),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Domeneshop", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    fn get_json(&self, url: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set("Authorization", self.auth.expose())
+            .call();
+
+        self.check_response(response)?
+            .into_json::<serde_json::Value>()
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))
+    }
+
+    /// See: https://api.domeneshop.no/docs/#tag/domains/paths/~1domains/get
+    fn get_zones(&self) -> Result<Vec<Zone>, DdnsUpdateError> {
+        let response = self.get_json("https://api.domeneshop.no/v0/domains")?;
+
+        let Some(listed) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("expected a list of domains".into()));
+        };
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let id = zone.get("id").and_then(|i| i.as_u64());
+            let name = zone.get("domain").and_then(|d| d.as_str());
+
+            let (Some(id), Some(name)) = (id, name) else {
+                return Err(DdnsUpdateError::Json("domain has no id or name?".into()));
+            };
+
+            zones.push(Zone {
+                id,
+                name: name.into(),
+            });
+        }
+
+        Ok(zones)
+    }
+
+    /// Points the record of the given host and type to the address, creating
+    /// the record if there is none.
+    ///
+    /// See: https://api.domeneshop.no/docs/#tag/dns/paths/~1domains~1{domainId}~1dns~1{recordId}/put
+    fn set_record(&self, zone: &Zone, host: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let url = format!("https://api.domeneshop.no/v0/domains/{}/dns", zone.id);
+
+        let response = self
+            .client
+            .get(&url)
+            .set("Authorization", self.auth.expose())
+            .query("host", host)
+            .query("type", kind)
+            .call();
+
+        let records = self
+            .check_response(response)?
+            .into_json::<serde_json::Value>()
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+        let existing = records.as_array().and_then(|records| records.first());
+
+        let (request, ttl) = match existing {
+            Some(record) => {
+                let current_ttl = record.get("ttl").and_then(|t| t.as_u64());
+                let ttl = self
+                    .config
+                    .ttl
+                    .or(current_ttl.map(|t| t as u32))
+                    .unwrap_or(DEFAULT_TTL);
+
+                if record.get("data").and_then(|d| d.as_str()) == Some(&ip.to_string())
+                    && current_ttl == Some(ttl as u64)
+                {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("id").and_then(|i| i.as_u64()) else {
+                    return Err(DdnsUpdateError::Json("record has no id?".into()));
+                };
+
+                (self.client.put(&format!("{}/{}", url, id)), ttl)
+            }
+
+            None => (
+                self.client.post(&url),
+                self.config.ttl.unwrap_or(DEFAULT_TTL),
+            ),
+        };
+
+        let body = serde_json::json!({
+            "host": host,
+            "ttl": ttl,
+            "type": kind,
+            "data": ip.to_string(),
+        });
+
+        let response = request
+            .set("Authorization", self.auth.expose())
+            .send_json(body);
+
+        self.check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    ///
+    /// See: https://api.domeneshop.no/docs/#tag/dns/paths/~1domains~1{domainId}~1dns/get
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            let url = format!("https://api.domeneshop.no/v0/domains/{}/dns", zone.id);
+
+            let Some(records) = self.get_json(&url)?.as_array().cloned() else {
+                return Err(DdnsUpdateError::Json("expected a list of records".into()));
+            };
+
+            for record in records {
+                let kind = match record.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match record.get("host").and_then(|h| h.as_str()) {
+                    Some("@") => zone.name.clone(),
+                    Some(host) => format!("{}.{}", host, zone.name).into(),
+                    None => return Err(DdnsUpdateError::Json("record has no host?".into())),
+                };
+
+                found.push(DiscoveredRecord { name, kind });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, host)) = find_zone(&self.cached_zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Domeneshop",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            for ip in &result {
+                self.set_record(zone, host, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/domeneshop.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: "token".into(),
+                secret: Secret::new("secret".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and www.example.com has
+        // no AAAA record yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.domeneshop.no/v0/domains",
+                "GET https://api.domeneshop.no/v0/domains/1/dns?host=%40&type=A",
+                "GET https://api.domeneshop.no/v0/domains/1/dns?host=%40&type=AAAA",
+                "PUT https://api.domeneshop.no/v0/domains/1/dns/11",
+                "GET https://api.domeneshop.no/v0/domains/1/dns?host=www&type=A",
+                "PUT https://api.domeneshop.no/v0/domains/1/dns/12",
+                "GET https://api.domeneshop.no/v0/domains/1/dns?host=www&type=AAAA",
+                "POST https://api.domeneshop.no/v0/domains/1/dns",
+            ]
+        );
+
+        // The TTL of existing records is kept.
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "host": "@", "ttl": 600, "type": "AAAA", "data": "2001:db8::1" })
+        );
+
+        let body = sent[7].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "host": "www", "ttl": 3600, "type": "AAAA", "data": "2001:db8::1" })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Invalid credentials (unauthorized)"
+        ));
+    }
+}
@@ -0,0 +1,395 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, DiscoveredRecord,
+    ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// Records created by dynners carry this comment, so they can be told apart in
+/// the panel.
+const COMMENT: &str = "Updated by dynners";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// An API key with access to the dns-list_records, dns-add_record and
+    /// dns-remove_record functions, created in the panel under API.
+    key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+
+    /// The records of the account, as last listed or changed. DreamHost has
+    /// no way to update a record, so their values are needed to remove them.
+    cached_records: Option<Vec<Record>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Record {
+    name: Box<str>,
+    zone: Box<str>,
+    kind: Box<str>,
+    value: Box<str>,
+    editable: bool,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_records: None,
+        }
+    }
+}
+
+/// Classifies the error DreamHost reports in place of the data, e.g.
+/// "no_such_record".
+fn error_kind(error: &str) -> ErrorKind {
+    match error {
+        "slow_down_bucko" => ErrorKind::RateLimited(None),
+        e if e.contains("key") || e == "no_access" => ErrorKind::Auth,
+        e if e.starts_with("no_such") => ErrorKind::NotFound,
+        e if e.starts_with("internal_error") => ErrorKind::Outage,
+        e if e.starts_with("invalid") || e.contains("not_editable") || e.contains("already") => {
+            ErrorKind::Validation
+        }
+        _ => ErrorKind::Other,
+    }
+}
+
+impl Service {
+    /// Runs the command, whose response is {"result": "success", "data": ...}
+    /// or {"result": "error", "data": "..."}. Errors are answered with 200
+    /// as well.
+    ///
+    /// See: https://help.dreamhost.com/hc/en-us/articles/217560167-API-overview
+    fn command(
+        &self,
+        command: &str,
+        params: &[(&str, &str)],
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let mut request = self
+            .client
+            .get("https://api.dreamhost.com/")
+            .query("key", self.config.key.expose())
+            .query("cmd", command)
+            .query("format", "json");
+
+        for (param, value) in params {
+            request = request.query(param, value);
+        }
+
+        let response = match request.call() {
+            Ok(resp) => resp,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = resp.into_string().unwrap_or_default();
+                return Err(DdnsUpdateError::provider(
+                    "DreamHost",
+                    kind,
+                    message.trim().into(),
+                ));
+            }
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        };
+
+        let mut response = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+        let data = response
+            .get_mut("data")
+            .map(serde_json::Value::take)
+            .unwrap_or_default();
+
+        match response.get("result").and_then(|r| r.as_str()) {
+            Some("success") => Ok(data),
+
+            _ => {
+                let error = data.as_str().unwrap_or("unknown_error");
+                let message = match response.get("reason").and_then(|r| r.as_str()) {
+                    Some(reason) => format!("{} ({})", reason, error),
+                    None => error.to_owned(),
+                };
+
+                Err(DdnsUpdateError::provider(
+                    "DreamHost",
+                    error_kind(error),
+                    message.into(),
+                ))
+            }
+        }
+    }
+
+    /// See: https://help.dreamhost.com/hc/en-us/articles/217555707-DNS-API-commands
+    fn list_records(&self) -> Result<Vec<Record>, DdnsUpdateError> {
+        let data = self.command("dns-list_records", &[])?;
+
+        let Some(listed) = data.as_array() else {
+            return Err(DdnsUpdateError::Json("expected a list of records".into()));
+        };
+
+        let mut records = Vec::with_capacity(listed.len());
+        for record in listed {
+            let field = |key| record.get(key).and_then(|v| v.as_str());
+
+            let (Some(name), Some(zone), Some(kind), Some(value)) = (
+                field("record"),
+                field("zone"),
+                field("type"),
+                field("value"),
+            ) else {
+                return Err(DdnsUpdateError::Json("record is missing fields?".into()));
+            };
+
+            records.push(Record {
+                name: name.into(),
+                zone: zone.into(),
+                kind: kind.into(),
+                value: value.into(),
+                editable: field("editable") != Some("0"),
+            });
+        }
+
+        Ok(records)
+    }
+
+    /// Replaces the records of the given name and type with the address. As
+    /// DreamHost can only remove and add records, a removed record is added
+    /// back if the new one is refused, so that the name does not end up
+    /// without any record.
+    fn set_record(&mut self, name: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let value = ip.to_string();
+
+        let existing = self
+            .cached_records
+            .iter()
+            .flatten()
+            .filter(|r| &*r.name == name && &*r.kind == kind)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if let [record] = &existing[..] {
+            if *record.value == value {
+                return Ok(());
+            }
+        }
+
+        if let Some(record) = existing.iter().find(|r| !r.editable) {
+            return Err(DdnsUpdateError::provider(
+                "DreamHost",
+                ErrorKind::Validation,
+                format!("the {} record of {} is not editable", record.kind, name).into(),
+            ));
+        }
+
+        let mut removed = Vec::new();
+        for record in &existing {
+            let params = [("record", name), ("type", kind), ("value", &*record.value)];
+
+            if let Err(e) = self.command("dns-remove_record", &params) {
+                self.restore(name, &removed);
+                return Err(e);
+            }
+
+            removed.push(record.clone());
+        }
+
+        let params = [
+            ("record", name),
+            ("type", kind),
+            ("value", &*value),
+            ("comment", COMMENT),
+        ];
+
+        if let Err(e) = self.command("dns-add_record", &params) {
+            self.restore(name, &removed);
+            return Err(e);
+        }
+
+        if let Some(records) = &mut self.cached_records {
+            let zone = existing.first().map(|r| r.zone.clone());
+
+            records.retain(|r| !(&*r.name == name && &*r.kind == kind));
+            records.push(Record {
+                name: name.into(),
+                zone: zone.unwrap_or_default(),
+                kind: kind.into(),
+                value: value.into(),
+                editable: true,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Adds back the records removed before the failure. If that fails as
+    /// well, the records are listed again with the next update.
+    fn restore(&mut self, name: &str, removed: &[Record]) {
+        for record in removed {
+            let params = [
+                ("record", name),
+                ("type", &*record.kind),
+                ("value", &*record.value),
+            ];
+
+            if self.command("dns-add_record", &params).is_err() {
+                self.cached_records = None;
+            }
+        }
+    }
+
+    /// Lists the A and AAAA records of the account, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for record in self.list_records()? {
+            let kind = match &*record.kind {
+                "A" => RecordType::A,
+                "AAAA" => RecordType::Aaaa,
+                _ => continue,
+            };
+
+            // A name has as many records of a type as it has addresses.
+            if !found
+                .iter()
+                .any(|r: &DiscoveredRecord| r.name == record.name && r.kind == kind)
+            {
+                found.push(DiscoveredRecord {
+                    name: record.name,
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_records.is_none() {
+            self.cached_records = Some(self.list_records()?);
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in self.config.domains.clone() {
+            for ip in &result {
+                if let Err(e) = self.set_record(&domain, *ip) {
+                    // The records may have been changed elsewhere.
+                    if e.kind() == ErrorKind::NotFound {
+                        self.cached_records = None;
+                    }
+
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.list_records().map(|records| {
+            let description = describe_found_domains(&self.config.domains, |domain| {
+                records.iter().any(|r| {
+                    domain == &*r.zone
+                        || domain
+                            .strip_suffix(&*r.zone)
+                            .is_some_and(|name| name.ends_with('.'))
+                })
+            });
+
+            self.cached_records = Some(records);
+            description
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dreamhost.txt"));
+
+        let mut service = Service::new(
+            Config {
+                key: Secret::new("key".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        // The A record of example.com is up to date.
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.dreamhost.com/?key=key&cmd=dns-list_records&format=json",
+                "https://api.dreamhost.com/?key=key&cmd=dns-remove_record&format=json&record=www.example.com&type=A&value=192.0.2.100",
+                "https://api.dreamhost.com/?key=key&cmd=dns-add_record&format=json&record=www.example.com&type=A&value=192.0.2.1&comment=Updated%20by%20dynners",
+            ]
+        );
+
+        // The records are cached, so nothing is sent as they are up to date.
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+        assert!(take_sent().is_empty());
+
+        // The new record is refused, so the old one is added back.
+        let ipv4 = "192.0.2.2".parse().unwrap();
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::RateLimited(None),
+                message: m,
+                ..
+            }) if &*m == "slow_down_bucko"
+        ));
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.dreamhost.com/?key=key&cmd=dns-remove_record&format=json&record=example.com&type=A&value=192.0.2.1",
+                "https://api.dreamhost.com/?key=key&cmd=dns-add_record&format=json&record=example.com&type=A&value=192.0.2.2&comment=Updated%20by%20dynners",
+                "https://api.dreamhost.com/?key=key&cmd=dns-add_record&format=json&record=example.com&type=A&value=192.0.2.1",
+            ]
+        );
+    }
+}
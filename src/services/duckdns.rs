@@ -2,27 +2,33 @@ use std::net::IpAddr;
 
 use serde_derive::{Deserialize, Serialize};
 
-use crate::http::{Error, Request};
-use crate::util::{one_or_more_string, FixedVec};
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
 
-use super::{DdnsService, DdnsUpdateError};
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct Config {
-    token: Box<str>,
+    token: Secret<Box<str>>,
 
     #[serde(deserialize_with = "one_or_more_string")]
     domains: Vec<Box<str>>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct Service {
     config: Config,
+    client: Client,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
-        Self { config }
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
     }
 }
 
@@ -31,9 +37,11 @@ impl DdnsService for Service {
         let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
         let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
 
-        let mut request = Request::get("https://www.duckdns.org/update")
+        let mut request = self
+            .client
+            .get("https://www.duckdns.org/update")
             .query("domains", &self.config.domains.join(","))
-            .query("token", &self.config.token);
+            .query("token", self.config.token.expose());
 
         let mut result = FixedVec::new();
 
@@ -49,17 +57,27 @@ impl DdnsService for Service {
 
         match request.call() {
             Ok(resp) | Err(Error::Status(_, resp)) => {
-                let resp = resp.into_string().map_err(|_| DdnsUpdateError::DuckDns)?;
+                let resp = resp.into_string().map_err(|e| {
+                    DdnsUpdateError::provider("DuckDNS", ErrorKind::Other, e.to_string().into())
+                })?;
 
                 if resp.starts_with("OK") || resp.starts_with("good") {
                     Ok(result)
                 } else if resp.starts_with("KO") {
-                    Err(DdnsUpdateError::DuckDns)
+                    Err(DdnsUpdateError::provider(
+                        "DuckDNS",
+                        ErrorKind::Auth,
+                        "the request was rejected - check again your tokens and domains".into(),
+                    ))
                 } else {
                     // According to the API documentation, the only possible responses
                     // (without setting verbose=true) are OK and KO. So theoretically
                     // we shouldn't reach this branch... but make an error if we do
-                    Err(DdnsUpdateError::DuckDns)
+                    Err(DdnsUpdateError::provider(
+                        "DuckDNS",
+                        ErrorKind::Other,
+                        format!("unexpected response \"{}\"", resp.trim()).into(),
+                    ))
                 }
             }
 
@@ -67,3 +85,45 @@ impl DdnsService for Service {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/duckdns.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["a".into(), "b".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let updated = service.update_record(&[ipv4, ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                ..
+            })
+        ));
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(&*sent[0].method, "GET");
+        assert_eq!(
+            &*sent[0].url,
+            "https://www.duckdns.org/update?domains=a%2Cb&token=token&ip=192.0.2.1"
+        );
+    }
+}
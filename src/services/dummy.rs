@@ -2,9 +2,12 @@ use std::net::IpAddr;
 
 use serde_derive::{Deserialize, Serialize};
 
+use crate::http::Settings;
 use crate::util::{one_or_more_string, FixedVec};
 
-use super::{DdnsService, DdnsUpdateError};
+use super::{DdnsService, DdnsUpdateError, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct Config {
@@ -16,8 +19,8 @@ pub struct Service {
     config: Config,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
+impl Service {
+    pub fn new(config: Config, _http: &Settings) -> Self {
         Self { config }
     }
 }
@@ -45,4 +48,8 @@ impl DdnsService for Service {
 
         Ok(result)
     }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        Some(Ok(String::from("nothing to check")))
+    }
 }
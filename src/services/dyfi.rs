@@ -0,0 +1,59 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::http::Settings;
+use crate::util::FixedVec;
+
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, ErrorKind, RecordType, SuspensionState};
+
+/// The username is the email address of the dy.fi account.
+pub type Config = shared_dyndns::Config;
+
+/// dy.fi only manages A records.
+pub const RECORDS: &[RecordType] = &[RecordType::A];
+
+/// dy.fi releases the hostnames which are not updated for 7 days, and asks
+/// clients not to update more often than every 5 days if the address is the
+/// same.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 24 * 60 * 60);
+
+pub struct Service {
+    inner: shared_dyndns::Service,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            inner: shared_dyndns::Service::from_config(
+                "dy.fi",
+                "https://www.dy.fi/nic/update",
+                config,
+                http,
+            ),
+        }
+    }
+}
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.suspension()
+    }
+
+    /// dy.fi always uses the address the request comes from, the address is
+    /// only sent along for the sake of the protocol.
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let Some(ipv4) = ips.iter().find(|ip| ip.is_ipv4()) else {
+            return Err(DdnsUpdateError::provider(
+                "dy.fi",
+                ErrorKind::Validation,
+                "dy.fi only supports IPv4 addresses".into(),
+            ));
+        };
+
+        self.inner.update_record(&[*ipv4])
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        Some(REFRESH_INTERVAL)
+    }
+}
@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{self, Settings};
+use crate::util::{one_or_more_string, unscrubbed_secret, FixedVec, Secret};
+
+use super::shared_dyndns::{self, Parameters};
+use super::{DdnsService, DdnsUpdateError, RecordType, SuspensionState};
+
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
+/// Any server speaking the DynDNS v2 protocol, for the providers without a
+/// service of their own.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The update URL, e.g. https://dyndns.example.com/nic/update.
+    url: Box<str>,
+
+    /// Another URL the IPv6 addresses are sent to, if the provider has an
+    /// endpoint for each.
+    #[serde(default)]
+    ipv6_url: Option<Box<str>>,
+
+    /// The query parameter the IPv6 address is sent in, e.g. "myipv6". By
+    /// default both addresses are sent in `myip`, separated by a comma.
+    #[serde(default)]
+    ipv6_parameter: Option<Box<str>>,
+
+    #[serde(deserialize_with = "unscrubbed_secret")]
+    username: Secret<Box<str>>,
+    password: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+pub struct Service {
+    inner: shared_dyndns::Service,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let Config {
+            url,
+            ipv6_url,
+            ipv6_parameter,
+            username,
+            password,
+            domains,
+        } = config;
+
+        // The URLs are the user's own and may carry a token, so they are kept
+        // out of the diagnostics as a whole.
+        http::register_secret(&url);
+        if let Some(ipv6_url) = &ipv6_url {
+            http::register_secret(ipv6_url);
+        }
+
+        let config = shared_dyndns::Config::new(username, password, domains);
+        let mut inner =
+            shared_dyndns::Service::from_config("DynDNS v2", String::from(url), config, http);
+
+        if let Some(ipv6_url) = ipv6_url {
+            inner = inner.with_ipv6_server(String::from(ipv6_url));
+        }
+
+        if let Some(ipv6_parameter) = ipv6_parameter {
+            inner = inner.with_parameters(Parameters {
+                ipv6: Some(Cow::Owned(ipv6_parameter.into())),
+                ..Parameters::DYNDNS
+            });
+        }
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ip: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        self.inner.update_record(ip)
+    }
+
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.suspension()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(
+            "> GET https://dyn.example.net/nic/update?hostname=home.example.net&myip=192.0.2.1&myipv6=2001:db8::1
+< 200
+< good 192.0.2.1,2001:db8::1",
+        );
+
+        let config = toml::from_str::<Config>(
+            r#"
+            url = "https://dyn.example.net/nic/update"
+            ipv6_parameter = "myipv6"
+            username = "user"
+            password = "pass"
+            domains = "home.example.net"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+        assert_eq!(take_sent().len(), 1);
+    }
+}
@@ -1,29 +1,654 @@
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv6Addr};
 
-use crate::util::FixedVec;
+use serde_derive::{Deserialize, Serialize};
 
-use super::{shared_dyndns, DdnsService, DdnsUpdateError};
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{one_or_more_string, unscrubbed_optional_secret, FixedVec, Secret};
 
-pub type Config = shared_dyndns::Config;
+use super::{
+    describe_found_domains, find_zone, shared_dyndns, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType, SuspensionState, ZoneName,
+};
 
-pub struct Service {
-    inner: shared_dyndns::Service,
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.dynu.com/v2";
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 300;
+
+/// Dynu is updated either through its DynDNS2 endpoint with the username and
+/// password of the account, or through its REST API with an API key, which
+/// also sets the TTL and group of the records.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub enum Config {
+    DynDns(shared_dyndns::Config),
+    Api(ApiConfig),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApiConfig {
+    api_key: Secret<Box<str>>,
+    domains: Vec<Box<str>>,
+    ttl: Option<u32>,
+    group: Option<Box<str>>,
+    ipv6_prefix_length: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    #[serde(default, deserialize_with = "unscrubbed_optional_secret")]
+    username: Option<Secret<Box<str>>>,
+    password: Option<Secret<Box<str>>>,
+
+    /// An API key, found in the control panel under API Credentials.
+    api_key: Option<Secret<Box<str>>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default)]
+    ttl: Option<u32>,
+
+    /// The group the records are put in, for updating them together.
+    #[serde(default)]
+    group: Option<Box<str>>,
+
+    /// The length of the IPv6 prefix delegated to this host. If given, the
+    /// AAAA records only take the prefix of the address and keep their own
+    /// interface identifier, so that hosts behind this one can be named.
+    #[serde(default)]
+    ipv6_prefix_length: Option<u8>,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
-        Self {
-            inner: shared_dyndns::Service::from_config(
+impl TryFrom<RawConfig> for Config {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        if raw.ipv6_prefix_length.is_some_and(|length| length > 128) {
+            return Err("ipv6_prefix_length must be at most 128");
+        }
+
+        match (raw.api_key, raw.username, raw.password) {
+            (Some(api_key), None, None) => Ok(Config::Api(ApiConfig {
+                api_key,
+                domains: raw.domains,
+                ttl: raw.ttl,
+                group: raw.group,
+                ipv6_prefix_length: raw.ipv6_prefix_length,
+            })),
+
+            (None, Some(_), Some(_))
+                if raw.ttl.is_some() || raw.group.is_some() || raw.ipv6_prefix_length.is_some() =>
+            {
+                Err("ttl, group and ipv6_prefix_length only apply when an api_key is given")
+            }
+
+            (None, Some(username), Some(password)) => Ok(Config::DynDns(
+                shared_dyndns::Config::new(username, password, raw.domains),
+            )),
+
+            _ => Err("either api_key, or username and password must be given"),
+        }
+    }
+}
+
+pub enum Service {
+    DynDns(shared_dyndns::Service),
+    Api(ApiService),
+}
+
+pub struct ApiService {
+    config: ApiConfig,
+    client: Client,
+    cached_zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    id: u64,
+    name: Box<str>,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        match config {
+            Config::DynDns(config) => Service::DynDns(shared_dyndns::Service::from_config(
                 "Dynu",
                 "https://api.dynu.com/nic/update",
                 config,
-            ),
+                http,
+            )),
+
+            Config::Api(config) => Service::Api(ApiService {
+                config,
+                client: Client::new(http),
+                cached_zones: Vec::new(),
+            }),
         }
     }
 }
 
+/// Takes the id and name of each domain listed.
+fn parse_zones(listed: &[serde_json::Value]) -> Result<Vec<Zone>, DdnsUpdateError> {
+    let mut zones = Vec::with_capacity(listed.len());
+    for zone in listed {
+        let id = zone.get("id").and_then(|i| i.as_u64());
+        let name = zone.get("name").and_then(|n| n.as_str());
+
+        let (Some(id), Some(name)) = (id, name) else {
+            return Err(DdnsUpdateError::Json("domain has no id or name?".into()));
+        };
+
+        zones.push(Zone {
+            id,
+            name: name.into(),
+        });
+    }
+
+    Ok(zones)
+}
+
+/// Puts the interface identifier of `host` behind the prefix of the given
+/// length `ip` belongs to.
+fn with_prefix(ip: Ipv6Addr, host: Ipv6Addr, length: u8) -> Ipv6Addr {
+    let mask = u128::MAX.checked_shl(128 - length as u32).unwrap_or(0);
+
+    Ipv6Addr::from((u128::from(ip) & mask) | (u128::from(host) & !mask))
+}
+
+impl ApiService {
+    /// Errors are reported as {"statusCode": 501, "type": "...",
+    /// "message": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let kind = resp_json.get("type").and_then(|t| t.as_str());
+        let message = resp_json.get("message").and_then(|m| m.as_str());
+
+        match (kind, message) {
+            (Some(kind), Some(message)) => Ok(format!("{} ({})", message, kind).into()),
+            (None, Some(message)) | (Some(message), None) => Ok(message.into()),
+            (None, None) => Err(String::from("expected string")),
+        }
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into())),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Dynu", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    fn get(&self, url: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set("API-Key", self.config.api_key.expose())
+            .call();
+
+        self.parse_and_check_response(response)
+    }
+
+    fn post(&self, url: &str, body: serde_json::Value) -> Result<(), DdnsUpdateError> {
+        let response = self
+            .client
+            .post(url)
+            .set("API-Key", self.config.api_key.expose())
+            .send_json(body);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Lists the domains of the account, along with the addresses the domains
+    /// themselves point to.
+    ///
+    /// See: https://www.dynu.com/Support/API#/dns/dnsGet
+    fn list_domains(&self) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let response = self.get(&format!("{}/dns", ENDPOINT))?;
+
+        match response.get("domains").and_then(|d| d.as_array()) {
+            Some(domains) => Ok(domains.clone()),
+            None => Err(DdnsUpdateError::Json("expected a list of domains".into())),
+        }
+    }
+
+    fn get_zones(&self) -> Result<Vec<Zone>, DdnsUpdateError> {
+        parse_zones(&self.list_domains()?)
+    }
+
+    /// See: https://www.dynu.com/Support/API#/dns/dnsIdRecordGet
+    fn get_records(&self, zone: &Zone) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let response = self.get(&format!("{}/dns/{}/record", ENDPOINT, zone.id))?;
+
+        match response.get("dnsRecords").and_then(|r| r.as_array()) {
+            Some(records) => Ok(records.clone()),
+            None => Err(DdnsUpdateError::Json("expected a list of records".into())),
+        }
+    }
+
+    /// The address a record of the given current address is pointed to.
+    fn address_for(&self, ip: IpAddr, current: Option<&str>) -> IpAddr {
+        match (ip, self.config.ipv6_prefix_length) {
+            (IpAddr::V6(ipv6), Some(length)) => {
+                match current.and_then(|c| c.parse::<Ipv6Addr>().ok()) {
+                    Some(host) => with_prefix(ipv6, host, length).into(),
+                    None => ip,
+                }
+            }
+            _ => ip,
+        }
+    }
+
+    /// Points the zone itself to the addresses, which are part of the domain
+    /// rather than records of their own in Dynu.
+    ///
+    /// See: https://www.dynu.com/Support/API#/dns/dnsIdPost
+    fn set_zone(&self, zone: &Zone, ips: &[IpAddr]) -> Result<(), DdnsUpdateError> {
+        let mut domain = self.get(&format!("{}/dns/{}", ENDPOINT, zone.id))?;
+        let original = domain.clone();
+
+        for ip in ips {
+            let (key, enabled) = match ip {
+                IpAddr::V4(_) => ("ipv4Address", "ipv4"),
+                IpAddr::V6(_) => ("ipv6Address", "ipv6"),
+            };
+
+            let current = domain.get(key).and_then(|a| a.as_str());
+            let address = self.address_for(*ip, current);
+
+            domain[key] = address.to_string().into();
+            domain[enabled] = true.into();
+        }
+
+        if let Some(ttl) = self.config.ttl {
+            domain["ttl"] = ttl.into();
+        }
+
+        if let Some(group) = &self.config.group {
+            domain["group"] = (**group).into();
+        }
+
+        if domain == original {
+            return Ok(());
+        }
+
+        self.post(&format!("{}/dns/{}", ENDPOINT, zone.id), domain)
+    }
+
+    /// Points the record of the given node and type to the address, creating
+    /// the record if there is none.
+    ///
+    /// See: https://www.dynu.com/Support/API#/dns/dnsIdRecordRecordIdPost
+    fn set_record(
+        &self,
+        zone: &Zone,
+        records: &[serde_json::Value],
+        node: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let (kind, key) = match ip {
+            IpAddr::V4(_) => ("A", "ipv4Address"),
+            IpAddr::V6(_) => ("AAAA", "ipv6Address"),
+        };
+
+        let existing = records.iter().find(|record| {
+            record.get("recordType").and_then(|t| t.as_str()) == Some(kind)
+                && record.get("nodeName").and_then(|n| n.as_str()) == Some(node)
+        });
+
+        let url = format!("{}/dns/{}/record", ENDPOINT, zone.id);
+
+        let current = existing.and_then(|r| r.get(key)).and_then(|a| a.as_str());
+        let address = self.address_for(ip, current).to_string();
+
+        let current_ttl = existing.and_then(|r| r.get("ttl")).and_then(|t| t.as_u64());
+        let ttl = self
+            .config
+            .ttl
+            .or(current_ttl.map(|t| t as u32))
+            .unwrap_or(DEFAULT_TTL);
+
+        let current_group = existing
+            .and_then(|r| r.get("group"))
+            .and_then(|g| g.as_str());
+        let group = self.config.group.as_deref().or(current_group).unwrap_or("");
+
+        let url = match existing {
+            Some(record) => {
+                if current == Some(&*address)
+                    && current_ttl == Some(ttl as u64)
+                    && current_group.unwrap_or("") == group
+                {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("id").and_then(|i| i.as_u64()) else {
+                    return Err(DdnsUpdateError::Json("record has no id?".into()));
+                };
+
+                format!("{}/{}", url, id)
+            }
+
+            None => url,
+        };
+
+        let body = serde_json::json!({
+            "nodeName": node,
+            "recordType": kind,
+            "ttl": ttl,
+            "state": true,
+            "group": group,
+            key: address,
+        });
+
+        self.post(&url, body)
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        // The records are listed once per zone, not once per domain.
+        let mut records: Vec<(u64, Vec<serde_json::Value>)> = Vec::new();
+
+        for domain in &self.config.domains {
+            let Some((zone, node)) = find_zone(&self.cached_zones, domain, "") else {
+                return Err(DdnsUpdateError::provider(
+                    "Dynu",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            if node.is_empty() {
+                self.set_zone(zone, result.as_slice())?;
+                continue;
+            }
+
+            let index = match records.iter().position(|(id, _)| *id == zone.id) {
+                Some(index) => index,
+                None => {
+                    records.push((zone.id, self.get_records(zone)?));
+                    records.len() - 1
+                }
+            };
+
+            for ip in &result {
+                self.set_record(zone, &records[index].1, node, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Result<String, DdnsUpdateError> {
+        self.cached_zones = self.get_zones()?;
+
+        Ok(describe_found_domains(&self.config.domains, |domain| {
+            find_zone(&self.cached_zones, domain, "").is_some()
+        }))
+    }
+
+    /// Lists the domains and their A and AAAA records, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        let domains = self.list_domains()?;
+        let zones = parse_zones(&domains)?;
+
+        for (domain, zone) in domains.iter().zip(&zones) {
+            for (key, kind) in [
+                ("ipv4Address", RecordType::A),
+                ("ipv6Address", RecordType::Aaaa),
+            ] {
+                if domain.get(key).is_some_and(|a| a.is_string()) {
+                    found.push(DiscoveredRecord {
+                        name: zone.name.clone(),
+                        kind,
+                    });
+                }
+            }
+
+            for record in self.get_records(zone)? {
+                let kind = match record.get("recordType").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let Some(name) = record.get("hostname").and_then(|h| h.as_str()) else {
+                    return Err(DdnsUpdateError::Json("record has no hostname?".into()));
+                };
+
+                found.push(DiscoveredRecord {
+                    name: name.into(),
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
 impl DdnsService for Service {
-    fn update_record(&mut self, ip: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
-        self.inner.update_record(ip)
+    fn suspension(&self) -> Option<&SuspensionState> {
+        match self {
+            Service::DynDns(inner) => inner.suspension(),
+            Service::Api(_) => None,
+        }
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        match self {
+            Service::DynDns(inner) => inner.update_record(ips),
+            Service::Api(service) => service.update_record(ips),
+        }
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        match self {
+            Service::DynDns(_) => None,
+            Service::Api(service) => Some(service.test_credentials()),
+        }
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        match self {
+            Service::DynDns(_) => None,
+            Service::Api(service) => Some(service.discover()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "example.com"
+            "#,
+        );
+        assert!(matches!(config, Ok(Config::DynDns(_))));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            api_key = "key"
+            domains = "example.com"
+            group = "home"
+            ipv6_prefix_length = 64
+            "#,
+        );
+        assert!(matches!(config, Ok(Config::Api(_))));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "example.com"
+            group = "home"
+            "#,
+        );
+        assert!(config.is_err());
+
+        let config = toml::from_str::<Config>(
+            r#"
+            api_key = "key"
+            domains = "example.com"
+            ipv6_prefix_length = 129
+            "#,
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn prefix() {
+        let ip = "2001:db8:5:6::1".parse().unwrap();
+        let host = "2001:db8:1:2::abcd".parse().unwrap();
+
+        let expected: Ipv6Addr = "2001:db8:5:6::abcd".parse().unwrap();
+        assert_eq!(with_prefix(ip, host, 64), expected);
+        assert_eq!(with_prefix(ip, host, 0), host);
+        assert_eq!(with_prefix(ip, host, 128), ip);
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dynu.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            api_key = "key"
+            domains = ["example.com", "www.example.com"]
+            ttl = 60
+            ipv6_prefix_length = 64
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8:5:6::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.dynu.com/v2/dns",
+                "GET https://api.dynu.com/v2/dns/1",
+                "POST https://api.dynu.com/v2/dns/1",
+                "GET https://api.dynu.com/v2/dns/1/record",
+                "POST https://api.dynu.com/v2/dns/1/record/10",
+                "POST https://api.dynu.com/v2/dns/1/record/11",
+            ]
+        );
+
+        // The domain keeps its interface identifier and its group.
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["ipv6Address"], "2001:db8:5:6::1");
+        assert_eq!(body["ttl"], 60);
+        assert_eq!(body["group"], "home");
+
+        let body = sent[5].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "nodeName": "www",
+                "recordType": "AAAA",
+                "ttl": 60,
+                "state": true,
+                "group": "home",
+                "ipv6Address": "2001:db8:5:6::abcd",
+            })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Invalid API key. (Authentication Exception)"
+        ));
+    }
+
+    #[test]
+    fn update_dyndns() {
+        add_fixtures(include_str!("fixtures/dynu_dyndns.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "home.example.com"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let updated = service.update_record(&["2001:db8::1".parse().unwrap()]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                provider: "Dynu",
+                kind: ErrorKind::Validation,
+                ..
+            })
+        ));
+        assert!(service.suspension().is_some());
+
+        assert_eq!(take_sent().len(), 2);
     }
 }
@@ -0,0 +1,206 @@
+use std::net::{IpAddr, Ipv6Addr};
+
+use serde::{Deserialize, Deserializer};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
+
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The HTTP token found on the "Instructions" page of each zone.
+    token: Secret<Box<str>>,
+
+    /// The zones to be updated, e.g. "myhost.dynv6.net".
+    #[serde(deserialize_with = "one_or_more_string")]
+    zones: Vec<Box<str>>,
+
+    /// The length of the IPv6 prefix delegated to this host. If given, the
+    /// prefix of the zone is updated as well, which moves the AAAA records of
+    /// the other hosts within the zone along with it.
+    #[serde(default, deserialize_with = "prefix_length")]
+    prefix_length: Option<u8>,
+}
+
+fn prefix_length<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let length = u8::deserialize(deserializer)?;
+
+    if length > 128 {
+        return Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Unsigned(length.into()),
+            &"a prefix length of at most 128",
+        ));
+    }
+
+    Ok(Some(length))
+}
+
+/// Formats the prefix of the given length the address belongs to, e.g.
+/// "2001:db8:1234::/48".
+fn prefix(ip: Ipv6Addr, length: u8) -> String {
+    let mask = u128::MAX.checked_shl(128 - length as u32).unwrap_or(0);
+    let network = Ipv6Addr::from(u128::from(ip) & mask);
+
+    format!("{}/{}", network, length)
+}
+
+#[derive(Clone, Debug)]
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+impl DdnsService for Service {
+    /// See: https://dynv6.com/docs/apis#update
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find_map(|ip| match ip {
+            IpAddr::V6(v6) => Some(*v6),
+            IpAddr::V4(_) => None,
+        });
+
+        for zone in &self.config.zones {
+            let mut request = self
+                .client
+                .get("https://dynv6.com/api/update")
+                .query("zone", zone)
+                .query("token", self.config.token.expose());
+
+            if let Some(ipv4) = ipv4 {
+                request = request.query("ipv4", &ipv4.to_string());
+            }
+
+            if let Some(ipv6) = ipv6 {
+                request = request.query("ipv6", &ipv6.to_string());
+
+                if let Some(length) = self.config.prefix_length {
+                    request = request.query("ipv6prefix", &prefix(ipv6, length));
+                }
+            }
+
+            // e.g. "addresses updated", "addresses unchanged", or (along with
+            // an error status) "invalid authentication token".
+            let response = match request.call() {
+                Ok(resp) => resp.into_string().map_err(|e| {
+                    DdnsUpdateError::provider("dynv6", ErrorKind::Other, e.to_string().into())
+                })?,
+
+                Err(Error::Status(status, resp)) => {
+                    let kind = ErrorKind::from_response(status, &resp);
+                    let message = resp.into_string().map_err(|e| {
+                        DdnsUpdateError::provider("dynv6", ErrorKind::Other, e.to_string().into())
+                    })?;
+                    return Err(DdnsUpdateError::provider(
+                        "dynv6",
+                        kind,
+                        format!("{} ({})", message.trim(), zone).into(),
+                    ));
+                }
+
+                Err(Error::Transport(tp)) => {
+                    return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+                }
+            };
+
+            if !response.starts_with("addresses") {
+                return Err(DdnsUpdateError::provider(
+                    "dynv6",
+                    ErrorKind::Other,
+                    format!("{} ({})", response.trim(), zone).into(),
+                ));
+            }
+        }
+
+        let mut result = FixedVec::new();
+        if let Some(ipv4) = ipv4 {
+            result.push(*ipv4);
+        }
+        if let Some(ipv6) = ipv6 {
+            result.push(IpAddr::V6(ipv6));
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            token = "token"
+            zones = "a.dynv6.net"
+            prefix_length = 129
+            "#,
+        );
+        assert!(config.is_err());
+
+        assert_eq!(
+            prefix("2001:db8:1234:5678::1".parse().unwrap(), 48),
+            "2001:db8:1234::/48"
+        );
+        assert_eq!(prefix("2001:db8::1".parse().unwrap(), 0), "::/0");
+        assert_eq!(
+            prefix("2001:db8::1".parse().unwrap(), 128),
+            "2001:db8::1/128"
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dynv6.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                zones: vec!["a.dynv6.net".into(), "b.dynv6.net".into()],
+                prefix_length: Some(56),
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8:0:12::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://dynv6.com/api/update?zone=a.dynv6.net&token=token&ipv4=192.0.2.1\
+                 &ipv6=2001%3Adb8%3A0%3A12%3A%3A1&ipv6prefix=2001%3Adb8%3A%3A%2F56",
+                "https://dynv6.com/api/update?zone=b.dynv6.net&token=token&ipv4=192.0.2.1\
+                 &ipv6=2001%3Adb8%3A0%3A12%3A%3A1&ipv6prefix=2001%3Adb8%3A%3A%2F56",
+            ]
+        );
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "invalid authentication token (a.dynv6.net)"
+        ));
+    }
+}
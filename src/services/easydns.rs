@@ -0,0 +1,205 @@
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
+
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, Suspension, SuspensionState};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// EasyDNS answers "TOOSOON" to updates sent less than 10 minutes apart.
+const TOO_SOON_SUSPENSION: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The username of the account, and the dynamic DNS token generated for
+    /// it under the DYNAMIC DNS settings of the domain, not the password.
+    username: Box<str>,
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Secret<Box<str>>,
+    suspension: Option<SuspensionState>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let username_token = String::from(config.username.clone()) + ":" + config.token.expose();
+        let base64 = data_encoding::BASE64.encode(username_token.as_bytes());
+        let auth = String::from("Basic ") + &base64;
+
+        Self {
+            config,
+            client: Client::new(http),
+            auth: Secret::new(auth.into()),
+            suspension: None,
+        }
+    }
+
+    /// Stops sending updates after the error, which is returned.
+    fn suspend(
+        &mut self,
+        suspension: Suspension,
+        kind: ErrorKind,
+        message: Box<str>,
+    ) -> DdnsUpdateError {
+        let error = DdnsUpdateError::provider("EasyDNS", kind, message);
+
+        self.suspension = Some(SuspensionState {
+            suspension,
+            reason: error.to_string().into(),
+            since: SystemTime::now(),
+        });
+
+        error
+    }
+
+    /// Points the hostname to the address. The answer is a page with one of
+    /// the return codes somewhere in it.
+    ///
+    /// See: https://kb.easydns.com/knowledge/dynamic-dns/
+    fn send(&mut self, hostname: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://api.cp.easydns.com/dyn/generic.php")
+            .set("Authorization", self.auth.expose())
+            .query("hostname", hostname)
+            .query("myip", &ip.to_string())
+            .call();
+
+        let resp = match response {
+            Ok(resp) | Err(Error::Status(_, resp)) => resp.into_string().map_err(|e| {
+                DdnsUpdateError::provider("EasyDNS", ErrorKind::Other, e.to_string().into())
+            })?,
+            Err(Error::Transport(t)) => {
+                return Err(DdnsUpdateError::TransportError(t.to_string().into()))
+            }
+        };
+
+        if resp.contains("NOERROR") {
+            return Ok(());
+        }
+
+        // Updating too often only holds the updates back for a while, but
+        // the other codes stay the same until the config is fixed, so the
+        // service is suspended for good rather than risk a ban.
+        let (suspension, kind, message) = if resp.contains("TOOSOON") {
+            let suspension = Suspension::Until(SystemTime::now() + TOO_SOON_SUSPENSION);
+            let message = String::from("Updates were sent too soon, suspending for 10 minutes");
+            (
+                suspension,
+                ErrorKind::RateLimited(Some(TOO_SOON_SUSPENSION)),
+                message,
+            )
+        } else if resp.contains("NOACCESS") {
+            let message = String::from(
+                "Bad authentication details were provided (the token is the dynamic DNS token, not the password)",
+            );
+            (Suspension::Indefinite, ErrorKind::Auth, message)
+        } else if resp.contains("NOSERVICE") {
+            let message = format!("Dynamic DNS is not enabled for {}", hostname);
+            (Suspension::Indefinite, ErrorKind::NotFound, message)
+        } else if resp.contains("ILLEGAL INPUT") {
+            let message = format!(
+                "The update of {} to {} was refused as invalid",
+                hostname, ip
+            );
+            (Suspension::Indefinite, ErrorKind::Validation, message)
+        } else {
+            let message = format!("unexpected response \"{}\"", resp.trim());
+            (Suspension::Indefinite, ErrorKind::Other, message)
+        };
+
+        Err(self.suspend(suspension, kind, message.into()))
+    }
+}
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.suspension
+            .as_ref()
+            .filter(|state| state.is_in_effect())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if let Some(state) = self.suspension() {
+            return Err(DdnsUpdateError::Suspended(state.suspension.clone()));
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        // Only one hostname and one address are taken per request.
+        for domain in self.config.domains.clone() {
+            for ip in &result {
+                self.send(&domain, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/easydns.txt"));
+
+        let mut service = Service::new(
+            Config {
+                username: "user".into(),
+                token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.cp.easydns.com/dyn/generic.php?hostname=example.com&myip=192.0.2.1",
+                "https://api.cp.easydns.com/dyn/generic.php?hostname=example.com&myip=2001%3Adb8%3A%3A1",
+                "https://api.cp.easydns.com/dyn/generic.php?hostname=www.example.com&myip=192.0.2.1",
+                "https://api.cp.easydns.com/dyn/generic.php?hostname=www.example.com&myip=2001%3Adb8%3A%3A1",
+            ]
+        );
+
+        // Updating too often only holds the service back for a while.
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { kind: ErrorKind::RateLimited(Some(_)), message: m, .. }) if m.starts_with("Updates were sent too soon")
+        ));
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Until(_)))
+        ));
+
+        assert_eq!(take_sent().len(), 1);
+    }
+}
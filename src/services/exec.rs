@@ -0,0 +1,198 @@
+use std::net::IpAddr;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::ip::{kill_group, read_in_background, wait_for_reader};
+use crate::util::FixedVec;
+
+use super::{one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// How often we check whether the child process has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A command run through the shell of [general] for every update, for the
+/// providers which dynners does not support. The addresses and the domains
+/// are given both as arguments ($1 is the IPv4 address, $2 the IPv6 address,
+/// empty if unknown, and the domains follow) and as the environment variables
+/// DYNNERS_IPV4, DYNNERS_IPV6 and DYNNERS_DOMAINS (comma-separated). The
+/// update succeeded if the command exits with 0.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    command: Box<str>,
+
+    #[serde(default, deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// In seconds, 0 waits forever.
+    #[serde(default = "default_timeout")]
+    timeout: u32,
+}
+
+fn default_timeout() -> u32 {
+    60
+}
+
+pub struct Service {
+    config: Config,
+    shell: Box<str>,
+}
+
+impl Service {
+    pub fn new(config: Config, shell: &str) -> Self {
+        Self {
+            config,
+            shell: shell.into(),
+        }
+    }
+
+    fn run(&self, ipv4: &str, ipv6: &str) -> Result<(), DdnsUpdateError> {
+        let error = |kind, message: String| DdnsUpdateError::provider("exec", kind, message.into());
+
+        let mut command = Command::new(&*self.shell);
+        command
+            .arg("-c")
+            .arg(&*self.config.command)
+            .arg("dynners")
+            .arg(ipv4)
+            .arg(ipv6)
+            .args(self.config.domains.iter().map(|domain| &**domain))
+            .env("DYNNERS_IPV4", ipv4)
+            .env("DYNNERS_IPV6", ipv6)
+            .env("DYNNERS_DOMAINS", self.config.domains.join(","))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        // The command gets a process group of its own, so that whatever it
+        // starts is killed along with it once it times out.
+        command.process_group(0);
+
+        let mut child = command.spawn().map_err(|e| {
+            error(
+                ErrorKind::Other,
+                format!("unable to run the command: {}", e),
+            )
+        })?;
+
+        let stderr = read_in_background(child.stderr.take());
+
+        let deadline = match self.config.timeout {
+            0 => None,
+            timeout => Some(Instant::now() + Duration::from_secs(timeout.into())),
+        };
+
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => (),
+                Err(e) => return Err(error(ErrorKind::Other, e.to_string())),
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                kill_group(&mut child);
+                return Err(error(
+                    ErrorKind::Transport,
+                    String::from("the command timed out"),
+                ));
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        if status.success() {
+            return Ok(());
+        }
+
+        // The last line a script prints to stderr usually tells what failed,
+        // unless something it left running still holds stderr open.
+        let stderr = match wait_for_reader(&stderr, &mut child, deadline) {
+            true => stderr.join().unwrap_or_default(),
+            false => Vec::new(),
+        };
+        let stderr = String::from_utf8_lossy(&stderr);
+        let message = match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+            Some(line) => format!("the command ended with {}: {}", status, line.trim()),
+            None => format!("the command ended with {}", status),
+        };
+
+        Err(error(ErrorKind::Other, message))
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        self.run(
+            &ipv4.map(|ip| ip.to_string()).unwrap_or_default(),
+            &ipv6.map(|ip| ip.to_string()).unwrap_or_default(),
+        )?;
+
+        let mut result = FixedVec::new();
+        result.extend(ipv4);
+        result.extend(ipv6);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(command: &str) -> Service {
+        let config = Config {
+            command: command.into(),
+            domains: vec!["a.example.com".into(), "b.example.com".into()],
+            timeout: 5,
+        };
+
+        Service::new(config, "/bin/sh")
+    }
+
+    #[test]
+    fn update() {
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let mut ok = service(
+            r#"[ "$1" = "" ] && [ "$2" = "2001:db8::1" ] && [ "$3 $4" = "a.example.com b.example.com" ] &&
+               [ "$DYNNERS_IPV6" = "$2" ] && [ "$DYNNERS_DOMAINS" = "a.example.com,b.example.com" ]"#,
+        );
+        assert_eq!(ok.update_record(&[ipv6]).unwrap().as_slice(), &[ipv6]);
+
+        let mut failing = service("echo 'first' >&2; echo 'badauth' >&2; exit 3");
+        assert!(matches!(
+            failing.update_record(&[ipv6]),
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Other,
+                message: m,
+                ..
+            }) if m.ends_with(": badauth")
+        ));
+    }
+
+    #[test]
+    fn timeout() {
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let mut service = service("sleep 30");
+        service.config.timeout = 1;
+
+        // The background sleep keeps stderr open after the shell has exited,
+        // and is killed along with it.
+        let started = Instant::now();
+        assert!(service.update_record(&[ipv6]).is_err());
+        service.config.command = "echo 'badauth' >&2; sleep 30 & exit 3".into();
+        assert!(matches!(
+            service.update_record(&[ipv6]),
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "the command ended with exit status: 3"
+        ));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,402 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, DiscoveredRecord,
+    ErrorKind, RecordType,
+};
+
+type RecordId = u64;
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.exoscale.com/dns/v1";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// An API key and its secret, created under IAM > Keys with access to
+    /// the DNS service.
+    api_key: Box<str>,
+    api_secret: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. The TTL of the records is left
+    /// as it is if not given.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    token: Secret<Box<str>>,
+    cached_records: Vec<Record>,
+}
+
+#[derive(Debug)]
+struct Record {
+    id: RecordId,
+
+    /// The domain (i.e. zone) the record belongs to, e.g. "example.com".
+    zone: Box<str>,
+
+    /// The fully-qualified name, e.g. "www.example.com".
+    fqdn: Box<str>,
+
+    kind: RecordKind,
+
+    /// The address the record was last known to point to.
+    data: Box<str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordKind {
+    A,
+    Aaaa,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let token = format!("{}:{}", config.api_key, config.api_secret.expose());
+        Self {
+            config,
+            client: Client::new(http),
+            token: Secret::new(token.into()),
+            cached_records: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"message": "..."}, or {"error": "..."} by the
+    /// gateway in front of the API.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("message")
+            .or_else(|| resp_json.get("error"))
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Exoscale", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// Fetches a listing, whose items are each wrapped in an object keyed by
+    /// their kind, e.g. [{"domain": {...}}], and returns the items alone.
+    fn get_list(&self, url: &str, key: &str) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set("Accept", "application/json")
+            .set("X-DNS-Token", self.token.expose())
+            .call();
+
+        let serde_json::Value::Array(items) = self.parse_and_check_response(response)? else {
+            return Err(DdnsUpdateError::Json(
+                format!("expected a list of {}s", key).into(),
+            ));
+        };
+
+        Ok(items
+            .into_iter()
+            .map(|mut item| {
+                item.get_mut(key)
+                    .map(serde_json::Value::take)
+                    .unwrap_or(item)
+            })
+            .collect())
+    }
+
+    /// See: https://community.exoscale.com/api/dns/v1/#list-domains
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let zones = self.get_list(&format!("{}/domains", ENDPOINT), "domain")?;
+
+        let mut names = Vec::with_capacity(zones.len());
+        for zone in zones {
+            let Some(name) = zone.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no name?".into()));
+            };
+
+            names.push(name.into());
+        }
+
+        Ok(names)
+    }
+
+    /// See: https://community.exoscale.com/api/dns/v1/#list-records
+    fn get_records(&self, zone: &str) -> Result<Vec<Record>, DdnsUpdateError> {
+        let url = format!("{}/domains/{}/records", ENDPOINT, zone);
+        let records = self.get_list(&url, "record")?;
+
+        let mut returned_records = Vec::new();
+        for record in records {
+            let Some(id) = record.get("id").and_then(|v| v.as_u64()) else {
+                return Err(DdnsUpdateError::Json("record has no id?".into()));
+            };
+
+            let Some(name) = record.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no name?".into()));
+            };
+
+            let Some(ty) = record.get("record_type").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no type?".into()));
+            };
+
+            let kind = match ty {
+                "A" => RecordKind::A,
+                "AAAA" => RecordKind::Aaaa,
+                _ => continue,
+            };
+
+            let data = record.get("content").and_then(|v| v.as_str()).unwrap_or("");
+
+            // The domain itself has an empty name.
+            let fqdn: Box<str> = if name.is_empty() {
+                zone.into()
+            } else {
+                format!("{}.{}", name, zone).into()
+            };
+
+            returned_records.push(Record {
+                id,
+                zone: zone.into(),
+                fqdn,
+                kind,
+                data: data.into(),
+            });
+        }
+
+        Ok(returned_records)
+    }
+
+    /// See: https://community.exoscale.com/api/dns/v1/#update-a-record
+    fn put_record(&self, record: &Record, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let url = format!("{}/domains/{}/records/{}", ENDPOINT, record.zone, record.id);
+
+        let mut body = serde_json::json!({ "record": { "content": ip.to_string() } });
+        if let Some(ttl) = self.config.ttl {
+            body["record"]["ttl"] = ttl.into();
+        }
+
+        let response = self
+            .client
+            .put(&url)
+            .set("Accept", "application/json")
+            .set("X-DNS-Token", self.token.expose())
+            .send_json(body);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Looks up the records of the configured domains and caches them, so that
+    /// they are only looked up once. Only the zones containing any of the
+    /// domains are looked into.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        for zone in self.get_zones()? {
+            let is_relevant = self
+                .config
+                .domains
+                .iter()
+                .any(|domain| **domain == *zone || domain.ends_with(&format!(".{}", zone)));
+
+            if !is_relevant {
+                continue;
+            }
+
+            for record in self.get_records(&zone)? {
+                if self.config.domains.contains(&record.fqdn) {
+                    self.cached_records.push(record)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_cached_records(
+        &mut self,
+        ipv4: Option<IpAddr>,
+        ipv6: Option<IpAddr>,
+    ) -> Result<(), DdnsUpdateError> {
+        for i in 0..self.cached_records.len() {
+            let record = &self.cached_records[i];
+
+            let ip = match (&record.kind, ipv4, ipv6) {
+                (RecordKind::A, Some(ipv4), _) => ipv4,
+                (RecordKind::Aaaa, _, Some(ipv6)) => ipv6,
+                _ => continue,
+            };
+
+            let data = ip.to_string();
+            if *record.data == data {
+                continue;
+            }
+
+            self.put_record(record, ip)?;
+            self.cached_records[i].data = data.into();
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                let kind = match record.kind {
+                    RecordKind::A => RecordType::A,
+                    RecordKind::Aaaa => RecordType::Aaaa,
+                };
+
+                found.push(DiscoveredRecord {
+                    name: record.fqdn,
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_records.is_empty() {
+            self.cache_records()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4()).copied();
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6()).copied();
+
+        // The records may have been changed or removed behind our back, so
+        // they are looked up again after a failure.
+        if let Err(e) = self.update_cached_records(ipv4, ipv6) {
+            self.cached_records.clear();
+            return Err(e);
+        }
+
+        let mut result = FixedVec::new();
+        result.extend(ipv4);
+        result.extend(ipv6);
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records
+                    .iter()
+                    .any(|record| &*record.fqdn == domain)
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/exoscale.txt"));
+
+        let mut service = Service::new(
+            Config {
+                api_key: "EXOkey".into(),
+                api_secret: Secret::new("secret".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: Some(300),
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // example.net is not looked into, and the AAAA record of example.com
+        // is up to date.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.exoscale.com/dns/v1/domains",
+                "GET https://api.exoscale.com/dns/v1/domains/example.com/records",
+                "PUT https://api.exoscale.com/dns/v1/domains/example.com/records/1",
+                "PUT https://api.exoscale.com/dns/v1/domains/example.com/records/3",
+            ]
+        );
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "record": { "content": "192.0.2.1", "ttl": 300 } })
+        );
+
+        // Nothing is sent while the addresses stay the same.
+        service.update_record(&[ipv4, ipv6]).unwrap();
+        assert!(take_sent().is_empty());
+
+        let ipv4 = "192.0.2.2".parse().unwrap();
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Invalid API key"
+        ));
+        assert!(service.cached_records.is_empty());
+    }
+}
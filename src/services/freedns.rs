@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, unscrubbed_optional_secret, FixedVec, Secret};
+
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, ErrorKind, RecordType, SuspensionState};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// FreeDNS is updated either through the randomized update URLs of its V2
+/// interface, one per record, or through its DynDNS-compatible interface with
+/// the account's username and password.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub enum Config {
+    Tokens {
+        ipv4: BTreeMap<Box<str>, Secret<Box<str>>>,
+        ipv6: BTreeMap<Box<str>, Secret<Box<str>>>,
+    },
+
+    Account(shared_dyndns::Config),
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    /// The update token of the A record of each domain, i.e. the last part of
+    /// its update URL, https://sync.afraid.org/u/<token>/.
+    #[serde(default)]
+    tokens: BTreeMap<Box<str>, Secret<Box<str>>>,
+
+    /// The same for the AAAA records, which have tokens of their own.
+    #[serde(default)]
+    ipv6_tokens: BTreeMap<Box<str>, Secret<Box<str>>>,
+
+    #[serde(default, deserialize_with = "unscrubbed_optional_secret")]
+    username: Option<Secret<Box<str>>>,
+    password: Option<Secret<Box<str>>>,
+
+    #[serde(default, deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let has_tokens = !raw.tokens.is_empty() || !raw.ipv6_tokens.is_empty();
+
+        match (has_tokens, raw.username, raw.password) {
+            (true, None, None) if raw.domains.is_empty() => Ok(Config::Tokens {
+                ipv4: raw.tokens,
+                ipv6: raw.ipv6_tokens,
+            }),
+
+            (false, Some(username), Some(password)) if !raw.domains.is_empty() => Ok(
+                Config::Account(shared_dyndns::Config::new(username, password, raw.domains)),
+            ),
+
+            _ => Err("either tokens (and/or ipv6_tokens), or username, password and domains must be given"),
+        }
+    }
+}
+
+pub enum Service {
+    Tokens {
+        ipv4: BTreeMap<Box<str>, Secret<Box<str>>>,
+        ipv6: BTreeMap<Box<str>, Secret<Box<str>>>,
+        client: Client,
+    },
+
+    /// The DynDNS interface takes a single hostname per request, so there is
+    /// one client for each of them.
+    Account(Vec<shared_dyndns::Service>),
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        match config {
+            Config::Tokens { ipv4, ipv6 } => Service::Tokens {
+                ipv4,
+                ipv6,
+                client: Client::new(http),
+            },
+
+            Config::Account(config) => {
+                let inner = config
+                    .split_domains()
+                    .into_iter()
+                    .map(|config| {
+                        shared_dyndns::Service::from_config(
+                            "FreeDNS",
+                            "https://freedns.afraid.org/nic/update",
+                            config,
+                            http,
+                        )
+                    })
+                    .collect();
+
+                Service::Account(inner)
+            }
+        }
+    }
+}
+
+/// Updates a single record through its V2 update URL. Returns whether the
+/// address was changed.
+///
+/// See: https://freedns.afraid.org/dynamic/v2/
+fn update_with_token(
+    client: &Client,
+    domain: &str,
+    token: &str,
+    ip: IpAddr,
+) -> Result<bool, DdnsUpdateError> {
+    let url = match ip {
+        IpAddr::V4(_) => format!("https://sync.afraid.org/u/{}/", token),
+        IpAddr::V6(_) => format!("https://v6.sync.afraid.org/u/{}/", token),
+    };
+
+    let response = match client.get(&url).query("ip", &ip.to_string()).call() {
+        Ok(resp) | Err(Error::Status(_, resp)) => resp.into_string().map_err(|e| {
+            DdnsUpdateError::provider("FreeDNS", ErrorKind::Other, e.to_string().into())
+        })?,
+
+        Err(Error::Transport(tp)) => {
+            return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+        }
+    };
+
+    let response = response.trim();
+
+    // e.g. "Updated 1 host(s) dyn.example.com to 192.0.2.1 in 0.14 seconds",
+    // "No IP change detected for dyn.example.com with IP 192.0.2.1, skipping
+    // update" and "ERROR: Address 192.0.2.1 has not changed."
+    if response.starts_with("Updated") {
+        Ok(true)
+    } else if response.starts_with("No IP change") || response.ends_with("has not changed.") {
+        Ok(false)
+    } else if response.is_empty() {
+        Err(DdnsUpdateError::provider(
+            "FreeDNS",
+            ErrorKind::Outage,
+            format!("empty response for {}", domain).into(),
+        ))
+    } else {
+        Err(DdnsUpdateError::provider(
+            "FreeDNS",
+            ErrorKind::Other,
+            format!("{} ({})", response, domain).into(),
+        ))
+    }
+}
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        match self {
+            Service::Tokens { .. } => None,
+            Service::Account(inner) => inner.iter().find_map(|inner| inner.suspension()),
+        }
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        let mut result = FixedVec::new();
+
+        match self {
+            Service::Tokens {
+                ipv4: ipv4_tokens,
+                ipv6: ipv6_tokens,
+                client,
+            } => {
+                for (ip, tokens) in [(ipv4, &*ipv4_tokens), (ipv6, &*ipv6_tokens)] {
+                    let Some(ip) = ip else {
+                        continue;
+                    };
+
+                    let mut is_updated = false;
+                    for (domain, token) in tokens {
+                        is_updated |= update_with_token(client, domain, token.expose(), *ip)?;
+                    }
+
+                    if is_updated {
+                        result.push(*ip);
+                    }
+                }
+            }
+
+            Service::Account(inner) => {
+                for inner in inner {
+                    // Only one address is accepted per request.
+                    for ip in [ipv4, ipv6].into_iter().flatten() {
+                        let updated = inner.update_record(&[*ip])?;
+
+                        for ip in updated {
+                            if !result.as_slice().contains(&ip) {
+                                result.push(ip);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            tokens = { "dyn.example.com" = "token4" }
+            ipv6_tokens = { "dyn.example.com" = "token6" }
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(config, Config::Tokens { .. }));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "dyn.example.com"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(config, Config::Account(_)));
+
+        // Mixing both ways is most likely a mistake.
+        let config = toml::from_str::<Config>(
+            r#"
+            tokens = { "dyn.example.com" = "token4" }
+            username = "user"
+            password = "pass"
+            "#,
+        );
+        assert!(config.is_err());
+
+        assert_eq!(
+            crate::util::struct_fields::<Config>(),
+            ["tokens", "ipv6_tokens", "username", "password", "domains"]
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/freedns.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            [tokens]
+            "a.example.com" = "token-a"
+            "b.example.com" = "token-b"
+
+            [ipv6_tokens]
+            "a.example.com" = "token-a6"
+            "#,
+        )
+        .unwrap();
+
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        // The AAAA record is already up to date.
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://sync.afraid.org/u/token-a/?ip=192.0.2.1",
+                "https://sync.afraid.org/u/token-b/?ip=192.0.2.1",
+                "https://v6.sync.afraid.org/u/token-a6/?ip=2001%3Adb8%3A%3A1",
+            ]
+        );
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Invalid update URL (2) (a.example.com)"
+        ));
+    }
+}
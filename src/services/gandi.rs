@@ -0,0 +1,250 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// A personal access token with the "Manage domain name technical
+    /// configurations" permission.
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds, Gandi requires at least 300.
+    ttl: u32,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+        config.token = Secret::new((String::from("Bearer ") + config.token.expose()).into());
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        // Validation errors come with a list of the offending fields, other
+        // errors only with a message.
+        let description = resp_json
+            .get("errors")
+            .and_then(|errors| errors.get(0))
+            .and_then(|error| error.get("description"))
+            .and_then(|d| d.as_str());
+
+        let message = resp_json.get("message").and_then(|m| m.as_str());
+
+        description
+            .or(message)
+            .map(Box::from)
+            .ok_or_else(|| String::from("expected string"))
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Gandi", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// See: https://api.gandi.net/docs/livedns/#get-v5-livedns-domains
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://api.gandi.net/v5/livedns/domains")
+            .set("Authorization", self.config.token.expose())
+            .call();
+
+        let response = self.parse_and_check_response(response)?;
+
+        let Some(zones) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("gandi returned 0 domains".into()));
+        };
+
+        let mut zone_names = Vec::with_capacity(zones.len());
+
+        for zone in zones {
+            let Some(fqdn) = zone.get("fqdn").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no fqdn?".into()));
+            };
+
+            zone_names.push(fqdn.into());
+        }
+
+        Ok(zone_names)
+    }
+
+    /// See: https://api.gandi.net/docs/livedns/#put-v5-livedns-domains-fqdn-records-rrset_name-rrset_type
+    fn put_record(&self, zone: &str, name: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let url = format!(
+            "https://api.gandi.net/v5/livedns/domains/{}/records/{}/{}",
+            zone,
+            name,
+            if ip.is_ipv4() { "A" } else { "AAAA" },
+        );
+
+        let response = self
+            .client
+            .put(&url)
+            .set("Authorization", self.config.token.expose())
+            .send_json(serde_json::json!({
+                "rrset_values": [ip.to_string()],
+                "rrset_ttl": self.config.ttl,
+            }));
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&self.cached_zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Gandi",
+                    ErrorKind::NotFound,
+                    format!("no domain managed by the token contains {}", domain).into(),
+                ));
+            };
+
+            if let Some(ipv4) = ipv4 {
+                self.put_record(zone, name, *ipv4)?;
+            }
+
+            if let Some(ipv6) = ipv6 {
+                self.put_record(zone, name, *ipv6)?;
+            }
+        }
+
+        let mut result = FixedVec::new();
+        if let Some(ipv4) = ipv4 {
+            result.push(*ipv4);
+        }
+        if let Some(ipv6) = ipv6 {
+            result.push(*ipv6);
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/gandi.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "www.sub.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.gandi.net/v5/livedns/domains",
+                "https://api.gandi.net/v5/livedns/domains/example.com/records/@/A",
+                "https://api.gandi.net/v5/livedns/domains/example.com/records/@/AAAA",
+                "https://api.gandi.net/v5/livedns/domains/sub.example.com/records/www/A",
+                "https://api.gandi.net/v5/livedns/domains/sub.example.com/records/www/AAAA",
+            ]
+        );
+
+        let body = sent[1].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["rrset_values"][0], "192.0.2.1");
+        assert_eq!(body["rrset_ttl"], 300);
+
+        // A domain outside of the zones is not even attempted.
+        service.config.domains = vec!["example.net".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: _, .. })
+        ));
+
+        service.config.domains = vec!["bad.example.com".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "rrset_ttl must be at least 300"
+        ));
+    }
+}
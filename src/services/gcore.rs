@@ -0,0 +1,354 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.gcore.com/dns/v2";
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 300;
+
+/// How many zones or records are asked for at once.
+const PAGE_SIZE: u64 = 100;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// A permanent API token, created under Profile > API Tokens, allowed to
+    /// manage the DNS zones.
+    api_token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The TTL of the records, which is otherwise left as it is.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Secret<Box<str>>,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let auth = String::from("APIKey ") + config.api_token.expose();
+
+        Self {
+            config,
+            client: Client::new(http),
+            auth: Secret::new(auth.into()),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"error": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("error")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into())),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Gcore", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// Collects the items under `key` from every page of the listing, which
+    /// tells how many there are in "total_amount".
+    fn get_pages(&self, url: &str, key: &str) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut items = Vec::new();
+
+        loop {
+            let response = self
+                .client
+                .get(url)
+                .set("Authorization", self.auth.expose())
+                .query("limit", &PAGE_SIZE.to_string())
+                .query("offset", &items.len().to_string())
+                .call();
+
+            let response = self.parse_and_check_response(response)?;
+
+            let listed = match response.get(key).and_then(|l| l.as_array()) {
+                Some(listed) if !listed.is_empty() => listed,
+                _ => break,
+            };
+            items.extend(listed.iter().cloned());
+
+            let total = response.get("total_amount").and_then(|t| t.as_u64());
+            if total.is_none_or(|total| items.len() as u64 >= total) {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// See: https://api.gcore.com/docs/dns#tag/Zones/operation/Zones
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let listed = self.get_pages(&format!("{}/zones", ENDPOINT), "zones")?;
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.get("name").and_then(|n| n.as_str()) else {
+                return Err(DdnsUpdateError::Json("zone has no name?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// Points the RRset to the address alone. The RRset is looked up first,
+    /// so that an up-to-date one is left alone, and a missing one is created
+    /// (with POST) instead of replaced (with PUT).
+    ///
+    /// See: https://api.gcore.com/docs/dns#tag/RRsets/operation/UpdateRRSet
+    fn set_record(&self, zone: &str, domain: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let url = format!("{}/zones/{}/{}/{}", ENDPOINT, zone, domain, kind);
+
+        let response = self
+            .client
+            .get(&url)
+            .set("Authorization", self.auth.expose())
+            .call();
+
+        let existing = match response {
+            Err(Error::Status(404, _)) => None,
+            response => Some(self.parse_and_check_response(response)?),
+        };
+
+        let resource_records = serde_json::json!([{ "content": [ip.to_string()] }]);
+
+        let (request, ttl) = match existing {
+            Some(rrset) => {
+                let current_ttl = rrset.get("ttl").and_then(|t| t.as_u64());
+                let ttl = self
+                    .config
+                    .ttl
+                    .or(current_ttl.map(|t| t as u32))
+                    .unwrap_or(DEFAULT_TTL);
+
+                let is_up_to_date = rrset
+                    .get("resource_records")
+                    .and_then(|r| r.as_array())
+                    .is_some_and(|existing| {
+                        existing.len() == 1
+                            && existing[0].get("content") == resource_records[0].get("content")
+                    })
+                    && current_ttl == Some(ttl as u64);
+
+                if is_up_to_date {
+                    return Ok(());
+                }
+
+                (self.client.put(&url), ttl)
+            }
+
+            None => (
+                self.client.post(&url),
+                self.config.ttl.unwrap_or(DEFAULT_TTL),
+            ),
+        };
+
+        let body = serde_json::json!({
+            "ttl": ttl,
+            "resource_records": resource_records,
+        });
+
+        let response = request
+            .set("Authorization", self.auth.expose())
+            .send_json(body);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA RRsets of every zone, for `dynners discover`.
+    ///
+    /// See: https://api.gcore.com/docs/dns#tag/RRsets/operation/ZoneRRSets
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            let url = format!("{}/zones/{}/rrsets", ENDPOINT, zone);
+
+            for rrset in self.get_pages(&url, "rrsets")? {
+                let kind = match rrset.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let Some(name) = rrset.get("name").and_then(|n| n.as_str()) else {
+                    return Err(DdnsUpdateError::Json("RRset has no name?".into()));
+                };
+
+                found.push(DiscoveredRecord {
+                    name: name.trim_end_matches('.').into(),
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some(zone) = find_zone(&self.cached_zones, domain, "").map(|(zone, _)| &**zone)
+            else {
+                return Err(DdnsUpdateError::provider(
+                    "Gcore",
+                    ErrorKind::NotFound,
+                    format!("no zone of the account contains {}", domain).into(),
+                ));
+            };
+
+            for ip in &result {
+                self.set_record(zone, domain, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "")
+                    .map(|(zone, _)| &**zone)
+                    .is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/gcore.txt"));
+
+        let mut service = Service::new(
+            Config {
+                api_token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "home.dyn.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A RRset of example.com is up to date, dyn.example.com is a zone
+        // of its own, and home.dyn.example.com has no AAAA RRset yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.gcore.com/dns/v2/zones?limit=100&offset=0",
+                "GET https://api.gcore.com/dns/v2/zones/example.com/example.com/A",
+                "GET https://api.gcore.com/dns/v2/zones/example.com/example.com/AAAA",
+                "PUT https://api.gcore.com/dns/v2/zones/example.com/example.com/AAAA",
+                "GET https://api.gcore.com/dns/v2/zones/dyn.example.com/home.dyn.example.com/A",
+                "PUT https://api.gcore.com/dns/v2/zones/dyn.example.com/home.dyn.example.com/A",
+                "GET https://api.gcore.com/dns/v2/zones/dyn.example.com/home.dyn.example.com/AAAA",
+                "POST https://api.gcore.com/dns/v2/zones/dyn.example.com/home.dyn.example.com/AAAA",
+            ]
+        );
+
+        let body = sent[5].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "ttl": 120,
+                "resource_records": [{ "content": ["192.0.2.1"] }],
+            })
+        );
+
+        let body = sent[7].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["ttl"], 300);
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "invalid token"
+        ));
+    }
+}
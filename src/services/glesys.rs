@@ -0,0 +1,364 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.glesys.com";
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 3600;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The project (e.g. "CL12345") and an API key of the project, created
+    /// under API access with the domain permissions.
+    project: Box<str>,
+    api_key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The TTL of the records, which is otherwise left as it is.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Secret<Box<str>>,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let project_key = String::from(config.project.clone()) + ":" + config.api_key.expose();
+        let base64 = data_encoding::BASE64.encode(project_key.as_bytes());
+        let auth = String::from("Basic ") + &base64;
+
+        Self {
+            config,
+            client: Client::new(http),
+            auth: Secret::new(auth.into()),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"response": {"status": {"code": 401, "text":
+    /// "..."}}}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .pointer("/response/status/text")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    /// Calls a function of the API with the JSON arguments, and returns what
+    /// is under "response".
+    ///
+    /// See: https://github.com/GleSYS/API/wiki/API-Documentation
+    fn call(
+        &self,
+        function: &str,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .post(&format!("{}/{}", ENDPOINT, function))
+            .set("Accept", "application/json")
+            .set("Authorization", self.auth.expose())
+            .send_json(args);
+
+        match response {
+            Ok(r) => {
+                let mut response = r
+                    .into_json::<serde_json::Value>()
+                    .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+                match response.get_mut("response") {
+                    Some(response) => Ok(response.take()),
+                    None => Err(DdnsUpdateError::Json("glesys returned no response".into())),
+                }
+            }
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("GleSYS", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// See: https://github.com/GleSYS/API/wiki/functions_domain#domainlist
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let response = self.call("domain/list", serde_json::json!({}))?;
+
+        let Some(listed) = response.get("domains").and_then(|d| d.as_array()) else {
+            return Err(DdnsUpdateError::Json("expected a list of domains".into()));
+        };
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.get("domainname").and_then(|d| d.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no domainname?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// See: https://github.com/GleSYS/API/wiki/functions_domain#domainlistrecords
+    fn get_records(&self, zone: &str) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut response = self.call(
+            "domain/listrecords",
+            serde_json::json!({ "domainname": zone }),
+        )?;
+
+        match response.get_mut("records").map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(records)) => Ok(records),
+            _ => Err(DdnsUpdateError::Json("expected a list of records".into())),
+        }
+    }
+
+    /// Points the record of the given host and type to the address, creating
+    /// the record if there is none.
+    ///
+    /// See:
+    ///   - https://github.com/GleSYS/API/wiki/functions_domain#domainupdaterecord
+    ///   - https://github.com/GleSYS/API/wiki/functions_domain#domainaddrecord
+    fn set_record(
+        &self,
+        records: &[serde_json::Value],
+        zone: &str,
+        host: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+
+        let existing = records.iter().find(|record| {
+            record.get("type").and_then(|t| t.as_str()) == Some(kind)
+                && record.get("host").and_then(|h| h.as_str()) == Some(host)
+        });
+
+        let (function, args) = match existing {
+            Some(record) => {
+                let current_ttl = record.get("ttl").and_then(|t| t.as_u64());
+                let ttl = self
+                    .config
+                    .ttl
+                    .or(current_ttl.map(|t| t as u32))
+                    .unwrap_or(DEFAULT_TTL);
+
+                if record.get("data").and_then(|d| d.as_str()) == Some(&ip.to_string())
+                    && current_ttl == Some(ttl as u64)
+                {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("recordid").and_then(|i| i.as_u64()) else {
+                    return Err(DdnsUpdateError::Json("record has no recordid?".into()));
+                };
+
+                let args = serde_json::json!({
+                    "recordid": id,
+                    "data": ip.to_string(),
+                    "ttl": ttl,
+                });
+
+                ("domain/updaterecord", args)
+            }
+
+            None => {
+                let args = serde_json::json!({
+                    "domainname": zone,
+                    "host": host,
+                    "type": kind,
+                    "data": ip.to_string(),
+                    "ttl": self.config.ttl.unwrap_or(DEFAULT_TTL),
+                });
+
+                ("domain/addrecord", args)
+            }
+        };
+
+        self.call(function, args)?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                let kind = match record.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match record.get("host").and_then(|h| h.as_str()) {
+                    None | Some("@") => zone.clone(),
+                    Some(host) => format!("{}.{}", host, zone).into(),
+                };
+
+                // A name has as many records of a type as it has addresses.
+                if !found
+                    .iter()
+                    .any(|r: &DiscoveredRecord| r.name == name && r.kind == kind)
+                {
+                    found.push(DiscoveredRecord { name, kind });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, host)) = find_zone(&self.cached_zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "GleSYS",
+                    ErrorKind::NotFound,
+                    format!("no domain of the project contains {}", domain).into(),
+                ));
+            };
+
+            let records = self.get_records(zone)?;
+
+            for ip in &result {
+                self.set_record(&records, zone, host, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/glesys.txt"));
+
+        let mut service = Service::new(
+            Config {
+                project: "CL12345".into(),
+                api_key: Secret::new("key".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and www.example.com has
+        // no AAAA record yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "POST https://api.glesys.com/domain/list",
+                "POST https://api.glesys.com/domain/listrecords",
+                "POST https://api.glesys.com/domain/updaterecord",
+                "POST https://api.glesys.com/domain/listrecords",
+                "POST https://api.glesys.com/domain/updaterecord",
+                "POST https://api.glesys.com/domain/addrecord",
+            ]
+        );
+
+        // The TTL of existing records is kept.
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "recordid": 2, "data": "2001:db8::1", "ttl": 600 })
+        );
+
+        let body = sent[5].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "domainname": "example.com",
+                "host": "www",
+                "type": "AAAA",
+                "data": "2001:db8::1",
+                "ttl": 3600,
+            })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Unauthorized: Invalid API key."
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The smallest TTL GoDaddy accepts, which is also its default.
+const MIN_TTL: u32 = 600;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// A production API key and its secret, created on the developer portal.
+    key: Box<str>,
+    secret: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// Raised to the minimum of 600 seconds if lower.
+    #[serde(default = "default_ttl")]
+    ttl: u32,
+}
+
+fn default_ttl() -> u32 {
+    MIN_TTL
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+        let auth = format!("sso-key {}:{}", config.key, config.secret.expose());
+        config.secret = Secret::new(auth.into());
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"code": "...", "message": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("message")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<Response, DdnsUpdateError> {
+        match response {
+            Ok(r) => Ok(r),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("GoDaddy", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    fn get_json(&self, url: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set("Authorization", self.config.secret.expose())
+            .call();
+
+        self.check_response(response)?
+            .into_json::<serde_json::Value>()
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))
+    }
+
+    /// See: https://developer.godaddy.com/doc/endpoint/domains#/v1/list
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let response =
+            self.get_json("https://api.godaddy.com/v1/domains?statuses=ACTIVE&limit=1000")?;
+
+        let Some(domains) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("expected a list of domains".into()));
+        };
+
+        let mut zones = Vec::with_capacity(domains.len());
+        for domain in domains {
+            let Some(name) = domain.get("domain").and_then(|d| d.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no name?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// GoDaddy replaces every record of the type and name with the ones sent,
+    /// so the records are looked up first, and left alone if they are already
+    /// right. This also keeps clear of its rate limit of 60 requests a minute.
+    ///
+    /// See: https://developer.godaddy.com/doc/endpoint/domains#/v1/recordReplaceTypeName
+    fn set_record(&self, zone: &str, name: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let url = format!(
+            "https://api.godaddy.com/v1/domains/{}/records/{}/{}",
+            zone, kind, name
+        );
+
+        let records = self.get_json(&url)?;
+        let ttl = self.config.ttl.max(MIN_TTL);
+
+        let is_up_to_date = records.as_array().is_some_and(|records| {
+            records.len() == 1
+                && records[0].get("data").and_then(|d| d.as_str()) == Some(&ip.to_string())
+                && records[0].get("ttl").and_then(|t| t.as_u64()) == Some(ttl as u64)
+        });
+
+        if is_up_to_date {
+            return Ok(());
+        }
+
+        let response = self
+            .client
+            .put(&url)
+            .set("Authorization", self.config.secret.expose())
+            .send_json(serde_json::json!([{ "data": ip.to_string(), "ttl": ttl }]));
+
+        self.check_response(response)?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&self.cached_zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "GoDaddy",
+                    ErrorKind::NotFound,
+                    format!("no active domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            for ip in &result {
+                self.set_record(zone, name, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/godaddy.txt"));
+
+        let mut service = Service::new(
+            Config {
+                key: "key".into(),
+                secret: Secret::new("secret".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and www.example.com has
+        // no AAAA record yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.godaddy.com/v1/domains?statuses=ACTIVE&limit=1000",
+                "GET https://api.godaddy.com/v1/domains/example.com/records/A/@",
+                "GET https://api.godaddy.com/v1/domains/example.com/records/AAAA/@",
+                "PUT https://api.godaddy.com/v1/domains/example.com/records/AAAA/@",
+                "GET https://api.godaddy.com/v1/domains/example.com/records/A/www",
+                "PUT https://api.godaddy.com/v1/domains/example.com/records/A/www",
+                "GET https://api.godaddy.com/v1/domains/example.com/records/AAAA/www",
+                "PUT https://api.godaddy.com/v1/domains/example.com/records/AAAA/www",
+            ]
+        );
+
+        // The TTL is raised to the minimum.
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{ "data": "2001:db8::1", "ttl": 600 }])
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider { message: m, .. })) if m.starts_with("Unauthorized")
+        ));
+    }
+}
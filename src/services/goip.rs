@@ -0,0 +1,254 @@
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
+
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, Suspension, SuspensionState};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://www.goip.de/setip";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    username: Box<str>,
+    password: Secret<Box<str>>,
+
+    /// The subdomains, e.g. "home.goip.de".
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service {
+    config: Config,
+    client: Client,
+    suspension: Option<SuspensionState>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            suspension: None,
+        }
+    }
+}
+
+/// What goip.de made of an update.
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+    Updated,
+    Unchanged,
+
+    /// The credentials or the subdomain were refused, which will not change
+    /// by retrying.
+    Refused(ErrorKind, &'static str),
+    Unknown,
+}
+
+/// goip.de answers with a line of plain text, e.g. "home.goip.de
+/// (192.0.2.1) updated successfully", in German or in English.
+fn parse_answer(text: &str) -> Answer {
+    let text = text.trim().to_lowercase();
+
+    if text.contains("unchanged") || text.contains("unverändert") || text.starts_with("nochg") {
+        Answer::Unchanged
+    } else if text.contains("success")
+        || text.contains("erfolgreich")
+        || text.contains("updated")
+        || text.contains("aktualisiert")
+        || text.starts_with("good")
+    {
+        Answer::Updated
+    } else if text.contains("zugriff verweigert")
+        || text.contains("access denied")
+        || text.contains("password")
+        || text.contains("passwort")
+        || text.starts_with("badauth")
+    {
+        Answer::Refused(ErrorKind::Auth, "Bad authentication details were provided")
+    } else if text.contains("subdomain") || text.starts_with("nohost") {
+        Answer::Refused(
+            ErrorKind::NotFound,
+            "The subdomain does not belong to the account",
+        )
+    } else {
+        Answer::Unknown
+    }
+}
+
+impl Service {
+    /// Stops sending updates after the error, which is returned.
+    fn suspend(&mut self, kind: ErrorKind, message: Box<str>) -> DdnsUpdateError {
+        let error = DdnsUpdateError::provider("goip.de", kind, message);
+
+        self.suspension = Some(SuspensionState {
+            suspension: Suspension::Indefinite,
+            reason: error.to_string().into(),
+            since: SystemTime::now(),
+        });
+
+        error
+    }
+
+    /// Updates a single subdomain, and returns whether it was changed.
+    fn send(
+        &mut self,
+        domain: &str,
+        ipv4: Option<&IpAddr>,
+        ipv6: Option<&IpAddr>,
+    ) -> Result<bool, DdnsUpdateError> {
+        let mut request = self
+            .client
+            .get(ENDPOINT)
+            .query("username", &self.config.username)
+            .query("password", self.config.password.expose())
+            .query("subdomain", domain);
+
+        if let Some(ipv4) = ipv4 {
+            request = request.query("ip", &ipv4.to_string());
+        }
+
+        if let Some(ipv6) = ipv6 {
+            request = request.query("ip6", &ipv6.to_string());
+        }
+
+        match request.call() {
+            Ok(resp) | Err(Error::Status(_, resp)) => {
+                let resp = resp.into_string().map_err(|e| {
+                    DdnsUpdateError::provider("goip.de", ErrorKind::Other, e.to_string().into())
+                })?;
+
+                match parse_answer(&resp) {
+                    Answer::Updated => Ok(true),
+                    Answer::Unchanged => Ok(false),
+
+                    // Retrying would only get the account blocked, so nothing
+                    // is sent anymore until the config is fixed.
+                    Answer::Refused(kind, message) => Err(self.suspend(kind, message.into())),
+
+                    Answer::Unknown => Err(DdnsUpdateError::provider(
+                        "goip.de",
+                        ErrorKind::Other,
+                        format!("unexpected response \"{}\"", resp.trim()).into(),
+                    )),
+                }
+            }
+
+            Err(Error::Transport(t)) => Err(DdnsUpdateError::TransportError(t.to_string().into())),
+        }
+    }
+}
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.suspension
+            .as_ref()
+            .filter(|state| state.is_in_effect())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if let Some(state) = self.suspension() {
+            return Err(DdnsUpdateError::Suspended(state.suspension.clone()));
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        // goip.de takes a single subdomain per request.
+        let mut changed = false;
+        for domain in self.config.domains.clone().iter() {
+            changed |= self.send(domain, ipv4, ipv6)?;
+        }
+
+        let mut result = FixedVec::new();
+        if changed {
+            result.extend(ipv4.copied());
+            result.extend(ipv6.copied());
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn answers() {
+        assert_eq!(
+            parse_answer("home.goip.de (192.0.2.1) updated successfully\n"),
+            Answer::Updated
+        );
+        assert_eq!(
+            parse_answer("home.goip.de (192.0.2.1) wurde erfolgreich aktualisiert"),
+            Answer::Updated
+        );
+        assert_eq!(
+            parse_answer("home.goip.de (192.0.2.1) bleibt unverändert"),
+            Answer::Unchanged
+        );
+        assert!(matches!(
+            parse_answer("Zugriff verweigert: Benutzername oder Passwort falsch"),
+            Answer::Refused(ErrorKind::Auth, _)
+        ));
+        assert_eq!(
+            parse_answer("<html>Wartungsarbeiten</html>"),
+            Answer::Unknown
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/goip.txt"));
+
+        let mut service = Service::new(
+            Config {
+                username: "user".into(),
+                password: Secret::new("pass".into()),
+                domains: vec!["home.goip.de".into(), "nas.goip.de".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // A refused password suspends the service for good.
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                ..
+            })
+        ));
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Indefinite))
+        ));
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://www.goip.de/setip?username=user&password=pass&subdomain=home.goip.de&ip=192.0.2.1&ip6=2001%3Adb8%3A%3A1",
+                "https://www.goip.de/setip?username=user&password=pass&subdomain=nas.goip.de&ip=192.0.2.1&ip6=2001%3Adb8%3A%3A1",
+                "https://www.goip.de/setip?username=user&password=pass&subdomain=home.goip.de&ip=192.0.2.1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::Settings;
+use crate::util::{FixedVec, Secret};
+
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, RecordType, SuspensionState};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The DDNS key of each domain, as generated on dns.he.net for the records
+    /// with "Enable entry for dynamic dns" ticked. The A and AAAA records of a
+    /// domain must share the same key.
+    keys: BTreeMap<Box<str>, Secret<Box<str>>>,
+}
+
+pub struct Service {
+    /// Every domain has its own key, so there is one client for each of them.
+    inner: Vec<shared_dyndns::Service>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let inner = config
+            .keys
+            .into_iter()
+            .map(|(domain, key)| {
+                // The domain doubles as the username.
+                let config =
+                    shared_dyndns::Config::new(Secret::new(domain.clone()), key, vec![domain]);
+
+                shared_dyndns::Service::from_config(
+                    "Hurricane Electric",
+                    "https://dyn.dns.he.net/nic/update",
+                    config,
+                    http,
+                )
+            })
+            .collect();
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    /// One suspended client holds back the others.
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.iter().find_map(|inner| inner.suspension())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        let mut result = FixedVec::new();
+
+        for inner in &mut self.inner {
+            // Only one address is accepted per request.
+            for ip in [ipv4, ipv6].into_iter().flatten() {
+                let updated = inner.update_record(&[*ip])?;
+
+                for ip in updated {
+                    if !result.as_slice().contains(&ip) {
+                        result.push(ip);
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent, Settings};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/henet.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            [keys]
+            "a.example.com" = "key-a"
+            "b.example.com" = "key-b"
+            "#,
+        )
+        .unwrap();
+
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://dyn.dns.he.net/nic/update?hostname=a.example.com&myip=192.0.2.1",
+                "https://dyn.dns.he.net/nic/update?hostname=a.example.com&myip=2001%3Adb8%3A%3A1",
+                "https://dyn.dns.he.net/nic/update?hostname=b.example.com&myip=192.0.2.1",
+                "https://dyn.dns.he.net/nic/update?hostname=b.example.com&myip=2001%3Adb8%3A%3A1",
+            ]
+        );
+
+        // The key of b.example.com is wrong.
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { provider: "Hurricane Electric", message: m, .. }) if &*m == "Bad authentication details were provided"
+        ));
+    }
+}
@@ -0,0 +1,580 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType, StaticRecords, ZoneName,
+};
+
+pub const RECORDS: &[RecordType] = &[
+    RecordType::A,
+    RecordType::Aaaa,
+    RecordType::Srv,
+    RecordType::Caa,
+];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    ttl: u32,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_records: Vec<Record>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    id: Box<str>,
+    name: Box<str>,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug)]
+struct Record {
+    id: Box<str>,
+    zone_id: Box<str>,
+
+    /// The name relative to the zone, e.g. "www" or "@", as it has to be sent
+    /// back to Hetzner.
+    name: Box<str>,
+
+    /// The fully-qualified name, e.g. "www.example.com".
+    fqdn: Box<str>,
+
+    kind: RecordKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordKind {
+    A,
+    Aaaa,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_records: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        // Depending on the endpoint, the message is found either at the top
+        // level or inside of an "error" object.
+        let message = resp_json
+            .get("error")
+            .and_then(|error| error.get("message"))
+            .or_else(|| resp_json.get("message"))
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Hetzner", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// See: https://dns.hetzner.com/api-docs#operation/GetZones
+    fn get_zones(&self) -> Result<Vec<Zone>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://dns.hetzner.com/api/v1/zones")
+            .set("Auth-API-Token", self.config.token.expose())
+            .call();
+
+        let response = self.parse_and_check_response(response)?;
+
+        let results = response.get("zones").and_then(|v| v.as_array());
+        let Some(zones) = results else {
+            return Err(DdnsUpdateError::Json("hetzner returned 0 zones".into()));
+        };
+
+        let mut returned_zones = Vec::with_capacity(zones.len());
+
+        for zone in zones {
+            let Some(id) = zone.get("id").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("zone has no id?".into()));
+            };
+
+            let Some(name) = zone.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("zone has no name?".into()));
+            };
+
+            returned_zones.push(Zone {
+                id: id.into(),
+                name: name.into(),
+            });
+        }
+
+        Ok(returned_zones)
+    }
+
+    /// See: https://dns.hetzner.com/api-docs#operation/GetRecords
+    fn list_records(&self, zone: &Zone) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://dns.hetzner.com/api/v1/records")
+            .query("zone_id", &zone.id)
+            .set("Auth-API-Token", self.config.token.expose())
+            .call();
+
+        let mut response = self.parse_and_check_response(response)?;
+
+        match response.get_mut("records").map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(records)) => Ok(records),
+            _ => Err(DdnsUpdateError::Json("hetzner returned 0 records".into())),
+        }
+    }
+
+    fn get_records(&self, zone: &Zone) -> Result<Vec<Record>, DdnsUpdateError> {
+        let records = self.list_records(zone)?;
+
+        let mut returned_records = Vec::new();
+        for record in records {
+            let Some(id) = record.get("id").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no id?".into()));
+            };
+
+            let Some(name) = record.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no name?".into()));
+            };
+
+            let Some(ty) = record.get("type").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no type?".into()));
+            };
+
+            let kind = match ty {
+                "A" => RecordKind::A,
+                "AAAA" => RecordKind::Aaaa,
+                _ => continue,
+            };
+
+            // The zone apex is named "@".
+            let fqdn: Box<str> = if name == "@" {
+                zone.name.clone()
+            } else {
+                format!("{}.{}", name, zone.name).into()
+            };
+
+            returned_records.push(Record {
+                id: id.into(),
+                zone_id: zone.id.clone(),
+                name: name.into(),
+                fqdn,
+                kind,
+            });
+        }
+
+        Ok(returned_records)
+    }
+
+    /// See: https://dns.hetzner.com/api-docs#operation/UpdateRecord
+    fn put_record(&self, record: &Record, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let url = format!("https://dns.hetzner.com/api/v1/records/{}", record.id);
+
+        let response = self
+            .client
+            .put(&url)
+            .set("Auth-API-Token", self.config.token.expose())
+            .send_json(serde_json::json!({
+                "value": ip.to_string(),
+                "ttl": self.config.ttl,
+                "type": if ip.is_ipv4() { "A" } else { "AAAA" },
+                "name": record.name.as_ref(),
+                "zone_id": record.zone_id.as_ref(),
+            }));
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Creates a record, or replaces the one with the given ID.
+    ///
+    /// See: https://dns.hetzner.com/api-docs#operation/CreateRecord
+    fn send_record(
+        &self,
+        id: Option<&str>,
+        zone: &Zone,
+        name: &str,
+        kind: &str,
+        value: &str,
+    ) -> Result<(), DdnsUpdateError> {
+        let request = match id {
+            Some(id) => self
+                .client
+                .put(&format!("https://dns.hetzner.com/api/v1/records/{}", id)),
+            None => self.client.post("https://dns.hetzner.com/api/v1/records"),
+        };
+
+        let response = request
+            .set("Auth-API-Token", self.config.token.expose())
+            .send_json(serde_json::json!({
+                "value": value,
+                "ttl": self.config.ttl,
+                "type": kind,
+                "name": name,
+                "zone_id": zone.id.as_ref(),
+            }));
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// A SRV record replaces the one of the same name, while a CAA record is
+    /// only ever added, as a name may have several of them (e.g. one for each
+    /// certificate authority).
+    fn put_static_records(&self, records: &StaticRecords) -> Result<u32, DdnsUpdateError> {
+        let zones = self.get_zones()?;
+
+        let wanted = records
+            .srv
+            .iter()
+            .map(|srv| (&srv.name, "SRV", srv.to_string()))
+            .chain(
+                records
+                    .caa
+                    .iter()
+                    .map(|caa| (&caa.name, "CAA", caa.to_string())),
+            );
+
+        // The records of each zone are only listed once.
+        let mut listed: Vec<(&str, Vec<serde_json::Value>)> = Vec::new();
+        let mut changed = 0;
+
+        for (fqdn, kind, value) in wanted {
+            let Some((zone, name)) = find_zone(&zones, fqdn, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Hetzner",
+                    ErrorKind::NotFound,
+                    format!("no zone of the account contains {}", fqdn).into(),
+                ));
+            };
+
+            if !listed.iter().any(|(id, _)| *id == &*zone.id) {
+                listed.push((&zone.id, self.list_records(zone)?));
+            }
+
+            let existing = listed
+                .iter()
+                .find(|(id, _)| *id == &*zone.id)
+                .map(|(_, records)| records.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .filter(|record| record["name"] == name && record["type"] == kind);
+
+            if existing.clone().any(|record| record["value"] == *value) {
+                continue;
+            }
+
+            let id = match kind {
+                "SRV" => existing
+                    .map(|record| record["id"].as_str())
+                    .next()
+                    .flatten(),
+                _ => None,
+            };
+
+            self.send_record(id, zone, name, kind, &value)?;
+            changed += 1;
+        }
+
+        Ok(changed)
+    }
+
+    /// Looks up the records of the configured domains and caches them, so that
+    /// they are only looked up once.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                if self.config.domains.contains(&record.fqdn) {
+                    self.cached_records.push(record)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                let kind = match record.kind {
+                    RecordKind::A => RecordType::A,
+                    RecordKind::Aaaa => RecordType::Aaaa,
+                };
+
+                found.push(DiscoveredRecord {
+                    name: record.fqdn,
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_records.is_empty() {
+            self.cache_records()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        for record in &self.cached_records {
+            match (&record.kind, ipv4, ipv6) {
+                (RecordKind::A, Some(ipv4), _) => self.put_record(record, *ipv4)?,
+                (RecordKind::Aaaa, _, Some(ipv6)) => self.put_record(record, *ipv6)?,
+                _ => (),
+            }
+        }
+
+        let mut result = FixedVec::new();
+        if let Some(ipv4) = ipv4 {
+            result.push(*ipv4);
+        }
+        if let Some(ipv6) = ipv6 {
+            result.push(*ipv6);
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records
+                    .iter()
+                    .any(|record| &*record.fqdn == domain)
+            })
+        });
+
+        Some(result)
+    }
+
+    fn set_static_records(
+        &mut self,
+        records: &StaticRecords,
+    ) -> Option<Result<u32, DdnsUpdateError>> {
+        Some(self.put_static_records(records))
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+    use crate::services::{CaaRecord, SrvRecord};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/hetzner.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "invalid AAAA record"
+        ));
+
+        // The records are cached, only the PUTs are sent this time.
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://dns.hetzner.com/api/v1/zones",
+                "https://dns.hetzner.com/api/v1/records?zone_id=zone1",
+                "https://dns.hetzner.com/api/v1/records?zone_id=zone2",
+                "https://dns.hetzner.com/api/v1/records/rec1",
+                "https://dns.hetzner.com/api/v1/records/rec2",
+                "https://dns.hetzner.com/api/v1/records/rec1",
+                "https://dns.hetzner.com/api/v1/records/rec3",
+            ]
+        );
+
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["value"], "192.0.2.1");
+        assert_eq!(body["name"], "@");
+        assert_eq!(body["zone_id"], "zone1");
+    }
+
+    #[test]
+    fn credentials() {
+        add_fixtures(include_str!("fixtures/hetzner.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["www.example.com".into(), "missing.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let description = service.test_credentials().unwrap().unwrap();
+        assert_eq!(
+            description,
+            "1 of 2 domain(s) found, missing: missing.example.com"
+        );
+
+        // Only the zones and records are looked up, nothing is modified.
+        let sent = take_sent();
+        assert!(sent.iter().all(|s| &*s.method == "GET"));
+    }
+
+    #[test]
+    fn discover() {
+        add_fixtures(include_str!("fixtures/hetzner.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["www.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        // Every A and AAAA record is listed, not only the configured ones.
+        let records = service.discover_records().unwrap().unwrap();
+        let records = records
+            .iter()
+            .map(|record| (&*record.name, record.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            [
+                ("example.com", RecordType::A),
+                ("example.com", RecordType::Aaaa),
+                ("www.example.com", RecordType::A),
+                ("mail.example.com", RecordType::A),
+                ("www.example.net", RecordType::A),
+            ]
+        );
+    }
+
+    #[test]
+    fn static_records() {
+        add_fixtures(include_str!("fixtures/hetzner.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["www.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let caa = |value: &str| CaaRecord {
+            name: "example.com".into(),
+            flags: 0,
+            tag: "issue".into(),
+            value: value.into(),
+        };
+
+        let records = StaticRecords {
+            srv: vec![SrvRecord {
+                name: "_minecraft._tcp.example.com".into(),
+                priority: 0,
+                weight: 5,
+                port: 25565,
+                target: "www.example.com".into(),
+            }],
+            caa: vec![caa("letsencrypt.org"), caa("sectigo.com")],
+        };
+
+        // The CAA record of Let's Encrypt exists already.
+        let changed = service.set_static_records(&records).unwrap().unwrap();
+        assert_eq!(changed, 2);
+
+        let sent = take_sent();
+        let methods = sent.iter().map(|s| &*s.method).collect::<Vec<_>>();
+        assert_eq!(methods, ["GET", "GET", "PUT", "POST"]);
+        assert_eq!(&*sent[2].url, "https://dns.hetzner.com/api/v1/records/rec7");
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["name"], "_minecraft._tcp");
+        assert_eq!(body["value"], "0 5 25565 www.example.com.");
+
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["name"], "@");
+        assert_eq!(body["type"], "CAA");
+        assert_eq!(body["value"], "0 issue \"sectigo.com\"");
+    }
+}
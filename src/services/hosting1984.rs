@@ -0,0 +1,441 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, CookieJar, Error, Response, Settings};
+use crate::util::{url_encode, FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType, ZoneName,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://1984.hosting";
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 3600;
+
+/// The cookie carrying the CSRF token, which is sent back along with each
+/// form.
+const CSRF_COOKIE: &str = "csrftoken";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The credentials used to sign in on the website, as 1984 Hosting has no
+    /// API for its DNS.
+    email: Box<str>,
+    password: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The TTL of the records, which is otherwise left as it is.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+/// A zone as found on the page listing the domains of the account.
+#[derive(Debug)]
+struct Zone {
+    id: Box<str>,
+    name: Box<str>,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Finds the zones linked from the page listing the domains, each of which
+/// looks like `<a href="/domains/zone/123/">example.com</a>`.
+fn parse_zones(html: &str) -> Vec<Zone> {
+    let mut zones = Vec::new();
+
+    for link in html.split("href=\"/domains/zone/").skip(1) {
+        let Some((id, rest)) = link.split_once('/') else {
+            continue;
+        };
+
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+
+        let name = rest
+            .split_once('>')
+            .and_then(|(_, text)| text.split_once('<'))
+            .map(|(name, _)| name.trim())
+            .unwrap_or("");
+
+        if !name.is_empty() && !zones.iter().any(|zone: &Zone| zone.id.as_ref() == id) {
+            zones.push(Zone {
+                id: id.into(),
+                name: name.into(),
+            });
+        }
+    }
+
+    zones
+}
+
+/// Encodes the fields as the body of a form.
+fn form(fields: &[(&str, &str)]) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, url_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+impl Service {
+    /// Takes the cookies set by the response and returns its body. A session
+    /// which has gone bad is mostly redirected to the login page, which is
+    /// not JSON, so the body is checked by the callers.
+    fn check_response(
+        &self,
+        cookies: &mut CookieJar,
+        response: Result<Response, Error>,
+    ) -> Result<String, DdnsUpdateError> {
+        let (kind, response) = match response {
+            Ok(resp) => (None, resp),
+            Err(Error::Status(status, resp)) => {
+                (Some(ErrorKind::from_response(status, &resp)), resp)
+            }
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        };
+
+        cookies.store(&response);
+
+        let body = response.into_string().map_err(|e| {
+            DdnsUpdateError::provider("1984 Hosting", ErrorKind::Other, e.to_string().into())
+        })?;
+
+        match kind {
+            None => Ok(body),
+            Some(kind) => Err(DdnsUpdateError::provider(
+                "1984 Hosting",
+                kind,
+                body.trim().into(),
+            )),
+        }
+    }
+
+    /// Sends a form, as the website itself would. The CSRF token is taken from
+    /// its cookie, and Django also insists on a Referer of the same site.
+    fn post_form(
+        &self,
+        cookies: &mut CookieJar,
+        path: &str,
+        fields: &[(&str, &str)],
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let csrf_token = cookies.get(CSRF_COOKIE).unwrap_or("").to_owned();
+
+        let response = self
+            .client
+            .post(&format!("{}{}", ENDPOINT, path))
+            .set("Cookie", &cookies.header())
+            .set("Referer", &format!("{}/", ENDPOINT))
+            .set("X-CSRFToken", &csrf_token)
+            .set("X-Requested-With", "XMLHttpRequest")
+            .set("Content-Type", "application/x-www-form-urlencoded")
+            .send_string(&form(fields));
+
+        let body = self.check_response(cookies, response)?;
+
+        serde_json::from_str(&body).map_err(|e| DdnsUpdateError::Json(e.to_string().into()))
+    }
+
+    fn get(&self, cookies: &mut CookieJar, path: &str) -> Result<String, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(&format!("{}{}", ENDPOINT, path))
+            .set("Cookie", &cookies.header())
+            .call();
+
+        self.check_response(cookies, response)
+    }
+
+    /// Signs in and returns the cookies of the session. The login page sets
+    /// the CSRF token, and the login itself the session.
+    fn login(&self) -> Result<CookieJar, DdnsUpdateError> {
+        let mut cookies = CookieJar::new();
+        self.get(&mut cookies, "/accounts/login/")?;
+
+        let response = self.post_form(
+            &mut cookies,
+            "/accounts/checkuserauth/",
+            &[
+                ("email", &self.config.email),
+                ("password", self.config.password.expose()),
+                ("otpkey", ""),
+            ],
+        )?;
+
+        if response.get("auth").and_then(|a| a.as_bool()) != Some(true) {
+            let message = response
+                .get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("the email or password is wrong");
+
+            return Err(DdnsUpdateError::provider(
+                "1984 Hosting",
+                ErrorKind::Auth,
+                message.into(),
+            ));
+        }
+
+        Ok(cookies)
+    }
+
+    fn get_zones(&self, cookies: &mut CookieJar) -> Result<Vec<Zone>, DdnsUpdateError> {
+        let html = self.get(cookies, "/domains/")?;
+        Ok(parse_zones(&html))
+    }
+
+    /// Lists the records of the zone, e.g. {"records": [{"id": 1, "host":
+    /// "www", "type": "A", "ttl": 3600, "rdata": "192.0.2.1"}]}.
+    fn get_records(
+        &self,
+        cookies: &mut CookieJar,
+        zone: &Zone,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let body = self.get(cookies, &format!("/domains/entries/{}/", zone.id))?;
+
+        let mut response = serde_json::from_str::<serde_json::Value>(&body)
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+        match response.get_mut("records").map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(records)) => Ok(records),
+            _ => Err(DdnsUpdateError::Json("expected a list of records".into())),
+        }
+    }
+
+    /// Points the record of the given host and type to the address, editing
+    /// the existing record or adding a new entry.
+    fn set_record(
+        &self,
+        cookies: &mut CookieJar,
+        records: &[serde_json::Value],
+        zone: &Zone,
+        host: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let rdata = ip.to_string();
+
+        let existing = records.iter().find(|record| {
+            record.get("type").and_then(|t| t.as_str()) == Some(kind)
+                && record.get("host").and_then(|h| h.as_str()) == Some(host)
+        });
+
+        let current_ttl = existing.and_then(|r| r.get("ttl")).and_then(|t| t.as_u64());
+        let ttl = self
+            .config
+            .ttl
+            .or(current_ttl.map(|t| t as u32))
+            .unwrap_or(DEFAULT_TTL);
+
+        let entry = match existing {
+            Some(record) => {
+                if record.get("rdata").and_then(|r| r.as_str()) == Some(&rdata)
+                    && current_ttl == Some(ttl as u64)
+                {
+                    return Ok(());
+                }
+
+                match record.get("id") {
+                    Some(serde_json::Value::Number(id)) => id.to_string(),
+                    Some(serde_json::Value::String(id)) => id.clone(),
+                    _ => return Err(DdnsUpdateError::Json("record has no id?".into())),
+                }
+            }
+
+            None => String::from("new"),
+        };
+
+        let ttl = ttl.to_string();
+        let response = self.post_form(
+            cookies,
+            "/domains/entry/",
+            &[
+                ("entry", &entry),
+                ("zone", &zone.id),
+                ("host", host),
+                ("type", kind),
+                ("ttl", &ttl),
+                ("priority", ""),
+                ("rdata", &rdata),
+            ],
+        )?;
+
+        if response.get("ok").and_then(|o| o.as_bool()) != Some(true) {
+            let message = response
+                .get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("the record was not saved");
+
+            return Err(DdnsUpdateError::provider(
+                "1984 Hosting",
+                ErrorKind::Validation,
+                message.into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    /// A new session is started for each update, which are rare enough that
+    /// keeping one alive is not worth it.
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut cookies = self.login()?;
+        let zones = self.get_zones(&mut cookies)?;
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, host)) = find_zone(&zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "1984 Hosting",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            let records = self.get_records(&mut cookies, zone)?;
+
+            for ip in &result {
+                self.set_record(&mut cookies, &records, zone, host, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.login().and_then(|mut cookies| {
+            let zones = self.get_zones(&mut cookies)?;
+
+            Ok(describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&zones, domain, "@").is_some()
+            }))
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn zones() {
+        let html = r#"
+            <ul>
+              <li><a href="/domains/zone/123/">example.com</a></li>
+              <li><a href="/domains/zone/123/" class="edit">Edit</a></li>
+              <li><a href="/domains/zone/456/">
+                example.net
+              </a></li>
+              <li><a href="/domains/zone/new/">Add a zone</a></li>
+            </ul>
+        "#;
+
+        let zones = parse_zones(html);
+        let zones = zones
+            .iter()
+            .map(|zone| (&*zone.id, &*zone.name))
+            .collect::<Vec<_>>();
+        assert_eq!(zones, [("123", "example.com"), ("456", "example.net")]);
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/hosting1984.txt"));
+
+        let mut service = Service::new(
+            Config {
+                email: "user@example.com".into(),
+                password: Secret::new("pass".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and www.example.com has
+        // no AAAA record yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://1984.hosting/accounts/login/",
+                "POST https://1984.hosting/accounts/checkuserauth/",
+                "GET https://1984.hosting/domains/",
+                "GET https://1984.hosting/domains/entries/123/",
+                "POST https://1984.hosting/domains/entry/",
+                "GET https://1984.hosting/domains/entries/123/",
+                "POST https://1984.hosting/domains/entry/",
+                "POST https://1984.hosting/domains/entry/",
+            ]
+        );
+
+        assert_eq!(
+            sent[1].body.as_deref(),
+            Some("email=user%40example.com&password=pass&otpkey=")
+        );
+        assert_eq!(
+            sent[4].body.as_deref(),
+            Some("entry=11&zone=123&host=%40&type=AAAA&ttl=3600&priority=&rdata=2001%3Adb8%3A%3A1")
+        );
+        assert_eq!(
+            sent[7].body.as_deref(),
+            Some(
+                "entry=new&zone=123&host=www&type=AAAA&ttl=3600&priority=&rdata=2001%3Adb8%3A%3A1"
+            )
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "the email or password is wrong"
+        ));
+    }
+}
@@ -0,0 +1,461 @@
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::hmac;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, CookieJar, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType, ZoneName,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://www.hover.com";
+
+/// The cookie carrying the login, set once it is complete.
+const AUTH_COOKIE: &str = "hoverauth";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The credentials used to sign in on the website, as Hover has no API.
+    username: Box<str>,
+    password: Secret<Box<str>>,
+
+    /// The Base32 secret of the authenticator app set up for two-step
+    /// verification, i.e. the text shown next to its QR code. Needed if Hover
+    /// asks for a code when signing in.
+    #[serde(default)]
+    totp_secret: Option<Secret<Box<str>>>,
+
+    /// The domains must have existing A or AAAA records, which are updated.
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+
+    /// The cookies of the current login. Signing in may require a code from
+    /// the authenticator app, so the login is kept for as long as it works.
+    cookies: CookieJar,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cookies: CookieJar::new(),
+        }
+    }
+}
+
+/// Returns the time-based one-time password (RFC 6238) of the secret at the
+/// given time, with 6 digits, a step of 30 seconds and HMAC-SHA1.
+fn totp(secret: &[u8], time: u64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let hash = hmac::sign(&key, &(time / 30).to_be_bytes());
+    let hash = hash.as_ref();
+
+    // The dynamic truncation of RFC 4226.
+    let offset = (hash[19] & 0x0f) as usize;
+    let code = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    format!("{:06}", code % 1_000_000)
+}
+
+/// The domains are listed as {"domain_name": "example.com", ...}. One without
+/// a name matches nothing.
+impl ZoneName for serde_json::Value {
+    fn zone_name(&self) -> &str {
+        self.get("domain_name")
+            .and_then(|n| n.as_str())
+            .unwrap_or_default()
+    }
+}
+
+impl Service {
+    /// Checks whether the request succeeded and takes the cookies it set. The
+    /// responses are {"succeeded": false, "error": "..."} on failure, with
+    /// either a 200 or an error status.
+    fn parse_and_check_response(
+        &mut self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let (kind, response) = match response {
+            Ok(resp) => (None, resp),
+            Err(Error::Status(status, resp)) => {
+                (Some(ErrorKind::from_response(status, &resp)), resp)
+            }
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        };
+
+        self.cookies.store(&response);
+
+        let response = response.into_json::<serde_json::Value>();
+
+        let message = match (&response, kind) {
+            (Ok(response), None) if response.get("succeeded") == Some(&true.into()) => {
+                return Ok(response.clone())
+            }
+            (Ok(response), _) => response
+                .get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("(null)"),
+            (Err(e), None) => return Err(DdnsUpdateError::Json(e.to_string().into())),
+            (Err(_), Some(_)) => "(no message)",
+        };
+
+        Err(DdnsUpdateError::provider(
+            "Hover",
+            kind.unwrap_or(ErrorKind::Other),
+            message.into(),
+        ))
+    }
+
+    /// Signs in, which takes the password and then possibly a code from the
+    /// authenticator app, and keeps the cookies of the login.
+    fn login(&mut self) -> Result<(), DdnsUpdateError> {
+        self.cookies.clear();
+
+        // The sign-in page sets the cookie of the session being signed into.
+        let response = self.client.get(&format!("{}/signin", ENDPOINT)).call();
+        match response {
+            Ok(ref resp) | Err(Error::Status(_, ref resp)) => self.cookies.store(resp),
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+
+        let response = self
+            .client
+            .post(&format!("{}/signin/auth.json", ENDPOINT))
+            .set("Cookie", &self.cookies.header())
+            .send_json(serde_json::json!({
+                "username": self.config.username,
+                "password": self.config.password.expose(),
+                "token": null,
+            }));
+        let response = self.parse_and_check_response(response)?;
+
+        if response.get("status").and_then(|s| s.as_str()) == Some("need_2fa") {
+            let Some(secret) = &self.config.totp_secret else {
+                return Err(DdnsUpdateError::provider(
+                    "Hover",
+                    ErrorKind::Auth,
+                    "a two-step verification code is needed, but totp_secret is not set".into(),
+                ));
+            };
+
+            // Authenticator apps show the secret in groups of four.
+            let secret = secret.expose().replace(' ', "").to_uppercase();
+            let Ok(secret) =
+                data_encoding::BASE32_NOPAD.decode(secret.trim_end_matches('=').as_bytes())
+            else {
+                return Err(DdnsUpdateError::provider(
+                    "Hover",
+                    ErrorKind::Auth,
+                    "totp_secret is not valid Base32".into(),
+                ));
+            };
+
+            let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(duration) => duration.as_secs(),
+                Err(_) => 0,
+            };
+
+            let response = self
+                .client
+                .post(&format!("{}/signin/auth2.json", ENDPOINT))
+                .set("Cookie", &self.cookies.header())
+                .send_json(serde_json::json!({ "code": totp(&secret, time) }));
+            self.parse_and_check_response(response)?;
+        }
+
+        if self.cookies.get(AUTH_COOKIE).is_none() {
+            return Err(DdnsUpdateError::provider(
+                "Hover",
+                ErrorKind::Auth,
+                "signed in, but no login cookie was set".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Sends a request with the cookies of the login, signing in first if
+    /// there is none. A login which was refused, e.g. because it expired, is
+    /// replaced once.
+    fn call(
+        &mut self,
+        method: &str,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let was_cached = !self.cookies.is_empty();
+        if !was_cached {
+            self.login()?;
+        }
+
+        let response = match self.send(method, path, body) {
+            Err(Error::Status(401, _)) if was_cached => {
+                self.login()?;
+                self.send(method, path, body)
+            }
+            response => response,
+        };
+
+        let response = self.parse_and_check_response(response);
+
+        // Any failure might as well be a login gone bad.
+        if response.is_err() {
+            self.cookies.clear();
+        }
+
+        response
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<Response, Error> {
+        let url = format!("{}{}", ENDPOINT, path);
+        let cookies = self.cookies.header();
+
+        match (method, body) {
+            ("PUT", Some(body)) => self
+                .client
+                .put(&url)
+                .set("Cookie", &cookies)
+                .send_json(body),
+            _ => self.client.get(&url).set("Cookie", &cookies).call(),
+        }
+    }
+
+    /// Lists the domains of the account, each with its "entries" (records).
+    fn get_zones(&mut self) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut response = self.call("GET", "/api/dns", None)?;
+
+        match response.get_mut("domains").map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(zones)) => Ok(zones),
+            _ => Err(DdnsUpdateError::Json("expected a list of domains".into())),
+        }
+    }
+
+    /// Points every record of the given name and type to the address.
+    fn set_records(
+        &mut self,
+        zone: &serde_json::Value,
+        name: &str,
+        domain: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let content = ip.to_string();
+
+        let entries = zone
+            .get("entries")
+            .and_then(|e| e.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter(|entry| {
+                entry.get("type").and_then(|t| t.as_str()) == Some(kind)
+                    && entry.get("name").and_then(|n| n.as_str()) == Some(name)
+            })
+            .collect::<Vec<_>>();
+
+        if entries.is_empty() {
+            return Err(DdnsUpdateError::provider(
+                "Hover",
+                ErrorKind::NotFound,
+                format!("{} has no {} record to update", domain, kind).into(),
+            ));
+        }
+
+        for entry in entries {
+            if entry.get("content").and_then(|c| c.as_str()) == Some(&content) {
+                continue;
+            }
+
+            let Some(id) = entry.get("id").and_then(|i| i.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no id?".into()));
+            };
+
+            let body = serde_json::json!({ "content": content });
+            self.call("PUT", &format!("/api/dns/{}", id), Some(&body))?;
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&mut self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            let Some(zone_name) = zone.get("domain_name").and_then(|n| n.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no domain_name?".into()));
+            };
+
+            let entries = zone.get("entries").and_then(|e| e.as_array());
+
+            for entry in entries.into_iter().flatten() {
+                let kind = match entry.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match entry.get("name").and_then(|n| n.as_str()) {
+                    None | Some("@") => zone_name.into(),
+                    Some(name) => format!("{}.{}", name, zone_name).into(),
+                };
+
+                // A name has as many records of a type as it has addresses.
+                if !found
+                    .iter()
+                    .any(|r: &DiscoveredRecord| r.name == name && r.kind == kind)
+                {
+                    found.push(DiscoveredRecord { name, kind });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let zones = self.get_zones()?;
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in self.config.domains.clone() {
+            let Some((zone, name)) = find_zone(&zones, &domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Hover",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            for ip in &result {
+                self.set_records(zone, name, &domain, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn one_time_password() {
+        // The SHA-1 test vectors of RFC 6238, cut down to 6 digits.
+        let secret = b"12345678901234567890";
+
+        assert_eq!(totp(secret, 59), "287082");
+        assert_eq!(totp(secret, 1111111109), "081804");
+        assert_eq!(totp(secret, 2000000000), "279037");
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/hover.txt"));
+
+        let mut service = Service::new(
+            Config {
+                username: "user".into(),
+                password: Secret::new("pass".into()),
+                totp_secret: Some(Secret::new(
+                    "GEZD GNBV GY3T QOJQ GEZD GNBV GY3T QOJQ".into(),
+                )),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        // The A record of example.com is up to date.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://www.hover.com/signin",
+                "POST https://www.hover.com/signin/auth.json",
+                "POST https://www.hover.com/signin/auth2.json",
+                "GET https://www.hover.com/api/dns",
+                "PUT https://www.hover.com/api/dns/dns2",
+            ]
+        );
+
+        let body = sent[4].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body, serde_json::json!({ "content": "192.0.2.1" }));
+
+        // The login is kept, and www.example.com has no AAAA record.
+        let ipv6 = "2001:db8::1".parse().unwrap();
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { kind: ErrorKind::NotFound, message: m, .. })
+                if &*m == "example.com has no AAAA record to update"
+        ));
+        assert_eq!(take_sent().len(), 1);
+
+        // The login has expired, and signing in again fails.
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Invalid username or password"
+        ));
+    }
+}
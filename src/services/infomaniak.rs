@@ -0,0 +1,524 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{one_or_more_string, unscrubbed_optional_secret, FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, shared_dyndns, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType, SuspensionState, ZoneName,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 300;
+
+/// Infomaniak is updated either through its DynDNS2 endpoint, with the
+/// credentials of a dynamic DNS entry set up in the Manager, or through its
+/// REST API with an API token.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub enum Config {
+    DynDns(shared_dyndns::Config),
+    Api(ApiConfig),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApiConfig {
+    token: Secret<Box<str>>,
+    domains: Vec<Box<str>>,
+    ttl: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    #[serde(default, deserialize_with = "unscrubbed_optional_secret")]
+    username: Option<Secret<Box<str>>>,
+    password: Option<Secret<Box<str>>>,
+
+    /// An API token with the "domain" scope, created under Manager > API.
+    token: Option<Secret<Box<str>>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        match (raw.token, raw.username, raw.password) {
+            (Some(token), None, None) => Ok(Config::Api(ApiConfig {
+                token,
+                domains: raw.domains,
+                ttl: raw.ttl,
+            })),
+
+            (None, Some(_), Some(_)) if raw.ttl.is_some() => {
+                Err("ttl only applies when a token is given")
+            }
+
+            (None, Some(username), Some(password)) => Ok(Config::DynDns(
+                shared_dyndns::Config::new(username, password, raw.domains),
+            )),
+
+            _ => Err("either token, or username and password must be given"),
+        }
+    }
+}
+
+pub enum Service {
+    /// The DynDNS endpoint takes a single hostname per request, so there is
+    /// one client for each of them.
+    DynDns(Vec<shared_dyndns::Service>),
+    Api(ApiService),
+}
+
+pub struct ApiService {
+    config: ApiConfig,
+    client: Client,
+    cached_zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    id: u64,
+    name: Box<str>,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        match config {
+            Config::DynDns(config) => {
+                let inner = config
+                    .split_domains()
+                    .into_iter()
+                    .map(|config| {
+                        shared_dyndns::Service::from_config(
+                            "Infomaniak",
+                            "https://infomaniak.com/nic/update",
+                            config,
+                            http,
+                        )
+                    })
+                    .collect();
+
+                Service::DynDns(inner)
+            }
+
+            Config::Api(config) => Service::Api(ApiService {
+                config,
+                client: Client::new(http),
+                cached_zones: Vec::new(),
+            }),
+        }
+    }
+}
+
+impl ApiService {
+    /// Errors are reported as {"result": "error", "error": {"code": "...",
+    /// "description": "..."}}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let error = resp_json
+            .get("error")
+            .ok_or_else(|| String::from("expected error"))?;
+
+        let code = error.get("code").and_then(|c| c.as_str());
+        let description = error.get("description").and_then(|d| d.as_str());
+
+        match (code, description) {
+            (Some(code), Some(description)) => Ok(format!("{} ({})", description, code).into()),
+            (Some(message), None) | (None, Some(message)) => Ok(message.into()),
+            (None, None) => Err(String::from("expected string")),
+        }
+    }
+
+    /// Returns the "data" of a successful response.
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let mut response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Infomaniak", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        if response.get("result").and_then(|r| r.as_str()) != Some("success") {
+            return Err(DdnsUpdateError::Json("result is not success?".into()));
+        }
+
+        Ok(response
+            .get_mut("data")
+            .map(serde_json::Value::take)
+            .unwrap_or_default())
+    }
+
+    fn get(&self, url: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set(
+                "Authorization",
+                &format!("Bearer {}", self.config.token.expose()),
+            )
+            .call();
+
+        self.parse_and_check_response(response)
+    }
+
+    /// See: https://developer.infomaniak.com/docs/api/get/1/product
+    fn get_zones(&self) -> Result<Vec<Zone>, DdnsUpdateError> {
+        let response = self.get("https://api.infomaniak.com/1/product?service_name=domain")?;
+
+        let Some(listed) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("expected a list of domains".into()));
+        };
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let id = zone.get("id").and_then(|i| i.as_u64());
+            let name = zone.get("customer_name").and_then(|n| n.as_str());
+
+            let (Some(id), Some(name)) = (id, name) else {
+                return Err(DdnsUpdateError::Json("domain has no id or name?".into()));
+            };
+
+            zones.push(Zone {
+                id,
+                name: name.into(),
+            });
+        }
+
+        Ok(zones)
+    }
+
+    /// See: https://developer.infomaniak.com/docs/api/get/1/domain/%7Bdomain%7D/dns/record
+    fn get_records(&self, zone: &Zone) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let url = format!("https://api.infomaniak.com/1/domain/{}/dns/record", zone.id);
+
+        match self.get(&url)? {
+            serde_json::Value::Array(records) => Ok(records),
+            _ => Err(DdnsUpdateError::Json("expected a list of records".into())),
+        }
+    }
+
+    /// Points the record of the given name and type to the address, creating
+    /// the record if there is none. The records listed carry their
+    /// fully-qualified name in "source_idn".
+    fn set_record(
+        &self,
+        zone: &Zone,
+        records: &[serde_json::Value],
+        domain: &str,
+        name: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+
+        let existing = records.iter().find(|record| {
+            record.get("type").and_then(|t| t.as_str()) == Some(kind)
+                && record.get("source_idn").and_then(|s| s.as_str()) == Some(domain)
+        });
+
+        let url = format!("https://api.infomaniak.com/1/domain/{}/dns/record", zone.id);
+
+        let request = match existing {
+            Some(record) => {
+                let is_up_to_date = record.get("target").and_then(|t| t.as_str())
+                    == Some(&ip.to_string())
+                    && self.config.ttl.is_none_or(|ttl| {
+                        record.get("ttl").and_then(|t| t.as_u64()) == Some(ttl as u64)
+                    });
+
+                if is_up_to_date {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("id").and_then(|i| i.as_u64()) else {
+                    return Err(DdnsUpdateError::Json("record has no id?".into()));
+                };
+
+                self.client.put(&format!("{}/{}", url, id))
+            }
+
+            None => self.client.post(&url),
+        };
+
+        // The zone itself is addressed as ".".
+        let source = if name.is_empty() { "." } else { name };
+
+        let response = request
+            .set(
+                "Authorization",
+                &format!("Bearer {}", self.config.token.expose()),
+            )
+            .send_json(serde_json::json!({
+                "source": source,
+                "type": kind,
+                "target": ip.to_string(),
+                "ttl": self.config.ttl.unwrap_or(DEFAULT_TTL),
+            }));
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        // The records are listed once per zone, not once per domain.
+        let mut records: Vec<(u64, Vec<serde_json::Value>)> = Vec::new();
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&self.cached_zones, domain, "") else {
+                return Err(DdnsUpdateError::provider(
+                    "Infomaniak",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            let index = match records.iter().position(|(id, _)| *id == zone.id) {
+                Some(index) => index,
+                None => {
+                    records.push((zone.id, self.get_records(zone)?));
+                    records.len() - 1
+                }
+            };
+
+            for ip in &result {
+                self.set_record(zone, &records[index].1, domain, name, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Result<String, DdnsUpdateError> {
+        self.cached_zones = self.get_zones()?;
+
+        Ok(describe_found_domains(&self.config.domains, |domain| {
+            find_zone(&self.cached_zones, domain, "").is_some()
+        }))
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                let kind = match record.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let Some(name) = record.get("source_idn").and_then(|s| s.as_str()) else {
+                    return Err(DdnsUpdateError::Json("record has no source_idn?".into()));
+                };
+
+                found.push(DiscoveredRecord {
+                    name: name.into(),
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        match self {
+            Service::DynDns(inner) => inner.iter().find_map(|inner| inner.suspension()),
+            Service::Api(_) => None,
+        }
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        match self {
+            Service::DynDns(inner) => {
+                let mut result = FixedVec::new();
+
+                for inner in inner {
+                    // Only one address is accepted per request.
+                    let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+                    let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+                    for ip in [ipv4, ipv6].into_iter().flatten() {
+                        for ip in inner.update_record(&[*ip])? {
+                            if !result.as_slice().contains(&ip) {
+                                result.push(ip);
+                            }
+                        }
+                    }
+                }
+
+                Ok(result)
+            }
+
+            Service::Api(service) => service.update_record(ips),
+        }
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        match self {
+            Service::DynDns(_) => None,
+            Service::Api(service) => Some(service.test_credentials()),
+        }
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        match self {
+            Service::DynDns(_) => None,
+            Service::Api(service) => Some(service.discover()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "dyn.example.com"
+            "#,
+        );
+        assert!(matches!(config, Ok(Config::DynDns(_))));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            token = "token"
+            domains = "dyn.example.com"
+            ttl = 60
+            "#,
+        );
+        assert!(matches!(config, Ok(Config::Api(_))));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            token = "token"
+            username = "user"
+            password = "pass"
+            domains = "dyn.example.com"
+            "#,
+        );
+        assert!(config.is_err());
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "dyn.example.com"
+            ttl = 60
+            "#,
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/infomaniak.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            token = "token"
+            domains = ["example.com", "www.example.com"]
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and www.example.com has
+        // no AAAA record yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.infomaniak.com/1/product?service_name=domain",
+                "GET https://api.infomaniak.com/1/domain/1/dns/record",
+                "PUT https://api.infomaniak.com/1/domain/1/dns/record/11",
+                "PUT https://api.infomaniak.com/1/domain/1/dns/record/12",
+                "POST https://api.infomaniak.com/1/domain/1/dns/record",
+            ]
+        );
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["source"], ".");
+
+        let body = sent[4].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "source": "www",
+                "type": "AAAA",
+                "target": "2001:db8::1",
+                "ttl": 300,
+            })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Authorization required (not_authorized)"
+        ));
+    }
+}
@@ -0,0 +1,497 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, unscrubbed_secret, FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, shared_dyndns, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType, SuspensionState,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.domrobot.com/jsonrpc/";
+
+/// The TTL of newly created records if none is configured, which is also the
+/// smallest one INWX allows.
+const DEFAULT_TTL: u32 = 300;
+
+/// How many zones are listed per request.
+const ZONES_PER_PAGE: usize = 100;
+
+/// INWX is updated either through its DynDNS2 endpoint, with the credentials
+/// of a DynDNS account set up for the records, or through its JSON-RPC API,
+/// with the credentials of the INWX account itself.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub enum Config {
+    DynDns(shared_dyndns::Config),
+    Api(ApiConfig),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApiConfig {
+    username: Secret<Box<str>>,
+    password: Secret<Box<str>>,
+    domains: Vec<Box<str>>,
+    ttl: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    #[default]
+    DynDns,
+    Api,
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    #[serde(default)]
+    mode: Mode,
+
+    #[serde(deserialize_with = "unscrubbed_secret")]
+    username: Secret<Box<str>>,
+    password: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        match raw.mode {
+            Mode::DynDns if raw.ttl.is_some() => Err("ttl only applies to mode = \"api\""),
+
+            Mode::DynDns => Ok(Config::DynDns(shared_dyndns::Config::new(
+                raw.username,
+                raw.password,
+                raw.domains,
+            ))),
+
+            Mode::Api => Ok(Config::Api(ApiConfig {
+                username: raw.username,
+                password: raw.password,
+                domains: raw.domains,
+                ttl: raw.ttl,
+            })),
+        }
+    }
+}
+
+pub enum Service {
+    DynDns(shared_dyndns::Service),
+    Api(ApiService),
+}
+
+pub struct ApiService {
+    config: ApiConfig,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        match config {
+            Config::DynDns(config) => Service::DynDns(shared_dyndns::Service::from_config(
+                "INWX",
+                "https://dyndns.inwx.com/nic/update",
+                config,
+                http,
+            )),
+
+            Config::Api(config) => Service::Api(ApiService {
+                config,
+                client: Client::new(http),
+                cached_zones: Vec::new(),
+            }),
+        }
+    }
+}
+
+impl ApiService {
+    /// Calls a method of the API and returns its "resData". The session is
+    /// kept in a cookie, which is None only when logging in. Failures are
+    /// reported with a code of 2000 or above, mostly with a status of 200.
+    ///
+    /// See: https://www.inwx.com/en/help/apidoc
+    fn call(
+        &self,
+        cookie: Option<&str>,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<(serde_json::Value, Option<Box<str>>), DdnsUpdateError> {
+        let mut request = self.client.post(ENDPOINT);
+
+        if let Some(cookie) = cookie {
+            request = request.set("Cookie", cookie);
+        }
+
+        let response = request.send_json(serde_json::json!({
+            "method": method,
+            "params": params,
+        }));
+
+        let response = match response {
+            Ok(resp) | Err(Error::Status(_, resp)) => resp,
+
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        };
+
+        // e.g. "domrobot=abc; path=/; HttpOnly", only the first part of which
+        // is sent back.
+        let session = response
+            .header("Set-Cookie")
+            .and_then(|cookie| cookie.split(';').next())
+            .map(Box::from);
+
+        let mut response = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+        let code = response.get("code").and_then(|c| c.as_u64()).unwrap_or(0);
+
+        if !(1000..2000).contains(&code) {
+            let message = response
+                .get("msg")
+                .and_then(|m| m.as_str())
+                .unwrap_or("(null)");
+
+            let message = match response.get("reason").and_then(|r| r.as_str()) {
+                Some(reason) => format!("{} ({})", message, reason),
+                None => message.into(),
+            };
+
+            // See: https://www.inwx.com/en/help/apidoc/f/ch04.html
+            let kind = match code {
+                2200..=2202 => ErrorKind::Auth,
+                2303 => ErrorKind::NotFound,
+                2002..=2005 | 2306 => ErrorKind::Validation,
+                2502 => ErrorKind::RateLimited(None),
+                _ => ErrorKind::Other,
+            };
+
+            return Err(DdnsUpdateError::provider("INWX", kind, message.into()));
+        }
+
+        let data = response
+            .get_mut("resData")
+            .map(serde_json::Value::take)
+            .unwrap_or_default();
+
+        Ok((data, session))
+    }
+
+    /// Logs in, hands the session cookie to `f` and logs out again afterwards,
+    /// whether `f` succeeds or not.
+    fn with_session<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self, &str) -> Result<T, DdnsUpdateError>,
+    ) -> Result<T, DdnsUpdateError> {
+        let (_, session) = self.call(
+            None,
+            "account.login",
+            serde_json::json!({
+                "user": self.config.username.expose(),
+                "pass": self.config.password.expose(),
+            }),
+        )?;
+
+        let Some(session) = session else {
+            return Err(DdnsUpdateError::provider(
+                "INWX",
+                ErrorKind::Other,
+                "no session cookie was set".into(),
+            ));
+        };
+
+        let result = f(self, &session);
+        let logout = self.call(Some(&session), "account.logout", serde_json::json!({}));
+
+        // The session expires on its own anyway, so a failed logout is only
+        // worth reporting if nothing else went wrong.
+        let value = result?;
+        logout?;
+
+        Ok(value)
+    }
+
+    fn get_zones(&self, session: &str) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let mut zones = Vec::new();
+
+        for page in 1.. {
+            let (data, _) = self.call(
+                Some(session),
+                "nameserver.list",
+                serde_json::json!({ "page": page, "pagelimit": ZONES_PER_PAGE }),
+            )?;
+
+            let Some(listed) = data.get("domains").and_then(|d| d.as_array()) else {
+                return Err(DdnsUpdateError::Json("expected a list of zones".into()));
+            };
+
+            for zone in listed {
+                let Some(name) = zone.get("domain").and_then(|d| d.as_str()) else {
+                    return Err(DdnsUpdateError::Json("zone has no domain".into()));
+                };
+
+                zones.push(name.into());
+            }
+
+            if listed.len() < ZONES_PER_PAGE {
+                break;
+            }
+        }
+
+        Ok(zones)
+    }
+
+    /// Points the record of the given name and type to the address, creating
+    /// the record if there is none. Unlike the names given when creating
+    /// them, the names of the records listed are fully-qualified.
+    fn set_record(
+        &self,
+        session: &str,
+        zone: &str,
+        name: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let fqdn = if name.is_empty() {
+            zone.to_owned()
+        } else {
+            format!("{}.{}", name, zone)
+        };
+
+        let (data, _) = self.call(
+            Some(session),
+            "nameserver.info",
+            serde_json::json!({ "domain": zone, "name": fqdn, "type": kind }),
+        )?;
+
+        let existing = data
+            .get("record")
+            .and_then(|r| r.as_array())
+            .and_then(|records| records.first());
+
+        match existing {
+            Some(record) => {
+                if record.get("content").and_then(|c| c.as_str()) == Some(&ip.to_string()) {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("id") else {
+                    return Err(DdnsUpdateError::Json("record has no id".into()));
+                };
+
+                let mut params = serde_json::json!({ "id": id, "content": ip.to_string() });
+                if let Some(ttl) = self.config.ttl {
+                    params["ttl"] = ttl.into();
+                }
+
+                self.call(Some(session), "nameserver.updateRecord", params)?;
+            }
+
+            None => {
+                self.call(
+                    Some(session),
+                    "nameserver.createRecord",
+                    serde_json::json!({
+                        "domain": zone,
+                        "type": kind,
+                        "name": name,
+                        "content": ip.to_string(),
+                        "ttl": self.config.ttl.unwrap_or(DEFAULT_TTL),
+                    }),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        self.with_session(|service, session| {
+            if service.cached_zones.is_empty() {
+                service.cached_zones = service.get_zones(session)?;
+            }
+
+            for domain in &service.config.domains {
+                let Some((zone, name)) = find_zone(&service.cached_zones, domain, "") else {
+                    return Err(DdnsUpdateError::provider(
+                        "INWX",
+                        ErrorKind::NotFound,
+                        format!("no zone of the account contains {}", domain).into(),
+                    ));
+                };
+
+                for ip in &result {
+                    service.set_record(session, zone, name, *ip)?;
+                }
+            }
+
+            Ok(())
+        })?;
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Result<String, DdnsUpdateError> {
+        self.with_session(|service, session| {
+            service.cached_zones = service.get_zones(session)?;
+
+            Ok(describe_found_domains(&service.config.domains, |domain| {
+                find_zone(&service.cached_zones, domain, "").is_some()
+            }))
+        })
+    }
+}
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        match self {
+            Service::DynDns(service) => service.suspension(),
+            Service::Api(_) => None,
+        }
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        match self {
+            Service::DynDns(service) => service.update_record(ips),
+            Service::Api(service) => service.update_record(ips),
+        }
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        match self {
+            Service::DynDns(_) => None,
+            Service::Api(service) => Some(service.test_credentials()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "password"
+            domains = "example.com"
+            "#,
+        );
+        assert!(matches!(config, Ok(Config::DynDns(_))));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            mode = "api"
+            username = "user"
+            password = "password"
+            domains = "example.com"
+            ttl = 3600
+            "#,
+        );
+        assert!(matches!(config, Ok(Config::Api(_))));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "password"
+            domains = "example.com"
+            ttl = 3600
+            "#,
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/inwx.txt"));
+
+        let mut service = Service::new(
+            Config::Api(ApiConfig {
+                username: Secret::new("user".into()),
+                password: Secret::new("password".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            }),
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent()
+            .iter()
+            .map(|s| serde_json::from_str::<serde_json::Value>(s.body.as_deref().unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        let methods = sent
+            .iter()
+            .map(|body| body["method"].as_str().unwrap())
+            .collect::<Vec<_>>();
+
+        // The A record of example.com is already up to date, and there is no
+        // AAAA record for www.example.com yet.
+        assert_eq!(
+            methods,
+            [
+                "account.login",
+                "nameserver.list",
+                "nameserver.info",
+                "nameserver.info",
+                "nameserver.updateRecord",
+                "nameserver.info",
+                "nameserver.updateRecord",
+                "nameserver.info",
+                "nameserver.createRecord",
+                "account.logout",
+            ]
+        );
+
+        assert_eq!(
+            sent[0]["params"],
+            serde_json::json!({ "user": "user", "pass": "password" })
+        );
+        assert_eq!(sent[2]["params"]["name"], "example.com");
+        assert_eq!(
+            sent[4]["params"],
+            serde_json::json!({ "id": 2, "content": "2001:db8::1" })
+        );
+        assert_eq!(
+            sent[8]["params"],
+            serde_json::json!({
+                "domain": "example.com",
+                "type": "AAAA",
+                "name": "www",
+                "content": "2001:db8::1",
+                "ttl": 300,
+            })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider { message: m, .. })) if &*m == "Authentication error"
+        ));
+    }
+}
@@ -1,22 +1,26 @@
 use std::net::IpAddr;
 
+use crate::http::Settings;
 use crate::util::FixedVec;
 
-use super::{shared_dyndns, DdnsService, DdnsUpdateError};
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, RecordType, SuspensionState};
 
 pub type Config = shared_dyndns::Config;
 
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
 pub struct Service {
     inner: shared_dyndns::Service,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
         Self {
             inner: shared_dyndns::Service::from_config(
                 "IPv64",
                 "https://ipv64.net/nic/update",
                 config,
+                http,
             ),
         }
     }
@@ -26,4 +30,49 @@ impl DdnsService for Service {
     fn update_record(&mut self, ip: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
         self.inner.update_record(ip)
     }
+
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.suspension()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+    use crate::services::{ErrorKind, Suspension};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/ipv64.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "home.any64.de"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                provider: "IPv64",
+                kind: ErrorKind::Outage,
+                ..
+            })
+        ));
+
+        // The server is left alone for a while after a 911.
+        assert!(matches!(
+            service.suspension().map(|state| &state.suspension),
+            Some(Suspension::Until(_))
+        ));
+        assert_eq!(take_sent().len(), 1);
+    }
 }
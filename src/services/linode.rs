@@ -2,17 +2,22 @@ use std::net::IpAddr;
 
 use serde_derive::{Deserialize, Serialize};
 
-use crate::http::{Error, Request, Response};
-use crate::util::FixedVec;
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
 
-use super::{one_or_more_string, DdnsService, DdnsUpdateError};
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, DiscoveredRecord,
+    ErrorKind, RecordType,
+};
 
 type RecordId = u64;
 type DomainId = u64;
 
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct Config {
-    token: Box<str>,
+    token: Secret<Box<str>>,
 
     #[serde(deserialize_with = "one_or_more_string")]
     domains: Vec<Box<str>>,
@@ -27,6 +32,7 @@ pub struct Config {
 
 pub struct Service {
     config: Config,
+    client: Client,
     cached_records: Vec<Record>,
 }
 
@@ -60,12 +66,13 @@ enum RecordKind {
     Aaaa,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
         let mut config = config;
-        config.token = (String::from("Bearer ") + &config.token).into();
+        config.token = Secret::new((String::from("Bearer ") + config.token.expose()).into());
         Self {
             config,
+            client: Client::new(http),
             cached_records: Vec::new(),
         }
     }
@@ -116,7 +123,8 @@ impl Service {
             Ok(r) => r
                 .into_json::<serde_json::Value>()
                 .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
-            Err(Error::Status(_, resp)) => {
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
                 let (field, reason) = self.parse_error(resp).map_err(|ref e| {
                     let error = String::from("unexpected error message structure - ");
                     DdnsUpdateError::Json((error + e).into_boxed_str())
@@ -128,7 +136,7 @@ impl Service {
                     format!("{} (field = {})", reason, field).into()
                 };
 
-                Err(DdnsUpdateError::Linode(error_message))?
+                Err(DdnsUpdateError::provider("Linode", kind, error_message))?
             }
             Err(Error::Transport(tp)) => {
                 Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
@@ -142,9 +150,11 @@ impl Service {
     ///   - https://www.linode.com/docs/api/domains/#domains-list
     ///   - https://www.linode.com/docs/api/domains/#domains-list__responses
     fn get_domains(&self) -> Result<Vec<Domain>, DdnsUpdateError> {
-        let response = Request::get("https://api.linode.com/v4/domains")
+        let response = self
+            .client
+            .get("https://api.linode.com/v4/domains")
             .set("Content-Type", "application/json")
-            .set("Authorization", &self.config.token)
+            .set("Authorization", self.config.token.expose())
             .call();
 
         let response = self.parse_and_check_response(response)?;
@@ -186,9 +196,11 @@ impl Service {
     fn get_records(&self, domain: Domain) -> Result<Vec<Record>, DdnsUpdateError> {
         let url = format!("https://api.linode.com/v4/domains/{}/records", domain.id);
 
-        let response = Request::get(&url)
+        let response = self
+            .client
+            .get(&url)
             .set("Content-Type", "application/json")
-            .set("Authorization", &self.config.token)
+            .set("Authorization", self.config.token.expose())
             .call();
 
         let response = self.parse_and_check_response(response)?;
@@ -251,8 +263,10 @@ impl Service {
 
         // We don't have to include the name again, just the target and TTL.
 
-        let response = Request::put(&url)
-            .set("Authorization", &self.config.token)
+        let response = self
+            .client
+            .put(&url)
+            .set("Authorization", self.config.token.expose())
             .send_json(serde_json::json!({
                 "target": ip.to_string(),
                 "ttl_sec": self.config.ttl,
@@ -262,18 +276,47 @@ impl Service {
 
         Ok(())
     }
+
+    /// Looks up the records of the configured domains and caches them, so that
+    /// they are only looked up once.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        for domain in self.get_domains()? {
+            for record in self.get_records(domain)? {
+                if self.config.domains.iter().any(|d| *d == record.name) {
+                    self.cached_records.push(record)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for domain in self.get_domains()? {
+            for record in self.get_records(domain)? {
+                let kind = match record.kind {
+                    RecordKind::A => RecordType::A,
+                    RecordKind::Aaaa => RecordType::Aaaa,
+                };
+
+                found.push(DiscoveredRecord {
+                    name: record.name,
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
 }
 
 impl DdnsService for Service {
     fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
         if self.cached_records.is_empty() {
-            for domain in self.get_domains()? {
-                for record in self.get_records(domain)? {
-                    if self.config.domains.iter().any(|d| *d == record.name) {
-                        self.cached_records.push(record)
-                    }
-                }
-            }
+            self.cache_records()?;
         }
 
         let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
@@ -297,4 +340,72 @@ impl DdnsService for Service {
 
         Ok(result)
     }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records
+                    .iter()
+                    .any(|record| &*record.name == domain)
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/linode.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["a.example.com".into(), "example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Invalid IP address (field = target)"
+        ));
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.linode.com/v4/domains",
+                "https://api.linode.com/v4/domains/1234/records",
+                "https://api.linode.com/v4/domains/1234/records/1",
+                "https://api.linode.com/v4/domains/1234/records/2",
+                "https://api.linode.com/v4/domains/1234/records/1",
+                "https://api.linode.com/v4/domains/1234/records/2",
+            ]
+        );
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["target"], "192.0.2.1");
+        assert_eq!(body["ttl_sec"], 300);
+    }
 }
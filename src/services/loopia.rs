@@ -0,0 +1,570 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{xml_escape, FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.loopia.se/RPCSERV";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The API user created in the customer zone, e.g. "user@loopiaapi". It
+    /// needs the permissions getDomains, getZoneRecords and updateZoneRecord.
+    username: Box<str>,
+    password: Secret<Box<str>>,
+
+    /// The customer to act on behalf of, only needed by resellers.
+    #[serde(default)]
+    customer_number: Option<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. The TTL of the records is left
+    /// as it is if not given.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_records: Vec<Record>,
+}
+
+#[derive(Debug)]
+struct Record {
+    id: i64,
+
+    /// The domain (i.e. zone) the record belongs to, e.g. "example.com".
+    zone: Box<str>,
+
+    /// The name within the zone, e.g. "www". The zone itself is "@".
+    name: Box<str>,
+
+    kind: RecordKind,
+    ttl: i64,
+    priority: i64,
+
+    /// The address the record was last known to point to.
+    data: Box<str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordKind {
+    A,
+    Aaaa,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_records: Vec::new(),
+        }
+    }
+}
+
+/// Appends the value as an XML-RPC <value>. Integers become <int>s, objects
+/// become <struct>s and arrays become <array>s.
+fn encode_value(value: &serde_json::Value, out: &mut String) {
+    use serde_json::Value;
+
+    out.push_str("<value>");
+    match value {
+        Value::Null => out.push_str("<nil/>"),
+        Value::Bool(b) => out.push_str(&format!("<boolean>{}</boolean>", u8::from(*b))),
+        Value::Number(n) if n.is_i64() => out.push_str(&format!("<int>{}</int>", n)),
+        Value::Number(n) => out.push_str(&format!("<double>{}</double>", n)),
+        Value::String(s) => out.push_str(&format!("<string>{}</string>", xml_escape(s))),
+        Value::Array(values) => {
+            out.push_str("<array><data>");
+            values.iter().for_each(|v| encode_value(v, out));
+            out.push_str("</data></array>");
+        }
+        Value::Object(members) => {
+            out.push_str("<struct>");
+            for (name, v) in members {
+                out.push_str(&format!("<member><name>{}</name>", xml_escape(name)));
+                encode_value(v, out);
+                out.push_str("</member>");
+            }
+            out.push_str("</struct>");
+        }
+    }
+    out.push_str("</value>");
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Skips the whitespace and the given tag, if the XML continues with it.
+fn eat(xml: &mut &str, tag: &str) -> bool {
+    match xml.trim_start().strip_prefix(tag) {
+        Some(rest) => {
+            *xml = rest;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the text up to the given closing tag and skips past it.
+fn text_until<'a>(xml: &mut &'a str, end: &str) -> Option<&'a str> {
+    let (text, rest) = xml.split_once(end)?;
+    *xml = rest;
+    Some(text)
+}
+
+/// Parses the XML-RPC <value> the XML starts with, and skips past it. The
+/// responses only ever contain the handful of types handled here.
+fn parse_value(xml: &mut &str) -> Option<serde_json::Value> {
+    use serde_json::Value;
+
+    if eat(xml, "<value/>") {
+        return Some(Value::String(String::new()));
+    }
+
+    if !eat(xml, "<value>") {
+        return None;
+    }
+
+    let value = if eat(xml, "<string/>") {
+        Value::String(String::new())
+    } else if eat(xml, "<string>") {
+        Value::String(xml_unescape(text_until(xml, "</string>")?))
+    } else if eat(xml, "<int>") {
+        Value::from(text_until(xml, "</int>")?.trim().parse::<i64>().ok()?)
+    } else if eat(xml, "<i4>") {
+        Value::from(text_until(xml, "</i4>")?.trim().parse::<i64>().ok()?)
+    } else if eat(xml, "<double>") {
+        Value::from(text_until(xml, "</double>")?.trim().parse::<f64>().ok()?)
+    } else if eat(xml, "<boolean>") {
+        Value::Bool(text_until(xml, "</boolean>")?.trim() == "1")
+    } else if eat(xml, "<array>") {
+        let mut values = Vec::new();
+        if !eat(xml, "<data/>") {
+            if !eat(xml, "<data>") {
+                return None;
+            }
+            while !eat(xml, "</data>") {
+                values.push(parse_value(xml)?);
+            }
+        }
+        if !eat(xml, "</array>") {
+            return None;
+        }
+        Value::Array(values)
+    } else if eat(xml, "<struct>") {
+        let mut members = serde_json::Map::new();
+        while !eat(xml, "</struct>") {
+            if !eat(xml, "<member>") || !eat(xml, "<name>") {
+                return None;
+            }
+            let name = xml_unescape(text_until(xml, "</name>")?.trim());
+            members.insert(name, parse_value(xml)?);
+            if !eat(xml, "</member>") {
+                return None;
+            }
+        }
+        Value::Object(members)
+    } else {
+        // A value without a type is a string.
+        return Some(Value::String(xml_unescape(text_until(xml, "</value>")?)));
+    };
+
+    eat(xml, "</value>").then_some(value)
+}
+
+/// Returns the value of the method response, or the fault as an error.
+fn parse_response(xml: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+    if let Some((_, mut fault)) = xml.split_once("<fault>") {
+        let fault = parse_value(&mut fault).unwrap_or_default();
+        let message = fault
+            .get("faultString")
+            .and_then(|m| m.as_str())
+            .unwrap_or("(null)");
+        let code = fault.get("faultCode").cloned().unwrap_or_default();
+        return Err(DdnsUpdateError::provider(
+            "Loopia",
+            ErrorKind::Other,
+            format!("{} ({})", message, code).into(),
+        ));
+    }
+
+    let Some((_, mut param)) = xml.split_once("<param>") else {
+        return Err(DdnsUpdateError::Json("expected a methodResponse".into()));
+    };
+
+    parse_value(&mut param).ok_or_else(|| DdnsUpdateError::Json("malformed XML-RPC value".into()))
+}
+
+fn status_error(status: &str) -> DdnsUpdateError {
+    let kind = match status {
+        "AUTH_ERROR" => ErrorKind::Auth,
+        "RATE_LIMITED" => ErrorKind::RateLimited(None),
+        "UNKNOWN_ERROR" => ErrorKind::Outage,
+        "BAD_INDATA" => ErrorKind::Validation,
+        _ => ErrorKind::Other,
+    };
+
+    DdnsUpdateError::provider("Loopia", kind, status.into())
+}
+
+/// The methods changing something return "OK" or a status like "AUTH_ERROR"
+/// or "RATE_LIMITED".
+fn check_status(value: &serde_json::Value) -> Result<(), DdnsUpdateError> {
+    match value.as_str() {
+        Some("OK") => Ok(()),
+        Some(status) => Err(status_error(status)),
+        None => Err(DdnsUpdateError::Json("expected a status".into())),
+    }
+}
+
+/// The methods listing something return the status instead of the list if
+/// they fail, sometimes wrapped in a list of its own.
+fn check_list(value: serde_json::Value) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+    match value {
+        serde_json::Value::Array(values) => match values.first().and_then(|v| v.as_str()) {
+            Some(status) => Err(status_error(status)),
+            None => Ok(values),
+        },
+        serde_json::Value::String(status) => Err(status_error(&status)),
+        _ => Err(DdnsUpdateError::Json("expected a list".into())),
+    }
+}
+
+impl Service {
+    /// Calls a method of the XML-RPC API with the credentials put before the
+    /// given parameters, as every method expects.
+    ///
+    /// See: https://www.loopia.com/api/
+    fn call(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let mut credentials = vec![
+            serde_json::Value::from(&*self.config.username),
+            serde_json::Value::from(&**self.config.password.expose()),
+        ];
+        if let Some(customer_number) = &self.config.customer_number {
+            credentials.push(serde_json::Value::from(&**customer_number));
+        }
+
+        let mut body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><methodCall><methodName>{}</methodName><params>",
+            method
+        );
+        for param in credentials.iter().chain(params) {
+            body.push_str("<param>");
+            encode_value(param, &mut body);
+            body.push_str("</param>");
+        }
+        body.push_str("</params></methodCall>");
+
+        let response = self
+            .client
+            .post(ENDPOINT)
+            .set("Content-Type", "text/xml")
+            .send_string(&body);
+
+        let response = match response {
+            Ok(resp) | Err(Error::Status(_, resp)) => resp.into_string().map_err(|e| {
+                DdnsUpdateError::provider("Loopia", ErrorKind::Other, e.to_string().into())
+            })?,
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        };
+
+        parse_response(&response)
+    }
+
+    /// See: https://www.loopia.com/api/getdomains/
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let domains = check_list(self.call("getDomains", &[])?)?;
+
+        let mut zones = Vec::with_capacity(domains.len());
+        for domain in domains {
+            let Some(name) = domain.get("domain").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no name?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// See: https://www.loopia.com/api/getzonerecords/
+    fn get_records(&self, zone: &str, name: &str) -> Result<Vec<Record>, DdnsUpdateError> {
+        let records = check_list(self.call("getZoneRecords", &[zone.into(), name.into()])?)?;
+
+        let mut returned_records = Vec::new();
+        for record in records {
+            let Some(id) = record.get("record_id").and_then(|v| v.as_i64()) else {
+                return Err(DdnsUpdateError::Json("record has no id?".into()));
+            };
+
+            let Some(ty) = record.get("type").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no type?".into()));
+            };
+
+            let kind = match ty {
+                "A" => RecordKind::A,
+                "AAAA" => RecordKind::Aaaa,
+                _ => continue,
+            };
+
+            returned_records.push(Record {
+                id,
+                zone: zone.into(),
+                name: name.into(),
+                kind,
+                ttl: record.get("ttl").and_then(|v| v.as_i64()).unwrap_or(3600),
+                priority: record.get("priority").and_then(|v| v.as_i64()).unwrap_or(0),
+                data: record
+                    .get("rdata")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .into(),
+            });
+        }
+
+        Ok(returned_records)
+    }
+
+    /// The record is identified by its ID, but has to be sent whole.
+    ///
+    /// See: https://www.loopia.com/api/updatezonerecord/
+    fn put_record(&self, record: &Record, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let ttl = self.config.ttl.map(i64::from).unwrap_or(record.ttl);
+        let kind = match record.kind {
+            RecordKind::A => "A",
+            RecordKind::Aaaa => "AAAA",
+        };
+
+        let response = self.call(
+            "updateZoneRecord",
+            &[
+                (&*record.zone).into(),
+                (&*record.name).into(),
+                serde_json::json!({
+                    "type": kind,
+                    "ttl": ttl,
+                    "priority": record.priority,
+                    "rdata": ip.to_string(),
+                    "record_id": record.id,
+                }),
+            ],
+        )?;
+
+        check_status(&response)
+    }
+
+    /// Looks up the records of the configured domains and caches them, so that
+    /// they are only looked up once. The API allows no more than 60 calls a
+    /// minute, so this matters with many domains.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        let zones = self.get_zones()?;
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&zones, domain, "@") else {
+                continue;
+            };
+
+            let records = self.get_records(zone, name)?;
+            self.cached_records.extend(records);
+        }
+
+        Ok(())
+    }
+
+    fn update_cached_records(
+        &mut self,
+        ipv4: Option<IpAddr>,
+        ipv6: Option<IpAddr>,
+    ) -> Result<(), DdnsUpdateError> {
+        for i in 0..self.cached_records.len() {
+            let record = &self.cached_records[i];
+
+            let ip = match (&record.kind, ipv4, ipv6) {
+                (RecordKind::A, Some(ipv4), _) => ipv4,
+                (RecordKind::Aaaa, _, Some(ipv6)) => ipv6,
+                _ => continue,
+            };
+
+            let data = ip.to_string();
+            if *record.data == data {
+                continue;
+            }
+
+            self.put_record(record, ip)?;
+            self.cached_records[i].data = data.into();
+        }
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_records.is_empty() {
+            self.cache_records()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4()).copied();
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6()).copied();
+
+        // The records may have been changed or removed behind our back, so
+        // they are looked up again after a failure.
+        if let Err(e) = self.update_cached_records(ipv4, ipv6) {
+            self.cached_records.clear();
+            return Err(e);
+        }
+
+        let mut result = FixedVec::new();
+        result.extend(ipv4);
+        result.extend(ipv6);
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records.iter().any(|record| {
+                    let fqdn = match &*record.name {
+                        "@" => record.zone.to_string(),
+                        name => format!("{}.{}", name, record.zone),
+                    };
+                    fqdn == domain
+                })
+            })
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn xml_rpc() {
+        let mut body = String::new();
+        encode_value(
+            &serde_json::json!({ "type": "A", "ttl": 300, "rdata": "<&>" }),
+            &mut body,
+        );
+        assert_eq!(
+            body,
+            "<value><struct>\
+             <member><name>rdata</name><value><string>&lt;&amp;&gt;</string></value></member>\
+             <member><name>ttl</name><value><int>300</int></value></member>\
+             <member><name>type</name><value><string>A</string></value></member>\
+             </struct></value>"
+        );
+
+        let mut xml = "<value>\n  <array><data><value><struct>\n\
+                       <member><name>a</name><value><i4>1</i4></value></member>\n\
+                       <member><name>b</name><value>x &amp; y</value></member>\n\
+                       </struct></value><value><boolean>1</boolean></value></data></array>\n\
+                       </value>";
+        assert_eq!(
+            parse_value(&mut xml),
+            Some(serde_json::json!([{ "a": 1, "b": "x & y" }, true]))
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/loopia.txt"));
+
+        let mut service = Service::new(
+            Config {
+                username: "user@loopiaapi".into(),
+                password: Secret::new("password".into()),
+                customer_number: None,
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The AAAA record of example.com is up to date.
+        let sent = take_sent();
+        let methods = sent
+            .iter()
+            .map(|s| {
+                let body = s.body.as_deref().unwrap();
+                let start = body.find("<methodName>").unwrap() + "<methodName>".len();
+                let end = body.find("</methodName>").unwrap();
+                &body[start..end]
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            [
+                "getDomains",
+                "getZoneRecords",
+                "getZoneRecords",
+                "updateZoneRecord",
+                "updateZoneRecord",
+            ]
+        );
+        assert!(sent[1].body.as_deref().unwrap().contains(
+            "<param><value><string>example.com</string></value></param>\
+             <param><value><string>@</string></value></param>"
+        ));
+        assert!(sent[4].body.as_deref().unwrap().contains(
+            "<member><name>priority</name><value><int>0</int></value></member>\
+             <member><name>rdata</name><value><string>192.0.2.1</string></value></member>\
+             <member><name>record_id</name><value><int>13</int></value></member>\
+             <member><name>ttl</name><value><int>600</int></value></member>"
+        ));
+
+        // Nothing is sent while the addresses stay the same.
+        service.update_record(&[ipv4, ipv6]).unwrap();
+        assert!(take_sent().is_empty());
+
+        let ipv4 = "192.0.2.2".parse().unwrap();
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "RATE_LIMITED"
+        ));
+        assert!(service.cached_records.is_empty());
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider { message: m, .. })) if &*m == "AUTH_ERROR"
+        ));
+    }
+}
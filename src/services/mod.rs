@@ -1,25 +1,111 @@
+pub mod alidns;
+pub mod allinkl;
+pub mod azure;
+pub mod bunny;
 pub mod cloudflare;
+pub mod cloudns;
+pub mod constellix;
+pub mod corenetworks;
+pub mod custom_http;
+pub mod ddnss;
+pub mod desec;
+pub mod digitalocean;
+pub mod dnshome;
+pub mod dnsimple;
 pub mod dnsomatic;
+pub mod dnspod;
+pub mod dode;
+pub mod domeneshop;
+pub mod dreamhost;
 pub mod duckdns;
 pub mod dummy;
+pub mod dyfi;
+pub mod dyndns2;
 pub mod dynu;
+pub mod dynv6;
+pub mod easydns;
+pub mod exec;
+pub mod exoscale;
+pub mod freedns;
+pub mod gandi;
+pub mod gcore;
+pub mod glesys;
+pub mod godaddy;
+pub mod goip;
+pub mod google;
+pub mod henet;
+pub mod hetzner;
+pub mod hosting1984;
+pub mod hover;
+pub mod infomaniak;
+pub mod inwx;
 pub mod ipv64;
 pub mod linode;
+pub mod loopia;
+pub mod mythicbeasts;
+pub mod namecheap;
+pub mod namecom;
+pub mod netcup;
+pub mod nfsn;
+pub mod njalla;
 pub mod noip;
+pub mod ns1;
+pub mod nsupdateinfo;
+pub mod oci;
+pub mod ovh;
+pub mod plugin;
 pub mod porkbun;
+pub mod powerdns;
+pub mod rfc2136;
+pub mod route53;
+pub mod scaleway;
 pub mod selfhost;
 pub mod shared_dyndns;
+pub mod spdyn;
+pub mod strato;
+pub mod transip;
+pub mod variomedia;
+pub mod vultr;
+pub mod webhook;
+pub mod zoneedit;
 
 use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
 
+use serde_derive::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::http::{self, Response};
 use crate::util::*;
 
+/// The options of [general] which the services follow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceSettings {
+    pub http: http::Settings,
+
+    /// The shell running the command of the exec service.
+    pub shell: Box<str>,
+
+    /// The directory the plugins are looked up in.
+    pub plugins_dir: Box<str>,
+}
+
+impl Default for ServiceSettings {
+    fn default() -> Self {
+        Self {
+            http: http::Settings::default(),
+            shell: "/bin/sh".into(),
+            plugins_dir: "/usr/local/lib/dynners/plugins".into(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Suspension {
-    // If the number of cycles is zero, the service proceeds as normal
-    Cycles(u32),
+    // Once this point in time has passed, the service proceeds as normal. It
+    // is a wall-clock time so that the time spent while the system itself is
+    // suspended counts as well.
+    Until(SystemTime),
 
     // Once suspended, the service is not updated until end of program
     Indefinite,
@@ -28,41 +114,265 @@ pub enum Suspension {
 impl std::fmt::Display for Suspension {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Suspension::Cycles(u) => write!(f, "{} cycle(s) left", u),
+            Suspension::Until(time) => {
+                let left = time
+                    .duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO);
+                write!(f, "{} minute(s) left", left.as_secs().div_ceil(60))
+            }
             Suspension::Indefinite => write!(f, "indefinitely"),
         }
     }
 }
 
+/// A suspension a service has imposed on itself, along with the error which
+/// caused it and when that happened.
+#[derive(Clone, Debug)]
+pub struct SuspensionState {
+    pub suspension: Suspension,
+    pub reason: Box<str>,
+    pub since: SystemTime,
+}
+
+impl SuspensionState {
+    /// Whether the service is still kept from sending updates.
+    pub fn is_in_effect(&self) -> bool {
+        match self.suspension {
+            Suspension::Until(time) => time > SystemTime::now(),
+            Suspension::Indefinite => true,
+        }
+    }
+}
+
+/// The kinds of DNS records a service is able to update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Srv,
+    Caa,
+}
+
+impl std::fmt::Display for RecordType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordType::A => write!(f, "A"),
+            RecordType::Aaaa => write!(f, "AAAA"),
+            RecordType::Srv => write!(f, "SRV"),
+            RecordType::Caa => write!(f, "CAA"),
+        }
+    }
+}
+
+/// A SRV record pointing to the domains updated by the service, see `srv` in
+/// the config.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct SrvRecord {
+    /// The fully-qualified name, e.g. "_minecraft._tcp.example.com".
+    pub name: Box<str>,
+
+    #[serde(default)]
+    pub priority: u16,
+    #[serde(default)]
+    pub weight: u16,
+    pub port: u16,
+
+    /// The host the record points to. If empty, it is the first of the
+    /// `domains` of the service, so that it follows the addresses.
+    #[serde(default)]
+    pub target: Box<str>,
+}
+
+/// Formats the record data as in a zone file, e.g. "0 5 25565 example.com.".
+impl std::fmt::Display for SrvRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}.",
+            self.priority,
+            self.weight,
+            self.port,
+            self.target.trim_end_matches('.')
+        )
+    }
+}
+
+/// A CAA record, which is static, see `caa` in the config.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct CaaRecord {
+    /// The fully-qualified name, e.g. "example.com".
+    pub name: Box<str>,
+
+    #[serde(default)]
+    pub flags: u8,
+
+    /// Either "issue", "issuewild" or "iodef".
+    pub tag: Box<str>,
+    pub value: Box<str>,
+}
+
+/// Formats the record data as in a zone file, e.g. `0 issue "letsencrypt.org"`.
+impl std::fmt::Display for CaaRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} \"{}\"", self.flags, self.tag, self.value)
+    }
+}
+
+/// The records maintained alongside the A and AAAA records of a service, which
+/// do not depend on the addresses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StaticRecords {
+    pub srv: Vec<SrvRecord>,
+    pub caa: Vec<CaaRecord>,
+}
+
+impl StaticRecords {
+    pub fn is_empty(&self) -> bool {
+        self.srv.is_empty() && self.caa.is_empty()
+    }
+}
+
+/// An A or AAAA record found by `dynners discover`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredRecord {
+    /// The fully-qualified name, e.g. "www.example.com".
+    pub name: Box<str>,
+    pub kind: RecordType,
+}
+
+/// What `dynners providers` shows about each kind of service.
+pub struct ProviderInfo {
+    /// The value of `service = "..."` in the config file.
+    pub service: &'static str,
+
+    /// The human-readable name of the provider.
+    pub name: &'static str,
+
+    /// The keys accepted by the service in its [ddns.*] entry, on top of the
+    /// ones every entry accepts.
+    pub config_keys: fn() -> &'static [&'static str],
+
+    pub records: &'static [RecordType],
+}
+
+/// What went wrong with an update, in broad strokes, so that the daemon can
+/// tell a bad token apart from a server which is down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The credentials were rejected, or lack the permissions needed.
+    Auth,
+
+    /// Too many requests were made, along with how long to wait before the
+    /// next one if the provider said so.
+    RateLimited(Option<Duration>),
+
+    /// The domain, zone or record does not exist, at least not in the account.
+    NotFound,
+
+    /// The request was refused as invalid, e.g. because of a TTL out of range.
+    Validation,
+
+    /// The provider could not be reached at all.
+    Transport,
+
+    /// The provider failed on its side, e.g. with a 5xx status.
+    Outage,
+
+    /// Anything else, such as a response which makes no sense.
+    Other,
+}
+
+impl ErrorKind {
+    /// Classifies an error by the HTTP status of the response. A 429 response
+    /// may tell how long to wait in its Retry-After header, in seconds (the
+    /// date form is not used by any of the providers).
+    pub fn from_response(status: u16, response: &Response) -> Self {
+        match ErrorKind::from_status(status) {
+            ErrorKind::RateLimited(_) => ErrorKind::RateLimited(
+                response
+                    .header("Retry-After")
+                    .and_then(|after| after.trim().parse().ok())
+                    .map(Duration::from_secs),
+            ),
+            kind => kind,
+        }
+    }
+
+    /// Classifies an error by an HTTP status, or by an error code which
+    /// borrows its meaning from them.
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 | 403 => ErrorKind::Auth,
+            429 => ErrorKind::RateLimited(None),
+            404 => ErrorKind::NotFound,
+            400 | 409 | 422 => ErrorKind::Validation,
+            500..=599 => ErrorKind::Outage,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Whether the same request may well succeed if it is sent again later.
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            ErrorKind::RateLimited(_) | ErrorKind::Transport | ErrorKind::Outage
+        )
+    }
+
+    /// The name used in the events, e.g. "rate_limited".
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Auth => "auth",
+            ErrorKind::RateLimited(_) => "rate_limited",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Validation => "validation",
+            ErrorKind::Transport => "transport",
+            ErrorKind::Outage => "outage",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
 #[derive(Clone, Error, Debug)]
 pub enum DdnsUpdateError {
-    // used when CF really returned an error
-    #[error("Cloudflare returned error code {0} \"{1}\"")]
-    Cloudflare(u32, Box<str>),
+    // used when the provider returned an error, or refused to do something
+    #[error("{provider} returned error: {message}")]
+    Provider {
+        provider: &'static str,
+        kind: ErrorKind,
+        message: Box<str>,
+    },
 
     // used when a service says it succeeded, but the returned JSON is nonsense
     #[error("received erroneous JSON: {0}")]
     Json(Box<str>),
 
-    #[error("DuckDNS rejected the request - check again your tokens and domains")]
-    DuckDns,
-
-    #[error("{0} returned error: {1}")]
-    DynDns(&'static str, Box<str>),
-
-    #[error("Linode returned error: {0}")]
-    Linode(Box<str>),
-
-    #[error("Porkbun returned error: {0}")]
-    Porkbun(Box<str>),
-
     #[error("the daemon has suspended updating this service ({0})")]
     Suspended(Suspension),
 
-    #[error("HTTP transport error: {0}")]
+    #[error("transport error: {0}")]
     TransportError(Box<str>),
 }
 
+impl DdnsUpdateError {
+    pub fn provider(provider: &'static str, kind: ErrorKind, message: Box<str>) -> Self {
+        DdnsUpdateError::Provider {
+            provider,
+            kind,
+            message,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DdnsUpdateError::Provider { kind, .. } => *kind,
+            DdnsUpdateError::Json(_) => ErrorKind::Other,
+            DdnsUpdateError::Suspended(_) => ErrorKind::Other,
+            DdnsUpdateError::TransportError(_) => ErrorKind::Transport,
+        }
+    }
+}
+
 pub trait DdnsService {
     /// Update the DNS records with the given IP addresses. If the update succeeds,
     /// one or two IP addresses (one for IPv4 and one for IPv6) will be returned.
@@ -70,4 +380,102 @@ pub trait DdnsService {
     /// address for a given domain, but many DDNS services already don't support
     /// that.
     fn update_record(&mut self, ip: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError>;
+
+    /// Check the credentials of the service using only read-only requests, such
+    /// as listing the zones and records, so that no record is ever modified.
+    /// On success, a short description of what was found is returned. Services
+    /// whose API has no such request return `None`.
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        None
+    }
+
+    /// The suspension the service has imposed on itself after an error, as long
+    /// as it is in effect. Until then, `update_record` only returns
+    /// `DdnsUpdateError::Suspended`.
+    fn suspension(&self) -> Option<&SuspensionState> {
+        None
+    }
+
+    /// How long the records may go without an update before the provider
+    /// releases them, even if the addresses have not changed. The daemon then
+    /// updates the service once this long has passed since its last update.
+    fn refresh_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Creates the given SRV and CAA records, or corrects them if they differ,
+    /// and returns how many records were changed. Only services with a full
+    /// zone API (listing SRV or CAA among their `RECORDS`) support this, the
+    /// others return `None`.
+    fn set_static_records(
+        &mut self,
+        _records: &StaticRecords,
+    ) -> Option<Result<u32, DdnsUpdateError>> {
+        None
+    }
+
+    /// Lists every A and AAAA record the credentials give access to, whether
+    /// it is among the configured domains or not. Only services whose API can
+    /// list the records of a whole account support this, the others return
+    /// `None`.
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        None
+    }
+}
+
+/// A DNS zone as listed by a provider, whose name is what `find_zone` goes by.
+trait ZoneName {
+    fn zone_name(&self) -> &str;
+}
+
+impl ZoneName for Box<str> {
+    fn zone_name(&self) -> &str {
+        self
+    }
+}
+
+/// Splits the domain into the zone it belongs to and its name within the zone,
+/// e.g. (zone of "example.com", "www"). The name of the zone itself is `apex`,
+/// which is "@" or "" depending on the provider.
+fn find_zone<'a, Z: ZoneName>(
+    zones: &'a [Z],
+    domain: &'a str,
+    apex: &'a str,
+) -> Option<(&'a Z, &'a str)> {
+    zones
+        .iter()
+        .filter_map(|zone| {
+            let zone_name = zone.zone_name();
+
+            if zone_name.is_empty() {
+                return None;
+            } else if domain == zone_name {
+                return Some((zone, apex));
+            }
+
+            let name = domain.strip_suffix(zone_name)?.strip_suffix('.')?;
+            Some((zone, name))
+        })
+        // The longest zone is the most specific one.
+        .max_by_key(|(zone, _)| zone.zone_name().len())
+}
+
+/// Describes which of the configured domains were found while testing the
+/// credentials, e.g. "1 of 2 domain(s) found, missing: www.example.com".
+fn describe_found_domains(domains: &[Box<str>], is_found: impl Fn(&str) -> bool) -> String {
+    let missing = domains
+        .iter()
+        .filter(|domain| !is_found(domain))
+        .map(|domain| &**domain)
+        .collect::<Vec<_>>();
+
+    let found = domains.len() - missing.len();
+    let mut description = format!("{} of {} domain(s) found", found, domains.len());
+
+    if !missing.is_empty() {
+        description += ", missing: ";
+        description += &missing.join(", ");
+    }
+
+    description
 }
@@ -0,0 +1,393 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The ID and secret of an API key, created in the control panel under
+    /// API Keys, with write access to the records.
+    key: Box<str>,
+    secret: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. The TTL of the records is left
+    /// to Mythic Beasts if not given.
+    #[serde(default)]
+    ttl: Option<u32>,
+
+    /// Whether to use the `dynamic` endpoint instead, which points the record
+    /// to the address the request itself comes from rather than the one found
+    /// by dynners. It only needs the key to be allowed to update the records.
+    #[serde(default)]
+    dynamic: bool,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Secret<Box<str>>,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let key_secret = String::from(config.key.clone()) + ":" + config.secret.expose();
+        let base64 = data_encoding::BASE64.encode(key_secret.as_bytes());
+        let auth = String::from("Basic ") + &base64;
+
+        Self {
+            config,
+            client: Client::new(http),
+            auth: Secret::new(auth.into()),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"error": "..."}, or as {"errors": ["...", ...]}
+    /// when a request is invalid.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        if let Some(message) = resp_json.get("error").and_then(|m| m.as_str()) {
+            return Ok(message.into());
+        }
+
+        let messages = resp_json
+            .get("errors")
+            .and_then(|e| e.as_array())
+            .ok_or_else(|| String::from("expected error or errors"))?
+            .iter()
+            .filter_map(|e| e.as_str())
+            .collect::<Vec<_>>();
+
+        Ok(messages.join(", ").into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Mythic Beasts", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// See: https://www.mythic-beasts.com/support/api/dnsv2#ep-get-zones
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://api.mythic-beasts.com/dns/v2/zones")
+            .set("Authorization", self.auth.expose())
+            .call();
+        let response = self.parse_and_check_response(response)?;
+
+        let Some(listed) = response.get("zones").and_then(|z| z.as_array()) else {
+            return Err(DdnsUpdateError::Json("expected a list of zones".into()));
+        };
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.as_str() else {
+                return Err(DdnsUpdateError::Json("zone is not a string?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// Replaces the records of the host and type with the address alone,
+    /// unless it is the only one already.
+    ///
+    /// See: https://www.mythic-beasts.com/support/api/dnsv2#ep-put-zoneszonerecordshosttype
+    fn set_record(&self, zone: &str, host: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let url = format!(
+            "https://api.mythic-beasts.com/dns/v2/zones/{}/records/{}/{}",
+            zone, host, kind
+        );
+
+        let response = self
+            .client
+            .get(&url)
+            .set("Authorization", self.auth.expose())
+            .call();
+        let response = self.parse_and_check_response(response)?;
+
+        let is_up_to_date = response
+            .get("records")
+            .and_then(|r| r.as_array())
+            .is_some_and(|existing| {
+                existing.len() == 1
+                    && existing[0].get("data").and_then(|d| d.as_str()) == Some(&ip.to_string())
+                    && self.config.ttl.is_none_or(|ttl| {
+                        existing[0].get("ttl").and_then(|t| t.as_u64()) == Some(ttl as u64)
+                    })
+            });
+
+        if is_up_to_date {
+            return Ok(());
+        }
+
+        let mut record = serde_json::json!({
+            "host": host,
+            "type": kind,
+            "data": ip.to_string(),
+        });
+        if let Some(ttl) = self.config.ttl {
+            record["ttl"] = ttl.into();
+        }
+
+        let response = self
+            .client
+            .put(&url)
+            .set("Authorization", self.auth.expose())
+            .send_json(serde_json::json!({ "records": [record] }));
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Points the record of the domain to the address the request comes from,
+    /// which is why it is sent to the IPv4-only or IPv6-only host of the API.
+    ///
+    /// See: https://www.mythic-beasts.com/support/api/dnsv2/dynamic-dns
+    fn set_dynamic(&self, domain: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let host = match ip {
+            IpAddr::V4(_) => "ipv4.api.mythic-beasts.com",
+            IpAddr::V6(_) => "ipv6.api.mythic-beasts.com",
+        };
+
+        let response = self
+            .client
+            .put(&format!("https://{}/dns/v2/dynamic/{}", host, domain))
+            .set("Authorization", self.auth.expose())
+            .call();
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    ///
+    /// See: https://www.mythic-beasts.com/support/api/dnsv2#ep-get-zoneszonerecords
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            let response = self
+                .client
+                .get(&format!(
+                    "https://api.mythic-beasts.com/dns/v2/zones/{}/records",
+                    zone
+                ))
+                .set("Authorization", self.auth.expose())
+                .call();
+            let response = self.parse_and_check_response(response)?;
+
+            let Some(records) = response.get("records").and_then(|r| r.as_array()) else {
+                return Err(DdnsUpdateError::Json("zone has no records?".into()));
+            };
+
+            for record in records {
+                let kind = match record.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match record.get("host").and_then(|h| h.as_str()) {
+                    Some("@") => zone.clone(),
+                    Some(host) => format!("{}.{}", host, zone).into(),
+                    None => return Err(DdnsUpdateError::Json("record has no host?".into())),
+                };
+
+                // A host has as many records of a type as it has addresses.
+                if !found
+                    .iter()
+                    .any(|r: &DiscoveredRecord| r.name == name && r.kind == kind)
+                {
+                    found.push(DiscoveredRecord { name, kind });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        if self.config.dynamic {
+            for domain in &self.config.domains {
+                for ip in &result {
+                    self.set_dynamic(domain, *ip)?;
+                }
+            }
+
+            return Ok(result);
+        }
+
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        for domain in &self.config.domains {
+            let Some((zone, host)) = find_zone(&self.cached_zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Mythic Beasts",
+                    ErrorKind::NotFound,
+                    format!("no zone of the key contains {}", domain).into(),
+                ));
+            };
+
+            for ip in &result {
+                self.set_record(zone, host, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/mythicbeasts.txt"));
+
+        let mut service = Service::new(
+            Config {
+                key: "key".into(),
+                secret: Secret::new("secret".into()),
+                domains: vec!["example.com".into(), "home.dyn.example.com".into()],
+                ttl: None,
+                dynamic: false,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and dyn.example.com is a
+        // zone of its own.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.mythic-beasts.com/dns/v2/zones",
+                "GET https://api.mythic-beasts.com/dns/v2/zones/example.com/records/@/A",
+                "GET https://api.mythic-beasts.com/dns/v2/zones/example.com/records/@/AAAA",
+                "PUT https://api.mythic-beasts.com/dns/v2/zones/example.com/records/@/AAAA",
+                "GET https://api.mythic-beasts.com/dns/v2/zones/dyn.example.com/records/home/A",
+                "PUT https://api.mythic-beasts.com/dns/v2/zones/dyn.example.com/records/home/A",
+                "GET https://api.mythic-beasts.com/dns/v2/zones/dyn.example.com/records/home/AAAA",
+                "PUT https://api.mythic-beasts.com/dns/v2/zones/dyn.example.com/records/home/AAAA",
+            ]
+        );
+
+        let body = sent[7].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "records": [
+                { "host": "home", "type": "AAAA", "data": "2001:db8::1" },
+            ]})
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Invalid API key"
+        ));
+        assert_eq!(take_sent().len(), 1);
+
+        // The dynamic endpoint needs neither the zones nor the records.
+        service.config.dynamic = true;
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "PUT https://ipv4.api.mythic-beasts.com/dns/v2/dynamic/example.com",
+                "PUT https://ipv6.api.mythic-beasts.com/dns/v2/dynamic/example.com",
+                "PUT https://ipv4.api.mythic-beasts.com/dns/v2/dynamic/home.dyn.example.com",
+                "PUT https://ipv6.api.mythic-beasts.com/dns/v2/dynamic/home.dyn.example.com",
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, xml_element, FixedVec, Secret};
+
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+/// Namecheap's Dynamic DNS only manages A records.
+pub const RECORDS: &[RecordType] = &[RecordType::A];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The Dynamic DNS password shown in the "Advanced DNS" tab of the
+    /// domain. It is not the password of the Namecheap account.
+    password: Secret<Box<str>>,
+
+    /// The domain as registered with Namecheap, e.g. "example.com".
+    domain: Box<str>,
+
+    /// The hosts within the domain, e.g. "www". Use "@" for the domain itself.
+    #[serde(deserialize_with = "one_or_more_string")]
+    hosts: Vec<Box<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+impl Service {
+    /// See: https://www.namecheap.com/support/knowledgebase/article.aspx/29/11/how-to-dynamically-update-the-hosts-ip-with-an-http-request/
+    fn update_host(&self, host: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://dynamicdns.park-your-domain.com/update")
+            .query("host", host)
+            .query("domain", &self.config.domain)
+            .query("password", self.config.password.expose())
+            .query("ip", &ip.to_string())
+            .call();
+
+        let response = match response {
+            Ok(resp) | Err(Error::Status(_, resp)) => resp.into_string().map_err(|e| {
+                DdnsUpdateError::provider("Namecheap", ErrorKind::Other, e.to_string().into())
+            })?,
+
+            Err(Error::Transport(t)) => {
+                return Err(DdnsUpdateError::TransportError(t.to_string().into()))
+            }
+        };
+
+        let Some(error_count) = xml_element(&response, "ErrCount") else {
+            return Err(DdnsUpdateError::provider(
+                "Namecheap",
+                ErrorKind::Other,
+                "unexpected response without ErrCount".into(),
+            ));
+        };
+
+        if error_count == "0" {
+            return Ok(());
+        }
+
+        let message = xml_element(&response, "Err1").unwrap_or("unknown error");
+
+        let kind = match message {
+            "Passwords do not match" => ErrorKind::Auth,
+            _ => ErrorKind::Other,
+        };
+
+        Err(DdnsUpdateError::provider(
+            "Namecheap",
+            kind,
+            format!("{} (host {})", message, host).into(),
+        ))
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let Some(ipv4) = ips.iter().find(|ip| ip.is_ipv4()) else {
+            return Err(DdnsUpdateError::provider(
+                "Namecheap",
+                ErrorKind::Validation,
+                "only IPv4 addresses are supported".into(),
+            ));
+        };
+
+        for host in &self.config.hosts {
+            self.update_host(host, *ipv4)?;
+        }
+
+        let mut result = FixedVec::new();
+        result.push(*ipv4);
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/namecheap.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            password = "password"
+            domain = "example.com"
+            hosts = ["@", "www"]
+            "#,
+        )
+        .unwrap();
+
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv6, ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(
+            &*sent[0].url,
+            "https://dynamicdns.park-your-domain.com/update?host=%40&domain=example.com&password=password&ip=192.0.2.1"
+        );
+
+        service.config.hosts = vec!["bad".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Passwords do not match (host bad)"
+        ));
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: _, .. })
+        ));
+    }
+}
@@ -0,0 +1,356 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.name.com/v4";
+
+/// The TTL of newly created records if none is configured, which is also the
+/// lowest name.com accepts.
+const DEFAULT_TTL: u32 = 300;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The username of the account and an API token, created under Account
+    /// Settings > API Settings.
+    username: Box<str>,
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The TTL of the records, which is otherwise left as it is.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Secret<Box<str>>,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let username_token = String::from(config.username.clone()) + ":" + config.token.expose();
+        let base64 = data_encoding::BASE64.encode(username_token.as_bytes());
+        let auth = String::from("Basic ") + &base64;
+
+        Self {
+            config,
+            client: Client::new(http),
+            auth: Secret::new(auth.into()),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"message": "...", "details": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json.get("message").and_then(|m| m.as_str());
+        let details = resp_json.get("details").and_then(|d| d.as_str());
+
+        match (message, details) {
+            (Some(message), Some(details)) => Ok(format!("{}: {}", message, details).into()),
+            (Some(message), None) => Ok(message.into()),
+            (None, _) => Err(String::from("expected string")),
+        }
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into())),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("name.com", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// Collects the items under `key` from every page of the listing.
+    fn get_pages(&self, url: &str, key: &str) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut items = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let response = self
+                .client
+                .get(url)
+                .set("Authorization", self.auth.expose())
+                .query("page", &page.to_string())
+                .call();
+
+            let response = self.parse_and_check_response(response)?;
+
+            if let Some(listed) = response.get(key).and_then(|l| l.as_array()) {
+                items.extend(listed.iter().cloned());
+            }
+
+            match response.get("nextPage").and_then(|n| n.as_u64()) {
+                Some(next) if next > page => page = next,
+                _ => break,
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// See: https://www.name.com/api-docs/domains#ListDomains
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let listed = self.get_pages(&format!("{}/domains", ENDPOINT), "domains")?;
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.get("domainName").and_then(|d| d.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no domainName?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// See: https://www.name.com/api-docs/dns#ListRecords
+    fn get_records(&self, zone: &str) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        self.get_pages(&format!("{}/domains/{}/records", ENDPOINT, zone), "records")
+    }
+
+    /// Points the record of the given host and type to the address, creating
+    /// the record if there is none.
+    ///
+    /// See: https://www.name.com/api-docs/dns#UpdateRecord
+    fn set_record(
+        &self,
+        records: &[serde_json::Value],
+        zone: &str,
+        host: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let url = format!("{}/domains/{}/records", ENDPOINT, zone);
+
+        // The host of the zone itself is left out of the record.
+        let existing = records.iter().find(|record| {
+            record.get("type").and_then(|t| t.as_str()) == Some(kind)
+                && record.get("host").and_then(|h| h.as_str()).unwrap_or("") == host
+        });
+
+        let (request, ttl) = match existing {
+            Some(record) => {
+                let current_ttl = record.get("ttl").and_then(|t| t.as_u64());
+                let ttl = self
+                    .config
+                    .ttl
+                    .or(current_ttl.map(|t| t as u32))
+                    .unwrap_or(DEFAULT_TTL);
+
+                if record.get("answer").and_then(|a| a.as_str()) == Some(&ip.to_string())
+                    && current_ttl == Some(ttl as u64)
+                {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("id").and_then(|i| i.as_u64()) else {
+                    return Err(DdnsUpdateError::Json("record has no id?".into()));
+                };
+
+                (self.client.put(&format!("{}/{}", url, id)), ttl)
+            }
+
+            None => (
+                self.client.post(&url),
+                self.config.ttl.unwrap_or(DEFAULT_TTL),
+            ),
+        };
+
+        let body = serde_json::json!({
+            "host": host,
+            "type": kind,
+            "answer": ip.to_string(),
+            "ttl": ttl,
+        });
+
+        let response = request
+            .set("Authorization", self.auth.expose())
+            .send_json(body);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                let kind = match record.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match record.get("host").and_then(|h| h.as_str()) {
+                    None | Some("") => zone.clone(),
+                    Some(host) => format!("{}.{}", host, zone).into(),
+                };
+
+                // A name has as many records of a type as it has addresses.
+                if !found
+                    .iter()
+                    .any(|r: &DiscoveredRecord| r.name == name && r.kind == kind)
+                {
+                    found.push(DiscoveredRecord { name, kind });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, host)) = find_zone(&self.cached_zones, domain, "") else {
+                return Err(DdnsUpdateError::provider(
+                    "name.com",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            let records = self.get_records(zone)?;
+
+            for ip in &result {
+                self.set_record(&records, zone, host, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/namecom.txt"));
+
+        let mut service = Service::new(
+            Config {
+                username: "user".into(),
+                token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "www.example.org".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The domains are listed over two pages, and the A record of
+        // example.com is up to date.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.name.com/v4/domains?page=1",
+                "GET https://api.name.com/v4/domains?page=2",
+                "GET https://api.name.com/v4/domains/example.com/records?page=1",
+                "PUT https://api.name.com/v4/domains/example.com/records/11",
+                "GET https://api.name.com/v4/domains/example.org/records?page=1",
+                "PUT https://api.name.com/v4/domains/example.org/records/21",
+                "POST https://api.name.com/v4/domains/example.org/records",
+            ]
+        );
+
+        // The TTL of existing records is kept.
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "host": "", "type": "AAAA", "answer": "2001:db8::1", "ttl": 3600 })
+        );
+
+        let body = sent[6].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "host": "www", "type": "AAAA", "answer": "2001:db8::1", "ttl": 300 })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Unauthenticated: Authentication failed"
+        ));
+    }
+}
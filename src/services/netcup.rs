@@ -0,0 +1,350 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://ccp.netcup.net/run/webservice/servers/endpoint.php?JSON";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    customer_number: Box<str>,
+
+    /// The API key and password created in the CCP under Master Data > API.
+    api_key: Secret<Box<str>>,
+    api_password: Secret<Box<str>>,
+
+    /// The domains registered at Netcup, e.g. "example.com". The API has no
+    /// way of listing them.
+    #[serde(deserialize_with = "one_or_more_string")]
+    zones: Vec<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+impl Service {
+    /// Calls an action of the API and returns its "responsedata". Failures
+    /// are reported with {"status": "error", "longmessage": "..."}, mostly
+    /// with a status of 200.
+    ///
+    /// See: https://ccp.netcup.net/run/webservice/servers/endpoint.php
+    fn call(
+        &self,
+        action: &str,
+        param: serde_json::Value,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .post(ENDPOINT)
+            .send_json(serde_json::json!({ "action": action, "param": param }));
+
+        let mut response = match response {
+            Ok(resp) | Err(Error::Status(_, resp)) => resp
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        };
+
+        if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+            let message = ["longmessage", "shortmessage"]
+                .iter()
+                .filter_map(|key| response.get(key).and_then(|m| m.as_str()))
+                .find(|message| !message.is_empty())
+                .unwrap_or("(null)");
+            let kind = match response.get("statuscode").and_then(|c| c.as_u64()) {
+                Some(4013) => ErrorKind::Validation,
+                _ => ErrorKind::Other,
+            };
+
+            return Err(DdnsUpdateError::provider("Netcup", kind, message.into()));
+        }
+
+        Ok(response
+            .get_mut("responsedata")
+            .map(serde_json::Value::take)
+            .unwrap_or_default())
+    }
+
+    /// Logs in, hands the session ID to `f` and logs out again afterwards,
+    /// whether `f` succeeds or not.
+    fn with_session<T>(
+        &self,
+        f: impl FnOnce(&str) -> Result<T, DdnsUpdateError>,
+    ) -> Result<T, DdnsUpdateError> {
+        let response = self.call(
+            "login",
+            serde_json::json!({
+                "customernumber": self.config.customer_number,
+                "apikey": self.config.api_key.expose(),
+                "apipassword": self.config.api_password.expose(),
+            }),
+        )?;
+
+        let Some(session) = response.get("apisessionid").and_then(|s| s.as_str()) else {
+            return Err(DdnsUpdateError::Json("no session was returned".into()));
+        };
+
+        let result = f(session);
+
+        let logout = self.call(
+            "logout",
+            serde_json::json!({
+                "customernumber": self.config.customer_number,
+                "apikey": self.config.api_key.expose(),
+                "apisessionid": session,
+            }),
+        );
+
+        // The session expires on its own anyway, so a failed logout is only
+        // worth reporting if nothing else went wrong.
+        let value = result?;
+        logout?;
+
+        Ok(value)
+    }
+
+    fn get_records(
+        &self,
+        session: &str,
+        zone: &str,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let response = self.call(
+            "infoDnsRecords",
+            serde_json::json!({
+                "domainname": zone,
+                "customernumber": self.config.customer_number,
+                "apikey": self.config.api_key.expose(),
+                "apisessionid": session,
+            }),
+        )?;
+
+        match response.get("dnsrecords").and_then(|r| r.as_array()) {
+            Some(records) => Ok(records.clone()),
+            None => Err(DdnsUpdateError::Json("expected a list of records".into())),
+        }
+    }
+
+    /// Points the records of the given names to the addresses, all in one
+    /// request. Records without an ID are created.
+    fn update_zone(
+        &self,
+        session: &str,
+        zone: &str,
+        names: &[&str],
+        ips: &[IpAddr],
+    ) -> Result<(), DdnsUpdateError> {
+        let records = self.get_records(session, zone)?;
+        let mut changed = Vec::new();
+
+        for name in names {
+            for ip in ips {
+                let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+
+                let existing = records.iter().find(|record| {
+                    record.get("hostname").and_then(|h| h.as_str()) == Some(name)
+                        && record.get("type").and_then(|t| t.as_str()) == Some(kind)
+                });
+
+                match existing {
+                    Some(record) => {
+                        if record.get("destination").and_then(|d| d.as_str())
+                            == Some(&ip.to_string())
+                        {
+                            continue;
+                        }
+
+                        let mut record = record.clone();
+                        record["destination"] = ip.to_string().into();
+                        changed.push(record);
+                    }
+
+                    None => changed.push(serde_json::json!({
+                        "hostname": name,
+                        "type": kind,
+                        "destination": ip.to_string(),
+                    })),
+                }
+            }
+        }
+
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        self.call(
+            "updateDnsRecords",
+            serde_json::json!({
+                "domainname": zone,
+                "customernumber": self.config.customer_number,
+                "apikey": self.config.api_key.expose(),
+                "apisessionid": session,
+                "dnsrecordset": { "dnsrecords": changed },
+            }),
+        )?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        // The domains are grouped by their zone, so that the records of each
+        // zone are only fetched and updated once.
+        let mut batches: Vec<(&str, Vec<&str>)> = Vec::new();
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&self.config.zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Netcup",
+                    ErrorKind::NotFound,
+                    format!("none of the zones contains {}", domain).into(),
+                ));
+            };
+
+            match batches.iter_mut().find(|(z, _)| **z == **zone) {
+                Some((_, names)) => names.push(name),
+                None => batches.push((zone, vec![name])),
+            }
+        }
+
+        self.with_session(|session| {
+            for (zone, names) in &batches {
+                self.update_zone(session, zone, names, result.as_slice())?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.with_session(|session| {
+            for zone in &self.config.zones {
+                self.get_records(session, zone)?;
+            }
+
+            Ok(describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.config.zones, domain, "@").is_some()
+            }))
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/netcup.txt"));
+
+        let mut service = Service::new(
+            Config {
+                customer_number: "12345".into(),
+                api_key: Secret::new("key".into()),
+                api_password: Secret::new("password".into()),
+                zones: vec!["example.com".into()],
+                domains: vec!["example.com".into(), "www.example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent()
+            .iter()
+            .map(|s| serde_json::from_str::<serde_json::Value>(s.body.as_deref().unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        let actions = sent
+            .iter()
+            .map(|body| body["action"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            ["login", "infoDnsRecords", "updateDnsRecords", "logout"]
+        );
+        assert_eq!(sent[1]["param"]["apisessionid"], "session");
+
+        // The A record of www.example.com is already up to date, and there
+        // is no AAAA record for it yet.
+        let records = &sent[2]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(
+            *records,
+            serde_json::json!([
+                {
+                    "id": "1",
+                    "hostname": "@",
+                    "type": "A",
+                    "priority": "0",
+                    "destination": "192.0.2.1",
+                    "deleterecord": false,
+                    "state": "yes",
+                },
+                {
+                    "id": "2",
+                    "hostname": "@",
+                    "type": "AAAA",
+                    "priority": "0",
+                    "destination": "2001:db8::1",
+                    "deleterecord": false,
+                    "state": "yes",
+                },
+                { "hostname": "www", "type": "AAAA", "destination": "2001:db8::1" },
+            ])
+        );
+
+        // The session is closed even though the update failed.
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "The domain is not registered with this customer."
+        ));
+        let sent = take_sent();
+        assert_eq!(sent.len(), 3);
+
+        service.config.domains = vec!["example.net".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "none of the zones contains example.net"
+        ));
+    }
+}
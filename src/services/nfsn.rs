@@ -0,0 +1,319 @@
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data_encoding::HEXLOWER;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{url_encode, FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.nearlyfreespeech.net";
+
+/// The TTL of the records if none is configured.
+const DEFAULT_TTL: u32 = 3600;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The member login, and an API key requested through a secure support
+    /// request.
+    login: Box<str>,
+    api_key: Secret<Box<str>>,
+
+    /// The DNS zones managed by NFSN, which the domains are found in. NFSN
+    /// has no way to list them.
+    #[serde(deserialize_with = "one_or_more_string")]
+    zones: Vec<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default = "default_ttl")]
+    ttl: u32,
+}
+
+fn default_ttl() -> u32 {
+    DEFAULT_TTL
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+/// Returns the value of the X-NFSN-Authentication header, which is
+/// "login;timestamp;salt;hash", the hash being the SHA-1 of the same fields
+/// followed by the API key, the path, and the SHA-1 of the body.
+///
+/// See: https://members.nearlyfreespeech.net/wiki/API/Introduction
+fn sign(login: &str, api_key: &str, timestamp: u64, salt: &str, path: &str, body: &str) -> String {
+    let sha1 = |data: &[u8]| {
+        HEXLOWER.encode(digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, data).as_ref())
+    };
+
+    let timestamp = timestamp.to_string();
+    let body_hash = sha1(body.as_bytes());
+    let hashed = [login, &timestamp, salt, api_key, path, &body_hash].join(";");
+
+    [login, &timestamp, salt, &sha1(hashed.as_bytes())].join(";")
+}
+
+impl Service {
+    /// Errors are reported as {"error": "...", "debug": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let error = resp_json.get("error").and_then(|e| e.as_str());
+        let debug = resp_json.get("debug").and_then(|d| d.as_str());
+
+        match (error, debug) {
+            (Some(error), Some(debug)) => Ok(format!("{} ({})", error, debug).into()),
+            (Some(error), None) => Ok(error.into()),
+            (None, _) => Err(String::from("expected string")),
+        }
+    }
+
+    /// Sends a signed POST request with the form-encoded parameters, and
+    /// returns the response body.
+    fn call(&self, path: &str, params: &[(&str, &str)]) -> Result<String, DdnsUpdateError> {
+        let body = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, url_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs(),
+            Err(_) => 0,
+        };
+
+        let mut salt = [0u8; 8];
+        SystemRandom::new().fill(&mut salt).map_err(|_| {
+            DdnsUpdateError::provider(
+                "NearlyFreeSpeech.NET",
+                ErrorKind::Other,
+                "no random numbers available".into(),
+            )
+        })?;
+        let salt = HEXLOWER.encode(&salt);
+
+        let auth = sign(
+            &self.config.login,
+            self.config.api_key.expose(),
+            timestamp,
+            &salt,
+            path,
+            &body,
+        );
+
+        let response = self
+            .client
+            .post(&format!("{}{}", ENDPOINT, path))
+            .set("X-NFSN-Authentication", &auth)
+            .set("Content-Type", "application/x-www-form-urlencoded")
+            .send_string(&body);
+
+        match response {
+            Ok(r) => r.into_string().map_err(|e| {
+                DdnsUpdateError::provider(
+                    "NearlyFreeSpeech.NET",
+                    ErrorKind::Other,
+                    e.to_string().into(),
+                )
+            }),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider(
+                    "NearlyFreeSpeech.NET",
+                    kind,
+                    message,
+                ))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// See: https://members.nearlyfreespeech.net/wiki/API/DNSListRRs
+    fn list_records(
+        &self,
+        zone: &str,
+        name: &str,
+        kind: &str,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let path = format!("/dns/{}/listRRs", zone);
+        let response = self.call(&path, &[("name", name), ("type", kind)])?;
+
+        let records = serde_json::from_str::<serde_json::Value>(&response)
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+        match records {
+            serde_json::Value::Array(records) => Ok(records),
+            _ => Err(DdnsUpdateError::Json("expected a list of records".into())),
+        }
+    }
+
+    /// Replaces the records of the given name and type with the address alone,
+    /// unless it is the only one already.
+    ///
+    /// See: https://members.nearlyfreespeech.net/wiki/API/DNSReplaceRR
+    fn set_record(&self, zone: &str, name: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let existing = self.list_records(zone, name, kind)?;
+
+        if let [record] = &existing[..] {
+            if record.get("data").and_then(|d| d.as_str()) == Some(&ip.to_string())
+                && record.get("ttl").and_then(|t| t.as_u64()) == Some(self.config.ttl as u64)
+            {
+                return Ok(());
+            }
+        }
+
+        let path = format!("/dns/{}/replaceRR", zone);
+        let params = [
+            ("name", name),
+            ("type", kind),
+            ("data", &ip.to_string()),
+            ("ttl", &self.config.ttl.to_string()),
+        ];
+
+        self.call(&path, &params)?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&self.config.zones, domain, "") else {
+                return Err(DdnsUpdateError::provider(
+                    "NearlyFreeSpeech.NET",
+                    ErrorKind::Validation,
+                    format!("none of the zones contains {}", domain).into(),
+                ));
+            };
+
+            for ip in &result {
+                self.set_record(zone, name, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Lists a record of every zone, as a zone which is not managed by the
+    /// login is refused.
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let mut found = Vec::new();
+
+        for zone in &self.config.zones {
+            match self.list_records(zone, "", "NS") {
+                Ok(_) => found.push(zone.clone()),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        Some(Ok(describe_found_domains(&self.config.domains, |domain| {
+            find_zone(&found, domain, "").is_some()
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn signature() {
+        let auth = sign(
+            "user",
+            "apikey",
+            1_700_000_000,
+            "0123456789abcdef",
+            "/dns/example.com/listRRs",
+            "name=www&type=A",
+        );
+
+        assert_eq!(
+            auth,
+            "user;1700000000;0123456789abcdef;8988c3f781f64a30ec33e67cf2ba68d6c4638216"
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/nfsn.txt"));
+
+        let mut service = Service::new(
+            Config {
+                login: "user".into(),
+                api_key: Secret::new("apikey".into()),
+                zones: vec!["example.com".into()],
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: 3600,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        // The A record of example.com is up to date.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {} {}", s.method, s.url, s.body.as_deref().unwrap_or("")))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "POST https://api.nearlyfreespeech.net/dns/example.com/listRRs name=&type=A",
+                "POST https://api.nearlyfreespeech.net/dns/example.com/listRRs name=www&type=A",
+                "POST https://api.nearlyfreespeech.net/dns/example.com/replaceRR name=www&type=A&data=192.0.2.1&ttl=3600",
+            ]
+        );
+
+        let updated = service.update_record(&["2001:db8::1".parse().unwrap()]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            }) if &*m == "Authentication error. (Signature mismatch.)"
+        ));
+    }
+}
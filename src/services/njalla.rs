@@ -0,0 +1,288 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The TTL of newly created records if none is configured. Njalla only
+/// accepts a handful of values: 60, 300, 900, 3600, 10800, 21600 and 86400.
+const DEFAULT_TTL: u32 = 10800;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// An API token created under Settings > API Access.
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+        config.token = Secret::new((String::from("Njalla ") + config.token.expose()).into());
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Calls a method of the API. Failed calls are answered with
+    /// {"error": {"code": 403, "message": "..."}}, mostly with a status of 200.
+    ///
+    /// See: https://njal.la/api/
+    fn call(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .post("https://njal.la/api/1/")
+            .set("Authorization", self.config.token.expose())
+            .send_json(serde_json::json!({ "method": method, "params": params }));
+
+        let response = match response {
+            Ok(resp) | Err(Error::Status(_, resp)) => resp
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+
+            Err(Error::Transport(tp)) => {
+                return Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        };
+
+        if let Some(error) = response.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("(null)");
+            // The codes are those of HTTP, e.g. 403 for a token without the
+            // permission needed.
+            let code = error.get("code").and_then(|c| c.as_u64()).unwrap_or(0);
+            let kind = ErrorKind::from_status(code.try_into().unwrap_or(0));
+
+            return Err(DdnsUpdateError::provider("Njalla", kind, message.into()));
+        }
+
+        match response.get("result") {
+            Some(result) => Ok(result.clone()),
+            None => Err(DdnsUpdateError::Json("no result returned".into())),
+        }
+    }
+
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let result = self.call("list-domains", serde_json::json!({}))?;
+
+        let Some(domains) = result.get("domains").and_then(|d| d.as_array()) else {
+            return Err(DdnsUpdateError::Json("expected a list of domains".into()));
+        };
+
+        domains
+            .iter()
+            .map(|domain| match domain.get("name").and_then(|n| n.as_str()) {
+                Some(name) => Ok(name.into()),
+                None => Err(DdnsUpdateError::Json("domain has no name".into())),
+            })
+            .collect()
+    }
+
+    /// Points the record of the given name and type to the address, creating
+    /// the record if there is none.
+    fn set_record(
+        &self,
+        zone: &str,
+        records: &[serde_json::Value],
+        name: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+
+        let existing = records.iter().find(|record| {
+            record.get("name").and_then(|n| n.as_str()) == Some(name)
+                && record.get("type").and_then(|t| t.as_str()) == Some(kind)
+        });
+
+        match existing {
+            Some(record) => {
+                if record.get("content").and_then(|c| c.as_str()) == Some(&ip.to_string()) {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("id") else {
+                    return Err(DdnsUpdateError::Json("record has no id".into()));
+                };
+
+                let mut params = serde_json::json!({
+                    "domain": zone,
+                    "id": id,
+                    "content": ip.to_string(),
+                });
+
+                if let Some(ttl) = self.config.ttl {
+                    params["ttl"] = ttl.into();
+                }
+
+                self.call("edit-record", params)?;
+            }
+
+            None => {
+                self.call(
+                    "add-record",
+                    serde_json::json!({
+                        "domain": zone,
+                        "type": kind,
+                        "name": name,
+                        "content": ip.to_string(),
+                        "ttl": self.config.ttl.unwrap_or(DEFAULT_TTL),
+                    }),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&self.cached_zones, domain, "@") else {
+                return Err(DdnsUpdateError::provider(
+                    "Njalla",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            let result = self.call("list-records", serde_json::json!({ "domain": zone }))?;
+            let Some(records) = result.get("records").and_then(|r| r.as_array()) else {
+                return Err(DdnsUpdateError::Json("expected a list of records".into()));
+            };
+
+            for ip in [ipv4, ipv6].into_iter().flatten() {
+                self.set_record(zone, records, name, *ip)?;
+            }
+        }
+
+        let mut result = FixedVec::new();
+        if let Some(ipv4) = ipv4 {
+            result.push(*ipv4);
+        }
+        if let Some(ipv6) = ipv6 {
+            result.push(*ipv6);
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "@").is_some()
+            })
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/njalla.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is already up to date, and there is no
+        // AAAA record for www.example.com yet.
+        let sent = take_sent();
+        let methods = sent
+            .iter()
+            .map(|s| {
+                let body = serde_json::from_str::<serde_json::Value>(s.body.as_deref().unwrap());
+                body.unwrap()["method"].as_str().unwrap().to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            [
+                "list-domains",
+                "list-records",
+                "edit-record",
+                "list-records",
+                "edit-record",
+                "add-record",
+            ]
+        );
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["params"]["id"], 2);
+        assert_eq!(body["params"]["content"], "2001:db8::1");
+
+        let body = sent[5].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["params"]["name"], "www");
+        assert_eq!(body["params"]["type"], "AAAA");
+        assert_eq!(body["params"]["ttl"], 10800);
+
+        service.config.domains = vec!["example.net".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "no domain of the account contains example.net"
+        ));
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider { message: m, .. })) if &*m == "Permission denied"
+        ));
+    }
+}
@@ -1,22 +1,26 @@
 use std::net::IpAddr;
 
+use crate::http::Settings;
 use crate::util::FixedVec;
 
-use super::{shared_dyndns, DdnsService, DdnsUpdateError};
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, RecordType, SuspensionState};
 
 pub type Config = shared_dyndns::Config;
 
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
 pub struct Service {
     inner: shared_dyndns::Service,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
         Self {
             inner: shared_dyndns::Service::from_config(
                 "NoIP",
                 "https://dynupdate.no-ip.com/nic/update",
                 config,
+                http,
             ),
         }
     }
@@ -26,4 +30,49 @@ impl DdnsService for Service {
     fn update_record(&mut self, ip: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
         self.inner.update_record(ip)
     }
+
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.suspension()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+    use crate::services::{ErrorKind, Suspension};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/noip.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "home.ddns.net"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                provider: "NoIP",
+                kind: ErrorKind::NotFound,
+                ..
+            })
+        ));
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Indefinite))
+        ));
+        assert_eq!(take_sent().len(), 1);
+    }
 }
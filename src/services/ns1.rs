@@ -0,0 +1,344 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// An API key created under Account Settings > API Keys, allowed to view
+    /// and manage the zones.
+    api_key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. The TTL of the records is left
+    /// as it is if not given, and new records get the one of their zone.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"message": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("message")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("NS1", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// See: https://developer.ibm.com/apis/catalog/ns1--ibm-ns1-connect-api/api/API--ns1--ibm-ns1-connect-api#listZones
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get("https://api.nsone.net/v1/zones")
+            .set("X-NSONE-Key", self.config.api_key.expose())
+            .call();
+        let response = self.parse_and_check_response(response)?;
+
+        let Some(listed) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("expected a list of zones".into()));
+        };
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.get("zone").and_then(|z| z.as_str()) else {
+                return Err(DdnsUpdateError::Json("zone has no name?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// Points the record to the address alone. The record is looked up first,
+    /// so that an up-to-date one is left alone, and a missing one is created
+    /// (with PUT) instead of updated (with POST).
+    ///
+    /// See: https://developer.ibm.com/apis/catalog/ns1--ibm-ns1-connect-api/api/API--ns1--ibm-ns1-connect-api#updateRecord
+    fn set_record(&self, zone: &str, domain: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let url = format!(
+            "https://api.nsone.net/v1/zones/{}/{}/{}",
+            zone, domain, kind
+        );
+
+        let response = self
+            .client
+            .get(&url)
+            .set("X-NSONE-Key", self.config.api_key.expose())
+            .call();
+
+        let existing = match response {
+            Err(Error::Status(404, _)) => None,
+            response => Some(self.parse_and_check_response(response)?),
+        };
+
+        let answers = serde_json::json!([{ "answer": [ip.to_string()] }]);
+
+        let request = match existing {
+            Some(record) => {
+                let is_up_to_date = record
+                    .get("answers")
+                    .and_then(|a| a.as_array())
+                    .is_some_and(|existing| {
+                        existing.len() == 1 && existing[0].get("answer") == answers[0].get("answer")
+                    })
+                    && self.config.ttl.is_none_or(|ttl| {
+                        record.get("ttl").and_then(|t| t.as_u64()) == Some(ttl as u64)
+                    });
+
+                if is_up_to_date {
+                    return Ok(());
+                }
+
+                self.client.post(&url)
+            }
+
+            None => self.client.put(&url),
+        };
+
+        let mut body = serde_json::json!({
+            "zone": zone,
+            "domain": domain,
+            "type": kind,
+            "answers": answers,
+        });
+        if let Some(ttl) = self.config.ttl {
+            body["ttl"] = ttl.into();
+        }
+
+        let response = request
+            .set("X-NSONE-Key", self.config.api_key.expose())
+            .send_json(body);
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    ///
+    /// See: https://developer.ibm.com/apis/catalog/ns1--ibm-ns1-connect-api/api/API--ns1--ibm-ns1-connect-api#getZone
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            let response = self
+                .client
+                .get(&format!("https://api.nsone.net/v1/zones/{}", zone))
+                .set("X-NSONE-Key", self.config.api_key.expose())
+                .call();
+            let response = self.parse_and_check_response(response)?;
+
+            let Some(records) = response.get("records").and_then(|r| r.as_array()) else {
+                return Err(DdnsUpdateError::Json("zone has no records?".into()));
+            };
+
+            for record in records {
+                let kind = match record.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let Some(domain) = record.get("domain").and_then(|d| d.as_str()) else {
+                    return Err(DdnsUpdateError::Json("record has no domain?".into()));
+                };
+
+                found.push(DiscoveredRecord {
+                    name: domain.into(),
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some(zone) = find_zone(&self.cached_zones, domain, "").map(|(zone, _)| &**zone)
+            else {
+                return Err(DdnsUpdateError::provider(
+                    "NS1",
+                    ErrorKind::NotFound,
+                    format!("no zone of the account contains {}", domain).into(),
+                ));
+            };
+
+            for ip in &result {
+                self.set_record(zone, domain, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "")
+                    .map(|(zone, _)| &**zone)
+                    .is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/ns1.txt"));
+
+        let mut service = Service::new(
+            Config {
+                api_key: Secret::new("key".into()),
+                domains: vec!["example.com".into(), "home.dyn.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, dyn.example.com is a
+        // zone of its own, and home.dyn.example.com has no AAAA record yet.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.nsone.net/v1/zones",
+                "GET https://api.nsone.net/v1/zones/example.com/example.com/A",
+                "GET https://api.nsone.net/v1/zones/example.com/example.com/AAAA",
+                "POST https://api.nsone.net/v1/zones/example.com/example.com/AAAA",
+                "GET https://api.nsone.net/v1/zones/dyn.example.com/home.dyn.example.com/A",
+                "POST https://api.nsone.net/v1/zones/dyn.example.com/home.dyn.example.com/A",
+                "GET https://api.nsone.net/v1/zones/dyn.example.com/home.dyn.example.com/AAAA",
+                "PUT https://api.nsone.net/v1/zones/dyn.example.com/home.dyn.example.com/AAAA",
+            ]
+        );
+
+        let body = sent[7].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "zone": "dyn.example.com",
+                "domain": "home.dyn.example.com",
+                "type": "AAAA",
+                "answers": [{ "answer": ["2001:db8::1"] }],
+            })
+        );
+
+        service.config.domains = vec!["example.net".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "no zone of the account contains example.net"
+        ));
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Unauthorized"
+        ));
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(e)) if e.kind() == ErrorKind::RateLimited(Some(Duration::from_secs(30)))
+        ));
+    }
+}
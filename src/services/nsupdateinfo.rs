@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::Settings;
+use crate::util::{FixedVec, Secret};
+
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, RecordType, SuspensionState};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The update secret of each host, as shown by nsupdate.info when the
+    /// host is added (or when a new one is generated). The host doubles as
+    /// the username.
+    secrets: BTreeMap<Box<str>, Secret<Box<str>>>,
+}
+
+pub struct Service {
+    /// Every host has its own secret, so there is one client for each of them.
+    inner: Vec<shared_dyndns::Service>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let inner = config
+            .secrets
+            .into_iter()
+            .map(|(host, secret)| {
+                let config =
+                    shared_dyndns::Config::new(Secret::new(host.clone()), secret, vec![host]);
+
+                // The address of each family is sent to an endpoint only
+                // reachable through that family.
+                shared_dyndns::Service::from_config(
+                    "nsupdate.info",
+                    "https://ipv4.nsupdate.info/nic/update",
+                    config,
+                    http,
+                )
+                .with_ipv6_server("https://ipv6.nsupdate.info/nic/update")
+            })
+            .collect();
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    /// One suspended client holds back the others.
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.iter().find_map(|inner| inner.suspension())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::new();
+
+        for inner in &mut self.inner {
+            for ip in inner.update_record(ips)? {
+                if !result.as_slice().contains(&ip) {
+                    result.push(ip);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent, Settings};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/nsupdateinfo.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            [secrets]
+            "a.nsupdate.info" = "secret-a"
+            "b.nsupdate.info" = "secret-b"
+            "#,
+        )
+        .unwrap();
+
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        // The AAAA record of b.nsupdate.info is up to date.
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://ipv4.nsupdate.info/nic/update?hostname=a.nsupdate.info&myip=192.0.2.1",
+                "https://ipv6.nsupdate.info/nic/update?hostname=a.nsupdate.info&myip=2001%3Adb8%3A%3A1",
+                "https://ipv4.nsupdate.info/nic/update?hostname=b.nsupdate.info&myip=192.0.2.1",
+                "https://ipv6.nsupdate.info/nic/update?hostname=b.nsupdate.info&myip=2001%3Adb8%3A%3A1",
+            ]
+        );
+
+        // Without an IPv4 address, only the IPv6 endpoint is used.
+        let updated = service.update_record(&[ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv6]);
+
+        let sent = take_sent();
+        assert!(sent.iter().all(|s| s.url.starts_with("https://ipv6.")));
+    }
+}
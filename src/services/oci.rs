@@ -0,0 +1,468 @@
+use std::net::IpAddr;
+
+use data_encoding::BASE64;
+use ring::digest;
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret, UtcDateTime};
+
+use super::{describe_found_domains, DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The version of the DNS API, which every path starts with.
+const API_VERSION: &str = "/20180115";
+
+/// The TTL of the records if none is configured.
+const DEFAULT_TTL: u32 = 300;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The OCIDs of the tenancy and of a user allowed to manage the zone, the
+    /// fingerprint of an API key of the user, and its PEM-encoded private key.
+    tenancy: Box<str>,
+    user: Box<str>,
+    fingerprint: Box<str>,
+    private_key: Secret<Box<str>>,
+
+    /// The region of the API, e.g. "eu-frankfurt-1".
+    region: Box<str>,
+
+    /// The name or OCID of the zone the domains belong to.
+    zone: Box<str>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    host: Box<str>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            host: format!("dns.{}.oci.oraclecloud.com", config.region).into(),
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+/// Signs a request the way OCI wants it and returns the value of its
+/// Authorization header. The date, request target and host are always signed,
+/// and so is the body of the requests which have one, given as the content
+/// type and the body.
+///
+/// See: https://docs.oracle.com/en-us/iaas/Content/API/Concepts/signingrequests.htm
+fn sign(
+    key_id: &str,
+    private_key: &str,
+    date: &str,
+    method: &str,
+    host: &str,
+    target: &str,
+    content: Option<(&str, &str)>,
+) -> Result<String, &'static str> {
+    // Keys generated by OCI are PKCS#8, older ones made with OpenSSL PKCS#1.
+    let is_pkcs1 = private_key.contains("BEGIN RSA PRIVATE KEY");
+
+    let der = private_key
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("-----"))
+        .collect::<String>();
+
+    let der = BASE64
+        .decode(der.as_bytes())
+        .map_err(|_| "private_key is not valid PEM")?;
+
+    let key_pair = if is_pkcs1 {
+        RsaKeyPair::from_der(&der)
+    } else {
+        RsaKeyPair::from_pkcs8(&der)
+    }
+    .map_err(|_| "private_key is not an RSA key")?;
+
+    let mut headers = vec![
+        ("date", date.to_owned()),
+        (
+            "(request-target)",
+            format!("{} {}", method.to_lowercase(), target),
+        ),
+        ("host", host.to_owned()),
+    ];
+
+    if let Some((content_type, body)) = content {
+        let sha256 = digest::digest(&digest::SHA256, body.as_bytes());
+        headers.push(("content-length", body.len().to_string()));
+        headers.push(("content-type", content_type.to_owned()));
+        headers.push(("x-content-sha256", BASE64.encode(sha256.as_ref())));
+    }
+
+    let signing_string = headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut signature = vec![0; key_pair.public().modulus_len()];
+    key_pair
+        .sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            signing_string.as_bytes(),
+            &mut signature,
+        )
+        .map_err(|_| "unable to sign the request")?;
+
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Ok(format!(
+        "Signature version=\"1\",keyId=\"{}\",algorithm=\"rsa-sha256\",headers=\"{}\",signature=\"{}\"",
+        key_id,
+        signed_headers,
+        BASE64.encode(&signature)
+    ))
+}
+
+impl Service {
+    /// Errors are reported as {"code": "...", "message": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let code = resp_json.get("code").and_then(|c| c.as_str());
+        let message = resp_json.get("message").and_then(|m| m.as_str());
+
+        match (code, message) {
+            (Some(code), Some(message)) => Ok(format!("{}: {}", code, message).into()),
+            (None, Some(message)) => Ok(message.into()),
+            (_, None) => Err(String::from("expected string")),
+        }
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into())),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Oracle Cloud DNS", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// Sends a signed request, with a JSON body if one is given. The path,
+    /// query included, is signed as it is sent.
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let target = format!("{}{}", API_VERSION, path);
+        let date = UtcDateTime::now().to_http_date();
+        let key_id = format!(
+            "{}/{}/{}",
+            self.config.tenancy, self.config.user, self.config.fingerprint
+        );
+
+        let body = body.map(|body| body.to_string());
+        let authorization = sign(
+            &key_id,
+            self.config.private_key.expose(),
+            &date,
+            method,
+            &self.host,
+            &target,
+            body.as_deref().map(|body| ("application/json", body)),
+        )
+        .map_err(|e| DdnsUpdateError::provider("Oracle Cloud DNS", ErrorKind::Auth, e.into()))?;
+
+        let url = format!("https://{}{}", self.host, target);
+        let request = match method {
+            "PATCH" => self.client.patch(&url),
+            _ => self.client.get(&url),
+        };
+
+        let request = request
+            .set("Date", &date)
+            .set("Authorization", &authorization);
+
+        // The client sends the Content-Length of the body itself.
+        let response = match body {
+            Some(body) => {
+                let sha256 = digest::digest(&digest::SHA256, body.as_bytes());
+                request
+                    .set("Content-Type", "application/json")
+                    .set("X-Content-SHA256", &BASE64.encode(sha256.as_ref()))
+                    .send_string(&body)
+            }
+            None => request.call(),
+        };
+
+        self.parse_and_check_response(response)
+    }
+
+    /// Lists the records of the domain of the given type, e.g. [{"domain":
+    /// "www.example.com", "recordHash": "...", "rdata": "192.0.2.1", "rtype":
+    /// "A", "ttl": 300}].
+    ///
+    /// See: https://docs.oracle.com/en-us/iaas/api/#/en/dns/20180115/Records/GetDomainRecords
+    fn get_records(
+        &self,
+        domain: &str,
+        kind: &str,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let path = format!(
+            "/zones/{}/records/{}?rtype={}",
+            self.config.zone, domain, kind
+        );
+        let mut response = self.send("GET", &path, None)?;
+
+        match response.get_mut("items").map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(records)) => Ok(records),
+            _ => Err(DdnsUpdateError::Json("expected a list of records".into())),
+        }
+    }
+
+    /// Points the records of the domain of the address' type to it alone, by
+    /// removing the other records of the type and adding the address.
+    ///
+    /// See: https://docs.oracle.com/en-us/iaas/api/#/en/dns/20180115/Records/PatchDomainRecords
+    fn set_records(&self, domain: &str, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let rdata = ip.to_string();
+        let existing = self.get_records(domain, kind)?;
+
+        let ttl = self.config.ttl.unwrap_or(DEFAULT_TTL);
+        let is_current = |record: &serde_json::Value| {
+            record.get("rdata").and_then(|r| r.as_str()) == Some(&rdata)
+                && record.get("ttl").and_then(|t| t.as_u64()) == Some(ttl as u64)
+        };
+
+        if let [record] = &existing[..] {
+            if is_current(record) {
+                return Ok(());
+            }
+        }
+
+        let mut operations = Vec::new();
+        for record in &existing {
+            let Some(hash) = record.get("recordHash").and_then(|h| h.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no recordHash?".into()));
+            };
+
+            operations.push(serde_json::json!({
+                "operation": "REMOVE",
+                "recordHash": hash,
+            }));
+        }
+
+        operations.push(serde_json::json!({
+            "operation": "ADD",
+            "domain": domain,
+            "rtype": kind,
+            "rdata": rdata,
+            "ttl": ttl,
+        }));
+
+        let path = format!("/zones/{}/records/{}", self.config.zone, domain);
+        self.send(
+            "PATCH",
+            &path,
+            Some(serde_json::json!({ "items": operations })),
+        )?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            for ip in &result {
+                self.set_records(domain, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Looks up the zone, whose name the domains have to end with.
+    ///
+    /// See: https://docs.oracle.com/en-us/iaas/api/#/en/dns/20180115/Zone/GetZone
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self
+            .send("GET", &format!("/zones/{}", self.config.zone), None)
+            .and_then(|zone| {
+                let Some(name) = zone.get("name").and_then(|n| n.as_str()) else {
+                    return Err(DdnsUpdateError::Json("zone has no name?".into()));
+                };
+
+                Ok(describe_found_domains(&self.config.domains, |domain| {
+                    domain == name || domain.ends_with(&format!(".{}", name))
+                }))
+            });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+    use ring::signature::{KeyPair, UnparsedPublicKey, RSA_PKCS1_2048_8192_SHA256};
+
+    const PRIVATE_KEY: &str = include_str!("fixtures/google-key.pem");
+
+    #[test]
+    fn signature() {
+        let body = r#"{"items":[]}"#;
+        let authorization = sign(
+            "ocid1.tenancy.oc1..aaaa/ocid1.user.oc1..bbbb/20:3b:97:13:55",
+            PRIVATE_KEY,
+            "Thu, 05 Jan 2014 21:31:40 GMT",
+            "PATCH",
+            "dns.eu-frankfurt-1.oci.oraclecloud.com",
+            "/20180115/zones/example.com/records/www.example.com",
+            Some(("application/json", body)),
+        )
+        .unwrap();
+
+        let (params, signature) = authorization.rsplit_once(",signature=").unwrap();
+        assert_eq!(
+            params,
+            "Signature version=\"1\",keyId=\"ocid1.tenancy.oc1..aaaa/ocid1.user.oc1..bbbb/20:3b:97:13:55\",\
+             algorithm=\"rsa-sha256\",headers=\"date (request-target) host content-length content-type x-content-sha256\""
+        );
+
+        let signing_string = "date: Thu, 05 Jan 2014 21:31:40 GMT\n\
+             (request-target): patch /20180115/zones/example.com/records/www.example.com\n\
+             host: dns.eu-frankfurt-1.oci.oraclecloud.com\n\
+             content-length: 12\n\
+             content-type: application/json\n\
+             x-content-sha256: 7vRnQa38Op92KU07ePN6RfETCSrJ1E7nfHoDioj/CaE=";
+
+        let der = PRIVATE_KEY
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>();
+        let key_pair = RsaKeyPair::from_pkcs8(&BASE64.decode(der.as_bytes()).unwrap()).unwrap();
+        let public_key =
+            UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key_pair.public_key().as_ref());
+
+        let signature = BASE64
+            .decode(signature.trim_matches('"').as_bytes())
+            .unwrap();
+        assert!(public_key
+            .verify(signing_string.as_bytes(), &signature)
+            .is_ok());
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/oci.txt"));
+
+        let mut service = Service::new(
+            Config {
+                tenancy: "ocid1.tenancy.oc1..aaaa".into(),
+                user: "ocid1.user.oc1..bbbb".into(),
+                fingerprint: "20:3b:97:13:55".into(),
+                private_key: Secret::new(PRIVATE_KEY.into()),
+                region: "eu-frankfurt-1".into(),
+                zone: "example.com".into(),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The A record of example.com is up to date, and www.example.com has
+        // no AAAA record yet.
+        let sent = take_sent();
+        let zone = "https://dns.eu-frankfurt-1.oci.oraclecloud.com/20180115/zones/example.com";
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                format!("GET {}/records/example.com?rtype=A", zone),
+                format!("GET {}/records/example.com?rtype=AAAA", zone),
+                format!("PATCH {}/records/example.com", zone),
+                format!("GET {}/records/www.example.com?rtype=A", zone),
+                format!("PATCH {}/records/www.example.com", zone),
+                format!("GET {}/records/www.example.com?rtype=AAAA", zone),
+                format!("PATCH {}/records/www.example.com", zone),
+            ]
+        );
+
+        let body = sent[4].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "items": [
+                { "operation": "REMOVE", "recordHash": "hash-3" },
+                { "operation": "REMOVE", "recordHash": "hash-4" },
+                { "operation": "ADD", "domain": "www.example.com", "rtype": "A", "rdata": "192.0.2.1", "ttl": 300 },
+            ] })
+        );
+
+        let body = sent[6].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "items": [
+                { "operation": "ADD", "domain": "www.example.com", "rtype": "AAAA", "rdata": "2001:db8::1", "ttl": 300 },
+            ] })
+        );
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "NotAuthenticated: The required information to complete authentication was not provided or was incorrect."
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+use std::net::IpAddr;
+
+use crate::http::Settings;
+use crate::util::FixedVec;
+
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, ErrorKind, RecordType, SuspensionState};
+
+/// The username and password are the ones of a DynHost identifier, created in
+/// the "DynHost" tab of the DNS zone. They are not the OVH account's.
+pub type Config = shared_dyndns::Config;
+
+/// DynHost only manages A records.
+pub const RECORDS: &[RecordType] = &[RecordType::A];
+
+pub struct Service {
+    /// DynHost does not accept several hostnames in one request, so there is
+    /// one client for each of them.
+    inner: Vec<shared_dyndns::Service>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let inner = config
+            .split_domains()
+            .into_iter()
+            .map(|config| {
+                shared_dyndns::Service::from_config(
+                    "OVH",
+                    "https://www.ovh.com/nic/update?system=dyndns",
+                    config,
+                    http,
+                )
+            })
+            .collect();
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    /// One suspended client holds back the others.
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.iter().find_map(|inner| inner.suspension())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let Some(ipv4) = ips.iter().find(|ip| ip.is_ipv4()) else {
+            return Err(DdnsUpdateError::provider(
+                "OVH",
+                ErrorKind::Validation,
+                "DynHost only supports IPv4 addresses".into(),
+            ));
+        };
+
+        let mut result = FixedVec::new();
+
+        for inner in &mut self.inner {
+            let updated = inner.update_record(&[*ipv4])?;
+
+            if result.get(0).is_none() {
+                if let Some(ip) = updated.get(0) {
+                    result.push(*ip);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent, Settings};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/ovh.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "example.com-dynhost"
+            password = "password"
+            domains = ["a.example.com", "b.example.com"]
+            "#,
+        )
+        .unwrap();
+
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv6, ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(
+            &*sent[1].url,
+            "https://www.ovh.com/nic/update?system=dyndns&hostname=b.example.com&myip=192.0.2.1"
+        );
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                provider: "OVH",
+                kind: ErrorKind::Validation,
+                ..
+            })
+        ));
+    }
+}
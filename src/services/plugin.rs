@@ -0,0 +1,459 @@
+//! Out-of-tree providers, as executables dropped into the `plugins_dir` of
+//! [general]. The plugin is run on every update with a JSON request as its
+//! stdin and answers with a JSON reply on its stdout:
+//!
+//!   {"version":1,"action":"update","domains":["home.example.net"],
+//!    "options":{...},"ipv4":"192.0.2.1","ipv6":null}
+//!
+//!   {"status":"updated","addresses":["192.0.2.1"]}
+//!   {"status":"unchanged"}
+//!   {"status":"error","kind":"auth","message":"the token was refused"}
+//!
+//! `options` is the [ddns.<name>.options] table of the entry as it is. The
+//! addresses of an "updated" reply may be left out if they are the ones sent,
+//! and the `kind` of an error is one of the names of `ErrorKind` (see
+//! `ErrorKind::name`), with `retry_after` in seconds for "rate_limited".
+//!
+//! `dynners test` sends the "test" action instead, without the addresses. It
+//! is answered with {"status":"ok","description":"..."}, an error, or
+//! {"status":"unsupported"} if the plugin cannot check its credentials
+//! without modifying any record. Anything a plugin prints to stderr is only
+//! used when it fails without a reply.
+
+use std::io::Write;
+use std::net::IpAddr;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::http;
+use crate::ip::{kill_group, read_in_background, wait_for_reader};
+use crate::util::{one_or_more_string, FixedVec, Secret};
+
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, ServiceSettings};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The version of the protocol spoken with the plugins.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// How often we check whether the plugin has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub struct Config {
+    plugin: Box<str>,
+    domains: Vec<Box<str>>,
+    options: Secret<Map<String, Value>>,
+    timeout: u32,
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    /// The file name of the executable within `plugins_dir`.
+    plugin: Box<str>,
+
+    #[serde(default, deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// Passed to the plugin as they are, e.g. its credentials.
+    #[serde(default)]
+    options: Map<String, Value>,
+
+    /// In seconds, 0 waits forever.
+    #[serde(default = "default_timeout")]
+    timeout: u32,
+}
+
+fn default_timeout() -> u32 {
+    60
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        // The plugins are confined to their directory.
+        if raw.plugin.is_empty() || raw.plugin.contains('/') || raw.plugin.starts_with('.') {
+            return Err("plugin must be the name of a file within plugins_dir");
+        }
+
+        // There is no telling which of the options are credentials, so all of
+        // them are kept out of the logs and the diagnostics.
+        raw.options.values().for_each(register_strings);
+
+        Ok(Config {
+            plugin: raw.plugin,
+            domains: raw.domains,
+            options: Secret::new(raw.options),
+            timeout: raw.timeout,
+        })
+    }
+}
+
+fn register_strings(value: &Value) {
+    match value {
+        Value::String(string) => http::register_secret(string),
+        Value::Array(values) => values.iter().for_each(register_strings),
+        Value::Object(values) => values.values().for_each(register_strings),
+        _ => (),
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    version: u32,
+    action: &'static str,
+    domains: &'a [Box<str>],
+    options: &'a Map<String, Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ipv4: Option<Option<IpAddr>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ipv6: Option<Option<IpAddr>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Reply {
+    Updated {
+        #[serde(default)]
+        addresses: Option<Vec<IpAddr>>,
+    },
+    Unchanged,
+    Ok {
+        #[serde(default)]
+        description: Option<Box<str>>,
+    },
+    Unsupported,
+    Error {
+        #[serde(default)]
+        kind: Option<Box<str>>,
+        #[serde(default)]
+        retry_after: Option<u64>,
+        message: Box<str>,
+    },
+}
+
+pub struct Service {
+    config: Config,
+    path: PathBuf,
+}
+
+impl Service {
+    pub fn new(config: Config, settings: &ServiceSettings) -> Self {
+        Self {
+            path: PathBuf::from(&*settings.plugins_dir).join(&*config.plugin),
+            config,
+        }
+    }
+
+    fn error(&self, kind: ErrorKind, message: &str) -> DdnsUpdateError {
+        let message = format!("{}: {}", self.config.plugin, message);
+        DdnsUpdateError::provider("plugin", kind, message.into())
+    }
+
+    /// Runs the plugin with the request, and waits for its reply.
+    fn call(&self, request: &Request) -> Result<Reply, DdnsUpdateError> {
+        // The plugin gets a process group of its own, so that whatever it
+        // starts is killed along with it once it times out.
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .map_err(|e| {
+                let message = format!("unable to run {}: {}", self.path.display(), e);
+                self.error(ErrorKind::Other, &message)
+            })?;
+
+        let deadline = match self.config.timeout {
+            0 => None,
+            timeout => Some(Instant::now() + Duration::from_secs(timeout.into())),
+        };
+
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+
+        // The request is written on another thread as well, so that a plugin
+        // which never reads it cannot keep us waiting past the deadline. A
+        // plugin which does not care about the request may well exit before
+        // reading it, so a broken pipe is not an error in itself.
+        if let Some(mut stdin) = child.stdin.take() {
+            let mut request = serde_json::to_vec(request).expect("unable to serialize the request");
+            request.push(b'\n');
+
+            thread::spawn(move || {
+                let _ = stdin.write_all(&request);
+            });
+        }
+
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => (),
+                Err(e) => return Err(self.error(ErrorKind::Other, &e.to_string())),
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                kill_group(&mut child);
+                return Err(self.error(ErrorKind::Transport, "timed out"));
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        // Something the plugin left running in the background may still hold
+        // its stdout or stderr open.
+        let timed_out = || self.error(ErrorKind::Transport, "timed out");
+
+        if !wait_for_reader(&stdout, &mut child, deadline) {
+            return Err(timed_out());
+        }
+
+        let stdout = stdout.join().unwrap_or_default();
+        if let Ok(reply) = serde_json::from_slice::<Reply>(&stdout) {
+            return Ok(reply);
+        }
+
+        if !wait_for_reader(&stderr, &mut child, deadline) {
+            return Err(timed_out());
+        }
+
+        let stderr = stderr.join().unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&stderr);
+        let last_line = stderr.lines().rev().find(|line| !line.trim().is_empty());
+
+        let message = match (status.success(), last_line) {
+            (true, _) => String::from("gave no valid reply"),
+            (false, Some(line)) => format!("ended with {}: {}", status, line.trim()),
+            (false, None) => format!("ended with {}", status),
+        };
+
+        Err(self.error(ErrorKind::Other, &message))
+    }
+
+    fn reply_error(
+        &self,
+        kind: Option<&str>,
+        retry_after: Option<u64>,
+        message: &str,
+    ) -> DdnsUpdateError {
+        let kind = match kind.unwrap_or("other") {
+            "auth" => ErrorKind::Auth,
+            "rate_limited" => ErrorKind::RateLimited(retry_after.map(Duration::from_secs)),
+            "not_found" => ErrorKind::NotFound,
+            "validation" => ErrorKind::Validation,
+            "transport" => ErrorKind::Transport,
+            "outage" => ErrorKind::Outage,
+            _ => ErrorKind::Other,
+        };
+
+        self.error(kind, message)
+    }
+
+    fn unexpected(&self, reply: Reply) -> DdnsUpdateError {
+        self.error(ErrorKind::Other, &format!("unexpected reply {:?}", reply))
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let ipv4 = ips.iter().copied().find(IpAddr::is_ipv4);
+        let ipv6 = ips.iter().copied().find(IpAddr::is_ipv6);
+
+        let request = Request {
+            version: PROTOCOL_VERSION,
+            action: "update",
+            domains: &self.config.domains,
+            options: self.config.options.expose(),
+            ipv4: Some(ipv4),
+            ipv6: Some(ipv6),
+        };
+
+        let mut result = FixedVec::new();
+
+        match self.call(&request)? {
+            Reply::Updated {
+                addresses: Some(addresses),
+            } => result.extend(addresses.into_iter().take(2)),
+            Reply::Updated { addresses: None } => {
+                result.extend(ipv4);
+                result.extend(ipv6);
+            }
+            Reply::Unchanged => (),
+            Reply::Error {
+                kind,
+                retry_after,
+                message,
+            } => return Err(self.reply_error(kind.as_deref(), retry_after, &message)),
+            reply => return Err(self.unexpected(reply)),
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let request = Request {
+            version: PROTOCOL_VERSION,
+            action: "test",
+            domains: &self.config.domains,
+            options: self.config.options.expose(),
+            ipv4: None,
+            ipv6: None,
+        };
+
+        let reply = match self.call(&request) {
+            Ok(reply) => reply,
+            Err(e) => return Some(Err(e)),
+        };
+
+        match reply {
+            Reply::Ok { description } => Some(Ok(description
+                .map(String::from)
+                .unwrap_or_else(|| String::from("the plugin accepted the credentials")))),
+            Reply::Unsupported => None,
+            Reply::Error {
+                kind,
+                retry_after,
+                message,
+            } => Some(Err(self.reply_error(
+                kind.as_deref(),
+                retry_after,
+                &message,
+            ))),
+            reply => Some(Err(self.unexpected(reply))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    /// A plugin which saves the request next to itself, and answers depending
+    /// on the token in its options.
+    const PLUGIN: &str = r#"#!/bin/sh
+read -r request
+echo "$request" > "$0.request"
+case "$request" in
+    *'"action":"test"'*) echo '{"status":"ok","description":"2 zones"}' ;;
+    *'"token":"good"'*) echo '{"status":"updated"}' ;;
+    *'"token":"forking"'*) sleep 30 & echo '{"status":"unchanged"}' ;;
+    *'"token":"slow"'*) echo '{"status":"error","kind":"rate_limited","retry_after":60,"message":"slow down"}' ;;
+    *) echo 'no such token' >&2; exit 1 ;;
+esac
+"#;
+
+    #[test]
+    fn plugin() {
+        let dir = std::env::temp_dir().join(format!("dynners-plugins-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("example");
+        fs::write(&path, PLUGIN).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let settings = ServiceSettings {
+            plugins_dir: dir.to_string_lossy().into(),
+            ..ServiceSettings::default()
+        };
+
+        let service = |token: &str| {
+            let config = toml::from_str::<Config>(&format!(
+                r#"
+                plugin = "example"
+                domains = "home.example.net"
+                options = {{ token = "{}" }}
+                "#,
+                token
+            ))
+            .unwrap();
+
+            Service::new(config, &settings)
+        };
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service("good").update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let request = fs::read_to_string(dir.join("example.request")).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&request).unwrap(),
+            serde_json::json!({
+                "version": 1,
+                "action": "update",
+                "domains": ["home.example.net"],
+                "options": { "token": "good" },
+                "ipv4": "192.0.2.1",
+                "ipv6": null,
+            })
+        );
+
+        assert!(matches!(
+            service("slow").update_record(&[ipv4]),
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::RateLimited(Some(d)),
+                message: m,
+                ..
+            }) if d == Duration::from_secs(60) && &*m == "example: slow down"
+        ));
+
+        assert!(matches!(
+            service("bad").update_record(&[ipv4]),
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Other,
+                message: m,
+                ..
+            }) if m.ends_with(": no such token")
+        ));
+
+        // The background sleep keeps stdout open after the plugin has exited,
+        // and is killed along with it.
+        let mut forking = service("forking");
+        forking.config.timeout = 1;
+        let started = Instant::now();
+        assert!(matches!(
+            forking.update_record(&[ipv4]),
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Transport,
+                ..
+            })
+        ));
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let tested = service("bad").test_credentials().unwrap().unwrap();
+        assert_eq!(tested, "2 zones");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(r#"plugin = "../bin/sh""#);
+        assert!(config.is_err());
+
+        let config = toml::from_str::<Config>(
+            r#"
+            plugin = "example"
+            options = { token = "hunter2hunter2" }
+            "#,
+        )
+        .unwrap();
+        assert!(!format!("{:?}", config).contains("hunter2hunter2"));
+        assert!(!serde_json::to_string(&config)
+            .unwrap()
+            .contains("hunter2hunter2"));
+    }
+}
@@ -3,29 +3,35 @@ use std::net::IpAddr;
 
 use serde_derive::{Deserialize, Serialize};
 
-use crate::http::{Error, Request};
-use crate::util::{one_or_more_string, FixedVec};
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
 
-use super::{DdnsService, DdnsUpdateError};
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct Config {
-    secret_api_key: Box<str>,
+    secret_api_key: Secret<Box<str>>,
 
-    api_key: Box<str>,
+    api_key: Secret<Box<str>>,
 
     #[serde(deserialize_with = "one_or_more_string")]
     domains: Vec<Box<str>>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct Service {
     config: Config,
+    client: Client,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
-        Self { config }
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
     }
 }
 
@@ -33,6 +39,7 @@ impl Service {
     fn parse_error(error: Error) -> Result<Infallible, DdnsUpdateError> {
         match error {
             Error::Status(code, resp) if code < 500 => {
+                let kind = ErrorKind::from_response(code, &resp);
                 let json = resp
                     .into_json::<serde_json::Value>()
                     .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
@@ -40,11 +47,19 @@ impl Service {
                     .get("message")
                     .and_then(|v| v.as_str())
                     .unwrap_or("(null)");
-                Err(DdnsUpdateError::Porkbun(message.to_owned().into()))
+                Err(DdnsUpdateError::provider(
+                    "Porkbun",
+                    kind,
+                    message.to_owned().into(),
+                ))
             }
             Error::Status(code, _resp) => {
                 let message = code.to_string();
-                Err(DdnsUpdateError::Porkbun(message.into()))
+                Err(DdnsUpdateError::provider(
+                    "Porkbun",
+                    ErrorKind::Outage,
+                    message.into(),
+                ))
             }
             Error::Transport(t) => Err(DdnsUpdateError::TransportError(t.to_string().into())),
         }
@@ -78,10 +93,12 @@ impl DdnsService for Service {
                     domain, subdomain
                 );
 
-                let request = Request::post(&url)
+                let request = self
+                    .client
+                    .post(&url)
                     .send_json(serde_json::json!({
-                        "secretapikey": &self.config.secret_api_key,
-                        "apikey": &self.config.api_key,
+                        "secretapikey": self.config.secret_api_key.expose(),
+                        "apikey": self.config.api_key.expose(),
                         "content": ipv4.to_string(),
                     }))
                     .map_err(|e| Self::parse_error(e).unwrap_err())?;
@@ -101,10 +118,12 @@ impl DdnsService for Service {
                     domain, subdomain
                 );
 
-                let request = Request::post(&url)
+                let request = self
+                    .client
+                    .post(&url)
                     .send_json(serde_json::json!({
-                        "secretapikey": &self.config.secret_api_key,
-                        "apikey": &self.config.api_key,
+                        "secretapikey": self.config.secret_api_key.expose(),
+                        "apikey": self.config.api_key.expose(),
                         "content": ipv6.to_string(),
                     }))
                     .map_err(|e| Self::parse_error(e).unwrap_err())?;
@@ -131,4 +150,102 @@ impl DdnsService for Service {
 
         Ok(result)
     }
+
+    /// See: https://porkbun.com/api/json/v3/documentation#Authentication
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let response = self
+            .client
+            .post("https://api.porkbun.com/api/json/v3/ping")
+            .send_json(serde_json::json!({
+                "secretapikey": self.config.secret_api_key.expose(),
+                "apikey": self.config.api_key.expose(),
+            }))
+            .map_err(|e| Self::parse_error(e).unwrap_err());
+
+        let result = response.and_then(|response| {
+            let json = response
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+            match json.get("status").and_then(|v| v.as_str()) {
+                Some("SUCCESS") => Ok(String::from("API keys accepted")),
+                _ => Err(DdnsUpdateError::provider(
+                    "Porkbun",
+                    ErrorKind::Auth,
+                    "ping was not successful".into(),
+                )),
+            }
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/porkbun.txt"));
+
+        let mut service = Service::new(
+            Config {
+                secret_api_key: Secret::new("secret".into()),
+                api_key: Secret::new("key".into()),
+                domains: vec!["a.example.com".into(), "example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(&*sent[0].method, "POST");
+
+        let body = sent[0].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["content"], "192.0.2.1");
+        assert_eq!(body["apikey"], "key");
+
+        let mut service = Service::new(
+            Config {
+                secret_api_key: Secret::new("secret".into()),
+                api_key: Secret::new("key".into()),
+                domains: vec!["a.b.example.net".into()],
+            },
+            &Settings::default(),
+        );
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(
+            matches!(updated, Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Invalid API key. (002)")
+        );
+    }
+
+    #[test]
+    fn credentials() {
+        add_fixtures(include_str!("fixtures/porkbun.txt"));
+
+        let mut service = Service::new(
+            Config {
+                secret_api_key: Secret::new("secret".into()),
+                api_key: Secret::new("key".into()),
+                domains: vec!["example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let description = service.test_credentials().unwrap().unwrap();
+        assert_eq!(description, "API keys accepted");
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(&*sent[0].url, "https://api.porkbun.com/api/json/v3/ping");
+    }
 }
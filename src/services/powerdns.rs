@@ -0,0 +1,372 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType, ZoneName,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The address of the webserver of PowerDNS, e.g. "http://127.0.0.1:8081"
+    /// (see `webserver-address` and `webserver-port` of pdns.conf).
+    url: Box<str>,
+
+    /// The `api-key` of pdns.conf.
+    api_key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default = "default_ttl")]
+    ttl: u32,
+}
+
+fn default_ttl() -> u32 {
+    300
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    /// The ID used in the URLs, usually the name with a trailing dot, e.g.
+    /// "example.com.".
+    id: Box<str>,
+
+    /// The name without the trailing dot, e.g. "example.com".
+    name: Box<str>,
+}
+
+impl ZoneName for Zone {
+    fn zone_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+        config.url = config.url.trim_end_matches('/').into();
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    fn zones_url(&self) -> String {
+        format!("{}/api/v1/servers/localhost/zones", self.config.url)
+    }
+
+    /// Errors are reported as {"error": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("error")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<Response, DdnsUpdateError> {
+        match response {
+            Ok(r) => Ok(r),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("PowerDNS", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    fn get_json(&self, url: &str) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set("X-API-Key", self.config.api_key.expose())
+            .call();
+
+        self.check_response(response)?
+            .into_json::<serde_json::Value>()
+            .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))
+    }
+
+    /// See: https://doc.powerdns.com/authoritative/http-api/zone.html#get--servers-server_id-zones
+    fn get_zones(&self) -> Result<Vec<Zone>, DdnsUpdateError> {
+        let response = self.get_json(&self.zones_url())?;
+
+        let Some(listed) = response.as_array() else {
+            return Err(DdnsUpdateError::Json("expected a list of zones".into()));
+        };
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let id = zone.get("id").and_then(|i| i.as_str());
+            let name = zone.get("name").and_then(|n| n.as_str());
+
+            let (Some(id), Some(name)) = (id, name) else {
+                return Err(DdnsUpdateError::Json("zone has no id or name?".into()));
+            };
+
+            zones.push(Zone {
+                id: id.into(),
+                name: name.trim_end_matches('.').into(),
+            });
+        }
+
+        Ok(zones)
+    }
+
+    /// Replaces the A and AAAA rrsets of the given domains of a zone, all in
+    /// one request.
+    ///
+    /// See: https://doc.powerdns.com/authoritative/http-api/zone.html#patch--servers-server_id-zones-zone_id
+    fn set_rrsets(
+        &self,
+        zone: &Zone,
+        domains: &[&str],
+        ips: &[IpAddr],
+    ) -> Result<(), DdnsUpdateError> {
+        let mut rrsets = Vec::with_capacity(domains.len() * ips.len());
+
+        for domain in domains {
+            for ip in ips {
+                rrsets.push(serde_json::json!({
+                    "name": format!("{}.", domain),
+                    "type": if ip.is_ipv4() { "A" } else { "AAAA" },
+                    "ttl": self.config.ttl,
+                    "changetype": "REPLACE",
+                    "records": [{ "content": ip.to_string(), "disabled": false }],
+                }));
+            }
+        }
+
+        let response = self
+            .client
+            .patch(&format!("{}/{}", self.zones_url(), zone.id))
+            .set("X-API-Key", self.config.api_key.expose())
+            .send_json(serde_json::json!({ "rrsets": rrsets }));
+
+        self.check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA rrsets of every zone, for `dynners discover`.
+    ///
+    /// See: https://doc.powerdns.com/authoritative/http-api/zone.html#get--servers-server_id-zones-zone_id
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            let response = self.get_json(&format!("{}/{}", self.zones_url(), zone.id))?;
+
+            let Some(rrsets) = response.get("rrsets").and_then(|r| r.as_array()) else {
+                return Err(DdnsUpdateError::Json("zone has no rrsets?".into()));
+            };
+
+            for rrset in rrsets {
+                let kind = match rrset.get("type").and_then(|t| t.as_str()) {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let Some(name) = rrset.get("name").and_then(|n| n.as_str()) else {
+                    return Err(DdnsUpdateError::Json("rrset has no name?".into()));
+                };
+
+                found.push(DiscoveredRecord {
+                    name: name.trim_end_matches('.').into(),
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        // The domains are grouped by their zone, so that each zone is only
+        // patched once.
+        let mut batches: Vec<(&Zone, Vec<&str>)> = Vec::new();
+
+        for domain in &self.config.domains {
+            let Some(zone) = find_zone(&self.cached_zones, domain, "").map(|(zone, _)| zone) else {
+                return Err(DdnsUpdateError::provider(
+                    "PowerDNS",
+                    ErrorKind::NotFound,
+                    format!("no zone of the server contains {}", domain).into(),
+                ));
+            };
+
+            match batches.iter_mut().find(|(z, _)| z.id == zone.id) {
+                Some((_, domains)) => domains.push(domain),
+                None => batches.push((zone, vec![domain])),
+            }
+        }
+
+        for (zone, domains) in &batches {
+            self.set_rrsets(zone, domains, result.as_slice())?;
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "")
+                    .map(|(zone, _)| zone)
+                    .is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/powerdns.txt"));
+
+        let mut service = Service::new(
+            Config {
+                url: "http://127.0.0.1:8081/".into(),
+                api_key: Secret::new("key".into()),
+                domains: vec![
+                    "example.com".into(),
+                    "home.dyn.example.com".into(),
+                    "www.example.com".into(),
+                ],
+                ttl: 60,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // dyn.example.com is a zone of its own.
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET http://127.0.0.1:8081/api/v1/servers/localhost/zones",
+                "PATCH http://127.0.0.1:8081/api/v1/servers/localhost/zones/example.com.",
+                "PATCH http://127.0.0.1:8081/api/v1/servers/localhost/zones/dyn.example.com.",
+            ]
+        );
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "rrsets": [
+                {
+                    "name": "home.dyn.example.com.",
+                    "type": "A",
+                    "ttl": 60,
+                    "changetype": "REPLACE",
+                    "records": [{ "content": "192.0.2.1", "disabled": false }],
+                },
+                {
+                    "name": "home.dyn.example.com.",
+                    "type": "AAAA",
+                    "ttl": 60,
+                    "changetype": "REPLACE",
+                    "records": [{ "content": "2001:db8::1", "disabled": false }],
+                },
+            ]})
+        );
+
+        let body = sent[1].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["rrsets"].as_array().unwrap().len(), 4);
+        assert_eq!(body["rrsets"][2]["name"], "www.example.com.");
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Validation,
+                message: m,
+                ..
+            }) if m.starts_with("RRset www.example.com. IN A: Conflicts with pre-existing RRset")
+        ));
+
+        service.config.domains = vec!["example.net".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::NotFound,
+                message: m,
+                ..
+            }) if &*m == "no zone of the server contains example.net"
+        ));
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "Unauthorized"
+        ));
+    }
+}
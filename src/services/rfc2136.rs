@@ -0,0 +1,614 @@
+//! Dynamic updates (RFC 2136) sent straight to the primary server of a zone,
+//! signed with a TSIG key (RFC 8945), as done by `nsupdate`. This works with
+//! BIND, Knot, PowerDNS and most other authoritative servers.
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, SystemTime};
+
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{self, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const TYPE_A: u16 = 1;
+const TYPE_SOA: u16 = 6;
+const TYPE_AAAA: u16 = 28;
+const TYPE_TSIG: u16 = 250;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+const OPCODE_UPDATE: u8 = 5;
+
+/// How far the clocks of the server and ours may drift apart, in seconds.
+const FUDGE: u16 = 300;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const ATTEMPTS: usize = 3;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Algorithm {
+    HmacSha1,
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+
+impl Algorithm {
+    /// The name of the algorithm as written in the TSIG record.
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::HmacSha1 => "hmac-sha1",
+            Algorithm::HmacSha256 => "hmac-sha256",
+            Algorithm::HmacSha384 => "hmac-sha384",
+            Algorithm::HmacSha512 => "hmac-sha512",
+        }
+    }
+
+    fn hmac(self) -> hmac::Algorithm {
+        match self {
+            Algorithm::HmacSha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            Algorithm::HmacSha256 => hmac::HMAC_SHA256,
+            Algorithm::HmacSha384 => hmac::HMAC_SHA384,
+            Algorithm::HmacSha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The primary server of the zone, e.g. "ns1.example.com" or
+    /// "192.0.2.53:5353". The port defaults to 53.
+    server: Box<str>,
+
+    /// The zone the domains belong to, e.g. "example.com".
+    zone: Box<str>,
+
+    /// The name, algorithm and secret of the TSIG key, as in the `key` clause
+    /// of named.conf or the output of `tsig-keygen`.
+    key_name: Box<str>,
+    #[serde(default = "default_algorithm")]
+    key_algorithm: Algorithm,
+    key_secret: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default = "default_ttl")]
+    ttl: u32,
+
+    /// Sends the updates over TCP instead of UDP.
+    #[serde(default)]
+    tcp: bool,
+}
+
+fn default_algorithm() -> Algorithm {
+    Algorithm::HmacSha256
+}
+
+fn default_ttl() -> u32 {
+    300
+}
+
+pub struct Service {
+    config: Config,
+    settings: Settings,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            settings: http.clone(),
+        }
+    }
+}
+
+fn rfc2136_error(kind: ErrorKind, message: String) -> DdnsUpdateError {
+    DdnsUpdateError::provider("the DNS server", kind, message.into())
+}
+
+/// Appends the name in wire format, in lowercase as TSIG wants it.
+fn push_name(message: &mut Vec<u8>, name: &str) -> Result<(), DdnsUpdateError> {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            let message = format!("invalid domain name {}", name);
+            return Err(rfc2136_error(ErrorKind::Validation, message));
+        }
+
+        message.push(label.len() as u8);
+        message.extend(label.to_ascii_lowercase().as_bytes());
+    }
+
+    message.push(0);
+    Ok(())
+}
+
+/// Appends a resource record. The deletion of an RRset is one without any
+/// data.
+fn push_record(
+    message: &mut Vec<u8>,
+    name: &str,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    data: &[u8],
+) -> Result<(), DdnsUpdateError> {
+    push_name(message, name)?;
+    message.extend(rtype.to_be_bytes());
+    message.extend(class.to_be_bytes());
+    message.extend(ttl.to_be_bytes());
+    message.extend((data.len() as u16).to_be_bytes());
+    message.extend(data);
+    Ok(())
+}
+
+/// Builds an UPDATE message for the zone, which replaces the RRsets of the
+/// domains with the addresses (of the same family only). Without any domain,
+/// the message only requires the zone to exist, and changes nothing.
+fn build_update(
+    id: u16,
+    zone: &str,
+    domains: &[Box<str>],
+    ips: &[IpAddr],
+    ttl: u32,
+) -> Result<Vec<u8>, DdnsUpdateError> {
+    let mut message = Vec::with_capacity(512);
+    message.extend(id.to_be_bytes());
+    message.extend([OPCODE_UPDATE << 3, 0]);
+
+    let prerequisites: u16 = domains.is_empty().into();
+    let updates = domains.len() * ips.len() * 2;
+    message.extend(1u16.to_be_bytes()); // The zone.
+    message.extend(prerequisites.to_be_bytes());
+    message.extend((updates as u16).to_be_bytes());
+    message.extend(0u16.to_be_bytes()); // The TSIG record is added later.
+
+    push_name(&mut message, zone)?;
+    message.extend(TYPE_SOA.to_be_bytes());
+    message.extend(CLASS_IN.to_be_bytes());
+
+    // "The name is in use", see section 2.4.4 of RFC 2136.
+    if domains.is_empty() {
+        push_record(&mut message, zone, TYPE_ANY, CLASS_ANY, 0, &[])?;
+    }
+
+    for domain in domains {
+        for ip in ips {
+            let (rtype, data) = match ip {
+                IpAddr::V4(v4) => (TYPE_A, v4.octets().to_vec()),
+                IpAddr::V6(v6) => (TYPE_AAAA, v6.octets().to_vec()),
+            };
+
+            // The whole RRset is deleted first, then the address is added.
+            push_record(&mut message, domain, rtype, CLASS_ANY, 0, &[])?;
+            push_record(&mut message, domain, rtype, CLASS_IN, ttl, &data)?;
+        }
+    }
+
+    Ok(message)
+}
+
+/// Signs the message with the key, appending the TSIG record.
+///
+/// See: https://www.rfc-editor.org/rfc/rfc8945#section-4.3
+fn sign(
+    message: &mut Vec<u8>,
+    key_name: &str,
+    algorithm: Algorithm,
+    secret: &[u8],
+    time: u64,
+) -> Result<(), DdnsUpdateError> {
+    let mut variables = Vec::with_capacity(64);
+    push_name(&mut variables, key_name)?;
+    variables.extend(CLASS_ANY.to_be_bytes());
+    variables.extend(0u32.to_be_bytes()); // TTL
+    push_name(&mut variables, algorithm.name())?;
+    variables.extend(&time.to_be_bytes()[2..]); // 48 bits
+    variables.extend(FUDGE.to_be_bytes());
+    variables.extend(0u16.to_be_bytes()); // Error
+    variables.extend(0u16.to_be_bytes()); // Other Len
+
+    let key = hmac::Key::new(algorithm.hmac(), secret);
+    let mut context = hmac::Context::with_key(&key);
+    context.update(message);
+    context.update(&variables);
+    let mac = context.sign();
+    let mac = mac.as_ref();
+
+    let mut data = Vec::with_capacity(32 + mac.len());
+    push_name(&mut data, algorithm.name())?;
+    data.extend(&time.to_be_bytes()[2..]);
+    data.extend(FUDGE.to_be_bytes());
+    data.extend((mac.len() as u16).to_be_bytes());
+    data.extend(mac);
+    data.extend(&message[0..2]); // Original ID
+    data.extend(0u16.to_be_bytes()); // Error
+    data.extend(0u16.to_be_bytes()); // Other Len
+
+    push_record(message, key_name, TYPE_TSIG, CLASS_ANY, 0, &data)?;
+
+    let additional = u16::from_be_bytes([message[10], message[11]]) + 1;
+    message[10..12].copy_from_slice(&additional.to_be_bytes());
+
+    Ok(())
+}
+
+fn rcode_name(rcode: u16) -> &'static str {
+    match rcode {
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        16 => "BADSIG",
+        17 => "BADKEY",
+        18 => "BADTIME",
+        22 => "BADTRUNC",
+        _ => "unknown error",
+    }
+}
+
+/// Returns the offset right after the (possibly compressed) name.
+fn skip_name(message: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(offset)?;
+
+        match length {
+            0 => return Some(offset + 1),
+            // A pointer to a name elsewhere, which always ends the name.
+            _ if length & 0xC0 == 0xC0 => return Some(offset + 2),
+            _ => offset += 1 + length as usize,
+        }
+    }
+}
+
+/// Finds the error of the TSIG record of the response, which tells why the
+/// signature of the request was refused.
+fn tsig_error(response: &[u8]) -> Option<u16> {
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = response.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    let zones = u16_at(4)?;
+    let records = u16_at(6)? as usize + u16_at(8)? as usize + u16_at(10)? as usize;
+
+    let mut offset = 12;
+    for _ in 0..zones {
+        offset = skip_name(response, offset)? + 4;
+    }
+
+    for _ in 0..records {
+        let end = skip_name(response, offset)?;
+        let rtype = u16_at(end)?;
+        let length = u16_at(end + 8)? as usize;
+        let start = end + 10;
+
+        if rtype == TYPE_TSIG {
+            // Algorithm Name, Time Signed, Fudge, MAC Size, MAC, Original ID
+            let offset = skip_name(response, start)? + 8;
+            let mac_size = u16_at(offset)? as usize;
+            return u16_at(offset + 2 + mac_size + 2);
+        }
+
+        offset = start + length;
+    }
+
+    None
+}
+
+/// Returns None if the response does not belong to the request.
+fn parse_response(id: u16, response: &[u8]) -> Option<Result<(), DdnsUpdateError>> {
+    if response.len() < 12 || response[0..2] != id.to_be_bytes() || response[2] & 0x80 == 0 {
+        return None;
+    }
+
+    let rcode = (response[3] & 0x0F) as u16;
+    if rcode == 0 {
+        return Some(Ok(()));
+    }
+
+    let kind = match rcode {
+        9 | 5 => ErrorKind::Auth,
+        2 => ErrorKind::Outage,
+        3 | 8 | 10 => ErrorKind::NotFound,
+        _ => ErrorKind::Validation,
+    };
+
+    let message = match tsig_error(response).filter(|error| *error != 0) {
+        Some(error) => format!("{} ({})", rcode_name(rcode), rcode_name(error)),
+        None => String::from(rcode_name(rcode)),
+    };
+
+    Some(Err(rfc2136_error(kind, message)))
+}
+
+fn send_udp(server: SocketAddr, id: u16, request: &[u8]) -> io::Result<Vec<u8>> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+
+    let mut buffer = [0u8; 1232];
+    let mut last_error = io::Error::new(io::ErrorKind::TimedOut, "DNS update timed out");
+
+    for _ in 0..ATTEMPTS {
+        socket.send(request)?;
+
+        // Anything not answering our request (e.g. a late answer to a
+        // previous attempt) is ignored until the timeout.
+        loop {
+            let length = match socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(e) => {
+                    last_error = e;
+                    break;
+                }
+            };
+
+            if length >= 2 && buffer[0..2] == id.to_be_bytes() {
+                return Ok(buffer[..length].to_vec());
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Over TCP, every message is preceded by its length.
+fn send_tcp(server: SocketAddr, request: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&server, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut framed = Vec::with_capacity(request.len() + 2);
+    framed.extend((request.len() as u16).to_be_bytes());
+    framed.extend(request);
+    stream.write_all(&framed)?;
+
+    let mut length = [0u8; 2];
+    stream.read_exact(&mut length)?;
+    let mut response = vec![0u8; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut response)?;
+
+    Ok(response)
+}
+
+impl Service {
+    /// Resolves the server as `pins` and `resolver` of [general] would have
+    /// it for an HTTP request.
+    fn server_addresses(&self) -> io::Result<Vec<SocketAddr>> {
+        let server = &*self.config.server;
+
+        if let Ok(address) = server.parse::<SocketAddr>() {
+            return Ok(vec![address]);
+        }
+
+        if let Ok(ip) = server.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Ok(vec![SocketAddr::new(ip, 53)]);
+        }
+
+        let (host, port) = match server.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(io::Error::other)?),
+            None => (server, 53),
+        };
+
+        match http::resolve_override(&self.settings, host, port) {
+            Some(result) => result,
+            None => Ok((host, port).to_socket_addrs()?.collect()),
+        }
+    }
+
+    /// Sends the update for the given domains, see `build_update`.
+    fn send_update(&self, domains: &[Box<str>], ips: &[IpAddr]) -> Result<(), DdnsUpdateError> {
+        for domain in domains {
+            let is_within = domain.eq_ignore_ascii_case(&self.config.zone)
+                || domain
+                    .to_ascii_lowercase()
+                    .strip_suffix(&*self.config.zone.to_ascii_lowercase())
+                    .is_some_and(|name| name.ends_with('.'));
+
+            if !is_within {
+                let message = format!("{} is not within the zone {}", domain, self.config.zone);
+                return Err(rfc2136_error(ErrorKind::NotFound, message));
+            }
+        }
+
+        let secret = data_encoding::BASE64
+            .decode(self.config.key_secret.expose().as_bytes())
+            .map_err(|_| {
+                let message = String::from("key_secret is not valid base64");
+                rfc2136_error(ErrorKind::Validation, message)
+            })?;
+
+        let mut id = [0u8; 2];
+        SystemRandom::new()
+            .fill(&mut id)
+            .map_err(|_| DdnsUpdateError::TransportError("no random numbers available".into()))?;
+        let id = u16::from_be_bytes(id);
+
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut request = build_update(id, &self.config.zone, domains, ips, self.config.ttl)?;
+        sign(
+            &mut request,
+            &self.config.key_name,
+            self.config.key_algorithm,
+            &secret,
+            time,
+        )?;
+
+        let transport_error = |e: io::Error| {
+            DdnsUpdateError::TransportError(format!("{}: {}", self.config.server, e).into())
+        };
+
+        let addresses = self.server_addresses().map_err(transport_error)?;
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address found");
+
+        for address in addresses {
+            let response = match self.config.tcp {
+                true => send_tcp(address, &request),
+                false => send_udp(address, id, &request),
+            };
+
+            match response {
+                Ok(response) => {
+                    return parse_response(id, &response).unwrap_or_else(|| {
+                        Err(DdnsUpdateError::Json(
+                            "the response does not match the update".into(),
+                        ))
+                    })
+                }
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(transport_error(last_error))
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        // All the domains are updated at once, or none of them is.
+        self.send_update(&self.config.domains, result.as_slice())?;
+
+        Ok(result)
+    }
+
+    /// An update which only requires the zone to exist is signed like any
+    /// other, but leaves every record alone.
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self
+            .send_update(&[], &[])
+            .map(|_| format!("the key was accepted for the zone {}", self.config.zone));
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update() {
+        let domains = ["example.com".into(), "www.Example.com".into()];
+        let ips = ["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+
+        let mut message = build_update(0x1234, "example.com", &domains, &ips, 300).unwrap();
+
+        let mut expected = Vec::<u8>::new();
+        expected.extend(b"\x12\x34\x28\x00\x00\x01\x00\x00\x00\x08\x00\x00");
+        expected.extend(b"\x07example\x03com\x00\x00\x06\x00\x01");
+        for name in [
+            &b"\x07example\x03com\x00"[..],
+            b"\x03www\x07example\x03com\x00",
+        ] {
+            expected.extend(name);
+            expected.extend(b"\x00\x01\x00\xFF\x00\x00\x00\x00\x00\x00");
+            expected.extend(name);
+            expected.extend(b"\x00\x01\x00\x01\x00\x00\x01\x2C\x00\x04\xC0\x00\x02\x01");
+            expected.extend(name);
+            expected.extend(b"\x00\x1C\x00\xFF\x00\x00\x00\x00\x00\x00");
+            expected.extend(name);
+            expected.extend(b"\x00\x1C\x00\x01\x00\x00\x01\x2C\x00\x10");
+            expected.extend(b"\x20\x01\x0d\xb8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01");
+        }
+        assert_eq!(message, expected);
+
+        let unsigned = message.len();
+        sign(
+            &mut message,
+            "ddns-key",
+            Algorithm::HmacSha256,
+            b"secret",
+            0x0102_0304_0506,
+        )
+        .unwrap();
+
+        // One additional record now, the TSIG record.
+        assert_eq!(&message[10..12], b"\x00\x01");
+
+        let mut tsig = Vec::<u8>::new();
+        tsig.extend(b"\x08ddns-key\x00\x00\xFA\x00\xFF\x00\x00\x00\x00\x00\x3D");
+        tsig.extend(b"\x0bhmac-sha256\x00\x01\x02\x03\x04\x05\x06\x01\x2C\x00\x20");
+        assert_eq!(&message[unsigned..unsigned + tsig.len()], &tsig[..]);
+        assert_eq!(&message[message.len() - 6..], b"\x12\x34\x00\x00\x00\x00");
+
+        // The MAC covers the message along with the TSIG variables.
+        let mut signed = expected.clone();
+        signed.extend(b"\x08ddns-key\x00\x00\xFF\x00\x00\x00\x00");
+        signed.extend(b"\x0bhmac-sha256\x00\x01\x02\x03\x04\x05\x06\x01\x2C\x00\x00\x00\x00");
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let mac = hmac::sign(&key, &signed);
+        let start = unsigned + tsig.len();
+        assert_eq!(&message[start..start + 32], mac.as_ref());
+
+        // Only the zone has to exist.
+        let check = build_update(0x1234, "example.com", &[], &[], 300).unwrap();
+        assert_eq!(&check[4..12], b"\x00\x01\x00\x01\x00\x00\x00\x00");
+        assert!(check.ends_with(b"\x07example\x03com\x00\x00\xFF\x00\xFF\x00\x00\x00\x00\x00\x00"));
+
+        assert!(build_update(0, "example.com", &["a..example.com".into()], &ips, 300).is_err());
+    }
+
+    #[test]
+    fn response() {
+        let request = build_update(0x1234, "example.com", &[], &[], 300).unwrap();
+        let mut response = request[..29].to_vec();
+        response[2] |= 0x80;
+        response[4..12].copy_from_slice(b"\x00\x01\x00\x00\x00\x00\x00\x00");
+
+        assert!(matches!(parse_response(0x1234, &response), Some(Ok(()))));
+        assert!(parse_response(0x4321, &response).is_none());
+
+        // NOTAUTH, along with a TSIG record telling that the key is unknown.
+        response[3] = 9;
+        response[11] = 1;
+        response.extend(b"\x08ddns-key\x00\x00\xFA\x00\xFF\x00\x00\x00\x00\x00\x1D");
+        response.extend(b"\x0bhmac-sha256\x00\x00\x00\x00\x00\x00\x00\x01\x2C\x00\x00");
+        response.extend(b"\x12\x34\x00\x11\x00\x00");
+
+        let result = parse_response(0x1234, &response);
+        assert!(matches!(
+            result,
+            Some(Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                message: m,
+                ..
+            })) if &*m == "NOTAUTH (BADKEY)"
+        ));
+
+        response[3] = 2;
+        response.truncate(29);
+        response[11] = 0;
+        assert!(matches!(
+            parse_response(0x1234, &response),
+            Some(Err(e)) if e.kind() == ErrorKind::Outage
+        ));
+    }
+}
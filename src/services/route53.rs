@@ -0,0 +1,374 @@
+use std::net::IpAddr;
+
+use data_encoding::HEXLOWER;
+use ring::{digest, hmac};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{xml_element, xml_escape, FixedVec, Secret, UtcDateTime};
+
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const HOST: &str = "route53.amazonaws.com";
+
+/// Route 53 is a global service, but its requests are signed for us-east-1.
+const REGION: &str = "us-east-1";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The access key of an IAM user allowed to
+    /// route53:ChangeResourceRecordSets on the hosted zone (and
+    /// route53:GetHostedZone, for `dynners test`).
+    access_key_id: Box<str>,
+
+    secret_access_key: Secret<Box<str>>,
+
+    /// The ID of the hosted zone, e.g. "Z0123456789ABCDEFGHIJ".
+    hosted_zone_id: Box<str>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    ttl: u32,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+
+        // The ID is also commonly written as "/hostedzone/<id>".
+        if let Some(id) = config.hosted_zone_id.strip_prefix("/hostedzone/") {
+            config.hosted_zone_id = id.into();
+        }
+
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+/// Signs a request with AWS Signature Version 4 and returns the value of its
+/// Authorization header. The headers must have lowercase names, be sorted by
+/// name, and include the host and x-amz-date headers.
+///
+/// See: https://docs.aws.amazon.com/IAM/latest/UserGuide/create-signed-request.html
+#[allow(clippy::too_many_arguments)]
+fn sign_v4(
+    access_key_id: &str,
+    secret_access_key: &str,
+    service: &str,
+    time: UtcDateTime,
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(&str, &str)],
+    payload: &[u8],
+) -> String {
+    let sha256 = |data: &[u8]| HEXLOWER.encode(digest::digest(&digest::SHA256, data).as_ref());
+    let hmac = |key: &[u8], data: &str| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        hmac::sign(&key, data.as_bytes())
+    };
+
+    let timestamp = time.to_basic_iso8601();
+    let date = &timestamp[..8];
+
+    let mut canonical_headers = String::new();
+    for (name, value) in headers {
+        canonical_headers += &format!("{}:{}\n", name, value.trim());
+    }
+
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        path,
+        query,
+        canonical_headers,
+        signed_headers,
+        sha256(payload)
+    );
+
+    let scope = format!("{}/{}/{}/aws4_request", date, REGION, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        sha256(canonical_request.as_bytes())
+    );
+
+    let key = hmac(format!("AWS4{}", secret_access_key).as_bytes(), date);
+    let key = hmac(key.as_ref(), REGION);
+    let key = hmac(key.as_ref(), service);
+    let key = hmac(key.as_ref(), "aws4_request");
+    let signature = HEXLOWER.encode(hmac(key.as_ref(), &string_to_sign).as_ref());
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id, scope, signed_headers, signature
+    )
+}
+
+impl Service {
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let response = response
+            .into_string()
+            .map_err(|e| String::from("unable to read response:") + &e.to_string())?;
+
+        let message = xml_element(&response, "Message")
+            .ok_or_else(|| String::from("expected a Message element"))?;
+
+        match xml_element(&response, "Code") {
+            Some(code) => Ok(format!("{} ({})", message, code).into()),
+            None => Ok(message.into()),
+        }
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<String, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r.into_string().map_err(|e| {
+                DdnsUpdateError::provider("Route 53", ErrorKind::Other, e.to_string().into())
+            })?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::provider(
+                        "Route 53",
+                        ErrorKind::Other,
+                        (error + e).into_boxed_str(),
+                    )
+                })?;
+                Err(DdnsUpdateError::provider("Route 53", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// Sends a signed request to the Route 53 API, whose paths start with
+    /// /2013-04-01.
+    fn send(&self, method: &str, path: &str, body: &str) -> Result<String, DdnsUpdateError> {
+        let timestamp = UtcDateTime::now();
+        let amz_date = timestamp.to_basic_iso8601();
+
+        let authorization = sign_v4(
+            &self.config.access_key_id,
+            self.config.secret_access_key.expose(),
+            "route53",
+            timestamp,
+            method,
+            path,
+            "",
+            &[("host", HOST), ("x-amz-date", &amz_date)],
+            body.as_bytes(),
+        );
+
+        let url = format!("https://{}{}", HOST, path);
+        let request = match method {
+            "GET" => self.client.get(&url),
+            _ => self.client.post(&url),
+        };
+
+        let request = request
+            .set("X-Amz-Date", &amz_date)
+            .set("Authorization", &authorization);
+
+        let response = match method {
+            "GET" => request.call(),
+            _ => request
+                .set("Content-Type", "application/xml")
+                .send_string(body),
+        };
+
+        self.parse_and_check_response(response)
+    }
+
+    /// See: https://docs.aws.amazon.com/Route53/latest/APIReference/API_ChangeResourceRecordSets.html
+    fn upsert_records(&self, ips: &[IpAddr]) -> Result<(), DdnsUpdateError> {
+        let mut changes = String::new();
+
+        for domain in &self.config.domains {
+            for ip in ips {
+                changes += &format!(
+                    "<Change><Action>UPSERT</Action><ResourceRecordSet>\
+                     <Name>{}</Name><Type>{}</Type><TTL>{}</TTL>\
+                     <ResourceRecords><ResourceRecord><Value>{}</Value></ResourceRecord></ResourceRecords>\
+                     </ResourceRecordSet></Change>",
+                    xml_escape(domain),
+                    if ip.is_ipv4() { "A" } else { "AAAA" },
+                    self.config.ttl,
+                    ip,
+                );
+            }
+        }
+
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <ChangeResourceRecordSetsRequest xmlns=\"https://route53.amazonaws.com/doc/2013-04-01/\">\
+             <ChangeBatch><Comment>Updated by dynners</Comment><Changes>{}</Changes></ChangeBatch>\
+             </ChangeResourceRecordSetsRequest>",
+            changes
+        );
+
+        let path = format!(
+            "/2013-04-01/hostedzone/{}/rrset/",
+            self.config.hosted_zone_id
+        );
+        self.send("POST", &path, &body)?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::new();
+        if let Some(ipv4) = ips.iter().find(|ip| ip.is_ipv4()) {
+            result.push(*ipv4);
+        }
+        if let Some(ipv6) = ips.iter().find(|ip| ip.is_ipv6()) {
+            result.push(*ipv6);
+        }
+
+        // All of the records are changed at once, or none of them are.
+        if !result.as_slice().is_empty() {
+            self.upsert_records(result.as_slice())?;
+        }
+
+        Ok(result)
+    }
+
+    /// See: https://docs.aws.amazon.com/Route53/latest/APIReference/API_GetHostedZone.html
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let path = format!("/2013-04-01/hostedzone/{}", self.config.hosted_zone_id);
+
+        let result = self.send("GET", &path, "").and_then(|response| {
+            let Some(zone) = xml_element(&response, "Name") else {
+                return Err(DdnsUpdateError::provider(
+                    "Route 53",
+                    ErrorKind::Other,
+                    "hosted zone has no name?".into(),
+                ));
+            };
+
+            let zone = zone.trim_end_matches('.');
+            let found = describe_found_domains(&self.config.domains, |domain| {
+                domain == zone || domain.ends_with(&format!(".{}", zone))
+            });
+
+            Ok(format!("hosted zone {}, {}", zone, found))
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn signature() {
+        // The example from the AWS documentation.
+        let time = UtcDateTime {
+            year: 2015,
+            month: 8,
+            day: 30,
+            hour: 12,
+            minute: 36,
+            second: 0,
+        };
+
+        let authorization = sign_v4(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "iam",
+            time,
+            "GET",
+            "/",
+            "Action=ListUsers&Version=2010-05-08",
+            &[
+                (
+                    "content-type",
+                    "application/x-www-form-urlencoded; charset=utf-8",
+                ),
+                ("host", "iam.amazonaws.com"),
+                ("x-amz-date", "20150830T123600Z"),
+            ],
+            b"",
+        );
+
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/route53.txt"));
+
+        let mut service = Service::new(
+            Config {
+                access_key_id: "AKIDEXAMPLE".into(),
+                secret_access_key: Secret::new("secret".into()),
+                hosted_zone_id: "/hostedzone/Z0123456789".into(),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            &*sent[0].url,
+            "https://route53.amazonaws.com/2013-04-01/hostedzone/Z0123456789/rrset/"
+        );
+
+        let body = sent[0].body.as_deref().unwrap();
+        assert_eq!(body.matches("<Action>UPSERT</Action>").count(), 4);
+        assert!(body.contains("<Name>www.example.com</Name><Type>AAAA</Type><TTL>300</TTL>"));
+        assert!(body.contains("<Value>2001:db8::1</Value>"));
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "The security token included in the request is invalid. (InvalidClientTokenId)"
+        ));
+
+        let tested = service.test_credentials().unwrap().unwrap();
+        assert_eq!(tested, "hosted zone example.com, 2 of 2 domain(s) found");
+    }
+}
@@ -0,0 +1,301 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind,
+    RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// The number of zones listed per request, which is as many as the API allows.
+const ZONES_PER_PAGE: usize = 1000;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The secret key of an API key with the DomainsDNSFullAccess permission.
+    secret_key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    #[serde(default = "default_ttl")]
+    ttl: u32,
+}
+
+fn default_ttl() -> u32 {
+    3600
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported as {"message": "...", "type": "..."}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("message")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        let response = match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?,
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Scaleway", kind, message))?
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))?
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// Subdomains delegated to a zone of their own are listed separately, as
+    /// e.g. {"domain": "example.com", "subdomain": "dyn"}.
+    ///
+    /// See: https://www.scaleway.com/en/developers/api/domains-and-dns/#path-dns-zones-list-dns-zones
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let mut zones = Vec::new();
+
+        for page in 1.. {
+            let response = self
+                .client
+                .get("https://api.scaleway.com/domain/v2beta1/dns-zones")
+                .query("page", &page.to_string())
+                .query("page_size", &ZONES_PER_PAGE.to_string())
+                .set("X-Auth-Token", self.config.secret_key.expose())
+                .call();
+            let response = self.parse_and_check_response(response)?;
+
+            let Some(listed) = response.get("dns_zones").and_then(|z| z.as_array()) else {
+                return Err(DdnsUpdateError::Json("expected a list of zones".into()));
+            };
+
+            for zone in listed {
+                let Some(domain) = zone.get("domain").and_then(|d| d.as_str()) else {
+                    return Err(DdnsUpdateError::Json("zone has no domain".into()));
+                };
+
+                match zone.get("subdomain").and_then(|s| s.as_str()) {
+                    Some(subdomain) if !subdomain.is_empty() => {
+                        zones.push(format!("{}.{}", subdomain, domain).into())
+                    }
+                    _ => zones.push(domain.into()),
+                }
+            }
+
+            if listed.len() < ZONES_PER_PAGE {
+                break;
+            }
+        }
+
+        Ok(zones)
+    }
+
+    /// Replaces the A and AAAA records of the given names with the addresses,
+    /// all in one batch of changes. A "set" change creates the records if
+    /// there are none.
+    ///
+    /// See: https://www.scaleway.com/en/developers/api/domains-and-dns/#path-records-update-records-within-a-dns-zone
+    fn set_records(
+        &self,
+        zone: &str,
+        names: &[&str],
+        ips: &[IpAddr],
+    ) -> Result<(), DdnsUpdateError> {
+        let mut changes = Vec::new();
+
+        for name in names {
+            for ip in ips {
+                let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+
+                changes.push(serde_json::json!({
+                    "set": {
+                        "id_fields": { "name": name, "type": kind },
+                        "records": [{
+                            "name": name,
+                            "type": kind,
+                            "data": ip.to_string(),
+                            "ttl": self.config.ttl,
+                        }],
+                    }
+                }));
+            }
+        }
+
+        let url = format!(
+            "https://api.scaleway.com/domain/v2beta1/dns-zones/{}/records",
+            zone
+        );
+
+        let response = self
+            .client
+            .patch(&url)
+            .set("X-Auth-Token", self.config.secret_key.expose())
+            .send_json(serde_json::json!({
+                "changes": changes,
+                "return_all_records": false,
+                "disallow_new_zone_creation": true,
+            }));
+
+        self.parse_and_check_response(response)?;
+
+        Ok(())
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        // The domains are grouped by their zone, so that each zone is only
+        // sent a single batch.
+        let mut batches: Vec<(&str, Vec<&str>)> = Vec::new();
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&self.cached_zones, domain, "") else {
+                return Err(DdnsUpdateError::provider(
+                    "Scaleway",
+                    ErrorKind::NotFound,
+                    format!("no zone of the account contains {}", domain).into(),
+                ));
+            };
+
+            match batches.iter_mut().find(|(z, _)| **z == **zone) {
+                Some((_, names)) => names.push(name),
+                None => batches.push((zone, vec![name])),
+            }
+        }
+
+        for (zone, names) in &batches {
+            self.set_records(zone, names, result.as_slice())?;
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "").is_some()
+            })
+        });
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/scaleway.txt"));
+
+        let mut service = Service::new(
+            Config {
+                secret_key: Secret::new("key".into()),
+                domains: vec![
+                    "example.com".into(),
+                    "www.example.com".into(),
+                    "home.dyn.example.com".into(),
+                ],
+                ttl: 300,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // dyn.example.com is a zone of its own.
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.scaleway.com/domain/v2beta1/dns-zones?page=1&page_size=1000",
+                "https://api.scaleway.com/domain/v2beta1/dns-zones/example.com/records",
+                "https://api.scaleway.com/domain/v2beta1/dns-zones/dyn.example.com/records",
+            ]
+        );
+
+        let body = sent[1].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        let changes = body["changes"].as_array().unwrap();
+        assert_eq!(changes.len(), 4);
+        assert_eq!(
+            changes[3]["set"],
+            serde_json::json!({
+                "id_fields": { "name": "www", "type": "AAAA" },
+                "records": [{ "name": "www", "type": "AAAA", "data": "2001:db8::1", "ttl": 300 }],
+            })
+        );
+        assert_eq!(changes[0]["set"]["id_fields"]["name"], "");
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["changes"][0]["set"]["id_fields"]["name"], "home");
+
+        service.config.domains = vec!["example.net".into()];
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "no zone of the account contains example.net"
+        ));
+
+        let tested = service.test_credentials();
+        assert!(matches!(
+            tested,
+            Some(Err(DdnsUpdateError::Provider { message: m, .. })) if &*m == "authentication is denied"
+        ));
+    }
+}
@@ -1,22 +1,26 @@
 use std::net::IpAddr;
 
+use crate::http::Settings;
 use crate::util::FixedVec;
 
-use super::{shared_dyndns, DdnsService, DdnsUpdateError};
+use super::{shared_dyndns, DdnsService, DdnsUpdateError, RecordType, SuspensionState};
 
 pub type Config = shared_dyndns::Config;
 
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
 pub struct Service {
     inner: shared_dyndns::Service,
 }
 
-impl From<Config> for Service {
-    fn from(config: Config) -> Self {
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
         Self {
             inner: shared_dyndns::Service::from_config(
                 "selfHOST",
                 "https://carol.selfhost.de/nic/update",
                 config,
+                http,
             ),
         }
     }
@@ -26,4 +30,54 @@ impl DdnsService for Service {
     fn update_record(&mut self, ip: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
         self.inner.update_record(ip)
     }
+
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.suspension()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+    use crate::services::ErrorKind;
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/selfhost.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            password = "pass"
+            domains = "home.selfhost.eu"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let updated = service
+            .update_record(&["192.0.2.1".parse().unwrap()])
+            .unwrap();
+        assert_eq!(
+            updated.as_slice(),
+            &["192.0.2.1".parse::<IpAddr>().unwrap()]
+        );
+
+        let updated = service.update_record(&["192.0.2.2".parse().unwrap()]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                provider: "selfHOST",
+                kind: ErrorKind::Auth,
+                ..
+            })
+        ));
+
+        let sent = take_sent();
+        assert_eq!(
+            &*sent[1].url,
+            "https://carol.selfhost.de/nic/update?hostname=home.selfhost.eu&myip=192.0.2.2"
+        );
+    }
 }
@@ -1,77 +1,210 @@
+use std::borrow::Cow;
 use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
 
 use serde_derive::{Deserialize, Serialize};
 
-use crate::http::{Error, Request};
-use crate::util::{one_or_more_string, FixedVec};
-use crate::GENERAL_CONFIG;
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
 
-use super::{DdnsService, DdnsUpdateError, Suspension};
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, Suspension, SuspensionState};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct Config {
-    username: Box<str>,
-    password: Box<str>,
+    username: Secret<Box<str>>,
+    password: Secret<Box<str>>,
 
     #[serde(deserialize_with = "one_or_more_string")]
     domains: Vec<Box<str>>,
 }
 
+impl Config {
+    pub fn new(
+        username: Secret<Box<str>>,
+        password: Secret<Box<str>>,
+        domains: Vec<Box<str>>,
+    ) -> Self {
+        Self {
+            username,
+            password,
+            domains,
+        }
+    }
+
+    /// Splits the config into one config per domain, for the servers which
+    /// only accept a single hostname per request.
+    pub fn split_domains(self) -> Vec<Config> {
+        self.domains
+            .iter()
+            .map(|domain| Config {
+                username: self.username.clone(),
+                password: self.password.clone(),
+                domains: vec![domain.clone()],
+            })
+            .collect()
+    }
+}
+
+/// How long to leave the server alone after it reports a server error.
+const SERVER_ERROR_SUSPENSION: Duration = Duration::from_secs(30 * 60);
+
+/// A response which means something else for a particular provider than what
+/// the DynDNS v2 protocol says, see `Service::with_quirks`.
+#[derive(Clone, Debug)]
+pub struct Quirk {
+    /// The start of the response, e.g. "abuse".
+    pub response: &'static str,
+    pub message: &'static str,
+    pub kind: ErrorKind,
+
+    /// How long to suspend the service for, or until the end of the program
+    /// if not given.
+    pub suspend_for: Option<Duration>,
+}
+
+/// The names of the query parameters the hostnames and addresses are sent in.
+#[derive(Clone, Debug)]
+pub struct Parameters {
+    pub hostname: Cow<'static, str>,
+    pub ip: Cow<'static, str>,
+
+    /// The parameter the IPv6 address is sent in, if not together with the
+    /// IPv4 address in `ip`.
+    pub ipv6: Option<Cow<'static, str>>,
+}
+
+impl Parameters {
+    /// The parameters of the DynDNS v2 protocol.
+    pub const DYNDNS: Parameters = Parameters {
+        hostname: Cow::Borrowed("hostname"),
+        ip: Cow::Borrowed("myip"),
+        ipv6: None,
+    };
+}
+
 /// This is a shared implementation for all services using DynDNS v2 as their
 /// API. All services using this implementation must provide a `name` which is
-/// human-readable (it shows up in the logs) and the URL to the `server`.
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// human-readable (it shows up in the logs) and the URL to the `server`, which
+/// may also be given by the user (see `dyndns2`).
+#[derive(Clone, Debug)]
 pub struct Service {
     name: &'static str,
-    server: &'static str,
+    server: Cow<'static, str>,
+
+    /// The server the IPv6 addresses are sent to instead, see
+    /// `Service::with_ipv6_server`.
+    ipv6_server: Option<Cow<'static, str>>,
+
+    /// The names of the query parameters, see `Service::with_parameters`.
+    parameters: Parameters,
     config: Config,
-    suspended: Suspension,
-    auth: Box<str>,
+    client: Client,
+    suspension: Option<SuspensionState>,
+    auth: Secret<Box<str>>,
+    quirks: &'static [Quirk],
 }
 
 impl Service {
-    pub fn from_config(name: &'static str, server: &'static str, config: Config) -> Self {
-        let username_password = String::from(config.username.clone()) + ":" + &config.password;
+    pub fn from_config(
+        name: &'static str,
+        server: impl Into<Cow<'static, str>>,
+        config: Config,
+        http: &Settings,
+    ) -> Self {
+        let username_password =
+            String::from(&**config.username.expose()) + ":" + config.password.expose();
         let base64 = data_encoding::BASE64.encode(username_password.as_bytes());
         let auth = String::from("Basic ") + &base64;
 
         Self {
             config,
-            suspended: Suspension::Cycles(0),
-            auth: auth.into(),
+            client: Client::new(http),
+            suspension: None,
+            auth: Secret::new(auth.into()),
             name,
-            server,
+            server: server.into(),
+            ipv6_server: None,
+            parameters: Parameters::DYNDNS,
+            quirks: &[],
         }
     }
-}
 
-impl DdnsService for Service {
-    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
-        match &mut self.suspended {
-            Suspension::Cycles(cycles) if *cycles > 0 => {
-                *cycles -= 1;
-                return Err(DdnsUpdateError::Suspended(self.suspended.clone()));
-            }
-            Suspension::Indefinite => {
-                return Err(DdnsUpdateError::Suspended(self.suspended.clone()))
-            }
-            _ => (),
-        }
+    /// Stops sending updates after the error, which is returned.
+    fn suspend(
+        &mut self,
+        suspension: Suspension,
+        kind: ErrorKind,
+        message: Box<str>,
+    ) -> DdnsUpdateError {
+        let error = DdnsUpdateError::provider(self.name, kind, message);
 
-        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
-        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+        self.suspension = Some(SuspensionState {
+            suspension,
+            reason: error.to_string().into(),
+            since: SystemTime::now(),
+        });
+
+        error
+    }
+
+    /// Handles the given error responses in a way of their own, instead of
+    /// how the protocol would have it.
+    pub fn with_quirks(mut self, quirks: &'static [Quirk]) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Sends the IPv6 addresses to another server than the IPv4 ones, for the
+    /// providers with an endpoint for each, e.g. ipv6.example.com. Each of
+    /// them is then sent in a request of its own.
+    pub fn with_ipv6_server(mut self, server: impl Into<Cow<'static, str>>) -> Self {
+        self.ipv6_server = Some(server.into());
+        self
+    }
+
+    /// Sends the hostnames and addresses in other query parameters than the
+    /// protocol's, for the providers which only borrow its responses.
+    pub fn with_parameters(mut self, parameters: Parameters) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// Sends the addresses to the server in one request.
+    fn send(
+        &mut self,
+        ipv6_server: bool,
+        ipv4: Option<&IpAddr>,
+        ipv6: Option<&IpAddr>,
+    ) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let server = match &self.ipv6_server {
+            Some(server) if ipv6_server => server,
+            _ => &self.server,
+        };
 
-        let request = Request::get(self.server)
-            .set("Authorization", &self.auth)
-            .query("hostname", &self.config.domains.join(","));
+        let request = self
+            .client
+            .get(server)
+            .set("Authorization", self.auth.expose())
+            .query(&self.parameters.hostname, &self.config.domains.join(","));
+
+        let ip = &*self.parameters.ip;
+        let ipv6_param = self.parameters.ipv6.as_deref();
 
         let request = if ipv4.is_some() && ipv6.is_some() {
-            let myip = ipv4.unwrap().to_string() + "," + &ipv6.unwrap().to_string();
-            request.query("myip", &myip)
+            if let Some(ipv6_param) = ipv6_param {
+                request
+                    .query(ip, &ipv4.unwrap().to_string())
+                    .query(ipv6_param, &ipv6.unwrap().to_string())
+            } else {
+                let myip = ipv4.unwrap().to_string() + "," + &ipv6.unwrap().to_string();
+                request.query(ip, &myip)
+            }
         } else if let Some(ipv4) = ipv4 {
-            request.query("myip", &ipv4.to_string())
+            request.query(ip, &ipv4.to_string())
         } else if let Some(ipv6) = ipv6 {
-            request.query("myip", &ipv6.to_string())
+            request.query(ipv6_param.unwrap_or(ip), &ipv6.to_string())
         } else {
             unreachable!()
         };
@@ -80,9 +213,9 @@ impl DdnsService for Service {
 
         match request.call() {
             Ok(resp) | Err(Error::Status(_, resp)) => {
-                let resp = resp
-                    .into_string()
-                    .map_err(|e| DdnsUpdateError::DynDns(self.name, e.to_string().into()))?;
+                let resp = resp.into_string().map_err(|e| {
+                    DdnsUpdateError::provider(self.name, ErrorKind::Other, e.to_string().into())
+                })?;
 
                 if let Some(resp) = resp.strip_prefix("good") {
                     let mut split = resp.split(',');
@@ -107,52 +240,67 @@ impl DdnsService for Service {
                     Ok(result)
                 } else if resp.starts_with("nochg") {
                     Ok(FixedVec::new())
-                } else if resp.starts_with("911") || resp.starts_with("dnserr") {
-                    let update_rate = GENERAL_CONFIG.get().unwrap().update_rate;
+                } else if let Some(quirk) =
+                    self.quirks.iter().find(|q| resp.starts_with(q.response))
+                {
+                    let suspension = match quirk.suspend_for {
+                        Some(duration) => Suspension::Until(SystemTime::now() + duration),
+                        None => Suspension::Indefinite,
+                    };
 
+                    Err(self.suspend(suspension, quirk.kind, quirk.message.into()))
+                } else if resp.starts_with("911") || resp.starts_with("dnserr") {
                     // We have encountered a server error - best to stop updating
                     // for about 30 minutes.
-                    let cycles = match update_rate {
-                        Some(rate) => (30 * 60) / u32::from(rate),
-                        None => 0, // doesn't matter anyway, the program dies after this
-                    };
+                    let suspension = Suspension::Until(SystemTime::now() + SERVER_ERROR_SUSPENSION);
+                    let error_message = "The server is down, suspending for 30 minutes";
 
-                    self.suspended = Suspension::Cycles(cycles);
-
-                    let error_message = match cycles {
-                        0 => String::from("The server is down"),
-                        n => format!("The server is down, suspending for {} cycles", n),
-                    };
-
-                    Err(DdnsUpdateError::DynDns(self.name, error_message.into()))
+                    Err(self.suspend(suspension, ErrorKind::Outage, error_message.into()))
                 } else {
                     // The user has done something wrong (or we have done something
                     // wrong). Suspend the updating of this service indefinitely or
                     // we risk having our client / user agent banned.
-                    self.suspended = Suspension::Indefinite;
-
-                    let resp = if resp.starts_with("!donator") {
-                        String::from("Only credited users are allowed")
+                    let (kind, resp) = if resp.starts_with("!donator") {
+                        (
+                            ErrorKind::Auth,
+                            String::from("Only credited users are allowed"),
+                        )
                     } else if resp.starts_with("badauth") {
-                        String::from("Bad authentication details were provided")
+                        (
+                            ErrorKind::Auth,
+                            String::from("Bad authentication details were provided"),
+                        )
                     } else if resp.starts_with("notfqdn") {
-                        String::from("Domain must be fully-qualified")
+                        (
+                            ErrorKind::Validation,
+                            String::from("Domain must be fully-qualified"),
+                        )
                     } else if resp.starts_with("nohost") {
-                        String::from("Hostname does not exist in the user account")
+                        (
+                            ErrorKind::NotFound,
+                            String::from("Hostname does not exist in the user account"),
+                        )
                     } else if resp.starts_with("abuse") {
-                        String::from("Domain is blocked because of abuse")
+                        (
+                            ErrorKind::Auth,
+                            String::from("Domain is blocked because of abuse"),
+                        )
                     } else if resp.starts_with("numhost") {
-                        String::from("Too many hosts are specified")
+                        (
+                            ErrorKind::Validation,
+                            String::from("Too many hosts are specified"),
+                        )
                     } else if resp.starts_with("badagent") {
-                        String::from(concat!(
+                        let message = concat!(
                             "Bad user agent was provided. ",
                             "Configure your user_agent properly in the config file."
-                        ))
+                        );
+                        (ErrorKind::Validation, String::from(message))
                     } else {
-                        resp
+                        (ErrorKind::Other, resp)
                     };
 
-                    Err(DdnsUpdateError::DynDns(self.name, resp.into()))
+                    Err(self.suspend(Suspension::Indefinite, kind, resp.into()))
                 }
             }
 
@@ -160,3 +308,136 @@ impl DdnsService for Service {
         }
     }
 }
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.suspension
+            .as_ref()
+            .filter(|state| state.is_in_effect())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if let Some(state) = self.suspension() {
+            return Err(DdnsUpdateError::Suspended(state.suspension.clone()));
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4());
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6());
+
+        if self.ipv6_server.is_none() || ipv6.is_none() {
+            return self.send(false, ipv4, ipv6);
+        }
+
+        let mut result = FixedVec::new();
+
+        if ipv4.is_some() {
+            result.extend(self.send(false, ipv4, None)?);
+        }
+
+        result.extend(self.send(true, None, ipv6)?);
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/dyndns.txt"));
+
+        let config = Config {
+            username: Secret::new("user".into()),
+            password: Secret::new("pass".into()),
+            domains: vec!["a.example.com".into(), "b.example.com".into()],
+        };
+        let mut service = Service::from_config(
+            "Dynu",
+            "https://api.dynu.com/nic/update",
+            config,
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let updated = service.update_record(&[ipv4]).unwrap();
+        assert!(updated.as_slice().is_empty());
+
+        // badauth suspends the service for good, so no more requests are made.
+        let updated = service.update_record(&[ipv4, ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                provider: "Dynu",
+                kind: ErrorKind::Auth,
+                ..
+            })
+        ));
+
+        let state = service.suspension().unwrap();
+        assert_eq!(state.suspension, Suspension::Indefinite);
+        assert_eq!(
+            &*state.reason,
+            "Dynu returned error: Bad authentication details were provided"
+        );
+
+        let updated = service.update_record(&[ipv4, ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Indefinite))
+        ));
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 3);
+        assert!(sent.iter().all(|s| &*s.method == "GET"));
+    }
+
+    #[test]
+    fn server_error() {
+        add_fixtures(
+            "> GET https://example.com/nic/update?hostname=a.example.com&myip=192.0.2.1
+< 200
+< 911",
+        );
+
+        let config = Config {
+            username: Secret::new("user".into()),
+            password: Secret::new("pass".into()),
+            domains: vec!["a.example.com".into()],
+        };
+        let mut service = Service::from_config(
+            "Example",
+            "https://example.com/nic/update",
+            config,
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                provider: "Example",
+                kind: ErrorKind::Outage,
+                ..
+            })
+        ));
+
+        // The suspension is measured in wall-clock time, not in cycles.
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Until(time))) if time > SystemTime::now()
+        ));
+
+        assert_eq!(take_sent().len(), 1);
+    }
+}
@@ -0,0 +1,173 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::Settings;
+use crate::util::{one_or_more_string, unscrubbed_optional_secret, FixedVec, Secret};
+
+use super::shared_dyndns::{self, Quirk};
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, SuspensionState};
+
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
+/// SPDYN is updated either with the username and password of the account, or
+/// with the update token of a host, which then takes the host as the username.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "RawConfig")]
+pub struct Config {
+    inner: shared_dyndns::Config,
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    #[serde(default, deserialize_with = "unscrubbed_optional_secret")]
+    username: Option<Secret<Box<str>>>,
+    password: Option<Secret<Box<str>>>,
+
+    /// The update token of a host, found in the settings of the host.
+    token: Option<Secret<Box<str>>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let inner = match (raw.token, raw.username, raw.password) {
+            (Some(token), None, None) => match &raw.domains[..] {
+                [domain] => {
+                    shared_dyndns::Config::new(Secret::new(domain.clone()), token, raw.domains)
+                }
+                _ => return Err("a host token only updates a single domain"),
+            },
+
+            (None, Some(username), Some(password)) => {
+                shared_dyndns::Config::new(username, password, raw.domains)
+            }
+
+            _ => return Err("either token, or username and password must be given"),
+        };
+
+        Ok(Config { inner })
+    }
+}
+
+/// SPDYN locks a host which keeps being updated after an error, so all of its
+/// errors stop the updates until the config is fixed.
+const QUIRKS: &[Quirk] = &[
+    Quirk {
+        response: "!yours",
+        message: "The host does not belong to the account",
+        kind: ErrorKind::NotFound,
+        suspend_for: None,
+    },
+    Quirk {
+        response: "fatal",
+        message: "The host has been disabled",
+        kind: ErrorKind::Validation,
+        suspend_for: None,
+    },
+    Quirk {
+        response: "abuse",
+        message: "The host has been locked for too many updates, unlock it in the SPDYN portal",
+        kind: ErrorKind::RateLimited(None),
+        suspend_for: None,
+    },
+];
+
+pub struct Service {
+    inner: shared_dyndns::Service,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let inner = shared_dyndns::Service::from_config(
+            "SPDYN",
+            "https://update.spdyn.de/nic/update",
+            config.inner,
+            http,
+        )
+        .with_quirks(QUIRKS);
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.suspension()
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        self.inner.update_record(ips)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent, Settings};
+    use crate::services::Suspension;
+
+    #[test]
+    fn config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            token = "token"
+            domains = ["home.spdyn.de", "nas.spdyn.de"]
+            "#,
+        );
+        assert!(config.is_err());
+
+        let config = toml::from_str::<Config>(
+            r#"
+            username = "user"
+            domains = "home.spdyn.de"
+            "#,
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/spdyn.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            token = "abcd-efgh-ijkl"
+            domains = "home.spdyn.de"
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // The host is the username of its token.
+        let sent = take_sent();
+        assert_eq!(
+            &*sent[0].url,
+            "https://update.spdyn.de/nic/update?hostname=home.spdyn.de&myip=192.0.2.1%2C2001%3Adb8%3A%3A1"
+        );
+
+        // A locked host stays locked, so nothing is sent anymore.
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { kind: ErrorKind::RateLimited(None), message: m, .. }) if m.starts_with("The host has been locked")
+        ));
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Indefinite))
+        ));
+        assert_eq!(take_sent().len(), 1);
+    }
+}
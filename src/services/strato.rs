@@ -0,0 +1,166 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::Settings;
+use crate::util::{one_or_more_string, unscrubbed_optional_secret, FixedVec, Secret};
+
+use super::shared_dyndns::{self, Quirk};
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType, SuspensionState};
+
+pub const RECORDS: &[RecordType] = shared_dyndns::RECORDS;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Strato takes the domain itself as the username, with the password set
+    /// for DynDNS in the customer login. If the username is not given, every
+    /// domain is therefore updated with itself as the username.
+    #[serde(default, deserialize_with = "unscrubbed_optional_secret")]
+    username: Option<Secret<Box<str>>>,
+    password: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+/// Strato sends "abuse" whenever it is updated too often, which only blocks
+/// the updates for a while. The other errors mostly mean that DynDNS has not
+/// been enabled for the domain.
+const QUIRKS: &[Quirk] = &[
+    Quirk {
+        response: "abuse",
+        message: "Too many updates were sent, suspending for 10 minutes",
+        kind: ErrorKind::RateLimited(Some(Duration::from_secs(10 * 60))),
+        suspend_for: Some(Duration::from_secs(10 * 60)),
+    },
+    Quirk {
+        response: "badauth",
+        message: "Bad authentication details were provided (the username is the domain, and DynDNS must be enabled for it)",
+        kind: ErrorKind::Auth,
+        suspend_for: None,
+    },
+    Quirk {
+        response: "nohost",
+        message: "DynDNS is not enabled for the domain, or it does not belong to the username",
+        kind: ErrorKind::NotFound,
+        suspend_for: None,
+    },
+];
+
+pub struct Service {
+    /// One client for each username, which is every domain unless a username
+    /// is given.
+    inner: Vec<shared_dyndns::Service>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let configs = match config.username {
+            Some(username) => vec![shared_dyndns::Config::new(
+                username,
+                config.password,
+                config.domains,
+            )],
+
+            None => config
+                .domains
+                .into_iter()
+                .map(|domain| {
+                    shared_dyndns::Config::new(
+                        Secret::new(domain.clone()),
+                        config.password.clone(),
+                        vec![domain],
+                    )
+                })
+                .collect(),
+        };
+
+        let inner = configs
+            .into_iter()
+            .map(|config| {
+                shared_dyndns::Service::from_config(
+                    "Strato",
+                    "https://dyndns.strato.com/nic/update",
+                    config,
+                    http,
+                )
+                .with_quirks(QUIRKS)
+            })
+            .collect();
+
+        Self { inner }
+    }
+}
+
+impl DdnsService for Service {
+    /// One suspended client holds back the others.
+    fn suspension(&self) -> Option<&SuspensionState> {
+        self.inner.iter().find_map(|inner| inner.suspension())
+    }
+
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let mut result = FixedVec::new();
+
+        for inner in &mut self.inner {
+            for ip in inner.update_record(ips)? {
+                if !result.as_slice().contains(&ip) {
+                    result.push(ip);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent, Settings};
+    use crate::services::Suspension;
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/strato.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            password = "pass"
+            domains = ["example.com", "home.example.org"]
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://dyndns.strato.com/nic/update?hostname=example.com&myip=192.0.2.1%2C2001%3Adb8%3A%3A1",
+                "https://dyndns.strato.com/nic/update?hostname=home.example.org&myip=192.0.2.1%2C2001%3Adb8%3A%3A1",
+            ]
+        );
+
+        // Updating too often only holds the service back for a while.
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { kind: ErrorKind::RateLimited(Some(_)), message: m, .. }) if m.starts_with("Too many updates")
+        ));
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Suspended(Suspension::Until(_)))
+        ));
+        assert_eq!(take_sent().len(), 1);
+    }
+}
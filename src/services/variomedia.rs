@@ -0,0 +1,438 @@
+use std::net::IpAddr;
+use std::thread;
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, find_zone, one_or_more_string, DdnsService, DdnsUpdateError,
+    DiscoveredRecord, ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+const ENDPOINT: &str = "https://api.variomedia.de";
+
+/// The version of the API, which every request has to ask for.
+const ACCEPT: &str = "application/vnd.variomedia.v1+json";
+
+/// The TTL of newly created records if none is configured.
+const DEFAULT_TTL: u32 = 3600;
+
+/// Changes are queued as jobs, which are checked this often until they are
+/// done, up to `POLL_ATTEMPTS` times.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: u32 = 30;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// An API token, created in the customer area under API.
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The TTL of the records, which is otherwise left as it is.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Secret<Box<str>>,
+    cached_zones: Vec<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let auth = String::from("token ") + config.token.expose();
+
+        Self {
+            config,
+            client: Client::new(http),
+            auth: Secret::new(auth.into()),
+            cached_zones: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// Errors are reported the JSON:API way, as {"errors": [{"title": "...",
+    /// "detail": "..."}]}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let error = resp_json
+            .get("errors")
+            .and_then(|e| e.get(0))
+            .ok_or_else(|| String::from("expected array"))?;
+
+        let title = error.get("title").and_then(|t| t.as_str());
+        let detail = error.get("detail").and_then(|d| d.as_str());
+
+        match (title, detail) {
+            (Some(title), Some(detail)) => Ok(format!("{}: {}", title, detail).into()),
+            (Some(message), None) | (None, Some(message)) => Ok(message.into()),
+            (None, None) => Err(String::from("expected string")),
+        }
+    }
+
+    fn parse_and_check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<serde_json::Value, DdnsUpdateError> {
+        match response {
+            Ok(r) => r
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into())),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Variomedia", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    fn get_data(&self, url: &str) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let response = self
+            .client
+            .get(url)
+            .set("Authorization", self.auth.expose())
+            .set("Accept", ACCEPT)
+            .call();
+        let response = self.parse_and_check_response(response)?;
+
+        match response.get("data").and_then(|d| d.as_array()) {
+            Some(data) => Ok(data.clone()),
+            None => Err(DdnsUpdateError::Json("expected a list in data".into())),
+        }
+    }
+
+    /// See: https://api.variomedia.de/docs/domains.html
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let listed = self.get_data(&format!("{}/domains", ENDPOINT))?;
+
+        let mut zones = Vec::with_capacity(listed.len());
+        for zone in listed {
+            let Some(name) = zone.get("id").and_then(|i| i.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no id?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// See: https://api.variomedia.de/docs/dns-records.html
+    fn get_records(&self, zone: &str) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let url = format!("{}/dns-records", ENDPOINT);
+        let response = self
+            .client
+            .get(&url)
+            .set("Authorization", self.auth.expose())
+            .set("Accept", ACCEPT)
+            .query("filter[domain]", zone)
+            .call();
+
+        let response = self.parse_and_check_response(response)?;
+
+        match response.get("data").and_then(|d| d.as_array()) {
+            Some(data) => Ok(data.clone()),
+            None => Err(DdnsUpdateError::Json("expected a list in data".into())),
+        }
+    }
+
+    /// Points the record of the given name and type to the address, creating
+    /// the record if there is none, and waits until the change is done.
+    ///
+    /// See: https://api.variomedia.de/docs/dns-records.html
+    fn set_record(
+        &self,
+        records: &[serde_json::Value],
+        zone: &str,
+        name: &str,
+        ip: IpAddr,
+    ) -> Result<(), DdnsUpdateError> {
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+
+        let existing = records.iter().find(|record| {
+            let attributes = record.get("attributes");
+            let attribute = |key| attributes.and_then(|a| a.get(key)).and_then(|v| v.as_str());
+
+            attribute("record_type") == Some(kind) && attribute("name") == Some(name)
+        });
+
+        let (request, body) = match existing {
+            Some(record) => {
+                let attributes = record.get("attributes");
+                let data = attributes
+                    .and_then(|a| a.get("data"))
+                    .and_then(|d| d.as_str());
+                let ttl = attributes
+                    .and_then(|a| a.get("ttl"))
+                    .and_then(|t| t.as_u64());
+
+                let ttl_matches = self.config.ttl.is_none_or(|t| Some(t as u64) == ttl);
+                if data == Some(&ip.to_string()) && ttl_matches {
+                    return Ok(());
+                }
+
+                let Some(id) = record.get("id").and_then(|i| i.as_str()) else {
+                    return Err(DdnsUpdateError::Json("record has no id?".into()));
+                };
+
+                let mut attributes = serde_json::json!({ "data": ip.to_string() });
+                if let Some(ttl) = self.config.ttl {
+                    attributes["ttl"] = ttl.into();
+                }
+
+                let body = serde_json::json!({
+                    "data": { "type": "dns-record", "id": id, "attributes": attributes }
+                });
+
+                let url = format!("{}/dns-records/{}", ENDPOINT, id);
+                (self.client.patch(&url), body)
+            }
+
+            None => {
+                let body = serde_json::json!({
+                    "data": {
+                        "type": "dns-record",
+                        "attributes": {
+                            "record_type": kind,
+                            "name": name,
+                            "domain": zone,
+                            "data": ip.to_string(),
+                            "ttl": self.config.ttl.unwrap_or(DEFAULT_TTL),
+                        }
+                    }
+                });
+
+                let url = format!("{}/dns-records", ENDPOINT);
+                (self.client.post(&url), body)
+            }
+        };
+
+        let response = request
+            .set("Authorization", self.auth.expose())
+            .set("Accept", ACCEPT)
+            .set("Content-Type", "application/vnd.api+json")
+            .send_string(&body.to_string());
+
+        let job = self.parse_and_check_response(response)?;
+        self.wait_for_job(&job)
+    }
+
+    /// Polls the queued job until it is done, or gives up after a minute.
+    ///
+    /// See: https://api.variomedia.de/docs/queue-jobs.html
+    fn wait_for_job(&self, job: &serde_json::Value) -> Result<(), DdnsUpdateError> {
+        let Some(id) = job.pointer("/data/id").and_then(|i| i.as_str()) else {
+            return Err(DdnsUpdateError::Json("queue job has no id?".into()));
+        };
+
+        let url = format!("{}/queue-jobs/{}", ENDPOINT, id);
+
+        for attempt in 0..POLL_ATTEMPTS {
+            if attempt > 0 {
+                thread::sleep(POLL_INTERVAL);
+            }
+
+            let response = self
+                .client
+                .get(&url)
+                .set("Authorization", self.auth.expose())
+                .set("Accept", ACCEPT)
+                .call();
+            let job = self.parse_and_check_response(response)?;
+
+            match job
+                .pointer("/data/attributes/status")
+                .and_then(|s| s.as_str())
+            {
+                Some("done") => return Ok(()),
+                Some("failed") => {
+                    return Err(DdnsUpdateError::provider(
+                        "Variomedia",
+                        ErrorKind::Other,
+                        format!("the queue job {} failed", id).into(),
+                    ))
+                }
+                Some(_) => continue,
+                None => return Err(DdnsUpdateError::Json("queue job has no status?".into())),
+            }
+        }
+
+        Err(DdnsUpdateError::provider(
+            "Variomedia",
+            ErrorKind::Outage,
+            format!("the queue job {} was not done in time", id).into(),
+        ))
+    }
+
+    /// Lists the A and AAAA records of every domain, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                let attributes = record.get("attributes");
+                let attribute = |key| attributes.and_then(|a| a.get(key)).and_then(|v| v.as_str());
+
+                let kind = match attribute("record_type") {
+                    Some("A") => RecordType::A,
+                    Some("AAAA") => RecordType::Aaaa,
+                    _ => continue,
+                };
+
+                let name = match attribute("name") {
+                    Some("") => zone.clone(),
+                    Some(name) => format!("{}.{}", name, zone).into(),
+                    None => return Err(DdnsUpdateError::Json("record has no name?".into())),
+                };
+
+                found.push(DiscoveredRecord { name, kind });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_zones.is_empty() {
+            self.cached_zones = self.get_zones()?;
+        }
+
+        let mut result = FixedVec::<IpAddr, 2>::new();
+        result.extend(ips.iter().find(|ip| ip.is_ipv4()));
+        result.extend(ips.iter().find(|ip| ip.is_ipv6()));
+
+        for domain in &self.config.domains {
+            let Some((zone, name)) = find_zone(&self.cached_zones, domain, "") else {
+                return Err(DdnsUpdateError::provider(
+                    "Variomedia",
+                    ErrorKind::NotFound,
+                    format!("no domain of the account contains {}", domain).into(),
+                ));
+            };
+
+            let records = self.get_records(zone)?;
+
+            for ip in &result {
+                self.set_record(&records, zone, name, *ip)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        let result = self.get_zones().map(|zones| {
+            self.cached_zones = zones;
+            describe_found_domains(&self.config.domains, |domain| {
+                find_zone(&self.cached_zones, domain, "").is_some()
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/variomedia.txt"));
+
+        let mut service = Service::new(
+            Config {
+                token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: None,
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        // The AAAA record of www.example.com is changed while the queue job of
+        // its A record fails.
+        let updated = service.update_record(&[ipv4, ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Other,
+                message: m,
+                ..
+            }) if &*m == "the queue job 2 failed"
+        ));
+
+        let sent = take_sent();
+        let requests = sent
+            .iter()
+            .map(|s| format!("{} {}", s.method, s.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "GET https://api.variomedia.de/domains",
+                "GET https://api.variomedia.de/dns-records?filter%5Bdomain%5D=example.com",
+                "POST https://api.variomedia.de/dns-records",
+                "GET https://api.variomedia.de/queue-jobs/1",
+                "GET https://api.variomedia.de/dns-records?filter%5Bdomain%5D=example.com",
+                "PATCH https://api.variomedia.de/dns-records/11",
+                "GET https://api.variomedia.de/queue-jobs/2",
+            ]
+        );
+
+        let body = sent[2].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "data": {
+                    "type": "dns-record",
+                    "attributes": {
+                        "record_type": "AAAA",
+                        "name": "",
+                        "domain": "example.com",
+                        "data": "2001:db8::1",
+                        "ttl": 3600,
+                    }
+                }
+            })
+        );
+
+        let body = sent[5].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "data": { "type": "dns-record", "id": "11", "attributes": { "data": "192.0.2.1" } }
+            })
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use std::net::IpAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Response, Settings};
+use crate::util::{FixedVec, Secret};
+
+use super::{
+    describe_found_domains, one_or_more_string, DdnsService, DdnsUpdateError, DiscoveredRecord,
+    ErrorKind, RecordType,
+};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The personal API key found under Account > API.
+    api_key: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+
+    /// The time to live expressed in seconds. The TTL of the records is left
+    /// as it is if not given.
+    #[serde(default)]
+    ttl: Option<u32>,
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+    cached_records: Vec<Record>,
+}
+
+#[derive(Debug)]
+struct Record {
+    id: Box<str>,
+
+    /// The domain (i.e. zone) the record belongs to, e.g. "example.com".
+    zone: Box<str>,
+
+    /// The fully-qualified name, e.g. "www.example.com".
+    fqdn: Box<str>,
+
+    kind: RecordKind,
+
+    /// The address the record was last known to point to.
+    data: Box<str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordKind {
+    A,
+    Aaaa,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let mut config = config;
+        config.api_key = Secret::new((String::from("Bearer ") + config.api_key.expose()).into());
+        Self {
+            config,
+            client: Client::new(http),
+            cached_records: Vec::new(),
+        }
+    }
+}
+
+impl Service {
+    /// The largest page size the API allows.
+    const PER_PAGE: &'static str = "500";
+
+    /// Errors are reported as {"error": "...", "status": 401}.
+    fn parse_error(&self, response: Response) -> Result<Box<str>, String> {
+        let resp_json = response
+            .into_json::<serde_json::Value>()
+            .map_err(|e| String::from("unable to parse response as JSON:") + &e.to_string())?;
+
+        let message = resp_json
+            .get("error")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| String::from("expected string"))?;
+
+        Ok(message.into())
+    }
+
+    /// Checks whether the request succeeded, returning the response if so.
+    fn check_response(
+        &self,
+        response: Result<Response, Error>,
+    ) -> Result<Response, DdnsUpdateError> {
+        match response {
+            Ok(r) => Ok(r),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let message = self.parse_error(resp).map_err(|ref e| {
+                    let error = String::from("unexpected error message structure - ");
+                    DdnsUpdateError::Json((error + e).into_boxed_str())
+                })?;
+                Err(DdnsUpdateError::provider("Vultr", kind, message))
+            }
+            Err(Error::Transport(tp)) => {
+                Err(DdnsUpdateError::TransportError(tp.to_string().into()))
+            }
+        }
+    }
+
+    /// Fetches every page of a listing and returns the items found under
+    /// `key` in each of them. Pages are linked by an opaque cursor, which is
+    /// empty on the last page.
+    ///
+    /// See: https://www.vultr.com/api/#section/Introduction/Meta-and-Pagination
+    fn get_all_pages(
+        &self,
+        url: &str,
+        key: &str,
+    ) -> Result<Vec<serde_json::Value>, DdnsUpdateError> {
+        let mut items = Vec::new();
+        let mut cursor = String::new();
+
+        loop {
+            let mut request = self
+                .client
+                .get(url)
+                .query("per_page", Self::PER_PAGE)
+                .set("Authorization", self.config.api_key.expose());
+
+            if !cursor.is_empty() {
+                request = request.query("cursor", &cursor);
+            }
+
+            let mut page = self
+                .check_response(request.call())?
+                .into_json::<serde_json::Value>()
+                .map_err(|e| DdnsUpdateError::Json(e.to_string().into()))?;
+
+            let Some(page_items) = page.get_mut(key).and_then(|v| v.as_array_mut()) else {
+                return Err(DdnsUpdateError::Json(
+                    format!("vultr returned no {}", key).into(),
+                ));
+            };
+
+            items.append(page_items);
+
+            match page.pointer("/meta/links/next").and_then(|v| v.as_str()) {
+                Some(next) if !next.is_empty() => cursor = next.into(),
+                _ => break,
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// See: https://www.vultr.com/api/#tag/dns/operation/list-dns-domains
+    fn get_zones(&self) -> Result<Vec<Box<str>>, DdnsUpdateError> {
+        let domains = self.get_all_pages("https://api.vultr.com/v2/domains", "domains")?;
+
+        let mut zones = Vec::with_capacity(domains.len());
+        for domain in domains {
+            let Some(name) = domain.get("domain").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("domain has no name?".into()));
+            };
+
+            zones.push(name.into());
+        }
+
+        Ok(zones)
+    }
+
+    /// See: https://www.vultr.com/api/#tag/dns/operation/list-dns-domain-records
+    fn get_records(&self, zone: &str) -> Result<Vec<Record>, DdnsUpdateError> {
+        let url = format!("https://api.vultr.com/v2/domains/{}/records", zone);
+        let records = self.get_all_pages(&url, "records")?;
+
+        let mut returned_records = Vec::new();
+        for record in records {
+            let Some(id) = record.get("id").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no id?".into()));
+            };
+
+            let Some(name) = record.get("name").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no name?".into()));
+            };
+
+            let Some(ty) = record.get("type").and_then(|v| v.as_str()) else {
+                return Err(DdnsUpdateError::Json("record has no type?".into()));
+            };
+
+            let kind = match ty {
+                "A" => RecordKind::A,
+                "AAAA" => RecordKind::Aaaa,
+                _ => continue,
+            };
+
+            let data = record.get("data").and_then(|v| v.as_str()).unwrap_or("");
+
+            // The domain itself has an empty name.
+            let fqdn: Box<str> = if name.is_empty() {
+                zone.into()
+            } else {
+                format!("{}.{}", name, zone).into()
+            };
+
+            returned_records.push(Record {
+                id: id.into(),
+                zone: zone.into(),
+                fqdn,
+                kind,
+                data: data.into(),
+            });
+        }
+
+        Ok(returned_records)
+    }
+
+    /// Succeeds with an empty response (204).
+    ///
+    /// See: https://www.vultr.com/api/#tag/dns/operation/update-dns-domain-record
+    fn patch_record(&self, record: &Record, ip: IpAddr) -> Result<(), DdnsUpdateError> {
+        let url = format!(
+            "https://api.vultr.com/v2/domains/{}/records/{}",
+            record.zone, record.id
+        );
+
+        let mut body = serde_json::json!({ "data": ip.to_string() });
+        if let Some(ttl) = self.config.ttl {
+            body["ttl"] = ttl.into();
+        }
+
+        let response = self
+            .client
+            .patch(&url)
+            .set("Authorization", self.config.api_key.expose())
+            .send_json(body);
+
+        self.check_response(response)?;
+
+        Ok(())
+    }
+
+    /// Looks up the records of the configured domains and caches them, so that
+    /// they are only looked up once. Only the zones containing any of the
+    /// domains are looked into.
+    fn cache_records(&mut self) -> Result<(), DdnsUpdateError> {
+        for zone in self.get_zones()? {
+            let is_relevant = self
+                .config
+                .domains
+                .iter()
+                .any(|domain| **domain == *zone || domain.ends_with(&format!(".{}", zone)));
+
+            if !is_relevant {
+                continue;
+            }
+
+            for record in self.get_records(&zone)? {
+                if self.config.domains.contains(&record.fqdn) {
+                    self.cached_records.push(record)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_cached_records(
+        &mut self,
+        ipv4: Option<IpAddr>,
+        ipv6: Option<IpAddr>,
+    ) -> Result<(), DdnsUpdateError> {
+        for i in 0..self.cached_records.len() {
+            let record = &self.cached_records[i];
+
+            let ip = match (&record.kind, ipv4, ipv6) {
+                (RecordKind::A, Some(ipv4), _) => ipv4,
+                (RecordKind::Aaaa, _, Some(ipv6)) => ipv6,
+                _ => continue,
+            };
+
+            let data = ip.to_string();
+            if *record.data == data {
+                continue;
+            }
+
+            self.patch_record(record, ip)?;
+            self.cached_records[i].data = data.into();
+        }
+
+        Ok(())
+    }
+
+    /// Lists the A and AAAA records of every zone, for `dynners discover`.
+    fn discover(&self) -> Result<Vec<DiscoveredRecord>, DdnsUpdateError> {
+        let mut found = Vec::new();
+
+        for zone in self.get_zones()? {
+            for record in self.get_records(&zone)? {
+                let kind = match record.kind {
+                    RecordKind::A => RecordType::A,
+                    RecordKind::Aaaa => RecordType::Aaaa,
+                };
+
+                found.push(DiscoveredRecord {
+                    name: record.fqdn,
+                    kind,
+                });
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        if self.cached_records.is_empty() {
+            self.cache_records()?;
+        }
+
+        let ipv4 = ips.iter().find(|ip| ip.is_ipv4()).copied();
+        let ipv6 = ips.iter().find(|ip| ip.is_ipv6()).copied();
+
+        // The records may have been changed or removed behind our back, so
+        // they are looked up again after a failure.
+        if let Err(e) = self.update_cached_records(ipv4, ipv6) {
+            self.cached_records.clear();
+            return Err(e);
+        }
+
+        let mut result = FixedVec::new();
+        result.extend(ipv4);
+        result.extend(ipv6);
+
+        Ok(result)
+    }
+
+    fn test_credentials(&mut self) -> Option<Result<String, DdnsUpdateError>> {
+        self.cached_records.clear();
+
+        let result = self.cache_records().map(|()| {
+            describe_found_domains(&self.config.domains, |domain| {
+                self.cached_records
+                    .iter()
+                    .any(|record| &*record.fqdn == domain)
+            })
+        });
+
+        Some(result)
+    }
+
+    fn discover_records(&mut self) -> Option<Result<Vec<DiscoveredRecord>, DdnsUpdateError>> {
+        Some(self.discover())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/vultr.txt"));
+
+        let mut service = Service::new(
+            Config {
+                api_key: Secret::new("key".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+                ttl: Some(300),
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4, ipv6]);
+
+        // example.net is not looked into, the records of example.com span
+        // two pages, and the AAAA record of example.com is up to date.
+        let sent = take_sent();
+        let urls = sent.iter().map(|s| &*s.url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://api.vultr.com/v2/domains?per_page=500",
+                "https://api.vultr.com/v2/domains/example.com/records?per_page=500",
+                "https://api.vultr.com/v2/domains/example.com/records?per_page=500&cursor=bmV4dA",
+                "https://api.vultr.com/v2/domains/example.com/records/a1",
+                "https://api.vultr.com/v2/domains/example.com/records/a3",
+            ]
+        );
+
+        let body = sent[3].body.as_deref().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body, serde_json::json!({ "data": "192.0.2.1", "ttl": 300 }));
+
+        // Nothing is sent while the addresses stay the same.
+        service.update_record(&[ipv4, ipv6]).unwrap();
+        assert!(take_sent().is_empty());
+
+        let ipv4 = "192.0.2.2".parse().unwrap();
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider { message: m, .. }) if &*m == "Invalid API token."
+        ));
+        assert!(service.cached_records.is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{FixedVec, Secret, UtcDateTime};
+
+use super::{one_or_more_string, DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+pub const RECORDS: &[RecordType] = &[RecordType::A, RecordType::Aaaa];
+
+/// A JSON body POSTed to a URL given by the user, for home-grown APIs and
+/// automation tools. The strings in the body may contain the placeholders
+/// below; a string which is nothing but a placeholder is replaced with a
+/// value of the appropriate JSON type instead, e.g. null for an address which
+/// is not known or an array for `{domains}`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    url: Box<str>,
+
+    /// The body sent if none is configured.
+    #[serde(default = "default_body")]
+    body: Value,
+
+    #[serde(default)]
+    headers: BTreeMap<Box<str>, Secret<Box<str>>>,
+
+    #[serde(default, deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+fn default_body() -> Value {
+    serde_json::json!({
+        "ipv4": "{ipv4}",
+        "ipv6": "{ipv6}",
+        "domains": "{domains}",
+        "timestamp": "{timestamp}",
+    })
+}
+
+pub struct Service {
+    config: Config,
+    client: Client,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        Self {
+            config,
+            client: Client::new(http),
+        }
+    }
+}
+
+/// The values of the placeholders at the time of an update.
+struct Values<'a> {
+    ipv4: Option<IpAddr>,
+    ipv6: Option<IpAddr>,
+    domains: &'a [Box<str>],
+    time: UtcDateTime,
+    timestamp: u64,
+}
+
+impl Values<'_> {
+    /// The value of a string which is exactly a placeholder.
+    fn json(&self, placeholder: &str) -> Option<Value> {
+        let ip = |ip: Option<IpAddr>| ip.map_or(Value::Null, |ip| ip.to_string().into());
+
+        let value = match placeholder {
+            "{ipv4}" => ip(self.ipv4),
+            "{ipv6}" => ip(self.ipv6),
+            "{domains}" => self.domains.iter().map(|d| Value::from(&**d)).collect(),
+            "{time}" => self.time.to_iso8601().into(),
+            "{timestamp}" => self.timestamp.into(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    /// Replaces the placeholders within a longer string with their text.
+    fn text(&self, template: &str) -> String {
+        let ip = |ip: Option<IpAddr>| ip.map(|ip| ip.to_string()).unwrap_or_default();
+
+        template
+            .replace("{ipv4}", &ip(self.ipv4))
+            .replace("{ipv6}", &ip(self.ipv6))
+            .replace("{domains}", &self.domains.join(","))
+            .replace("{time}", &self.time.to_iso8601())
+            .replace("{timestamp}", &self.timestamp.to_string())
+    }
+
+    fn expand(&self, template: &Value) -> Value {
+        match template {
+            Value::String(s) => self.json(s).unwrap_or_else(|| self.text(s).into()),
+            Value::Array(items) => items.iter().map(|item| self.expand(item)).collect(),
+            Value::Object(fields) => fields
+                .iter()
+                .map(|(key, value)| (key.clone(), self.expand(value)))
+                .collect(),
+            other => other.clone(),
+        }
+    }
+}
+
+impl DdnsService for Service {
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let now = SystemTime::now();
+        let timestamp = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let values = Values {
+            ipv4: ips.iter().copied().find(IpAddr::is_ipv4),
+            ipv6: ips.iter().copied().find(IpAddr::is_ipv6),
+            domains: &self.config.domains,
+            time: UtcDateTime::from(now),
+            timestamp,
+        };
+
+        let body = values.expand(&self.config.body);
+
+        let mut request = self.client.post(&self.config.url);
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value.expose());
+        }
+
+        match request.send_json(&body) {
+            Ok(_) => (),
+            Err(Error::Status(status, resp)) => {
+                let kind = ErrorKind::from_response(status, &resp);
+                let body = resp.into_string().unwrap_or_default();
+                let body = body.trim();
+                let excerpt = match body.char_indices().nth(200) {
+                    Some((end, _)) => &body[..end],
+                    None => body,
+                };
+
+                return Err(DdnsUpdateError::provider(
+                    "webhook",
+                    kind,
+                    format!("unsuccessful update (status {}): {}", status, excerpt).into(),
+                ));
+            }
+            Err(Error::Transport(t)) => {
+                return Err(DdnsUpdateError::TransportError(t.to_string().into()))
+            }
+        }
+
+        let mut result = FixedVec::new();
+        result.extend(values.ipv4);
+        result.extend(values.ipv6);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn expand() {
+        let values = Values {
+            ipv4: Some("192.0.2.1".parse().unwrap()),
+            ipv6: None,
+            domains: &["a.example.com".into(), "b.example.com".into()],
+            time: UtcDateTime::from(SystemTime::UNIX_EPOCH),
+            timestamp: 0,
+        };
+
+        let template = serde_json::json!({
+            "address": "{ipv4}",
+            "address6": "{ipv6}",
+            "hosts": "{domains}",
+            "text": "{ipv4} for {domains} at {time}",
+            "nested": [{ "at": "{timestamp}", "ttl": 60 }],
+        });
+
+        assert_eq!(
+            values.expand(&template),
+            serde_json::json!({
+                "address": "192.0.2.1",
+                "address6": null,
+                "hosts": ["a.example.com", "b.example.com"],
+                "text": "192.0.2.1 for a.example.com,b.example.com at 1970-01-01T00:00:00Z",
+                "nested": [{ "at": 0, "ttl": 60 }],
+            })
+        );
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/webhook.txt"));
+
+        let config = toml::from_str::<Config>(
+            r#"
+            url = "https://hooks.example.com/dns"
+            headers = { Authorization = "Bearer token" }
+            domains = "home.example.com"
+            body = { host = "{domains}", ip = "{ipv6}" }
+            "#,
+        )
+        .unwrap();
+        let mut service = Service::new(config, &Settings::default());
+
+        let ipv6 = "2001:db8::1".parse().unwrap();
+        let updated = service.update_record(&[ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv6]);
+
+        let sent = take_sent();
+        let body = serde_json::from_str::<Value>(sent[0].body.as_deref().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "host": ["home.example.com"], "ip": "2001:db8::1" })
+        );
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Auth,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::http::{Client, Error, Settings};
+use crate::util::{one_or_more_string, FixedVec, Secret};
+
+use super::{DdnsService, DdnsUpdateError, ErrorKind, RecordType};
+
+/// ZoneEdit only updates A records through its dynamic endpoint.
+pub const RECORDS: &[RecordType] = &[RecordType::A];
+
+/// ZoneEdit refuses updates sent less than 10 minutes apart.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The username of the account, and the dynamic authentication token
+    /// shown under DNS Settings > DYN records, not the password.
+    username: Box<str>,
+    token: Secret<Box<str>>,
+
+    #[serde(deserialize_with = "one_or_more_string")]
+    domains: Vec<Box<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service {
+    config: Config,
+    client: Client,
+    auth: Secret<Box<str>>,
+}
+
+impl Service {
+    pub fn new(config: Config, http: &Settings) -> Self {
+        let username_token = String::from(config.username.clone()) + ":" + config.token.expose();
+        let base64 = data_encoding::BASE64.encode(username_token.as_bytes());
+        let auth = String::from("Basic ") + &base64;
+
+        Self {
+            config,
+            client: Client::new(http),
+            auth: Secret::new(auth.into()),
+        }
+    }
+}
+
+/// One of the tags ZoneEdit answers with, one for each host, e.g.
+/// <SUCCESS CODE="200" TEXT="Update succeeded." ZONE="example.com" HOST="www.example.com" IP="192.0.2.1">
+#[derive(Debug, PartialEq, Eq)]
+struct Tag<'a> {
+    is_success: bool,
+    attributes: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Tag<'a> {
+    fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Picks the SUCCESS and ERROR tags out of the response, which is not quite
+/// XML (the tags are never closed) and may be surrounded by other text.
+fn parse_tags(text: &str) -> Vec<Tag<'_>> {
+    let mut tags = Vec::new();
+
+    for part in text.split('<').skip(1) {
+        let Some((tag, _)) = part.split_once('>') else {
+            continue;
+        };
+
+        let tag = tag.trim().trim_end_matches('/');
+        let (name, mut rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+
+        let is_success = match name {
+            "SUCCESS" => true,
+            "ERROR" => false,
+            _ => continue,
+        };
+
+        let mut attributes = Vec::new();
+        while let Some((key, after)) = rest.split_once("=\"") {
+            let Some((value, after)) = after.split_once('"') else {
+                break;
+            };
+
+            attributes.push((key.trim(), value));
+            rest = after;
+        }
+
+        tags.push(Tag {
+            is_success,
+            attributes,
+        });
+    }
+
+    tags
+}
+
+/// Classifies the code of an ERROR tag.
+fn error_kind(code: &str) -> ErrorKind {
+    match code {
+        // Duplicate updates are handled before this, as the address is up to
+        // date already.
+        "701" => ErrorKind::NotFound,
+        "702" => ErrorKind::RateLimited(Some(UPDATE_INTERVAL)),
+        "703" | "704" | "705" | "706" => ErrorKind::Validation,
+        "708" | "709" => ErrorKind::Auth,
+        _ => ErrorKind::Other,
+    }
+}
+
+impl DdnsService for Service {
+    /// See: https://support.zoneedit.com/en/knowledgebase/article/dynamic-dns
+    fn update_record(&mut self, ips: &[IpAddr]) -> Result<FixedVec<IpAddr, 2>, DdnsUpdateError> {
+        let Some(ipv4) = ips.iter().find(|ip| ip.is_ipv4()) else {
+            return Err(DdnsUpdateError::provider(
+                "ZoneEdit",
+                ErrorKind::Validation,
+                "ZoneEdit only supports IPv4 addresses".into(),
+            ));
+        };
+
+        let response = self
+            .client
+            .get("https://dynamic.zoneedit.com/auth/dynamic.html")
+            .set("Authorization", self.auth.expose())
+            .query("host", &self.config.domains.join(","))
+            .query("dnsto", &ipv4.to_string())
+            .call();
+
+        let (status, text) = match response {
+            Ok(resp) => (None, resp.into_string()),
+            Err(Error::Status(status, resp)) => (Some(status), resp.into_string()),
+            Err(Error::Transport(t)) => {
+                return Err(DdnsUpdateError::TransportError(t.to_string().into()))
+            }
+        };
+
+        let text = text.map_err(|e| {
+            DdnsUpdateError::provider("ZoneEdit", ErrorKind::Other, e.to_string().into())
+        })?;
+
+        let tags = parse_tags(&text);
+
+        // A bad login is answered with a page of its own rather than a tag.
+        if tags.is_empty() {
+            let kind = status.map_or(ErrorKind::Other, ErrorKind::from_status);
+            let message = format!("unexpected response \"{}\"", text.trim());
+            return Err(DdnsUpdateError::provider("ZoneEdit", kind, message.into()));
+        }
+
+        for tag in tags.iter().filter(|tag| !tag.is_success) {
+            let code = tag.attribute("CODE").unwrap_or_default();
+            if code == "707" {
+                continue;
+            }
+
+            let text = tag.attribute("TEXT").unwrap_or("unknown error");
+            let message = match tag.attribute("HOST").or(tag.attribute("ZONE")) {
+                Some(host) => format!("{} ({}, for {})", text, code, host),
+                None => format!("{} ({})", text, code),
+            };
+
+            return Err(DdnsUpdateError::provider(
+                "ZoneEdit",
+                error_kind(code),
+                message.into(),
+            ));
+        }
+
+        let mut result = FixedVec::new();
+        result.push(*ipv4);
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{add_fixtures, take_sent};
+
+    #[test]
+    fn tags() {
+        let text = concat!(
+            "<SUCCESS CODE=\"200\" TEXT=\"Update succeeded.\" ZONE=\"example.com\" HOST=\"example.com\" IP=\"192.0.2.1\">\n",
+            "<ERROR CODE=\"707\" TEXT=\"Duplicate updates for the same host/ip, adjust client settings\" ZONE=\"example.com\" HOST=\"www.example.com\">\n",
+        );
+
+        let tags = parse_tags(text);
+        assert_eq!(tags.len(), 2);
+        assert!(tags[0].is_success);
+        assert_eq!(tags[0].attribute("IP"), Some("192.0.2.1"));
+        assert!(!tags[1].is_success);
+        assert_eq!(tags[1].attribute("code"), Some("707"));
+        assert_eq!(tags[1].attribute("HOST"), Some("www.example.com"));
+    }
+
+    #[test]
+    fn update() {
+        add_fixtures(include_str!("fixtures/zoneedit.txt"));
+
+        let mut service = Service::new(
+            Config {
+                username: "user".into(),
+                token: Secret::new("token".into()),
+                domains: vec!["example.com".into(), "www.example.com".into()],
+            },
+            &Settings::default(),
+        );
+
+        let ipv4 = "192.0.2.1".parse().unwrap();
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        // A duplicate update of www.example.com is as good as a success.
+        let updated = service.update_record(&[ipv4, ipv6]).unwrap();
+        assert_eq!(updated.as_slice(), &[ipv4]);
+
+        let updated = service.update_record(&[ipv4]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::NotFound,
+                message: m,
+                ..
+            }) if &*m == "Zone is not set up in this account (701, for www.example.com)"
+        ));
+
+        let updated = service.update_record(&[ipv6]);
+        assert!(matches!(
+            updated,
+            Err(DdnsUpdateError::Provider {
+                kind: ErrorKind::Validation,
+                ..
+            })
+        ));
+
+        let sent = take_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(
+            &*sent[0].url,
+            "https://dynamic.zoneedit.com/auth/dynamic.html?host=example.com%2Cwww.example.com&dnsto=192.0.2.1"
+        );
+    }
+}
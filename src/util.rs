@@ -1,8 +1,14 @@
 use serde::de::Visitor;
 use serde::Deserialize;
 use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use std::ffi::CStr;
+use std::fmt::Display;
 use std::mem::MaybeUninit;
+use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroU32;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// This helper is intended to aid deserializing fields that can contain a
 /// string or a string array. It will always deserialize a single string into
@@ -104,6 +110,298 @@ where
     deserializer.deserialize_any(OptionalNonzero)
 }
 
+/// Returns the names of the fields of a struct deriving `Deserialize`, i.e. the
+/// keys it accepts in the config file. Renames done with `#[serde(...)]` are
+/// taken into account, as the names are the ones the derived code asks for.
+pub fn struct_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    #[derive(Debug)]
+    struct Probed;
+
+    impl Display for Probed {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "probed")
+        }
+    }
+
+    impl std::error::Error for Probed {}
+
+    impl serde::de::Error for Probed {
+        fn custom<E: Display>(_: E) -> Self {
+            Probed
+        }
+    }
+
+    /// A deserializer which only notes down the fields it is asked for.
+    struct FieldsProbe<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de, 'a> Deserializer<'de> for FieldsProbe<'a> {
+        type Error = Probed;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Probed> {
+            Err(Probed)
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Probed> {
+            *self.0 = fields;
+            Err(Probed)
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsProbe(&mut fields));
+    fields
+}
+
+/// A credential such as a token or a password. Its `Debug` and `Serialize`
+/// output is masked, so that it cannot leak into the logs by accident; use
+/// `expose()` where the actual value is needed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> std::fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("<redacted>")
+    }
+}
+
+/// The secrets read from the config file are kept out of the diagnostics.
+impl<'de, T: Deserialize<'de> + AsRef<str>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let secret = T::deserialize(deserializer)?;
+        crate::http::register_secret(secret.as_ref());
+        Ok(Self(secret))
+    }
+}
+
+/// Reads a credential which is masked like any other `Secret`, but left in the
+/// diagnostics: a username is often the domain itself, or otherwise shows up
+/// all over the exchanges.
+pub fn unscrubbed_secret<'de, D, T>(deserializer: D) -> Result<Secret<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Secret)
+}
+
+pub fn unscrubbed_optional_secret<'de, D, T>(deserializer: D) -> Result<Option<Secret<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(|value| value.map(Secret))
+}
+
+/// Parses the address of a DNS server, whose port defaults to 53. For
+/// example, "9.9.9.9", "9.9.9.9:5353" and "[2620:fe::fe]:53" are all accepted,
+/// and "" means that no server is given.
+pub(super) fn parse_optional_dns_server<'de, D>(
+    deserializer: D,
+) -> Result<Option<SocketAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let server = String::deserialize(deserializer)?;
+
+    if server.is_empty() {
+        return Ok(None);
+    }
+
+    server
+        .parse::<SocketAddr>()
+        .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+        .map(Some)
+        .map_err(|_| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Str(&server),
+                &"an IP address, optionally with a port",
+            )
+        })
+}
+
+/// Returns the hostname of this machine, without the domain part. For
+/// example, "myhost.example.com" will be returned as "myhost".
+pub fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+
+    // SAFETY: the buffer is valid for writes of its entire length. If the
+    // hostname is too long, it is truncated (and possibly not null-terminated,
+    // which CStr::from_bytes_until_nul takes care of).
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut _, buffer.len()) };
+
+    if result != 0 {
+        return None;
+    }
+
+    let hostname = CStr::from_bytes_until_nul(&buffer).ok()?.to_str().ok()?;
+
+    hostname
+        .split('.')
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+}
+
+/// Percent-encodes everything but the unreserved characters, for use in a query
+/// string or a form-encoded body.
+pub fn url_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded += &format!("%{:02X}", byte),
+        }
+    }
+
+    encoded
+}
+
+/// Returns the text within the first <tag>...</tag> of the document. The
+/// responses of the XML APIs are simple enough that a proper XML parser is not
+/// worth it.
+pub fn xml_element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].trim())
+}
+
+/// Escapes the text so that it can be put inside an XML element.
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// A point in time broken down into its UTC calendar date and time of day, as
+/// needed by the APIs which sign their requests with a timestamp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UtcDateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl UtcDateTime {
+    pub fn now() -> Self {
+        Self::from(SystemTime::now())
+    }
+
+    /// Formats the time as YYYYMMDDTHHMMSSZ, the basic format of ISO 8601.
+    pub fn to_basic_iso8601(self) -> String {
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Formats the time as YYYY-MM-DDTHH:MM:SSZ, the extended format of ISO
+    /// 8601.
+    pub fn to_iso8601(self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Formats the time as in the Date header of HTTP, e.g. "Thu, 01 Jan 1970
+    /// 00:00:00 GMT".
+    pub fn to_http_date(self) -> String {
+        const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+
+        // Sakamoto's method, which counts the years from March on.
+        let offsets = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+        let year = self.year - (self.month < 3) as i64;
+        let weekday = (year + year.div_euclid(4) - year.div_euclid(100)
+            + year.div_euclid(400)
+            + offsets[self.month as usize - 1]
+            + self.day as i64)
+            .rem_euclid(7);
+
+        format!(
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[weekday as usize],
+            self.day,
+            MONTHS[self.month as usize - 1],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+impl From<SystemTime> for UtcDateTime {
+    fn from(time: SystemTime) -> Self {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+
+        let days = secs.div_euclid(86400);
+        let seconds_of_day = secs.rem_euclid(86400) as u32;
+
+        // Converts the days since the epoch into a date of the proleptic
+        // Gregorian calendar. See: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: seconds_of_day / 3600,
+            minute: seconds_of_day / 60 % 60,
+            second: seconds_of_day % 60,
+        }
+    }
+}
+
 /// A super simple fixed-allocation vector.
 pub struct FixedVec<T, const N: usize> {
     length: u32,
@@ -123,15 +421,24 @@ impl<T: Copy, const N: usize> FixedVec<T, N> {
     }
 
     pub fn push(&mut self, item: T) -> Option<T> {
+        self.try_push(item).err()
+    }
+
+    /// Appends the item, or gives it back if the vector is full.
+    pub fn try_push(&mut self, item: T) -> Result<(), T> {
         if self.length < N as u32 {
             self.array[self.length as usize] = MaybeUninit::new(item);
             self.length += 1;
-            None
+            Ok(())
         } else {
-            Some(item)
+            Err(item)
         }
     }
 
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
     pub fn as_slice(&self) -> &[T] {
         // CAST-SAFETY: MaybeUninit<T> is sized & aligned the same as T
         let ptr = self.array.as_ptr() as *const T;
@@ -143,9 +450,108 @@ impl<T: Copy, const N: usize> FixedVec<T, N> {
     }
 }
 
+impl<T: Copy, const N: usize> Default for FixedVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Extending beyond the capacity is a bug, just like indexing out of bounds,
+/// so it panics. Use `try_push` if the items may not fit.
+impl<T: Copy, const N: usize> Extend<T> for FixedVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            if self.try_push(item).is_err() {
+                panic!("FixedVec: capacity of {} exceeded", N);
+            }
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a, const N: usize> Extend<&'a T> for FixedVec<T, N> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+pub struct FixedVecIntoIter<T, const N: usize> {
+    vec: FixedVec<T, N>,
+    index: u32,
+}
+
+impl<T: Copy, const N: usize> Iterator for FixedVecIntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let item = self.vec.get(self.index).copied()?;
+        self.index += 1;
+        Some(item)
+    }
+}
+
+impl<T: Copy, const N: usize> IntoIterator for FixedVec<T, N> {
+    type Item = T;
+    type IntoIter = FixedVecIntoIter<T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        FixedVecIntoIter {
+            vec: self,
+            index: 0,
+        }
+    }
+}
+
+impl<'a, T: Copy, const N: usize> IntoIterator for &'a FixedVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::util::FixedVec;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::util::{xml_element, FixedVec, Secret, UtcDateTime};
+
+    #[test]
+    fn secret() {
+        let secret = toml::from_str::<toml::Table>(r#"token = "hunter2""#).unwrap()["token"]
+            .clone()
+            .try_into::<Secret<Box<str>>>()
+            .unwrap();
+
+        assert_eq!(&**secret.expose(), "hunter2");
+        assert_eq!(format!("{:?}", secret), "<redacted>");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), r#""<redacted>""#);
+    }
+
+    #[test]
+    fn xml() {
+        let xml = "<a><ErrCount>1</ErrCount><errors><Err1> oops </Err1></errors></a>";
+        assert_eq!(xml_element(xml, "ErrCount"), Some("1"));
+        assert_eq!(xml_element(xml, "Err1"), Some("oops"));
+        assert_eq!(xml_element(xml, "Err2"), None);
+    }
+
+    #[test]
+    fn utc_date_time() {
+        let time = UtcDateTime::from(UNIX_EPOCH);
+        assert_eq!(time.to_basic_iso8601(), "19700101T000000Z");
+        assert_eq!(time.to_http_date(), "Thu, 01 Jan 1970 00:00:00 GMT");
+
+        // A leap day.
+        let time = UtcDateTime::from(UNIX_EPOCH + Duration::from_secs(1709251199));
+        assert_eq!(time.to_basic_iso8601(), "20240229T235959Z");
+        assert_eq!(time.to_iso8601(), "2024-02-29T23:59:59Z");
+        assert_eq!(time.to_http_date(), "Thu, 29 Feb 2024 23:59:59 GMT");
+
+        let time = UtcDateTime::from(UNIX_EPOCH + Duration::from_secs(1440938160));
+        assert_eq!(time.to_basic_iso8601(), "20150830T123600Z");
+        assert_eq!(time.to_http_date(), "Sun, 30 Aug 2015 12:36:00 GMT");
+    }
 
     #[test]
     fn fixed_vec() {
@@ -169,5 +575,22 @@ mod tests {
         assert_eq!(vec.as_slice().len(), 2);
         assert!(!vec.push(30).is_none());
         assert_eq!(vec.as_slice().len(), 2);
+        assert_eq!(vec.try_push(30), Err(30));
+
+        assert_eq!(vec.iter().sum::<u32>(), 30);
+        assert_eq!((&vec).into_iter().count(), 2);
+        assert_eq!(vec.into_iter().collect::<Vec<_>>(), [10, 20]);
+
+        let mut vec = FixedVec::<u32, 3>::default();
+        vec.extend([1, 2]);
+        vec.extend(&[3]);
+        assert_eq!(vec.as_slice(), &[1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn fixed_vec_overflow() {
+        let mut vec = FixedVec::<u32, 2>::new();
+        vec.extend([1, 2, 3]);
     }
 }